@@ -43,6 +43,8 @@ export interface CaptionSegment {
   endMs: number
   text: string
   words: WordSpan[]
+  // Manual anchor overriding the global position (percent of the frame)
+  position?: { xPct?: number; yPct?: number }
 }
 
 interface CaptionEditorProps {
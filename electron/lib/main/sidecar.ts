@@ -104,7 +104,18 @@ export class Sidecar {
           this.progressCb(msg)
           return
         }
-        if (msg.result !== undefined && msg.id) {
+        if (msg.resultPath && msg.id) {
+          // Results too big for one line (e.g. a 4K frame) arrive as a JSON file
+          try {
+            const result = JSON.parse(fs.readFileSync(msg.resultPath, 'utf8'))
+            this.pending.get(msg.id)?.resolve(result)
+          } catch (err) {
+            this.pending.get(msg.id)?.reject(err)
+          } finally {
+            fs.rm(msg.resultPath, { force: true }, () => {})
+          }
+          this.pending.delete(msg.id)
+        } else if (msg.result !== undefined && msg.id) {
           // Create a safe copy for logging that doesn't contain huge strings
           const safeResult = { ...msg.result }
           if (safeResult.imageData && safeResult.imageData.length > 100) {
@@ -119,7 +130,7 @@ export class Sidecar {
           this.pending.delete(msg.id)
         } else if (msg.error && msg.id) {
           console.log('[SIDECAR] Error response for:', msg.id, msg.error)
-          const friendlyError = this.createFriendlyError(msg.error)
+          const friendlyError = this.createFriendlyError(msg.error, msg.code)
           this.pending.get(msg.id)?.reject(friendlyError)
           this.pending.delete(msg.id)
         }
@@ -131,11 +142,25 @@ export class Sidecar {
     console.log('[SIDECAR] Rust sidecar started successfully')
   }
 
-  private createFriendlyError(errorMessage: string): Error {
+  private createFriendlyError(errorMessage: string, code?: string): Error {
     const error = new Error()
 
     // Determine error type and create user-friendly message
-    if (errorMessage.includes('API key not provided') || errorMessage.includes("You didn't provide an API key")) {
+    if (code === 'NO_AUDIO') {
+      error.name = 'NO_AUDIO'
+      error.message = 'This video has no audio track. Pick a different file that contains speech.'
+    } else if (code === 'INVALID_COLOR') {
+      // The core names the offending field, so show its message as-is
+      error.name = 'INVALID_COLOR'
+      error.message = errorMessage
+    } else if (code === 'TIMEOUT') {
+      error.name = 'TIMEOUT'
+      error.message = 'The operation took too long and was stopped. Try again, or allow more time in settings.'
+    } else if (code === 'INVALID_SEGMENT') {
+      // Names the segment index and its timings
+      error.name = 'INVALID_SEGMENT'
+      error.message = errorMessage
+    } else if (errorMessage.includes('API key not provided') || errorMessage.includes("You didn't provide an API key")) {
       error.name = 'API_KEY_MISSING'
       error.message = 'OpenAI API key is not configured. Add it in settings for better transcription quality.'
     } else if (errorMessage.includes('401 Unauthorized') || errorMessage.includes('Unauthorized')) {
@@ -9,16 +9,23 @@ pub async fn extract_audio(
     p: ExtractAudioParams,
     mut emit: impl FnMut(RpcEvent),
 ) -> anyhow::Result<ExtractAudioResult> {
-    let out = p.out.unwrap_or_else(|| {
+    let out = p.out.clone().unwrap_or_else(|| {
         let mut pb = PathBuf::from(&p.input);
         pb.set_extension("m4a");
         pb.to_string_lossy().to_string()
     });
 
-    let target_codec = p.codec.unwrap_or_else(|| "aac".to_string());
+    let target_codec = p.codec.clone().unwrap_or_else(|| "aac".to_string());
+    let resampling = p.sample_rate.is_some() || p.channels.is_some() || p.bitrate.is_some();
+    let filters = audio_filters(&p);
+    validate_audio_options(&p)?;
 
-    // Probe input to determine if we can use stream copy
-    let use_copy = if let Ok(probe_result) = probe(id, &p.input, &mut emit).await {
+    // Probe input to determine if we can use stream copy (not when filtering, resampling or re-rating)
+    let trim_args = crate::video::trim_input_args(p.trim_start_ms, p.trim_end_ms);
+    // Stream copy can only cut on packet boundaries, so a trimmed extract is re-encoded
+    let use_copy = if resampling || filters.is_some() || !trim_args.is_empty() {
+        false
+    } else if let Ok(probe_result) = probe(id, &p.input, &mut emit).await {
         if let Some(audio_codec) = &probe_result.audio_codec {
             let codec_lower = audio_codec.to_lowercase();
             match target_codec.as_str() {
@@ -48,25 +55,259 @@ pub async fn extract_audio(
         &target_codec
     };
 
-    let mut cmd = TokioCommand::new("ffmpeg");
+    let ffmpeg_path = crate::whisper::find_ffmpeg_binary()
+        .await
+        .map_err(|e| anyhow::anyhow!("FFmpeg not found: {}", e))?;
+    let mut cmd = TokioCommand::new(ffmpeg_path);
     cmd.kill_on_drop(true);
     cmd.arg("-y")
+        .args(&trim_args)
         .arg("-i")
         .arg(&p.input)
         .arg("-vn")
         .arg("-acodec")
         .arg(audio_codec);
 
-    // Add explicit bitrate only when re-encoding
-    if !use_copy && target_codec == "aac" {
-        cmd.arg("-b:a").arg("160k"); // Explicit AAC bitrate for quality
+    if let Some(filters) = &filters {
+        emit(RpcEvent::Log {
+            id: id.into(),
+            message: format!("Applying audio filters: {}", filters),
+        });
+        cmd.arg("-af").arg(filters);
+    }
+    if !use_copy {
+        cmd.args(encode_args(&target_codec, &p));
     }
 
     cmd.arg(&out);
 
-    let status = cmd.status().await?;
-    if !status.success() {
-        return Err(anyhow::anyhow!("ffmpeg audio extraction failed"));
+    if p.dry_run {
+        let command = crate::logging::command_argv(&cmd);
+        emit(RpcEvent::Log {
+            id: id.into(),
+            message: format!("Dry run, not running: {}", command.join(" ")),
+        });
+        return Ok(ExtractAudioResult {
+            audio: out,
+            command: Some(command),
+        });
+    }
+    crate::logging::log_command(&cmd);
+    let output = cmd.output().await?;
+    if !output.status.success() {
+        let tail = crate::video::stderr_tail(&output.stderr);
+        return Err(crate::video::ffmpeg_failure(
+            id,
+            "ffmpeg audio extraction failed",
+            &tail,
+            &mut emit,
+        ));
+    }
+    Ok(ExtractAudioResult {
+        audio: out,
+        command: None,
+    })
+}
+
+/// An ffmpeg run whose MP3 output is being streamed into an upload (see [`stream_audio`]).
+/// Dropping it kills ffmpeg.
+pub struct AudioStream {
+    child: tokio::process::Child,
+    stderr: tokio::task::JoinHandle<Vec<String>>,
+}
+
+impl AudioStream {
+    /// Wait for ffmpeg once its output has been sent; a failed run means the upload
+    /// was cut short
+    pub async fn finish(mut self, id: &str, emit: &mut impl FnMut(RpcEvent)) -> anyhow::Result<()> {
+        if self.child.wait().await?.success() {
+            return Ok(());
+        }
+        let tail = (&mut self.stderr).await.unwrap_or_default();
+        Err(crate::video::ffmpeg_failure(
+            id,
+            "ffmpeg audio extraction failed",
+            &tail,
+            emit,
+        ))
+    }
+}
+
+/// Start ffmpeg encoding the audio as MP3 to its stdout and hand that output over as a
+/// request body, so the audio never touches disk or sits whole in memory. Meant for
+/// short clips uploaded right away; `p.out` and `p.codec` are ignored.
+pub async fn stream_audio(p: &ExtractAudioParams) -> anyhow::Result<(reqwest::Body, AudioStream)> {
+    validate_audio_options(p)?;
+    let ffmpeg_path = crate::whisper::find_ffmpeg_binary()
+        .await
+        .map_err(|e| anyhow::anyhow!("FFmpeg not found: {}", e))?;
+    let mut cmd = TokioCommand::new(ffmpeg_path);
+    cmd.kill_on_drop(true);
+    cmd.args(crate::video::trim_input_args(
+        p.trim_start_ms,
+        p.trim_end_ms,
+    ))
+    .arg("-i")
+    .arg(&p.input)
+    .args(["-vn", "-acodec", "libmp3lame"]);
+    if let Some(filters) = audio_filters(p) {
+        cmd.arg("-af").arg(filters);
+    }
+    cmd.args(encode_args("mp3", p))
+        .args(["-f", "mp3", "pipe:1"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped());
+    crate::logging::log_command(&cmd);
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to run ffmpeg: {}", e))?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = crate::video::collect_stderr_tail(&mut child);
+    let body = reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(stdout));
+    Ok((body, AudioStream { child, stderr }))
+}
+
+// Single-pass EBU R128 normalization to podcast-style levels
+const LOUDNORM_FILTER: &str = "loudnorm=I=-16:TP=-1.5:LRA=11";
+
+// FFT denoiser: -25 dB noise floor with noise tracking, for wind and room tone
+const DENOISE_FILTER: &str = "afftdn=nf=-25:tn=1";
+
+// Clean-up filter chain applied during extraction, if any was requested.
+// Denoise runs first so loudnorm doesn't raise the noise along with the speech.
+fn audio_filters(p: &ExtractAudioParams) -> Option<String> {
+    let mut filters = Vec::new();
+    if p.denoise {
+        filters.push(DENOISE_FILTER);
+    }
+    if p.normalize {
+        filters.push(LOUDNORM_FILTER);
+    }
+    (!filters.is_empty()).then(|| filters.join(","))
+}
+
+// Sample rate, channel and bitrate flags for a re-encode
+fn encode_args(target_codec: &str, p: &ExtractAudioParams) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(rate) = p.sample_rate {
+        args.extend(["-ar".to_string(), rate.to_string()]);
+    } else if p.normalize {
+        // loudnorm resamples to 192 kHz internally; bring it back to a normal rate
+        args.extend(["-ar".to_string(), "48000".to_string()]);
+    }
+    if let Some(channels) = p.channels {
+        args.extend(["-ac".to_string(), channels.to_string()]);
+    }
+    match (&p.bitrate, target_codec) {
+        (Some(bitrate), _) => args.extend(["-b:a".to_string(), bitrate.clone()]),
+        // Explicit AAC bitrate for quality
+        (None, "aac") => args.extend(["-b:a".to_string(), "160k".to_string()]),
+        _ => {}
+    }
+    args
+}
+
+fn validate_audio_options(p: &ExtractAudioParams) -> anyhow::Result<()> {
+    if let Some(rate) = p.sample_rate {
+        if !(8_000..=192_000).contains(&rate) {
+            return Err(anyhow::anyhow!(
+                "sampleRate must be between 8000 and 192000 Hz, got {}",
+                rate
+            ));
+        }
+    }
+    if let Some(channels) = p.channels {
+        if !(1..=8).contains(&channels) {
+            return Err(anyhow::anyhow!(
+                "channels must be between 1 and 8, got {}",
+                channels
+            ));
+        }
+    }
+    if let Some(bitrate) = &p.bitrate {
+        let digits = bitrate.strip_suffix(['k', 'K']).unwrap_or(bitrate);
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(anyhow::anyhow!(
+                "bitrate must look like \"64k\" or \"64000\", got \"{}\"",
+                bitrate
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(
+        sample_rate: Option<u32>,
+        channels: Option<u8>,
+        bitrate: Option<&str>,
+    ) -> ExtractAudioParams {
+        ExtractAudioParams {
+            input: "in.mp4".into(),
+            codec: None,
+            out: None,
+            sample_rate,
+            channels,
+            bitrate: bitrate.map(String::from),
+            normalize: false,
+            denoise: false,
+            trim_start_ms: None,
+            trim_end_ms: None,
+            dry_run: false,
+            ffmpeg_path: None,
+        }
+    }
+
+    #[test]
+    fn test_encode_args_for_whisper_upload() {
+        let p = params(Some(16_000), Some(1), Some("32k"));
+        assert_eq!(
+            encode_args("mp3", &p),
+            vec!["-ar", "16000", "-ac", "1", "-b:a", "32k"]
+        );
+    }
+
+    #[test]
+    fn test_encode_args_keep_aac_default_bitrate() {
+        assert_eq!(
+            encode_args("aac", &params(None, None, None)),
+            vec!["-b:a", "160k"]
+        );
+        assert!(encode_args("mp3", &params(None, None, None)).is_empty());
+    }
+
+    #[test]
+    fn test_normalize_adds_loudnorm_and_output_rate() {
+        let mut p = params(None, None, None);
+        assert_eq!(audio_filters(&p), None);
+        p.normalize = true;
+        assert_eq!(audio_filters(&p).as_deref(), Some(LOUDNORM_FILTER));
+        assert_eq!(encode_args("mp3", &p), vec!["-ar", "48000"]);
+        p.sample_rate = Some(16_000);
+        assert_eq!(encode_args("mp3", &p), vec!["-ar", "16000"]);
+    }
+
+    #[test]
+    fn test_denoise_runs_before_normalize() {
+        let mut p = params(None, None, None);
+        p.denoise = true;
+        assert_eq!(audio_filters(&p).as_deref(), Some(DENOISE_FILTER));
+        assert!(encode_args("mp3", &p).is_empty());
+        p.normalize = true;
+        assert_eq!(
+            audio_filters(&p),
+            Some(format!("{},{}", DENOISE_FILTER, LOUDNORM_FILTER))
+        );
+    }
+
+    #[test]
+    fn test_validate_audio_options() {
+        assert!(validate_audio_options(&params(Some(16_000), Some(1), Some("64k"))).is_ok());
+        assert!(validate_audio_options(&params(Some(100), None, None)).is_err());
+        assert!(validate_audio_options(&params(None, Some(0), None)).is_err());
+        assert!(validate_audio_options(&params(None, None, Some("fast"))).is_err());
     }
-    Ok(ExtractAudioResult { audio: out })
 }
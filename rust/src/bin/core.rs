@@ -1,14 +1,27 @@
 use core::captions;
-use core::rpc::{new_id, RpcError, RpcEvent, RpcRequest, RpcResponse};
-use std::io::{self, BufRead, Write};
+use core::rpc::{error_code, new_id, RpcError, RpcEvent, RpcRequest};
+use std::io::{self, BufRead};
 
 // Shared cancellation map: request_id -> cancellation_sender
 type CancelMap = std::sync::Arc<
     std::sync::Mutex<std::collections::HashMap<String, tokio::sync::broadcast::Sender<()>>>,
 >;
 
+// Slots for heavy (ffmpeg/whisper) requests; the rest queue until one frees up
+type JobSlots = std::sync::Arc<tokio::sync::Semaphore>;
+
+// How long a shutdown signal waits for cancelled requests to kill their ffmpeg
+// children and drop their temp files before the process exits anyway
+const SHUTDOWN_GRACE: std::time::Duration = std::time::Duration::from_secs(5);
+
+// How long exiting waits for queued output to reach a reader that has stopped reading
+const OUTPUT_FLUSH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // Optional file logging, controlled by CAPSLAP_LOG
+    let _log_guard = core::logging::init();
+
     // Install panic hook to diagnose silent crashes
     std::panic::set_hook(Box::new(|info| {
         let msg = match info.payload().downcast_ref::<&'static str>() {
@@ -22,6 +35,7 @@ async fn main() -> anyhow::Result<()> {
             .location()
             .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()))
             .unwrap_or_else(|| "unknown".to_string());
+        tracing::error!("panic at {}: {}", location, msg);
         let log_path = "/tmp/capslap-panic.log";
         let _ = std::fs::write(log_path, format!("Panic occurred at {}: {}", location, msg));
     }));
@@ -30,6 +44,11 @@ async fn main() -> anyhow::Result<()> {
     let mut tasks = tokio::task::JoinSet::new();
     let cancel_map: CancelMap =
         std::sync::Arc::new(std::sync::Mutex::new(std::collections::HashMap::new()));
+    let job_slots: JobSlots =
+        std::sync::Arc::new(tokio::sync::Semaphore::new(core::rpc::max_heavy_jobs()));
+
+    // SIGTERM/SIGINT (e.g. Electron quitting) cancel everything in flight first
+    tokio::spawn(drain_on_signal(cancel_map.clone()));
 
     for line in stdin.lock().lines() {
         let line = line?;
@@ -41,50 +60,149 @@ async fn main() -> anyhow::Result<()> {
         match req {
             Ok(r) => {
                 let cancel_map = cancel_map.clone();
+                let job_slots = job_slots.clone();
+                // Any request may name the ffmpeg to use (ffmpegPath on the params types); it
+                // applies to everything the request runs, so it's read before dispatch
+                let ffmpeg_path = r
+                    .params
+                    .get("ffmpegPath")
+                    .and_then(|p| p.as_str())
+                    .map(str::to_string);
                 // Spawn each request as a concurrent task
-                tasks.spawn(async move { handle_request(r, cancel_map).await });
+                tasks.spawn(core::whisper::with_ffmpeg_path(ffmpeg_path, async move {
+                    handle_request(r, cancel_map, job_slots).await
+                }));
             }
             Err(e) => {
                 let err =
                     serde_json::json!({ "id": new_id(), "error": format!("Bad request: {}", e) });
-                println!("{}", err);
-                let _ = io::stdout().flush();
+                core::rpc::write_line(err.to_string()).await;
             }
         }
     }
 
     // Wait for all tasks to complete (though this won't be reached in normal operation)
     while tasks.join_next().await.is_some() {}
+    core::rpc::flush_output(OUTPUT_FLUSH_TIMEOUT).await;
     Ok(())
 }
 
-async fn handle_request(r: RpcRequest, cancel_map: CancelMap) {
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => tokio::select! {
+                _ = tokio::signal::ctrl_c() => "SIGINT",
+                _ = term.recv() => "SIGTERM",
+            },
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+                "SIGINT"
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl-C"
+    }
+}
+
+async fn drain_on_signal(cancel_map: CancelMap) {
+    let signal = shutdown_signal().await;
+
+    // Same broadcast the `cancel` method uses; each request's guard leaves the map once it has unwound
+    let senders: Vec<_> = cancel_map.lock().unwrap().values().cloned().collect();
+    for tx in &senders {
+        let _ = tx.send(());
+    }
+
+    let deadline = tokio::time::Instant::now() + SHUTDOWN_GRACE;
+    let mut remaining = senders.len();
+    while remaining > 0 && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        remaining = cancel_map.lock().unwrap().len();
+    }
+
+    tracing::info!(
+        signal,
+        cancelled = senders.len(),
+        remaining,
+        "shutting down"
+    );
+    eprintln!(
+        "Received {}: cancelled {} in-flight task(s), {} drained, {} still running at exit",
+        signal,
+        senders.len(),
+        senders.len().saturating_sub(remaining),
+        remaining
+    );
+    core::rpc::flush_output(OUTPUT_FLUSH_TIMEOUT).await;
+    std::process::exit(if remaining == 0 { 0 } else { 1 });
+}
+
+async fn handle_request(r: RpcRequest, cancel_map: CancelMap, job_slots: JobSlots) {
     let id = r.id.clone();
 
-    // Emit progress/log events — no captured stdout handle.
-    let mut emit = |ev: RpcEvent| {
-        println!("{}", serde_json::to_string(&ev).unwrap());
-        let _ = io::stdout().flush();
+    tracing::info!(id = %id, method = %r.method, "request started");
+    tracing::debug!(id = %id, params = %core::logging::redact_params(&r.params), "request params");
+    // Logs the request's duration however it ends (returned, failed or cancelled)
+    struct RequestTimer {
+        id: String,
+        method: String,
+        started: std::time::Instant,
+    }
+    impl Drop for RequestTimer {
+        fn drop(&mut self) {
+            tracing::info!(
+                id = %self.id,
+                method = %self.method,
+                elapsed_ms = self.started.elapsed().as_millis() as u64,
+                "request finished"
+            );
+        }
+    }
+    let _timer = RequestTimer {
+        id: id.clone(),
+        method: r.method.clone(),
+        started: std::time::Instant::now(),
     };
 
-    let write_ok = |value: serde_json::Value| {
-        let resp = RpcResponse {
-            id: id.clone(),
-            result: value,
-        };
-        println!("{}", serde_json::to_string(&resp).unwrap());
-        let _ = io::stdout().flush();
+    // Emit progress/log events through the bounded stdout queue, progress at most every 100 ms
+    let mut throttle = core::rpc::ProgressThrottle::new(core::rpc::PROGRESS_INTERVAL);
+    let mut emit = |ev: RpcEvent| {
+        for (ev, droppable) in throttle.push(ev, std::time::Instant::now()) {
+            core::rpc::write_event(&ev, droppable);
+        }
     };
 
-    let write_err = |e: String| {
+    // Oversized results (e.g. a 4K frame) are spilled to a file the client reads
+    let write_ok = |value: serde_json::Value| core::rpc::write_result(&id, value);
+
+    let write_err_with_code = |e: String, code: Option<&str>| {
+        let e = core::logging::redact_secrets(&e);
+        tracing::warn!(id = %id, code, "request failed: {}", e);
         let err = RpcError {
             id: id.clone(),
             error: e,
+            code: code.map(str::to_string),
         };
-        println!("{}", serde_json::to_string(&err).unwrap());
-        let _ = io::stdout().flush();
+        core::rpc::write_line(serde_json::to_string(&err).unwrap())
     };
 
+    let write_err = |e: String| write_err_with_code(e, None);
+
+    // Failures from the pipeline keep any machine-readable code attached to them
+    let write_failure = |e: anyhow::Error| write_err_with_code(e.to_string(), error_code(&e));
+
+    if let Some(path) = core::whisper::ffmpeg_override() {
+        if let Err(e) = core::whisper::validate_ffmpeg_path(&path) {
+            write_failure(e).await;
+            return;
+        }
+    }
+
     // Setup cancellation token for this request
     let (tx, mut rx) = tokio::sync::broadcast::channel(1);
     {
@@ -108,20 +226,91 @@ async fn handle_request(r: RpcRequest, cancel_map: CancelMap) {
         map: cancel_map.clone(),
     };
 
+    // Heartbeats until this request returns or is cancelled (the guard aborts the ticker)
+    struct HeartbeatGuard(tokio::task::JoinHandle<()>);
+    impl Drop for HeartbeatGuard {
+        fn drop(&mut self) {
+            self.0.abort();
+        }
+    }
+    let _heartbeat = HeartbeatGuard(tokio::spawn({
+        let id = id.clone();
+        async move {
+            let start = tokio::time::Instant::now() + core::rpc::HEARTBEAT_INTERVAL;
+            let mut ticker = tokio::time::interval_at(start, core::rpc::HEARTBEAT_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let beat = RpcEvent::Heartbeat { id: id.clone() };
+                core::rpc::write_line(serde_json::to_string(&beat).unwrap()).await;
+            }
+        }
+    }));
+
+    // Heavy requests hold a slot for their whole run; a queued one can still be cancelled
+    let _slot = if core::rpc::is_heavy_method(&r.method) {
+        match job_slots.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                emit(RpcEvent::Progress {
+                    id: id.clone(),
+                    status: "Queued".to_string(),
+                    progress: 0.0,
+                });
+                tokio::select! {
+                    permit = job_slots.acquire_owned() => Some(permit.expect("job semaphore is never closed")),
+                    _ = rx.recv() => {
+                        write_err("Cancelled".to_string()).await;
+                        return;
+                    }
+                }
+            }
+        }
+    } else {
+        None
+    };
+
+    // Cancellation or the request's time limit, whichever comes first. Either way the
+    // pipeline future is dropped, which kills its ffmpeg/whisper children.
+    let timeout = core::rpc::request_timeout(
+        &r.method,
+        r.params.get("timeoutSec").and_then(|v| v.as_u64()),
+    );
+    let work_dir = r
+        .params
+        .get("workDir")
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+    let interrupted = core::rpc::interruption(&mut rx, timeout);
+    tokio::pin!(interrupted);
+    let write_interrupted = |stop: core::rpc::Interruption| match stop {
+        core::rpc::Interruption::Cancelled => write_err("Cancelled".to_string()),
+        core::rpc::Interruption::TimedOut(limit) => {
+            captions::remove_job_files(work_dir.as_deref(), &id);
+            write_err_with_code(
+                format!(
+                    "{} timed out after {} s; raise timeoutSec to allow longer runs",
+                    r.method,
+                    limit.as_secs()
+                ),
+                Some("TIMEOUT"),
+            )
+        }
+    };
+
     match r.method.as_str() {
-        "ping" => write_ok(serde_json::json!({"ok": true})),
+        "ping" => write_ok(serde_json::json!({"ok": true})).await,
         "cancel" => {
             // New cancel method
             if let Some(target_id) = r.params.as_str() {
-                let map = cancel_map.lock().unwrap();
-                if let Some(tx) = map.get(target_id) {
+                let target = cancel_map.lock().unwrap().get(target_id).cloned();
+                if let Some(tx) = target {
                     let _ = tx.send(()); // Send cancellation signal
-                    write_ok(serde_json::json!({ "cancelled": true }));
+                    write_ok(serde_json::json!({ "cancelled": true })).await;
                 } else {
-                    write_err(format!("Task with id {} not found", target_id));
+                    write_err(format!("Task with id {} not found", target_id)).await;
                 }
             } else {
-                write_err("Invalid params for cancel, expected string id".to_string());
+                write_err("Invalid params for cancel, expected string id".to_string()).await;
             }
         }
         "generateCaptions" => {
@@ -130,16 +319,14 @@ async fn handle_request(r: RpcRequest, cancel_map: CancelMap) {
                     tokio::select! {
                         res = captions::generate_captions(&id, p, &mut emit) => {
                             match res {
-                                Ok(v) => write_ok(serde_json::to_value(v).unwrap()),
-                                Err(e) => write_err(e.to_string()),
+                                Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                                Err(e) => write_failure(e).await,
                             }
                         }
-                        _ = rx.recv() => {
-                            write_err("Cancelled".to_string());
-                        }
+                        stop = &mut interrupted => write_interrupted(stop).await,
                     }
                 }
-                Err(e) => write_err(format!("Invalid params for generateCaptions: {}", e)),
+                Err(e) => write_err(format!("Invalid params for generateCaptions: {}", e)).await,
             }
         }
         "downloadModel" => {
@@ -148,39 +335,93 @@ async fn handle_request(r: RpcRequest, cancel_map: CancelMap) {
                     tokio::select! {
                        res = core::whisper::download_model_rpc(&id, p, &mut emit) => {
                             match res {
-                               Ok(v) => write_ok(serde_json::to_value(v).unwrap()),
-                               Err(e) => write_err(e.to_string()),
+                               Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                               Err(e) => write_failure(e).await,
                            }
                        }
-                       _ = rx.recv() => {
-                            write_err("Cancelled".to_string());
-                       }
+                       stop = &mut interrupted => write_interrupted(stop).await,
                     }
                 }
-                Err(e) => write_err(format!("Invalid params for downloadModel: {}", e)),
+                Err(e) => write_err(format!("Invalid params for downloadModel: {}", e)).await,
             }
         }
+        "cacheStats" => match core::whisper::cache_stats().await {
+            Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+            Err(e) => write_failure(e).await,
+        },
+        "listModels" => match core::whisper::list_models() {
+            Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+            Err(e) => write_failure(e).await,
+        },
+        "deleteModel" => match serde_json::from_value::<core::types::DeleteModelParams>(r.params) {
+            Ok(p) => match core::whisper::delete_model(p) {
+                Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                Err(e) => write_failure(e).await,
+            },
+            Err(e) => write_err(format!("Invalid params for deleteModel: {}", e)).await,
+        },
         "checkModelExists" => match serde_json::from_value::<String>(r.params) {
-            Ok(model_name) => match core::whisper::check_model_exists(&model_name) {
-                Ok(exists) => write_ok(serde_json::to_value(exists).unwrap()),
-                Err(e) => write_err(e.to_string()),
+            Ok(model_name) => match core::whisper::check_model_exists(&model_name).await {
+                Ok(exists) => write_ok(serde_json::to_value(exists).unwrap()).await,
+                Err(e) => write_failure(e).await,
             },
-            Err(e) => write_err(format!("Invalid params for checkModelExists: {}", e)),
+            Err(e) => write_err(format!("Invalid params for checkModelExists: {}", e)).await,
         },
         "extractFirstFrame" => {
             match serde_json::from_value::<core::types::ExtractFirstFrameParams>(r.params) {
-                Ok(p) => match core::video::extract_first_frame(&p.video_path) {
-                    Ok(base64_img) => write_ok(
-                        serde_json::to_value(core::types::ExtractFirstFrameResult {
-                            image_data: base64_img,
+                Ok(p) => {
+                    let result = if p.to_file {
+                        core::video::extract_first_frame_to_file(&p.video_path, p.out.as_deref())
+                            .map(|path| core::types::ExtractFirstFrameResult {
+                                image_data: String::new(),
+                                path: Some(path),
+                            })
+                    } else {
+                        core::video::extract_first_frame(&p.video_path).map(|base64_img| {
+                            core::types::ExtractFirstFrameResult {
+                                image_data: base64_img,
+                                path: None,
+                            }
                         })
-                        .unwrap(),
-                    ),
-                    Err(e) => write_err(e.to_string()),
-                },
-                Err(e) => write_err(format!("Invalid params for extractFirstFrame: {}", e)),
+                    };
+                    match result {
+                        Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                        Err(e) => write_failure(e).await,
+                    }
+                }
+                Err(e) => write_err(format!("Invalid params for extractFirstFrame: {}", e)).await,
+            }
+        }
+        "extractThumbnails" => {
+            match serde_json::from_value::<core::types::ExtractThumbnailsParams>(r.params) {
+                Ok(p) => {
+                    tokio::select! {
+                        res = core::video::extract_thumbnails(&id, p, &mut emit) => {
+                            match res {
+                                Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                                Err(e) => write_failure(e).await,
+                            }
+                        }
+                        stop = &mut interrupted => write_interrupted(stop).await,
+                    }
+                }
+                Err(e) => write_err(format!("Invalid params for extractThumbnails: {}", e)).await,
             }
         }
+        "probe" => match serde_json::from_value::<core::types::ProbeParams>(r.params) {
+            Ok(p) => {
+                tokio::select! {
+                    res = core::video::probe(&id, &p.video_path, &mut emit) => {
+                        match res {
+                            Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                            Err(e) => write_failure(e).await,
+                        }
+                    }
+                    stop = &mut interrupted => write_interrupted(stop).await,
+                }
+            }
+            Err(e) => write_err(format!("Invalid params for probe: {}", e)).await,
+        },
         "transcribe" => {
             match serde_json::from_value::<core::types::GenerateCaptionsParams>(r.params) {
                 Ok(p) => {
@@ -190,9 +431,22 @@ async fn handle_request(r: RpcRequest, cancel_map: CancelMap) {
                             &p.input_video,
                             p.split_by_words,
                             p.model,
+                            p.model_fallbacks,
                             p.language,
                             p.api_key,
                             p.prompt,
+                            p.vocabulary,
+                            p.merge_gap_ms,
+                            p.drop_no_speech_above,
+                            p.resegment,
+                            p.max_cps,
+                            p.normalize_audio,
+                            p.denoise_audio,
+                            p.inline_audio,
+                            p.encode_options.trim_start_ms,
+                            p.encode_options.trim_end_ms,
+                            p.force,
+                            p.work_dir.as_deref(),
                             &mut emit
                         ) => {
                             match res {
@@ -201,17 +455,15 @@ async fn handle_request(r: RpcRequest, cancel_map: CancelMap) {
                                         "probeResult": probe,
                                         "audioFile": audio,
                                         "transcription": transcription
-                                    }));
+                                    })).await;
                                 },
-                                Err(e) => write_err(e.to_string()),
+                                Err(e) => write_failure(e).await,
                             }
                         }
-                        _ = rx.recv() => {
-                            write_err("Cancelled".to_string());
-                        }
+                        stop = &mut interrupted => write_interrupted(stop).await,
                     }
                 }
-                Err(e) => write_err(format!("Invalid params for transcribe: {}", e)),
+                Err(e) => write_err(format!("Invalid params for transcribe: {}", e)).await,
             }
         }
         "burn" => match serde_json::from_value::<core::types::BurnCaptionsParams>(r.params) {
@@ -219,53 +471,204 @@ async fn handle_request(r: RpcRequest, cancel_map: CancelMap) {
                 tokio::select! {
                     res = captions::burn_captions_with_segments(&id, p, &mut emit) => {
                         match res {
-                            Ok(v) => write_ok(serde_json::to_value(v).unwrap()),
-                            Err(e) => write_err(e.to_string()),
+                            Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                            Err(e) => write_failure(e).await,
                         }
                     }
-                    _ = rx.recv() => {
-                        write_err("Cancelled".to_string());
-                    }
+                    stop = &mut interrupted => write_interrupted(stop).await,
                 }
             }
-            Err(e) => write_err(format!("Invalid params for burn: {}", e)),
+            Err(e) => write_err(format!("Invalid params for burn: {}", e)).await,
         },
+        "burnFromSubtitleFile" => {
+            match serde_json::from_value::<core::types::BurnFromSubtitleFileParams>(r.params) {
+                Ok(p) => {
+                    tokio::select! {
+                        res = captions::burn_from_subtitle_file(&id, p, &mut emit) => {
+                            match res {
+                                Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                                Err(e) => write_failure(e).await,
+                            }
+                        }
+                        stop = &mut interrupted => write_interrupted(stop).await,
+                    }
+                }
+                Err(e) => {
+                    write_err(format!("Invalid params for burnFromSubtitleFile: {}", e)).await
+                }
+            }
+        }
         "previewLayout" => {
             match serde_json::from_value::<core::types::PreviewLayoutParams>(r.params) {
                 Ok(p) => match captions::generate_preview_layout(p) {
-                    Ok(v) => write_ok(serde_json::to_value(v).unwrap()),
-                    Err(e) => write_err(e.to_string()),
+                    Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                    Err(e) => write_failure(e).await,
                 },
-                Err(e) => write_err(format!("Invalid params for previewLayout: {}", e)),
+                Err(e) => write_err(format!("Invalid params for previewLayout: {}", e)).await,
             }
         }
+        "estimate" => {
+            match serde_json::from_value::<core::types::GenerateCaptionsParams>(r.params) {
+                Ok(p) => {
+                    tokio::select! {
+                        res = captions::estimate(&id, p, &mut emit) => {
+                            match res {
+                                Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                                Err(e) => write_failure(e).await,
+                            }
+                        }
+                        stop = &mut interrupted => write_interrupted(stop).await,
+                    }
+                }
+                Err(e) => write_err(format!("Invalid params for estimate: {}", e)).await,
+            }
+        }
+        "listFonts" => match captions::list_fonts() {
+            Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+            Err(e) => write_failure(e).await,
+        },
         "saveCaptions" => {
             match serde_json::from_value::<core::types::SaveCaptionsParams>(r.params) {
-                Ok(p) => match captions::save_captions(p) {
-                    Ok(_) => write_ok(serde_json::json!({ "ok": true })),
-                    Err(e) => write_err(e.to_string()),
+                Ok(p) => match captions::save_captions(p).await {
+                    Ok(_) => write_ok(serde_json::json!({ "ok": true })).await,
+                    Err(e) => write_failure(e).await,
                 },
-                Err(e) => write_err(format!("Invalid params for saveCaptions: {}", e)),
+                Err(e) => write_err(format!("Invalid params for saveCaptions: {}", e)).await,
+            }
+        }
+        "exportSubtitles" => {
+            match serde_json::from_value::<core::types::ExportSubtitlesParams>(r.params) {
+                Ok(p) => match core::subtitles::export_subtitles(p) {
+                    Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                    Err(e) => write_failure(e).await,
+                },
+                Err(e) => write_err(format!("Invalid params for exportSubtitles: {}", e)).await,
+            }
+        }
+        "importSubtitles" => {
+            match serde_json::from_value::<core::types::ImportSubtitlesParams>(r.params) {
+                Ok(p) => match core::subtitles::load_subtitle_file(std::path::Path::new(&p.path)) {
+                    Ok(segments) => {
+                        write_ok(
+                            serde_json::to_value(core::types::ImportSubtitlesResult { segments })
+                                .unwrap(),
+                        )
+                        .await
+                    }
+                    Err(e) => write_failure(e).await,
+                },
+                Err(e) => write_err(format!("Invalid params for importSubtitles: {}", e)).await,
             }
         }
         "loadCaptions" => {
             match serde_json::from_value::<core::types::LoadCaptionsParams>(r.params) {
                 Ok(p) => match captions::load_captions(p) {
-                    Ok(v) => write_ok(serde_json::to_value(v).unwrap()),
-                    Err(e) => write_err(e.to_string()),
+                    Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                    Err(e) => write_failure(e).await,
+                },
+                Err(e) => write_err(format!("Invalid params for loadCaptions: {}", e)).await,
+            }
+        }
+        "saveStylePreset" => {
+            match serde_json::from_value::<core::types::SaveStylePresetParams>(r.params) {
+                Ok(p) => match captions::save_style_preset(p) {
+                    Ok(_) => write_ok(serde_json::json!({ "ok": true })).await,
+                    Err(e) => write_failure(e).await,
+                },
+                Err(e) => write_err(format!("Invalid params for saveStylePreset: {}", e)).await,
+            }
+        }
+        "loadStylePreset" => {
+            match serde_json::from_value::<core::types::LoadStylePresetParams>(r.params) {
+                Ok(p) => match captions::load_style_preset(p) {
+                    Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                    Err(e) => write_failure(e).await,
                 },
-                Err(e) => write_err(format!("Invalid params for loadCaptions: {}", e)),
+                Err(e) => write_err(format!("Invalid params for loadStylePreset: {}", e)).await,
+            }
+        }
+        "listStylePresets" => match captions::list_style_presets() {
+            Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+            Err(e) => write_failure(e).await,
+        },
+        "listExportFormats" => {
+            // Params are optional, so a missing/null params object means "no source"
+            match serde_json::from_value::<Option<core::types::ListExportFormatsParams>>(r.params) {
+                Ok(p) => match core::video::list_export_formats(&p.unwrap_or_default()) {
+                    Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                    Err(e) => write_failure(e).await,
+                },
+                Err(e) => write_err(format!("Invalid params for listExportFormats: {}", e)).await,
+            }
+        }
+        "checkFfmpegCapabilities" => {
+            // Params are optional; without them only the pipeline's own encoders are checked
+            match serde_json::from_value::<Option<core::types::CheckFfmpegCapabilitiesParams>>(
+                r.params,
+            ) {
+                Ok(p) => {
+                    match core::video::check_ffmpeg_capabilities(&p.unwrap_or_default()).await {
+                        Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                        Err(e) => write_failure(e).await,
+                    }
+                }
+                Err(e) => {
+                    write_err(format!("Invalid params for checkFfmpegCapabilities: {}", e)).await
+                }
+            }
+        }
+        "listPlatformPresets" => {
+            write_ok(
+                serde_json::to_value(core::types::ListPlatformPresetsResult {
+                    presets: core::video::platform_presets(),
+                })
+                .unwrap(),
+            )
+            .await
+        }
+        "generatePreviewClip" => {
+            match serde_json::from_value::<core::types::PreviewClipParams>(r.params) {
+                Ok(p) => {
+                    tokio::select! {
+                        res = captions::generate_preview_clip(&id, p, &mut emit) => {
+                            match res {
+                                Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                                Err(e) => write_failure(e).await,
+                            }
+                        }
+                        stop = &mut interrupted => write_interrupted(stop).await,
+                    }
+                }
+                Err(e) => write_err(format!("Invalid params for generatePreviewClip: {}", e)).await,
+            }
+        }
+        "concatVideos" => {
+            match serde_json::from_value::<core::types::ConcatVideosParams>(r.params) {
+                Ok(p) => {
+                    tokio::select! {
+                        res = core::video::concat_videos(&id, p, &mut emit) => {
+                            match res {
+                                Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                                Err(e) => write_failure(e).await,
+                            }
+                        }
+                        stop = &mut interrupted => write_interrupted(stop).await,
+                    }
+                }
+                Err(e) => write_err(format!("Invalid params for concatVideos: {}", e)).await,
             }
         }
         "generatePreviewFrame" => {
             match serde_json::from_value::<core::types::PreviewFrameParams>(r.params) {
-                Ok(p) => match captions::generate_preview_frame(p).await {
-                    Ok(v) => write_ok(serde_json::to_value(v).unwrap()),
-                    Err(e) => write_err(e.to_string()),
+                Ok(p) => match captions::generate_preview_frame(&id, p, &mut emit).await {
+                    Ok(v) => write_ok(serde_json::to_value(v).unwrap()).await,
+                    Err(e) => write_failure(e).await,
                 },
-                Err(e) => write_err(format!("Invalid params for generatePreviewFrame: {}", e)),
+                Err(e) => {
+                    write_err(format!("Invalid params for generatePreviewFrame: {}", e)).await
+                }
             }
         }
-        _ => write_err(format!("Unknown method: {}", r.method)),
+        _ => write_err(format!("Unknown method: {}", r.method)).await,
     }
 }
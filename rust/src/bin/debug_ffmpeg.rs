@@ -1,7 +1,7 @@
-
 use std::process::Command;
 
-fn main() {
+#[tokio::main]
+async fn main() {
     println!("Debugging FFmpeg detection...");
 
     // 1. Check env var
@@ -12,33 +12,29 @@ fn main() {
         println!("FFMPEG_PATH env var is NOT set");
     }
 
-    // 2. Check bundled
-    if let Ok(exe_path) = std::env::current_exe() {
-        println!("Current executable path: {:?}", exe_path);
-        if let Some(exe_dir) = exe_path.parent() {
-            println!("Executable directory: {:?}", exe_dir);
-            // ... (check bundled logic from video.rs) ...
+    // 2. What the core would actually use (bundled > project > system)
+    match core::whisper::find_ffmpeg_binary().await {
+        Ok(path) => {
+            println!("Detected ffmpeg: {}", path);
+            check_path(&path);
+        }
+        Err(e) => {
+            println!("No ffmpeg detected: {}", e);
+            return;
         }
     }
 
-    // 3. Check common paths
-    let paths = vec![
-        "/opt/homebrew/bin/ffmpeg",
-        "/usr/local/bin/ffmpeg",
-        "/usr/bin/ffmpeg",
-        "ffmpeg"
-    ];
-
-    for path in paths {
-        println!("Checking path: {}", path);
-        check_path(path);
+    // 3. Features the pipeline relies on (same report as the checkFfmpegCapabilities RPC)
+    match core::video::check_ffmpeg_capabilities(&Default::default()).await {
+        Ok(caps) => println!("{}", serde_json::to_string_pretty(&caps).unwrap()),
+        Err(e) => println!("Capability check failed: {}", e),
     }
 }
 
 fn check_path(path: &str) {
     let exists = std::path::Path::new(path).exists();
     println!("  -> Exists (std::fs): {}", exists);
-    
+
     let which_res = which::which(path);
     println!("  -> Which result: {:?}", which_res);
 
@@ -47,7 +43,7 @@ fn check_path(path: &str) {
         match Command::new(path).arg("-version").output() {
             Ok(output) => {
                 println!("  -> Execution successful. Status: {}", output.status);
-            },
+            }
             Err(e) => {
                 println!("  -> Execution failed: {}", e);
             }
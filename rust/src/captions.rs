@@ -1,17 +1,22 @@
-use crate::rpc::RpcEvent;
+use crate::rpc::{CodedError, RpcEvent};
 use crate::types::{
-    BurnCaptionsParams, CaptionSegment, CaptionedVideoResult, ExtractAudioParams,
-    GenerateCaptionsParams, GenerateCaptionsResult, LoadCaptionsParams, LoadCaptionsResult,
-    SaveCaptionsParams, TranscribeSegmentsParams, TranscribeSegmentsResult, WordSpan,
+    BurnCaptionsParams, CaptionSegment, CaptionStyleOptions, CaptionedVideoResult, EncodeOptions,
+    EstimateResult, ExtractAudioParams, GenerateCaptionsParams, GenerateCaptionsResult,
+    ListStylePresetsResult, LoadCaptionsParams, LoadCaptionsResult, LoadStylePresetParams,
+    LoadStylePresetResult, SaveCaptionsParams, SaveStylePresetParams, SegmentPosition, ShadowSpec,
+    TranscribeSegmentsParams, TranscribeSegmentsResult, WordSpan,
 };
 use crate::video::probe;
 use crate::{audio, whisper};
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
+use std::path::{Path, PathBuf};
 use tokio::io::AsyncBufReadExt;
 use tokio::process::Command as TokioCommand;
 use tokio::sync::mpsc;
+use unicode_bidi::{bidi_class, BidiClass, Level, ParagraphBidiInfo};
+use unicode_segmentation::UnicodeSegmentation;
 
 #[derive(Debug)]
 enum InternalUpdate {
@@ -19,47 +24,458 @@ enum InternalUpdate {
     Event(RpcEvent),
 }
 
+/// Root for intermediate files: the request's `work_dir`, else `CAPSLAP_WORK_DIR`, else the
+/// OS temp dir. Final outputs still go next to the source.
+pub fn work_root(work_dir: Option<&str>) -> PathBuf {
+    work_dir
+        .filter(|d| !d.trim().is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("CAPSLAP_WORK_DIR")
+                .filter(|d| !d.is_empty())
+                .map(PathBuf::from)
+        })
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// Remove a request's scratch files, e.g. after it timed out mid-run: the captions job
+/// directory and any preview frame/clip directories it created
+pub fn remove_job_files(work_dir: Option<&str>, id: &str) {
+    let root = work_root(work_dir);
+    let _ = fs::remove_dir_all(root.join(format!("capslap_captions_{}", id)));
+    let previews = [
+        format!("capslap_preview_{}_", id),
+        format!("capslap_clip_{}_", id),
+    ];
+    for entry in fs::read_dir(&root).into_iter().flatten().flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if previews
+            .iter()
+            .any(|prefix| name.starts_with(prefix.as_str()))
+        {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+// Per-job scratch directory under the work root
+fn job_dir(work_dir: Option<&str>, name: String) -> Result<PathBuf> {
+    let dir = work_root(work_dir).join(name);
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create work directory {}", dir.display()))?;
+    Ok(dir)
+}
+
+// Where each generateCaptions stage ends on the overall bar: probe, audio extraction,
+// then transcription, then ASS layout + encode. Transcribe and encode dominate wall time.
+const PROBE_STAGE_END: f32 = 0.02;
+const EXTRACT_STAGE_END: f32 = 0.1;
+const TRANSCRIBE_STAGE_END: f32 = 0.6;
+
+// The probe, extraction and transcription bands of `extract_and_transcribe`. They are
+// relative to that function so the standalone transcribe RPC gets a full bar;
+// generateCaptions squeezes the whole thing into its transcribe share.
+fn transcribe_bands() -> [(f32, f32); 3] {
+    let probe_end = PROBE_STAGE_END / TRANSCRIBE_STAGE_END;
+    let extract_end = EXTRACT_STAGE_END / TRANSCRIBE_STAGE_END;
+    [
+        (0.0, probe_end),
+        (probe_end, extract_end),
+        (extract_end, 1.0),
+    ]
+}
+
+/// Wrap `emit` so a stage's own 0.0-1.0 `Progress` lands in `[start, end]` of the
+/// caller's bar. Other events pass through unchanged.
+fn stage_progress<'a>(
+    emit: &'a mut impl FnMut(RpcEvent),
+    start: f32,
+    end: f32,
+) -> impl FnMut(RpcEvent) + 'a {
+    move |event| match event {
+        RpcEvent::Progress {
+            id,
+            status,
+            progress,
+        } => emit(RpcEvent::Progress {
+            id,
+            status,
+            progress: start + (end - start) * progress.clamp(0.0, 1.0),
+        }),
+        other => emit(other),
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub async fn extract_and_transcribe(
     id: &str,
     input_video: &str,
     split_by_words: bool,
     model: Option<String>,
+    model_fallbacks: Vec<String>,
     language: Option<String>,
-    api_key: Option<String>,
+    api_key: Option<crate::types::ApiKey>,
     prompt: Option<String>,
+    vocabulary: Vec<String>,
+    merge_gap_ms: Option<u32>,
+    drop_no_speech_above: Option<f32>,
+    resegment: Option<String>,
+    max_cps: Option<f32>,
+    normalize_audio: bool,
+    denoise_audio: bool,
+    inline_audio: bool,
+    trim_start_ms: Option<u64>,
+    trim_end_ms: Option<u64>,
+    force: bool,
+    work_dir: Option<&str>,
     mut emit: impl FnMut(RpcEvent),
 ) -> Result<(crate::video::ProbeResult, String, TranscribeSegmentsResult)> {
-    let temp_dir = std::env::temp_dir().join(format!("capslap_captions_{}", id));
-    if let Err(e) = fs::create_dir_all(&temp_dir) {
-        return Err(anyhow!("Failed to create temp directory: {}", e));
-    }
+    // Before the audio is extracted, not once it reaches Whisper
+    whisper::parse_resegment(resegment.as_deref())?;
+    let temp_dir = job_dir(work_dir, format!("capslap_captions_{}", id))?;
 
-    let probe_result = probe(id, input_video, &mut emit).await?;
+    let [probe_band, extract_band, transcribe_band] = transcribe_bands();
+    // The probe reports its own 1.0, which must not run ahead of the extraction
+    let probe_result = probe(
+        id,
+        input_video,
+        stage_progress(&mut emit, probe_band.0, probe_band.1),
+    )
+    .await?;
+    if !probe_result.audio {
+        return Err(CodedError::new(
+            "NO_AUDIO",
+            "This video has no audio track, so there is nothing to transcribe. \
+             Pick a different file that contains speech.",
+        )
+        .into());
+    }
+    // Only the trimmed range is extracted, so only it is transcribed (and paid for)
+    let trimmed = trim_probe(&probe_result, trim_start_ms, trim_end_ms)?;
 
-    let audio_filename = format!("audio_{}.mp3", id);
-    let temp_audio_path = temp_dir.join(&audio_filename);
-    let audio_params = ExtractAudioParams {
-        input: input_video.to_string(),
-        codec: Some("mp3".to_string()),
-        out: Some(temp_audio_path.to_string_lossy().to_string()),
+    // Extracted audio is kept per input + preprocessing, so a retry (say, after fixing a
+    // style param) skips extraction and then hits the transcription cache as well
+    let audio_path = resumable_audio_path(
+        input_video,
+        normalize_audio,
+        denoise_audio,
+        trim_start_ms,
+        trim_end_ms,
+    )?;
+    let extract_end = extract_band.1;
+    // A concurrent request for the same audio waits here, then reuses the finished file
+    let extraction = audio_extraction_lock(&audio_path);
+    let extracting = extraction.lock().await;
+    let reusable = !force && is_reusable_audio(&audio_path);
+    let inline = inline_audio
+        && !reusable
+        && inline_audio_fits(model.as_deref(), trimmed.duration, &mut emit, id);
+    let mut inline_audio = None;
+    let audio_file = if inline {
+        // Extracted during the upload itself
+        inline_audio = Some(transcription_audio_params(
+            input_video,
+            None,
+            normalize_audio,
+            denoise_audio,
+            trim_start_ms,
+            trim_end_ms,
+        ));
+        String::new()
+    } else if reusable {
+        emit(RpcEvent::Log {
+            id: id.into(),
+            message: format!(
+                "Reusing extracted audio {} (pass force to redo)",
+                audio_path.display()
+            ),
+        });
+        audio_path.to_string_lossy().to_string()
+    } else {
+        extract_resumable_audio(
+            id,
+            input_video,
+            &audio_path,
+            normalize_audio,
+            denoise_audio,
+            trim_start_ms,
+            trim_end_ms,
+            stage_progress(&mut emit, extract_band.0, extract_band.1),
+        )
+        .await?
     };
-    let audio_result = audio::extract_audio(id, audio_params, &mut emit).await?;
+    drop(extracting);
+    emit(RpcEvent::Progress {
+        id: id.into(),
+        status: "Transcribing...".into(),
+        progress: extract_end,
+    });
 
     let transcribe_params = TranscribeSegmentsParams {
-        audio: audio_result.audio.clone(),
+        audio: if inline_audio.is_some() {
+            // Only names the upload
+            "audio.mp3".to_string()
+        } else {
+            audio_file.clone()
+        },
         model,
+        model_fallbacks,
         language,
         split_by_words,
         api_key,
         prompt,
+        vocabulary,
         video_file: Some(input_video.to_string()),
+        merge_gap_ms,
+        drop_no_speech_above,
+        resegment,
+        max_cps,
+        force,
+        ffmpeg_path: None, // the request's override already applies to this task
     };
-    let transcription =
-        whisper::transcribe_segments_with_temp(id, transcribe_params, Some(&temp_dir), &mut emit)
-            .await?;
+    let mut transcription = match inline_audio {
+        Some(audio) => {
+            whisper::transcribe_audio_stream(
+                id,
+                &audio,
+                trimmed.duration,
+                transcribe_params,
+                Some(&temp_dir),
+                stage_progress(&mut emit, transcribe_band.0, transcribe_band.1),
+            )
+            .await?
+        }
+        None => {
+            whisper::transcribe_segments_with_temp(
+                id,
+                transcribe_params,
+                Some(&temp_dir),
+                stage_progress(&mut emit, transcribe_band.0, transcribe_band.1),
+            )
+            .await?
+        }
+    };
+    // The clip's timings start at 0; put them back on the source timeline, which is
+    // what saved captions and later burns (trimmed or not) refer to
+    if let Some(start) = trim_start_ms.filter(|&ms| ms > 0) {
+        transcription.segments = offset_segments(&transcription.segments, start as i64);
+    }
+    emit(RpcEvent::Progress {
+        id: id.into(),
+        status: "Transcription complete".into(),
+        progress: 1.0,
+    });
+
+    Ok((probe_result, audio_file, transcription))
+}
+
+// How many extracted audio files to keep around for resuming
+const RESUMABLE_AUDIO_KEEP: usize = 8;
+
+// Stable audio location for an input: same file (path, size, mtime) and the same
+// preprocessing map to the same path. Lives next to the transcription cache.
+fn resumable_audio_path(
+    input_video: &str,
+    normalize: bool,
+    denoise: bool,
+    trim_start_ms: Option<u64>,
+    trim_end_ms: Option<u64>,
+) -> Result<PathBuf> {
+    let meta =
+        fs::metadata(input_video).with_context(|| format!("Failed to read {}", input_video))?;
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos());
+    let canonical = fs::canonicalize(input_video).unwrap_or_else(|_| PathBuf::from(input_video));
+    let key = serde_json::json!({
+        "input": canonical.to_string_lossy(),
+        "size": meta.len(),
+        "modified": modified.to_string(),
+        "normalize": normalize,
+        "denoise": denoise,
+        "trimStartMs": trim_start_ms,
+        "trimEndMs": trim_end_ms,
+        "format": "mp3-16k-mono-48k",
+    });
+    let hash = blake3::hash(key.to_string().as_bytes()).to_hex();
+    let dir = whisper::get_cache_dir()?.join("audio");
+    fs::create_dir_all(&dir)?;
+    Ok(dir.join(format!("{}.mp3", &hash[..16])))
+}
+
+// Per-path lock for extractions in this process, so requests started together for the
+// same source (transcribe, then generateCaptions before it finishes) extract only once
+fn audio_extraction_lock(audio_path: &Path) -> std::sync::Arc<tokio::sync::Mutex<()>> {
+    type Locks = HashMap<PathBuf, std::sync::Arc<tokio::sync::Mutex<()>>>;
+    static LOCKS: std::sync::OnceLock<std::sync::Mutex<Locks>> = std::sync::OnceLock::new();
+    let mut locks = LOCKS.get_or_init(Default::default).lock().unwrap();
+    // Drop locks nobody holds any more
+    locks.retain(|_, lock| std::sync::Arc::strong_count(lock) > 1);
+    locks.entry(audio_path.to_path_buf()).or_default().clone()
+}
+
+// Only finished extractions are renamed into place, so any non-empty file is complete
+fn is_reusable_audio(path: &Path) -> bool {
+    let reusable = fs::metadata(path).is_ok_and(|m| m.is_file() && m.len() > 0);
+    if reusable {
+        // Bump the mtime so pruning treats it as recently used
+        let _ = fs::File::options()
+            .append(true)
+            .open(path)
+            .and_then(|f| f.set_modified(std::time::SystemTime::now()));
+    }
+    reusable
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn extract_resumable_audio(
+    id: &str,
+    input_video: &str,
+    audio_path: &Path,
+    normalize: bool,
+    denoise: bool,
+    trim_start_ms: Option<u64>,
+    trim_end_ms: Option<u64>,
+    emit: impl FnMut(RpcEvent),
+) -> Result<String> {
+    let partial_path = audio_path.with_extension(format!("{}.partial.mp3", id));
+    let audio_params = transcription_audio_params(
+        input_video,
+        Some(partial_path.to_string_lossy().to_string()),
+        normalize,
+        denoise,
+        trim_start_ms,
+        trim_end_ms,
+    );
+    if let Err(e) = audio::extract_audio(id, audio_params, emit).await {
+        let _ = fs::remove_file(&partial_path);
+        return Err(e);
+    }
+    fs::rename(&partial_path, audio_path)?;
+    if let Some(dir) = audio_path.parent() {
+        prune_resumable_audio(dir, RESUMABLE_AUDIO_KEEP);
+    }
+    Ok(audio_path.to_string_lossy().to_string())
+}
+
+// Audio settings for transcription, written to `out` or streamed when it's None
+fn transcription_audio_params(
+    input_video: &str,
+    out: Option<String>,
+    normalize: bool,
+    denoise: bool,
+    trim_start_ms: Option<u64>,
+    trim_end_ms: Option<u64>,
+) -> ExtractAudioParams {
+    ExtractAudioParams {
+        input: input_video.to_string(),
+        codec: Some("mp3".to_string()),
+        out,
+        // Whisper resamples to 16 kHz mono anyway; sending that keeps uploads small
+        sample_rate: Some(16_000),
+        channels: Some(1),
+        bitrate: Some(TRANSCRIPTION_AUDIO_BITRATE.to_string()),
+        // The preprocessed audio is what gets hashed, so the transcription cache stays deterministic
+        normalize,
+        denoise,
+        trim_start_ms,
+        trim_end_ms,
+        dry_run: false,
+        ffmpeg_path: None, // the request's override already applies to this task
+    }
+}
+
+// Bitrate of transcription audio; also sizes inline uploads
+const TRANSCRIPTION_AUDIO_BITRATE: &str = "48k";
+const TRANSCRIPTION_AUDIO_BYTES_PER_SEC: f64 = 48_000.0 / 8.0;
+
+// Largest estimated upload streamed straight out of ffmpeg (about 11 minutes); longer
+// audio goes through a file, which later runs can reuse and whose result gets cached
+const INLINE_AUDIO_MAX_BYTES: f64 = 4.0 * 1024.0 * 1024.0;
+
+// Whether inline audio applies: only the OpenAI API takes bytes (local engines read a
+// file), and the clip's size has to be known and small
+fn inline_audio_fits(
+    model: Option<&str>,
+    duration_sec: Option<f64>,
+    emit: &mut impl FnMut(RpcEvent),
+    id: &str,
+) -> bool {
+    let reason = if !model.is_some_and(whisper::is_api_model) {
+        "local transcription reads a file"
+    } else {
+        match duration_sec.map(|d| d * TRANSCRIPTION_AUDIO_BYTES_PER_SEC) {
+            None => "the audio length is unknown",
+            Some(bytes) if bytes > INLINE_AUDIO_MAX_BYTES => "the clip is too long",
+            Some(_) => return true,
+        }
+    };
+    emit(RpcEvent::Log {
+        id: id.into(),
+        message: format!(
+            "Writing audio to a file instead of streaming it: {}",
+            reason
+        ),
+    });
+    false
+}
 
-    Ok((probe_result, audio_result.audio, transcription))
+// Drop all but the `keep` most recently used audio files (and stale partials)
+fn prune_resumable_audio(dir: &Path, keep: usize) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let modified = e.metadata().ok()?.modified().ok()?;
+            Some((modified, e.path()))
+        })
+        .collect();
+    files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    let mut kept = 0;
+    for (_, path) in files {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.ends_with(".partial.mp3") {
+            continue; // may belong to a running extraction
+        }
+        if kept < keep {
+            kept += 1;
+        } else {
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
+/// Burn the cues of an SRT/WebVTT file with our styling, without transcribing
+pub async fn burn_from_subtitle_file(
+    id: &str,
+    params: crate::types::BurnFromSubtitleFileParams,
+    mut emit: impl FnMut(RpcEvent),
+) -> Result<Vec<CaptionedVideoResult>> {
+    let mut burn = params.burn;
+    burn.segments = crate::subtitles::load_subtitle_file(Path::new(&params.subtitle_path))?;
+    if burn.segments.is_empty() {
+        return Err(CodedError::new(
+            "INVALID_SEGMENT",
+            format!("{} has no subtitle cues", params.subtitle_path),
+        )
+        .into());
+    }
+    // Hand-made subtitle files often overlap by a frame or two; tidy rather than reject
+    burn.fix_segments = true;
+    emit(RpcEvent::Log {
+        id: id.into(),
+        message: format!(
+            "Loaded {} cues from {}",
+            burn.segments.len(),
+            params.subtitle_path
+        ),
+    });
+    burn_captions_with_segments(id, burn, &mut emit).await
 }
 
 pub async fn burn_captions_with_segments(
@@ -67,18 +483,58 @@ pub async fn burn_captions_with_segments(
     params: BurnCaptionsParams,
     mut emit: impl FnMut(RpcEvent),
 ) -> Result<Vec<CaptionedVideoResult>> {
-    let temp_dir = std::env::temp_dir().join(format!("capslap_captions_{}", id));
-    if let Err(e) = fs::create_dir_all(&temp_dir) {
-        return Err(anyhow!("Failed to create temp directory: {}", e));
-    }
+    let temp_dir = job_dir(
+        params.work_dir.as_deref(),
+        format!("capslap_captions_{}", id),
+    )?;
+
+    let segments = check_segments(id, &params.segments, params.fix_segments, &mut emit)?;
+    let segments = retime_segments(
+        id,
+        &segments,
+        params.timing_offset_ms,
+        params.time_scale,
+        &mut emit,
+    )?;
 
     // We need to re-probe to get video dimensions
     let probe_result = probe(id, &params.input_video, &mut emit).await?;
 
+    let overlay = match params.mode.as_deref().unwrap_or("burn") {
+        "burn" => None,
+        "overlay" => Some(crate::video::AlphaCodec::parse(
+            params.overlay_codec.as_deref(),
+        )?),
+        "mux" => {
+            if params.encode_options.is_trimmed() {
+                return Err(anyhow!(
+                    "Trimming needs a re-encode, so it only works with mode \"burn\""
+                ));
+            }
+            let muxed = mux_captions(
+                id,
+                &params.input_video,
+                &segments,
+                &probe_result,
+                &params.encode_options,
+                &temp_dir,
+                &mut emit,
+            )
+            .await?;
+            return Ok(vec![muxed]);
+        }
+        other => {
+            return Err(anyhow!(
+                "Unknown caption mode '{}': use burn, mux or overlay",
+                other
+            ))
+        }
+    };
+
     optimized_multi_format_encode(
         id,
         &params.input_video,
-        &params.segments,
+        &segments,
         &params.export_formats,
         &probe_result,
         &temp_dir,
@@ -93,6 +549,9 @@ pub async fn burn_captions_with_segments(
         params.position,
         params.output_size,
         params.crop_strategy,
+        &params.style_options,
+        &params.encode_options,
+        overlay,
         &mut emit,
     )
     .await
@@ -106,25 +565,202 @@ pub async fn generate_captions(
     generate_captions_single_pass(id, params, emit).await
 }
 
+/// Predict the cost and run time of `generateCaptions` with these params, from a probe only
+pub async fn estimate(
+    id: &str,
+    mut params: GenerateCaptionsParams,
+    mut emit: impl FnMut(RpcEvent),
+) -> Result<EstimateResult> {
+    apply_platform_preset(&mut params)?;
+    let probe_result = probe(id, &params.input_video, &mut emit).await?;
+    let trimmed = trim_probe(
+        &probe_result,
+        params.encode_options.trim_start_ms,
+        params.encode_options.trim_end_ms,
+    )?;
+    let route =
+        crate::whisper::transcription_route(params.model.as_deref(), &params.model_fallbacks).await;
+    estimate_job(
+        &trimmed,
+        &route,
+        &params.export_formats,
+        params.output_size.as_deref(),
+    )
+}
+
+// OpenAI transcription price per audio minute, in USD
+fn api_price_per_minute(model: &str) -> Option<f64> {
+    match model {
+        "whisper-1" | "gpt-4o-transcribe" => Some(0.006),
+        "gpt-4o-mini-transcribe" => Some(0.003),
+        _ => None,
+    }
+}
+
+// Seconds of work per second of audio for local whisper.cpp models on a typical CPU
+fn local_realtime_factor(model: &str) -> f64 {
+    match model.split(['.', '-']).next().unwrap_or(model) {
+        "tiny" => 0.03,
+        "base" => 0.06,
+        "small" => 0.2,
+        "medium" => 0.5,
+        _ => 1.0, // large and anything unknown
+    }
+}
+
+// API round trip: upload and queueing, then roughly 20x real time
+const API_OVERHEAD_SEC: f64 = 2.0;
+const API_REALTIME_FACTOR: f64 = 0.05;
+
+// libx264 seconds per second of 1080p video, including decode and caption rendering
+const ENCODE_1080P_FACTOR: f64 = 0.6;
+
+fn estimate_job(
+    probe_result: &crate::video::ProbeResult,
+    route: &crate::whisper::TranscriptionRoute,
+    export_formats: &[String],
+    output_size: Option<&str>,
+) -> Result<EstimateResult> {
+    let duration = probe_result.duration.ok_or_else(|| {
+        anyhow!("The video's duration is unknown, so there is nothing to estimate")
+    })?;
+
+    let (model, engine, cost, transcribe_sec, fallbacks) = match route {
+        crate::whisper::TranscriptionRoute::Local(model) => (
+            model.as_str(),
+            "local",
+            Some(0.0),
+            duration * local_realtime_factor(model),
+            &[][..],
+        ),
+        // The first model is what a run costs unless it fails over
+        crate::whisper::TranscriptionRoute::Api(chain) => (
+            chain[0].as_str(),
+            "api",
+            api_price_per_minute(&chain[0]).map(|price| price * duration / 60.0),
+            API_OVERHEAD_SEC + duration * API_REALTIME_FACTOR,
+            &chain[1..],
+        ),
+    };
+
+    let (src_w, src_h) = probe_result.display_dimensions().unwrap_or((1920, 1080));
+    let mut export_sec = 0.0;
+    for format in export_formats {
+        let ar = crate::video::parse_target_ar(format)?;
+        let (w, h) = crate::video::export_dimensions(ar, output_size, src_w, src_h);
+        let pixels = w as f64 * h as f64 / (1920.0 * 1080.0);
+        export_sec += duration * ENCODE_1080P_FACTOR * pixels;
+    }
+
+    Ok(EstimateResult {
+        duration_sec: duration,
+        model: model.to_string(),
+        engine: engine.to_string(),
+        fallback_models: fallbacks.to_vec(),
+        transcription_cost_usd: cost,
+        transcribe_sec,
+        export_sec,
+        total_sec: transcribe_sec + export_sec,
+    })
+}
+
 pub async fn generate_captions_single_pass(
     id: &str,
-    params: GenerateCaptionsParams,
+    mut params: GenerateCaptionsParams,
     mut emit: impl FnMut(RpcEvent),
 ) -> Result<GenerateCaptionsResult> {
-    let temp_dir = std::env::temp_dir().join(format!("capslap_captions_{}", id));
-    let (probe_result, audio_file, transcription) = extract_and_transcribe(
-        id,
-        &params.input_video,
-        params.split_by_words,
-        params.model,
-        params.language,
-        params.api_key,
-        params.prompt,
-        &mut emit,
-    )
-    .await?;
+    apply_platform_preset(&mut params)?;
+    // Bad colors would otherwise only surface after the (slow) transcription
+    validate_caption_style(
+        params.text_color.as_deref(),
+        params.highlight_word_color.as_deref(),
+        params.outline_color.as_deref(),
+        &params.style_options,
+    )?;
+    validate_encode_options(&params.encode_options)?;
+    whisper::parse_resegment(params.resegment.as_deref())?;
+    // A preset may have filled these in; otherwise fail before paying for a transcription
+    if params.export_formats.is_empty() {
+        return Err(anyhow!("No export formats specified"));
+    }
+    let temp_dir = job_dir(
+        params.work_dir.as_deref(),
+        format!("capslap_captions_{}", id),
+    )?;
+    // A dry run only wants the ffmpeg commands, so it never pays for a transcription
+    let mut extraction_command = None;
+    let (probe_result, audio_file, transcription) = if params.encode_options.dry_run {
+        let probe_result = probe(
+            id,
+            &params.input_video,
+            stage_progress(&mut emit, 0.0, PROBE_STAGE_END),
+        )
+        .await?;
+        let transcription = dry_run_transcription(
+            &probe_result,
+            params.encode_options.trim_start_ms,
+            params.encode_options.trim_end_ms,
+        )?;
+        let audio_path = resumable_audio_path(
+            &params.input_video,
+            params.normalize_audio,
+            params.denoise_audio,
+            params.encode_options.trim_start_ms,
+            params.encode_options.trim_end_ms,
+        )?;
+        let mut audio_params = transcription_audio_params(
+            &params.input_video,
+            Some(audio_path.to_string_lossy().to_string()),
+            params.normalize_audio,
+            params.denoise_audio,
+            params.encode_options.trim_start_ms,
+            params.encode_options.trim_end_ms,
+        );
+        audio_params.dry_run = true;
+        let extraction = audio::extract_audio(id, audio_params, &mut emit).await?;
+        extraction_command = extraction.command;
+        (probe_result, extraction.audio, transcription)
+    } else {
+        extract_and_transcribe(
+            id,
+            &params.input_video,
+            params.split_by_words,
+            params.model,
+            params.model_fallbacks,
+            params.language,
+            params.api_key,
+            params.prompt,
+            params.vocabulary,
+            params.merge_gap_ms,
+            params.drop_no_speech_above,
+            params.resegment,
+            params.max_cps,
+            params.normalize_audio,
+            params.denoise_audio,
+            params.inline_audio,
+            params.encode_options.trim_start_ms,
+            params.encode_options.trim_end_ms,
+            params.force,
+            params.work_dir.as_deref(),
+            stage_progress(&mut emit, 0.0, TRANSCRIBE_STAGE_END),
+        )
+        .await?
+    };
+
+    // Persist before the (long, cancellable) burn so a cancel doesn't lose the transcript
+    if params.save_captions && !params.encode_options.dry_run {
+        save_captions(SaveCaptionsParams {
+            video_path: params.input_video.clone(),
+            segments: transcription.segments.clone(),
+        })
+        .await?;
+        emit(RpcEvent::Log {
+            id: id.into(),
+            message: format!("Saved captions to {}.capslap.json", params.input_video),
+        });
+    }
 
-    let captioned_videos = optimized_multi_format_encode(
+    let mut captioned_videos = optimized_multi_format_encode(
         id,
         &params.input_video,
         &transcription.segments,
@@ -142,9 +778,18 @@ pub async fn generate_captions_single_pass(
         params.position,
         params.output_size,
         params.crop_strategy,
-        &mut emit,
+        &params.style_options,
+        &params.encode_options,
+        None,
+        &mut stage_progress(&mut emit, TRANSCRIBE_STAGE_END, 1.0),
     )
     .await?;
+    // Extraction runs once for all formats, but each video's passes depend on it
+    if let Some(command) = extraction_command {
+        for video in &mut captioned_videos {
+            video.commands.insert(0, command.clone());
+        }
+    }
 
     Ok(GenerateCaptionsResult {
         probe_result,
@@ -154,9 +799,79 @@ pub async fn generate_captions_single_pass(
     })
 }
 
+// Stands in for the transcript on a dry run: one placeholder caption over the processed
+// range, so the commands point at a real subtitle file
+fn dry_run_transcription(
+    probe_result: &crate::video::ProbeResult,
+    trim_start_ms: Option<u64>,
+    trim_end_ms: Option<u64>,
+) -> Result<TranscribeSegmentsResult> {
+    let trimmed = trim_probe(probe_result, trim_start_ms, trim_end_ms)?;
+    let start_ms = trim_start_ms.unwrap_or(0);
+    let length_ms = trimmed
+        .duration
+        .map_or(1000, |d| (d * 1000.0) as u64)
+        .max(1);
+    let text = "[dry run]".to_string();
+    Ok(TranscribeSegmentsResult {
+        segments: vec![CaptionSegment {
+            start_ms,
+            end_ms: start_ms + length_ms,
+            text: text.clone(),
+            words: vec![WordSpan {
+                start_ms,
+                end_ms: start_ms + length_ms,
+                text: text.clone(),
+            }],
+            no_speech_prob: None,
+            position: None,
+        }],
+        full_text: text,
+        duration: trimmed.duration,
+        json_file: String::new(),
+        detected_language: None,
+    })
+}
+
+// Fill the export fields a platform preset covers; anything the caller set wins
+fn apply_platform_preset(params: &mut GenerateCaptionsParams) -> Result<()> {
+    let Some(name) = params.preset.as_deref() else {
+        return Ok(());
+    };
+    let preset = crate::video::platform_preset(name)?;
+    if params.export_formats.is_empty() {
+        params.export_formats = preset.export_formats;
+    }
+    params.output_size.get_or_insert(preset.output_size);
+    params.crop_strategy.get_or_insert(preset.crop_strategy);
+    params.position.get_or_insert(preset.position);
+    let style = &mut params.style_options;
+    style
+        .safe_margin_top_pct
+        .get_or_insert(preset.safe_margin_top_pct);
+    style
+        .safe_margin_bottom_pct
+        .get_or_insert(preset.safe_margin_bottom_pct);
+    style
+        .safe_margin_x_pct
+        .get_or_insert(preset.safe_margin_x_pct);
+    // Any explicit rate control (quality, bitrate or size) replaces the preset's bitrate
+    let encode = &mut params.encode_options;
+    if encode.crf.is_none() && encode.video_bitrate.is_none() && encode.target_size_mb.is_none() {
+        encode.video_bitrate = Some(preset.video_bitrate);
+    }
+    Ok(())
+}
+
 pub fn generate_preview_layout(
     params: crate::types::PreviewLayoutParams,
 ) -> Result<crate::types::PreviewLayoutResult> {
+    validate_caption_style(
+        params.text_color.as_deref(),
+        params.highlight_word_color.as_deref(),
+        params.outline_color.as_deref(),
+        &params.style_options,
+    )?;
     let style = default_ass_style(
         params.width,
         params.height,
@@ -167,40 +882,40 @@ pub fn generate_preview_layout(
         params.glow_effect,
         params.position.as_deref(),
         params.font_size,
+        &params.style_options,
     );
 
     let mut cues = Vec::new();
+    let layout_w = layout_width(&style, params.width);
 
-    // Determine Y position as percentage for frontend
-    // In ASS, we calculated margin_v.
-    // If align is 5 (center), y_pct is 50.
-    // If align is 2 (bottom), y_pct is (100 - margin_pct).
-    // Let's reconstruct consistent pct from style.
-    let y_pct = if style.align == 5 {
-        50.0
-    } else {
-        // margin_v was calculated from pct_h
-        // margin_v = frame_h * (pct / 100)
-        // so pct = (margin_v / frame_h) * 100
-        let margin_pct = (style.margin_v as f32 / params.height as f32) * 100.0;
-        match style.align {
-            8 => margin_pct,         // Top aligned, margin from top
-            _ => 100.0 - margin_pct, // Bottom aligned (2), margin from bottom
-        }
-    };
-
-    if params.karaoke {
-        let phrases = coalesce_phrases(&params.segments);
+    if style.vertical {
+        cues = vertical_preview_cues(
+            &params.segments,
+            &style,
+            (params.width, params.height),
+            params.karaoke,
+        );
+    } else if params.karaoke {
+        let phrases = limit_phrase_words(
+            timed_phrases(&params.segments, &style),
+            style.max_words_per_cue,
+        );
         // let white_bgr = bgr_from_aa_bgrr(&style.primary);
         // let hi_bgr    = bgr_from_aa_bgrr(&style.highlight);
 
         for ph in phrases {
-            let tokens_upper = normalize_tokens(&ph.spans);
-            let segments =
-                split_phrase_for_width(&tokens_upper, &ph.spans, params.width, style.font_size);
+            let tokens_upper = normalize_tokens(&ph.spans, &style);
+            let segments = match style.max_chars_per_line {
+                Some(max_chars) => split_phrase_max_chars(&tokens_upper, &ph.spans, max_chars, 2),
+                None => split_phrase_for_width(&tokens_upper, &ph.spans, layout_w, style.font_size),
+            };
 
             for (segment_tokens, segment_spans) in segments {
                 let windows = contiguous_cs_windows(&segment_spans);
+                let line_starts = style
+                    .max_chars_per_line
+                    .map(|m| line_starts(&segment_tokens, m, style.balance_lines))
+                    .unwrap_or_default();
 
                 for (i, (cs0, cs1)) in windows.iter().enumerate() {
                     let start_ms = (*cs0 as u64) * 10;
@@ -210,36 +925,75 @@ pub fn generate_preview_layout(
                     // The segment_tokens correspond to segment_spans.
                     // But wait, split_phrase_for_width returns tokens that match spans.
 
-                    let mut preview_words = Vec::new();
-                    for (w_idx, token) in segment_tokens.iter().enumerate() {
-                        preview_words.push(crate::types::PreviewWord {
-                            text: token.clone(),
-                            is_highlighted: w_idx == i,
-                        });
+                    let mut lines = preview_lines(&segment_tokens, i, &line_starts);
+                    let phase = word_animation_phase(
+                        style.animation,
+                        &segment_spans[i],
+                        start_ms,
+                        end_ms as i64 - start_ms as i64,
+                    );
+                    if let Some(word) = lines
+                        .iter_mut()
+                        .flat_map(|l| l.words.iter_mut())
+                        .find(|w| w.is_highlighted)
+                    {
+                        // Fill sweeps from the cue start to the phase end
+                        if let Some(p) = phase.as_ref().filter(|p| p.kind == "fill") {
+                            word.fill_pct = Some(fill_pct_at(p, params.timestamp_ms));
+                        }
+                        word.animation_phase = phase;
                     }
 
+                    let (fade_in_ms, fade_out_ms) = cue_fades(
+                        (windows[0].0 * 10, windows[windows.len() - 1].1 * 10),
+                        (start_ms as i64, end_ms as i64),
+                        style.fade_ms,
+                    );
+                    let (x_pct, y_pct) = preview_anchor(
+                        &style,
+                        ph.position,
+                        &segment_tokens,
+                        (params.width, params.height),
+                    );
                     cues.push(crate::types::PreviewCue {
                         start_ms,
                         end_ms,
-                        lines: vec![crate::types::PreviewLine {
-                            words: preview_words,
-                        }],
+                        lines,
                         y_pct,
+                        x_pct,
+                        rtl: false,
+                        vertical: false,
+                        background: None,
+                        fade_in_ms,
+                        fade_out_ms,
+                        visible_chars: style.typewriter.then(|| {
+                            visible_chars_at(&segment_tokens, &segment_spans, params.timestamp_ms)
+                        }),
+                        bounds: None,
                     });
                 }
             }
         }
     } else {
-        let phrases = coalesce_phrases(&params.segments);
+        let phrases = timed_phrases(&params.segments, &style);
         let mut hl_state = HighlightState::new(&params.segments);
 
         for (p_idx, phrase) in phrases.iter().enumerate() {
-            let tokens_upper = normalize_tokens(&phrase.spans);
-
-            let segments = if style.align == 5 {
-                split_phrase_multiline(&tokens_upper, &phrase.spans, params.width, style.font_size)
-            } else {
-                split_phrase_for_width(&tokens_upper, &phrase.spans, params.width, style.font_size)
+            let tokens_upper = normalize_tokens(&phrase.spans, &style);
+
+            let segments = match style.max_chars_per_line {
+                Some(max_chars) => split_phrase_max_chars(
+                    &tokens_upper,
+                    &phrase.spans,
+                    max_chars,
+                    if style.align == 5 { 4 } else { 2 },
+                ),
+                None if style.align == 5 => {
+                    split_phrase_multiline(&tokens_upper, &phrase.spans, layout_w, style.font_size)
+                }
+                None => {
+                    split_phrase_for_width(&tokens_upper, &phrase.spans, layout_w, style.font_size)
+                }
             };
 
             for (segment_tokens, segment_spans) in segments {
@@ -263,107 +1017,473 @@ pub fn generate_preview_layout(
                 // Wait, logic in build_ass_document call to assemble_colored_two_lines passed usize::MAX as break index.
                 // So split_phrase_for_width produces 1 line per segment.
 
-                // For storyteller (split_phrase_multiline), it produces segments but we passed chunks.
-                // The chunk needs further wrapping?
-                // `assemble_multiline` does wrapping based on `max_chars_per_line`.
-                // We need to replicate `assemble_multiline` wrapping logic here to determine lines.
-
-                let est_char_width = (style.font_size as f32 * 0.7).max(1.0);
-
                 let lines_structure = if style.align == 5 {
-                    // Storyteller logic
-                    let max_chars =
-                        ((params.width as f32 * 0.85) / est_char_width).floor() as usize;
-                    let total_chars: usize = segment_tokens.iter().map(|t| t.len()).sum();
-                    let soft_target = (total_chars as f32 / 3.5).ceil() as usize;
-                    let min_chars = 25.min(max_chars).max(1);
-                    let wrapping_width = soft_target.clamp(min_chars, max_chars);
-
-                    // Perform wrapping
-                    let mut lines = Vec::new();
-                    let mut current_line_words = Vec::new();
-                    let mut line_len = 0;
-
-                    for (i, token) in segment_tokens.iter().enumerate() {
-                        let t_len = token.len(); // raw length
-                                                 // Note: assemble_multiline counts escaped length, we use raw here which is close enough or better
-
-                        if line_len > 0 && line_len + t_len + 1 > wrapping_width {
-                            lines.push(crate::types::PreviewLine {
-                                words: current_line_words,
-                            });
-                            current_line_words = Vec::new();
-                            line_len = 0;
-                        } else if line_len > 0 {
-                            line_len += 1; // space
-                        }
-
-                        current_line_words.push(crate::types::PreviewWord {
-                            text: token.clone(),
-                            is_highlighted: i == hi_idx,
-                        });
-                        line_len += t_len;
-                    }
-                    if !current_line_words.is_empty() {
-                        lines.push(crate::types::PreviewLine {
-                            words: current_line_words,
-                        });
-                    }
-                    lines
+                    // Storyteller: same wrap width and wrap as `assemble_multiline`
+                    let wrapping_width = storyteller_wrap_width(&segment_tokens, layout_w, &style);
+                    let line_starts: Vec<usize> =
+                        wrap_multiline(&segment_tokens, wrapping_width, style.balance_lines)
+                            .iter()
+                            .filter_map(|l| l.first().copied())
+                            .collect();
+                    preview_lines(&segment_tokens, hi_idx, &line_starts)
                 } else {
-                    // Standard logic (single line per segment usually)
-                    let mut words = Vec::new();
-                    for (i, token) in segment_tokens.iter().enumerate() {
-                        words.push(crate::types::PreviewWord {
-                            text: token.clone(),
-                            is_highlighted: i == hi_idx,
-                        });
-                    }
-                    vec![crate::types::PreviewLine { words }]
+                    // Standard logic (single line per segment unless a wrap width was requested)
+                    let line_starts = style
+                        .max_chars_per_line
+                        .map(|m| line_starts(&segment_tokens, m, style.balance_lines))
+                        .unwrap_or_default();
+                    preview_lines(&segment_tokens, hi_idx, &line_starts)
                 };
 
+                let cue_ms = (start_ms as i64, end_ms as i64);
+                let (fade_in_ms, fade_out_ms) = cue_fades(cue_ms, cue_ms, style.fade_ms);
+                let (x_pct, y_pct) = preview_anchor(
+                    &style,
+                    phrase.position,
+                    &segment_tokens,
+                    (params.width, params.height),
+                );
                 cues.push(crate::types::PreviewCue {
                     start_ms,
                     end_ms,
                     lines: lines_structure,
                     y_pct,
+                    x_pct,
+                    rtl: false,
+                    vertical: false,
+                    background: None,
+                    fade_in_ms,
+                    fade_out_ms,
+                    visible_chars: style.typewriter.then(|| {
+                        visible_chars_at(&segment_tokens, &segment_spans, params.timestamp_ms)
+                    }),
+                    bounds: None,
                 });
             }
         }
     }
 
-    Ok(crate::types::PreviewLayoutResult { cues })
-}
+    // Right-to-left cues: put each line's words in display order, like the burn path
+    for cue in &mut cues {
+        let tokens: Vec<String> = cue
+            .lines
+            .iter()
+            .flat_map(|l| l.words.iter().map(|w| w.text.clone()))
+            .collect();
+        if !has_rtl_chars(&tokens) {
+            continue;
+        }
+        cue.rtl = is_rtl_tokens(&tokens);
+        if cue.rtl {
+            // Right-to-left cues are burned without the typewriter reveal
+            cue.visible_chars = None;
+        }
+        for line in &mut cue.lines {
+            let texts: Vec<String> = line.words.iter().map(|w| w.text.clone()).collect();
+            let mut words: Vec<Option<crate::types::PreviewWord>> = std::mem::take(&mut line.words)
+                .into_iter()
+                .map(Some)
+                .collect();
+            line.words = bidi_visual_order(&texts, cue.rtl)
+                .into_iter()
+                .filter_map(|i| words[i].take())
+                .collect();
+        }
+    }
 
-pub fn save_captions(params: SaveCaptionsParams) -> Result<()> {
-    let video_path = std::path::Path::new(&params.video_path);
-    // Sidecar file: video.mp4 -> video.capslap.json
-    // Let's sticker to replacing extension to keep it clean, OR append if we want to be safe against collisions (vid.mp4 vs vid.mov).
-    // The plan said: <video_path>.capslap.json.
-    // If video is "movie.mp4", "movie.capslap.json" is fine.
-    // But if I have "movie.mp4" and "movie.avi", they would clash.
-    // Let's append ".capslap.json" to the full filename.
-    let _json_path = video_path.with_extension("capslap.json"); // Kept variable but underscored to suppress warning if we don't use it, but wait, we use `json_path` (the other one) below.
-                                                                // Actually the line 314 was unused because I shadowed it with line 321 `let json_path = ...`.
-                                                                // So I should just remove line 314.
+    // Emoji clusters become their own words so the editor treats them atomically
+    for line in cues.iter_mut().flat_map(|c| c.lines.iter_mut()) {
+        let mut words = Vec::with_capacity(line.words.len());
+        for word in std::mem::take(&mut line.words) {
+            let pieces = split_emoji_runs(&word.text);
+            if pieces.len() == 1 && !pieces[0].1 {
+                words.push(word);
+                continue;
+            }
+            for (k, (text, emoji)) in pieces.into_iter().enumerate() {
+                words.push(crate::types::PreviewWord {
+                    text,
+                    is_highlighted: word.is_highlighted,
+                    animation_phase: word.animation_phase.clone(),
+                    fill_pct: word.fill_pct,
+                    emoji,
+                    attached: k > 0,
+                });
+            }
+        }
+        line.words = words;
+    }
 
-    // If the video path didn't have an extension, with_extension replaces the last component?
-    // No, it replaces extension. If video is /path/to/vid, it becomes /path/to/vid.capslap.json
-    // If video is /path/to/vid.mp4, it becomes /path/to/vid.capslap.json.
-    // Wait, if I want video.mp4.capslap.json, I should just append.
-    // Let's stick to replacing extension to keep it clean, OR append if we want to be safe against collisions (vid.mp4 vs vid.mov).
-    // The plan said: <video_path>.capslap.json.
-    // If video is "movie.mp4", "movie.capslap.json" is fine.
-    // But if I have "movie.mp4" and "movie.avi", they would clash.
-    // Let's append ".capslap.json" to the full filename.
-    let json_path = format!("{}.capslap.json", params.video_path);
+    // Rendered line widths, measured with the caption font at the style's size
+    let metrics = FontMetrics::load(&style.font_name);
+    for cue in &mut cues {
+        for line in &mut cue.lines {
+            line.est_width_px = if cue.vertical {
+                line.words
+                    .iter()
+                    .flat_map(|w| w.text.graphemes(true))
+                    .map(|g| metrics.text_width(g, style.font_size))
+                    .fold(0.0, f32::max)
+            } else {
+                let mut text = String::new();
+                for (i, word) in line.words.iter().enumerate() {
+                    if i > 0 && !word.attached {
+                        text.push(' ');
+                    }
+                    text.push_str(&word.text);
+                }
+                metrics.text_width(&text, style.font_size)
+            };
+        }
+    }
+
+    // Measured text box per cue, grown by the outline (and glow) drawn around the glyphs
+    let (fw, fh) = (params.width as f32, params.height as f32);
+    let pad = stroke_pad(&style, params.glow_effect);
+    for cue in &mut cues {
+        let fs = style.font_size as f32;
+        let widest = cue.lines.iter().map(|l| l.est_width_px).fold(0.0, f32::max);
+        let rows = if cue.vertical {
+            cue.lines
+                .iter()
+                .map(|l| {
+                    let texts: Vec<String> = l.words.iter().map(|w| w.text.clone()).collect();
+                    column_glyphs(&texts)
+                })
+                .sum()
+        } else {
+            cue.lines.len()
+        };
+        let align = if cue.rtl && !cue.vertical {
+            rtl_anchor(&style, params.width).0
+        } else {
+            style.align
+        };
+        let rect = anchored_rect(
+            widest + 2.0 * pad,
+            rows.max(1) as f32 * fs * BOX_LINE_H + 2.0 * pad,
+            cue.x_pct / 100.0 * fw,
+            cue.y_pct / 100.0 * fh,
+            align,
+        );
+        cue.bounds = Some(crate::types::PreviewBounds {
+            x_pct: rect.left / fw * 100.0,
+            y_pct: rect.top / fh * 100.0,
+            width_pct: rect.width / fw * 100.0,
+            height_pct: rect.height / fh * 100.0,
+        });
+    }
+
+    // Background box per cue, sized to its final wrapped lines
+    if let Some(bg) = &style.background {
+        for cue in &mut cues {
+            let line_chars: Vec<usize> = if cue.vertical {
+                vec![cue
+                    .lines
+                    .iter()
+                    .map(|l| {
+                        let texts: Vec<String> = l.words.iter().map(|w| w.text.clone()).collect();
+                        column_glyphs(&texts)
+                    })
+                    .sum()]
+            } else {
+                cue.lines
+                    .iter()
+                    .map(|l| {
+                        let texts: Vec<String> = l.words.iter().map(|w| w.text.clone()).collect();
+                        joined_chars(&texts)
+                    })
+                    .collect()
+            };
+            let align = if cue.rtl && !cue.vertical {
+                rtl_anchor(&style, params.width).0
+            } else {
+                style.align
+            };
+            let rect = background_rect(
+                &line_chars,
+                style.font_size,
+                cue.x_pct / 100.0 * fw,
+                cue.y_pct / 100.0 * fh,
+                align,
+                cue.vertical,
+            );
+            cue.background = Some(crate::types::PreviewBox {
+                x_pct: rect.left / fw * 100.0,
+                y_pct: rect.top / fh * 100.0,
+                width_pct: rect.width / fw * 100.0,
+                height_pct: rect.height / fh * 100.0,
+                radius_px: bg.radius.min(rect.width.min(rect.height) / 2.0),
+                color: bg.color.clone(),
+                opacity: bg.opacity,
+            });
+        }
+    }
+
+    Ok(crate::types::PreviewLayoutResult {
+        cues,
+        text_shadow: params.style_options.shadow.as_ref().map(css_text_shadow),
+        text_gradient: params
+            .style_options
+            .text_gradient
+            .as_ref()
+            .map(css_text_gradient),
+    })
+}
+
+// Apply a time mapping to every segment and word span
+fn map_segment_times(segments: &[CaptionSegment], map: impl Fn(u64) -> u64) -> Vec<CaptionSegment> {
+    segments
+        .iter()
+        .map(|s| CaptionSegment {
+            start_ms: map(s.start_ms),
+            end_ms: map(s.end_ms),
+            text: s.text.clone(),
+            words: s
+                .words
+                .iter()
+                .map(|w| WordSpan {
+                    start_ms: map(w.start_ms),
+                    end_ms: map(w.end_ms),
+                    text: w.text.clone(),
+                })
+                .collect(),
+            no_speech_prob: None,
+            position: s.position,
+        })
+        .collect()
+}
+
+/// Move source-timeline segments onto a clip trimmed to `[start_ms, end_ms)`: cues and
+/// words outside the range are dropped, the rest are clamped to it and shifted to start at 0.
+pub fn trim_segments(
+    segments: &[CaptionSegment],
+    start_ms: u64,
+    end_ms: Option<u64>,
+) -> Vec<CaptionSegment> {
+    let end_ms = end_ms.unwrap_or(u64::MAX);
+    let overlaps = |start: u64, end: u64| start < end_ms && end > start_ms;
+    let kept: Vec<CaptionSegment> = segments
+        .iter()
+        .filter(|s| overlaps(s.start_ms, s.end_ms))
+        .map(|s| CaptionSegment {
+            words: s
+                .words
+                .iter()
+                .filter(|w| overlaps(w.start_ms, w.end_ms))
+                .cloned()
+                .collect(),
+            ..s.clone()
+        })
+        .collect();
+    map_segment_times(&kept, |ms| ms.clamp(start_ms, end_ms) - start_ms)
+}
+
+// The probe as seen through a trim, with the clip's length as its duration
+fn trim_probe(
+    probe_result: &crate::video::ProbeResult,
+    start_ms: Option<u64>,
+    end_ms: Option<u64>,
+) -> Result<crate::video::ProbeResult> {
+    let duration = crate::video::trimmed_duration(probe_result.duration, start_ms, end_ms);
+    if duration == Some(0.0) {
+        return Err(anyhow!(
+            "The trim range ({} ms to {}) is outside the {:.1} s video",
+            start_ms.unwrap_or(0),
+            end_ms.map_or("the end".to_string(), |ms| format!("{} ms", ms)),
+            probe_result.duration.unwrap_or(0.0)
+        ));
+    }
+    Ok(crate::video::ProbeResult {
+        duration,
+        ..probe_result.clone()
+    })
+}
+
+/// Shift every segment and word span by a signed offset (subtitle sync), clamping at 0.
+pub fn offset_segments(segments: &[CaptionSegment], offset_ms: i64) -> Vec<CaptionSegment> {
+    map_segment_times(segments, |ms| (ms as i64 + offset_ms).max(0) as u64)
+}
+
+/// Multiply every segment and word timing by `scale` (e.g. 0.8 for a 1.25x sped-up export).
+pub fn scale_segments(segments: &[CaptionSegment], scale: f64) -> Vec<CaptionSegment> {
+    map_segment_times(segments, |ms| (ms as f64 * scale).round() as u64)
+}
+
+/// Check that there are segments, in order, with a non-negative duration and no overlap.
+/// The first bad one is an INVALID_SEGMENT error naming its index; with `fix` they are
+/// instead sorted, clamped to end where the next starts, and dropped if left empty.
+fn check_segments(
+    id: &str,
+    segments: &[CaptionSegment],
+    fix: bool,
+    emit: &mut impl FnMut(RpcEvent),
+) -> Result<Vec<CaptionSegment>> {
+    if segments.is_empty() {
+        return Err(CodedError::new("INVALID_SEGMENT", "No segments to burn").into());
+    }
+    if !fix {
+        if let Some((index, problem)) = segment_problem(segments) {
+            let s = &segments[index];
+            return Err(CodedError::new(
+                "INVALID_SEGMENT",
+                format!(
+                    "Segment {} ({}-{} ms, \"{}\") {}",
+                    index,
+                    s.start_ms,
+                    s.end_ms,
+                    s.text.trim(),
+                    problem
+                ),
+            )
+            .into());
+        }
+        return Ok(segments.to_vec());
+    }
+    let fixed = fix_segment_timings(segments);
+    if fixed.is_empty() {
+        return Err(CodedError::new(
+            "INVALID_SEGMENT",
+            "No segments left to burn: every segment was empty",
+        )
+        .into());
+    }
+    let changed = segments.len() != fixed.len()
+        || segments
+            .iter()
+            .zip(&fixed)
+            .any(|(a, b)| a.start_ms != b.start_ms || a.end_ms != b.end_ms);
+    if changed {
+        emit(RpcEvent::Log {
+            id: id.into(),
+            message: format!(
+                "Fixed segment timings: {} segments kept of {}",
+                fixed.len(),
+                segments.len()
+            ),
+        });
+    }
+    Ok(fixed)
+}
+
+// Index of the first invalid segment and what is wrong with it
+fn segment_problem(segments: &[CaptionSegment]) -> Option<(usize, String)> {
+    segments.iter().enumerate().find_map(|(i, s)| {
+        if s.end_ms < s.start_ms {
+            return Some((i, "ends before it starts".to_string()));
+        }
+        let prev = &segments[i.checked_sub(1)?];
+        if s.start_ms < prev.start_ms {
+            Some((
+                i,
+                format!("starts before segment {} ({} ms)", i - 1, prev.start_ms),
+            ))
+        } else if s.start_ms < prev.end_ms {
+            Some((
+                i,
+                format!(
+                    "overlaps segment {}, which ends at {} ms",
+                    i - 1,
+                    prev.end_ms
+                ),
+            ))
+        } else {
+            None
+        }
+    })
+}
+
+// Drop segments without a duration, sort by start, clamp each end to the next start
+// (dropping any left empty) and clamp word timings into their segment
+fn fix_segment_timings(segments: &[CaptionSegment]) -> Vec<CaptionSegment> {
+    let mut sorted: Vec<CaptionSegment> = segments
+        .iter()
+        .filter(|s| s.end_ms > s.start_ms)
+        .cloned()
+        .collect();
+    sorted.sort_by_key(|s| s.start_ms);
+    let next_starts: Vec<Option<u64>> = (0..sorted.len())
+        .map(|i| sorted.get(i + 1).map(|s| s.start_ms))
+        .collect();
+    sorted
+        .into_iter()
+        .zip(next_starts)
+        .filter_map(|(mut s, next)| {
+            s.end_ms = s.end_ms.min(next.unwrap_or(u64::MAX));
+            if s.end_ms <= s.start_ms {
+                return None;
+            }
+            for w in &mut s.words {
+                w.start_ms = w.start_ms.clamp(s.start_ms, s.end_ms);
+                w.end_ms = w.end_ms.clamp(w.start_ms, s.end_ms);
+            }
+            Some(s)
+        })
+        .collect()
+}
+
+// Offset then scale the caller's segments for a render, logging any change in duration
+fn retime_segments(
+    id: &str,
+    segments: &[CaptionSegment],
+    offset_ms: i64,
+    time_scale: f64,
+    emit: &mut impl FnMut(RpcEvent),
+) -> Result<Vec<CaptionSegment>> {
+    if !(time_scale.is_finite() && time_scale > 0.0) {
+        return Err(anyhow!(
+            "time_scale must be a positive number, got {}",
+            time_scale
+        ));
+    }
+    let shifted = offset_segments(segments, offset_ms);
+    if time_scale == 1.0 {
+        return Ok(shifted);
+    }
+    let scaled = scale_segments(&shifted, time_scale);
+    let last_end = |s: &[CaptionSegment]| s.iter().map(|x| x.end_ms).max().unwrap_or(0);
+    emit(RpcEvent::Log {
+        id: id.into(),
+        message: format!(
+            "Scaled caption timings by {}: captions now end at {} ms (was {} ms)",
+            time_scale,
+            last_end(&scaled),
+            last_end(&shifted)
+        ),
+    });
+    Ok(scaled)
+}
+
+/// Write `<video>.capslap.json` without ever leaving a truncated file: the JSON must read
+/// back as the same captions, then is written atomically with `whisper::write_atomic`.
+/// The version it replaces is kept as `<video>.capslap.json.bak`.
+pub async fn save_captions(params: SaveCaptionsParams) -> Result<()> {
+    // Appended to the full name, so movie.mp4 and movie.mov don't share a sidecar
+    let json_path = PathBuf::from(format!("{}.capslap.json", params.video_path));
 
     let json = serde_json::to_string_pretty(&params.segments)?;
-    fs::write(&json_path, json)?;
+    let round_trip: Vec<CaptionSegment> = serde_json::from_str(&json)?;
+    if serde_json::to_string_pretty(&round_trip)? != json {
+        return Err(anyhow!(
+            "Captions did not survive a JSON round trip; not saving"
+        ));
+    }
 
-    Ok(())
+    if json_path.exists() {
+        // Copied, not moved, so there is always a complete file at the target
+        let mut backup = json_path.clone().into_os_string();
+        backup.push(".bak");
+        tokio::fs::copy(&json_path, &backup)
+            .await
+            .with_context(|| format!("Failed to back up {}", json_path.display()))?;
+    }
+    whisper::write_atomic(&json_path, &json)
+        .await
+        .with_context(|| format!("Failed to save {}", json_path.display()))
 }
 
+/// Load the captions saved next to a video. Without our JSON, a subtitle sidecar with the
+/// video's basename (`clip.srt`, then `clip.vtt`) is parsed instead; `source` says which.
 pub fn load_captions(params: LoadCaptionsParams) -> Result<LoadCaptionsResult> {
     let json_path = format!("{}.capslap.json", params.video_path);
     let path = std::path::Path::new(&json_path);
@@ -371,57 +1491,505 @@ pub fn load_captions(params: LoadCaptionsParams) -> Result<LoadCaptionsResult> {
     if path.exists() {
         let content = fs::read_to_string(path)?;
         let segments: Vec<CaptionSegment> = serde_json::from_str(&content)?;
-        Ok(LoadCaptionsResult {
+        return Ok(LoadCaptionsResult {
             segments: Some(segments),
-        })
+            source: Some("capslap".to_string()),
+            path: Some(json_path),
+        });
+    }
+    for ext in ["srt", "vtt"] {
+        let sidecar = Path::new(&params.video_path).with_extension(ext);
+        if sidecar.is_file() {
+            return Ok(LoadCaptionsResult {
+                segments: Some(crate::subtitles::load_subtitle_file(&sidecar)?),
+                source: Some(ext.to_string()),
+                path: Some(sidecar.to_string_lossy().to_string()),
+            });
+        }
+    }
+    Ok(LoadCaptionsResult {
+        segments: None,
+        source: None,
+        path: None,
+    })
+}
+
+// ---- Style presets ----
+
+/// Per-user config directory for CapSlap (presets live under `presets/`).
+pub fn get_config_dir() -> Result<PathBuf> {
+    let config_dir = if cfg!(target_os = "macos") {
+        let home = std::env::var("HOME").context("HOME environment variable not set")?;
+        PathBuf::from(home).join("Library/Application Support/CapSlap")
+    } else if cfg!(target_os = "windows") {
+        let appdata = std::env::var("APPDATA").context("APPDATA environment variable not set")?;
+        PathBuf::from(appdata).join("CapSlap")
+    } else if let Ok(xdg) = std::env::var("XDG_CONFIG_HOME") {
+        PathBuf::from(xdg).join("capslap")
     } else {
-        Ok(LoadCaptionsResult { segments: None })
+        let home = std::env::var("HOME").context("HOME environment variable not set")?;
+        PathBuf::from(home).join(".config/capslap")
+    };
+    Ok(config_dir)
+}
+
+/// Read `config.json` from the config directory; a missing file is the default config.
+pub fn load_core_config() -> Result<crate::types::CoreConfig> {
+    let path = get_config_dir()?.join("config.json");
+    match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content)
+            .with_context(|| format!("Invalid config file {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Default::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
     }
 }
 
-pub async fn generate_preview_frame(
-    params: crate::types::PreviewFrameParams,
-) -> Result<crate::types::PreviewFrameResult> {
-    let temp_dir = std::env::temp_dir().join(format!("capslap_preview_{}", uuid::Uuid::new_v4()));
-    if let Err(e) = fs::create_dir_all(&temp_dir) {
-        return Err(anyhow!("Failed to create temp directory: {}", e));
+/// The core config, or the defaults when it can't be loaded. A config.json that fails
+/// to parse is reported once per process (stderr and the log) instead of being ignored.
+pub fn core_config_or_default() -> crate::types::CoreConfig {
+    static WARNED: std::sync::Once = std::sync::Once::new();
+    load_core_config().unwrap_or_else(|e| {
+        WARNED.call_once(|| {
+            tracing::warn!("{:#}; using default settings", e);
+            eprintln!("Warning: {:#}; using default settings", e);
+        });
+        Default::default()
+    })
+}
+
+fn presets_dir() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("presets"))
+}
+
+// Preset names become file names, so keep them to a safe character set
+fn preset_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    let name = name.trim();
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_');
+    if !valid {
+        return Err(anyhow!(
+            "Invalid preset name '{}': use letters, digits, spaces, '-' or '_'",
+            name
+        ));
     }
+    Ok(dir.join(format!("{}.json", name)))
+}
 
-    // We need to probe to get video dimensions
-    // We don't have an ID for logs here, so we use a placeholder
-    let probe_id = "preview_probe";
-    let probe_result = probe(probe_id, &params.input_video, |e| {
-        // Ignore logs for preview
-        let _ = e;
+fn save_style_preset_in(dir: &Path, params: SaveStylePresetParams) -> Result<()> {
+    let path = preset_path(dir, &params.name)?;
+    fs::create_dir_all(dir)?;
+    fs::write(&path, serde_json::to_string_pretty(&params.preset)?)?;
+    Ok(())
+}
+
+fn load_style_preset_in(
+    dir: &Path,
+    params: LoadStylePresetParams,
+) -> Result<LoadStylePresetResult> {
+    let path = preset_path(dir, &params.name)?;
+    if !path.exists() {
+        return Ok(LoadStylePresetResult { preset: None });
+    }
+    let content = fs::read_to_string(&path)?;
+    let preset = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse preset {}", path.display()))?;
+    Ok(LoadStylePresetResult {
+        preset: Some(preset),
     })
-    .await?;
+}
 
-    // Determine target dimensions
-    let target_ar = crate::video::parse_target_ar(&params.export_format)?;
-    let src_w = probe_result.width.unwrap_or(1920) as u32;
-    let src_h = probe_result.height.unwrap_or(1080) as u32;
-
-    let (target_w, target_h) = if let Some(size) = &params.output_size {
-        match size.as_str() {
-            "1080p" => {
-                let (base_w, base_h) = crate::video::ar_wh(target_ar);
-                let ar = base_w as f64 / base_h as f64;
-                if base_w > base_h {
-                    let w = (1080.0 * ar).round() as u32;
-                    (crate::video::round_even(w), 1080)
-                } else {
-                    let h = (1080.0 / ar).round() as u32;
-                    (1080, crate::video::round_even(h))
+fn list_style_presets_in(dir: &Path) -> Result<ListStylePresetsResult> {
+    let mut names = Vec::new();
+    if dir.is_dir() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("json") {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    names.push(stem.to_string());
                 }
             }
-            _ => crate::video::canvas_no_downscale(src_w, src_h, target_ar),
+        }
+    }
+    names.sort();
+    Ok(ListStylePresetsResult { names })
+}
+
+pub fn save_style_preset(params: SaveStylePresetParams) -> Result<()> {
+    save_style_preset_in(&presets_dir()?, params)
+}
+
+pub fn load_style_preset(params: LoadStylePresetParams) -> Result<LoadStylePresetResult> {
+    load_style_preset_in(&presets_dir()?, params)
+}
+
+pub fn list_style_presets() -> Result<ListStylePresetsResult> {
+    list_style_presets_in(&presets_dir()?)
+}
+
+// ---- Fonts ----
+
+struct FontEntry {
+    names: Vec<String>, // family, typographic family, full and PostScript names
+    family: String,
+    full_name: String,
+    path: PathBuf,
+    index: u32, // face index within a font collection
+    bundled: bool,
+}
+
+// Platform font directories searched after the bundled fonts
+fn system_font_dirs() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let mut dirs = Vec::new();
+    if cfg!(target_os = "macos") {
+        dirs.push(PathBuf::from("/System/Library/Fonts"));
+        dirs.push(PathBuf::from("/Library/Fonts"));
+        dirs.extend(home.map(|h| h.join("Library/Fonts")));
+    } else if cfg!(windows) {
+        let windir =
+            std::env::var_os("WINDIR").map_or_else(|| PathBuf::from(r"C:\Windows"), PathBuf::from);
+        dirs.push(windir.join("Fonts"));
+        if let Some(local) = std::env::var_os("LOCALAPPDATA") {
+            dirs.push(PathBuf::from(local).join(r"Microsoft\Windows\Fonts"));
         }
     } else {
-        crate::video::canvas_no_downscale(src_w, src_h, target_ar)
+        dirs.push(PathBuf::from("/usr/share/fonts"));
+        dirs.push(PathBuf::from("/usr/local/share/fonts"));
+        if let Some(h) = home {
+            dirs.push(h.join(".local/share/fonts"));
+            dirs.push(h.join(".fonts"));
+        }
+    }
+    dirs
+}
+
+fn collect_font_files(dir: &Path, out: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_font_files(&path, out);
+        } else if path.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+            matches!(
+                e.to_ascii_lowercase().as_str(),
+                "ttf" | "otf" | "ttc" | "otc"
+            )
+        }) {
+            out.push(path);
+        }
+    }
+}
+
+// Every face in a font file (collections hold several)
+fn read_font_entries(path: &Path, bundled: bool) -> Vec<FontEntry> {
+    use ttf_parser::name_id;
+    let Ok(data) = fs::read(path) else {
+        return Vec::new();
+    };
+    let count = ttf_parser::fonts_in_collection(&data).unwrap_or(1);
+    (0..count)
+        .filter_map(|index| {
+            let face = ttf_parser::Face::parse(&data, index).ok()?;
+            let name = |id: u16| {
+                face.names()
+                    .into_iter()
+                    .filter(|n| n.name_id == id)
+                    .find_map(|n| n.to_string())
+            };
+            let family = name(name_id::FAMILY).or_else(|| name(name_id::TYPOGRAPHIC_FAMILY))?;
+            let names = [
+                name_id::FAMILY,
+                name_id::TYPOGRAPHIC_FAMILY,
+                name_id::FULL_NAME,
+                name_id::POST_SCRIPT_NAME,
+            ]
+            .into_iter()
+            .filter_map(name)
+            .collect();
+            Some(FontEntry {
+                names,
+                full_name: name(name_id::FULL_NAME).unwrap_or_else(|| family.clone()),
+                family,
+                path: path.to_path_buf(),
+                index,
+                bundled,
+            })
+        })
+        .collect()
+}
+
+// Bundled fonts first so they win over system fonts with the same name.
+// Scanned once per process; fonts installed later need a restart.
+fn font_index() -> &'static [FontEntry] {
+    use std::sync::LazyLock;
+    static INDEX: LazyLock<Vec<FontEntry>> = LazyLock::new(|| {
+        let mut index = Vec::new();
+        let bundled = crate::video::get_fonts_dir();
+        let dirs = bundled
+            .iter()
+            .map(|d| (d.clone(), true))
+            .chain(system_font_dirs().into_iter().map(|d| (d, false)));
+        for (dir, is_bundled) in dirs {
+            let mut files = Vec::new();
+            collect_font_files(&dir, &mut files);
+            files.sort();
+            for file in files {
+                index.extend(read_font_entries(&file, is_bundled));
+            }
+        }
+        index
+    });
+    &INDEX
+}
+
+/// Resolve a font name to the file libass will load. Family, full and PostScript
+/// names are matched case-insensitively; a missing font is a FONT_NOT_FOUND error
+/// instead of libass silently substituting its default.
+pub fn resolve_font(name: &str) -> Result<PathBuf> {
+    find_font(name).map(|f| f.path.clone()).ok_or_else(|| {
+        CodedError::new(
+            "FONT_NOT_FOUND",
+            format!("Font '{}' is not installed or bundled", name),
+        )
+        .into()
+    })
+}
+
+fn find_font(name: &str) -> Option<&'static FontEntry> {
+    let wanted = name.trim().to_lowercase();
+    font_index()
+        .iter()
+        .find(|f| f.names.iter().any(|n| n.to_lowercase() == wanted))
+}
+
+// Glyph advances of the caption font, for measuring text the way libass lays it out
+struct FontMetrics {
+    data: std::borrow::Cow<'static, [u8]>,
+    index: u32,
+}
+
+impl FontMetrics {
+    // The named font, or the embedded default when it can't be found (as renders do)
+    fn load(font_name: &str) -> Self {
+        find_font(font_name)
+            .and_then(|f| {
+                let data = fs::read(&f.path).ok()?;
+                ttf_parser::Face::parse(&data, f.index).ok()?;
+                Some(FontMetrics {
+                    data: data.into(),
+                    index: f.index,
+                })
+            })
+            .unwrap_or(FontMetrics {
+                data: DEFAULT_FONT_TTF.into(),
+                index: 0,
+            })
+    }
+
+    fn face(&self) -> Option<ttf_parser::Face<'_>> {
+        ttf_parser::Face::parse(&self.data, self.index).ok()
+    }
+
+    // libass sizes fonts so the Windows ascent + descent spans `font_size` pixels
+    fn scale(face: &ttf_parser::Face, font_size: u32) -> f32 {
+        let height = face
+            .tables()
+            .os2
+            .map(|os2| os2.windows_ascender() as i32 + os2.windows_descender() as i32)
+            .filter(|h| *h > 0)
+            .unwrap_or(face.ascender() as i32 - face.descender() as i32);
+        font_size as f32 / height.max(1) as f32
+    }
+
+    fn advance(face: &ttf_parser::Face, ch: char) -> f32 {
+        face.glyph_index(ch)
+            .or_else(|| face.glyph_index('\u{FFFD}'))
+            .and_then(|g| face.glyph_hor_advance(g))
+            // Glyphs the font lacks come from a fallback font; assume half an em
+            .unwrap_or(face.units_per_em() / 2) as f32
+    }
+
+    // One grapheme cluster: its base character, plus whatever the font has for the rest.
+    // Marks the font lacks, ZWJ-joined emoji and a flag's second letter draw as part of
+    // the first glyph rather than as extra fallback glyphs.
+    fn cluster_advance(face: &ttf_parser::Face, cluster: &str) -> f32 {
+        let mut chars = cluster.chars();
+        let Some(base) = chars.next() else {
+            return 0.0;
+        };
+        let rest: f32 = chars
+            .filter_map(|ch| face.glyph_index(ch))
+            .filter_map(|g| face.glyph_hor_advance(g))
+            .map(f32::from)
+            .sum();
+        Self::advance(face, base) + rest
+    }
+
+    fn text_width(&self, text: &str, font_size: u32) -> f32 {
+        let Some(face) = self.face() else {
+            return grapheme_count(text) as f32 * font_size as f32 * BOX_CHAR_W;
+        };
+        let units: f32 = text
+            .graphemes(true)
+            .map(|g| Self::cluster_advance(&face, g))
+            .sum();
+        units * Self::scale(&face, font_size)
+    }
+}
+
+// Default caption font, compiled in so renders don't depend on what the machine has installed
+const DEFAULT_FONT_FAMILY: &str = "Montserrat Black";
+static DEFAULT_FONT_TTF: &[u8] = include_bytes!("fonts/Montserrat Black.ttf");
+
+// Folder holding only the embedded default font, written to the temp dir on first use
+fn default_font_dir() -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join("capslap-fonts");
+    let path = dir.join(format!("{}.ttf", DEFAULT_FONT_FAMILY));
+    let stale = fs::metadata(&path).map_or(true, |m| m.len() != DEFAULT_FONT_TTF.len() as u64);
+    if stale {
+        fs::create_dir_all(&dir)?;
+        // Write then rename so concurrent renders never load a half-written file
+        let tmp = dir.join(format!("{}.ttf.tmp", uuid::Uuid::new_v4()));
+        fs::write(&tmp, DEFAULT_FONT_TTF)?;
+        fs::rename(&tmp, &path)?;
+    }
+    Ok(dir)
+}
+
+// Font for a render: (family for the ASS style, libass fontsdir). A missing or
+// unresolvable font_name falls back to the embedded default, with a Log event.
+fn caption_font(
+    id: &str,
+    font_name: Option<&str>,
+    emit: &mut impl FnMut(RpcEvent),
+) -> Result<(String, PathBuf)> {
+    let reason = match font_name {
+        Some(name) => match resolve_font(name) {
+            Ok(path) => {
+                if let Some(dir) = path.parent() {
+                    return Ok((name.to_string(), dir.to_path_buf()));
+                }
+                format!("Font '{}' has no parent folder", name)
+            }
+            Err(e) => e.to_string(),
+        },
+        None => "No font requested".to_string(),
     };
+    emit(RpcEvent::Log {
+        id: id.into(),
+        message: format!("{}; using bundled {}", reason, DEFAULT_FONT_FAMILY),
+    });
+    Ok((DEFAULT_FONT_FAMILY.to_string(), default_font_dir()?))
+}
+
+pub fn list_fonts() -> Result<crate::types::ListFontsResult> {
+    let mut seen = HashSet::new();
+    let mut fonts: Vec<crate::types::FontInfo> = font_index()
+        .iter()
+        .filter(|f| seen.insert(f.full_name.to_lowercase()))
+        .map(|f| crate::types::FontInfo {
+            family: f.family.clone(),
+            full_name: f.full_name.clone(),
+            path: f.path.to_string_lossy().to_string(),
+            bundled: f.bundled,
+        })
+        .collect();
+    fonts.sort_by(|a, b| {
+        (a.family.to_lowercase(), a.full_name.to_lowercase())
+            .cmp(&(b.family.to_lowercase(), b.full_name.to_lowercase()))
+    });
+    Ok(crate::types::ListFontsResult { fonts })
+}
+
+const MAX_PREVIEW_DIM: u32 = 8192;
+
+// Preview canvas: exact width/height overrides when given, else the export format's
+// aspect ratio at `output_size` (as the burn path sizes it)
+fn preview_frame_size(
+    params: &crate::types::PreviewFrameParams,
+    src_w: u32,
+    src_h: u32,
+) -> Result<(u32, u32)> {
+    match (params.width, params.height) {
+        (Some(w), Some(h)) => {
+            if !(2..=MAX_PREVIEW_DIM).contains(&w) || !(2..=MAX_PREVIEW_DIM).contains(&h) {
+                return Err(anyhow!(
+                    "Preview width and height must be between 2 and {}, got {}x{}",
+                    MAX_PREVIEW_DIM,
+                    w,
+                    h
+                ));
+            }
+            return Ok((w, h));
+        }
+        (None, None) => {}
+        _ => {
+            return Err(anyhow!(
+                "Pass both width and height for a preview, or neither"
+            ))
+        }
+    }
+
+    let target_ar = crate::video::parse_target_ar(&params.export_format)?;
+    Ok(crate::video::export_dimensions(
+        target_ar,
+        params.output_size.as_deref(),
+        src_w,
+        src_h,
+    ))
+}
 
+// Writes the caption ASS for a preview render into `temp_dir` and returns the video
+// filter that frames the source at `target` and burns the captions in
+fn preview_filter(
+    id: &str,
+    params: &crate::types::PreviewFrameParams,
+    target: (u32, u32),
+    probe_result: &crate::video::ProbeResult,
+    temp_dir: &Path,
+    emit: &mut impl FnMut(RpcEvent),
+) -> Result<String> {
     // Calculate crop strategy
     let crop_strategy = params.crop_strategy.as_deref().unwrap_or("fit");
+    let (ass_path, fonts_dir) = preview_ass(id, params, target, temp_dir, emit)?;
+
+    // Construct filter graph
+    let ass_str = ass_path.to_string_lossy().to_string();
+    let is_hdr = crate::video::is_hdr(probe_result);
+    // Use software encoder logic for filter because we are extracting a PNG,
+    // and we don't need hardware encode for a single frame usually, or it complicates things.
+    // `build_fitpad_filter_with_options` is what we want.
+    Ok(crate::video::build_fitpad_filter_with_fonts(
+        target.0,
+        target.1,
+        Some(&ass_str),
+        crate::video::HardwareEncoder::Software, // Use software mode for compatibility
+        crop_strategy,
+        is_hdr,
+        Some(&fonts_dir),
+    ))
+}
+
+// Writes the caption ASS for a preview render at `target` into `temp_dir`; returns its
+// path and the fonts directory libass needs for it
+fn preview_ass(
+    id: &str,
+    params: &crate::types::PreviewFrameParams,
+    (target_w, target_h): (u32, u32),
+    temp_dir: &Path,
+    emit: &mut impl FnMut(RpcEvent),
+) -> Result<(PathBuf, PathBuf)> {
+    validate_caption_style(
+        params.text_color.as_deref(),
+        params.highlight_word_color.as_deref(),
+        params.outline_color.as_deref(),
+        &params.style_options,
+    )?;
 
     // Build ASS file for valid segments
     // Filter segments that overlap with timestamp ??
@@ -430,23 +1998,32 @@ pub async fn generate_preview_frame(
     // It's safer to pass all segments and let ASS renderer handle the timing,
     // since we use timestamp to seek.
 
+    let (font_family, fonts_dir) = caption_font(id, params.font_name.as_deref(), emit)?;
     let style = default_ass_style(
         target_w,
         target_h,
-        params.font_name.as_deref(),
+        Some(&font_family),
         params.text_color.as_deref(),
         params.highlight_word_color.as_deref(),
         params.outline_color.as_deref(),
         params.glow_effect,
         params.position.as_deref(),
         params.font_size,
+        &params.style_options,
     );
 
-    let ass_doc = build_ass_document(
-        target_w,
+    let segments = retime_segments(
+        id,
+        &params.segments,
+        params.timing_offset_ms,
+        params.time_scale,
+        emit,
+    )?;
+    let ass_doc = build_ass_document(
+        target_w,
         target_h,
         &style,
-        &params.segments,
+        &segments,
         params.karaoke,
         params.multiline,
         params.glow_effect,
@@ -454,21 +2031,110 @@ pub async fn generate_preview_frame(
 
     let ass_path = temp_dir.join("preview.ass");
     fs::write(&ass_path, &ass_doc)?;
+    Ok((ass_path, fonts_dir))
+}
 
-    // Construct filter graph
-    let ass_str = ass_path.to_string_lossy().to_string();
-    let is_hdr = crate::video::is_hdr(&probe_result);
-    // Use software encoder logic for filter because we are extracting a PNG,
-    // and we don't need hardware encode for a single frame usually, or it complicates things.
-    // `build_fitpad_filter_with_options` is what we want.
-    let vf = crate::video::build_fitpad_filter_with_options(
-        target_w,
-        target_h,
-        Some(&ass_str),
-        crate::video::HardwareEncoder::Software, // Use software mode for compatibility
-        crop_strategy,
-        is_hdr,
+// ffmpeg arguments rendering the captions at `timestamp_ms` alone onto a transparent
+// canvas, as one RGBA PNG on stdout
+fn transparent_frame_args(
+    ass_path: &Path,
+    fonts_dir: &Path,
+    (target_w, target_h): (u32, u32),
+    timestamp_ms: u64,
+) -> Vec<String> {
+    let overlay = crate::video::caption_overlay_filter(
+        &ass_path.to_string_lossy(),
+        Some(fonts_dir),
+        timestamp_ms as f64 / 1000.0,
     );
+    [
+        "-f",
+        "lavfi",
+        "-i",
+        &crate::video::transparent_canvas(target_w, target_h, "1", 1.0),
+        "-vf",
+        &overlay,
+        "-frames:v",
+        "1",
+        "-f",
+        "image2",
+        "-c:v",
+        "png",
+        "-pix_fmt",
+        "rgba",
+        "-",
+    ]
+    .iter()
+    .map(|a| a.to_string())
+    .collect()
+}
+
+pub async fn generate_preview_frame(
+    id: &str,
+    params: crate::types::PreviewFrameParams,
+    emit: &mut impl FnMut(RpcEvent),
+) -> Result<crate::types::PreviewFrameResult> {
+    let temp_dir = job_dir(
+        params.work_dir.as_deref(),
+        format!("capslap_preview_{}_{}", id, uuid::Uuid::new_v4()),
+    )?;
+
+    // We need to probe to get video dimensions
+    // We don't have an ID for logs here, so we use a placeholder
+    let probe_id = "preview_probe";
+    let probe_result = probe(probe_id, &params.input_video, |e| {
+        // Ignore logs for preview
+        let _ = e;
+    })
+    .await?;
+
+    // Determine target dimensions
+    let (src_w, src_h) = probe_result.display_dimensions().unwrap_or((1920, 1080));
+    let (target_w, target_h) = preview_frame_size(&params, src_w, src_h)?;
+
+    if params.transparent {
+        let result = async {
+            let (ass_path, fonts_dir) =
+                preview_ass(id, &params, (target_w, target_h), &temp_dir, emit)?;
+            let output = TokioCommand::new(crate::video::get_ffmpeg_path_sync())
+                .kill_on_drop(true)
+                .args(transparent_frame_args(
+                    &ass_path,
+                    &fonts_dir,
+                    (target_w, target_h),
+                    params.timestamp_ms,
+                ))
+                .output()
+                .await
+                .map_err(|e| anyhow!("Failed to run ffmpeg: {}", e))?;
+            if !output.status.success() {
+                return Err(anyhow!(crate::video::with_stderr_tail(
+                    "FFmpeg preview failed",
+                    &crate::video::stderr_tail(&output.stderr)
+                )));
+            }
+            Ok(output.stdout)
+        }
+        .await;
+        let _ = fs::remove_dir_all(&temp_dir);
+
+        use base64::{engine::general_purpose, Engine as _};
+        return Ok(crate::types::PreviewFrameResult {
+            image_data: format!(
+                "data:image/png;base64,{}",
+                general_purpose::STANDARD.encode(result?)
+            ),
+        });
+    }
+
+    let vf = preview_filter(
+        id,
+        &params,
+        (target_w, target_h),
+        &probe_result,
+        &temp_dir,
+        emit,
+    )?;
 
     // Extract frame using FFmpeg
     let ffmpeg_path = crate::video::get_ffmpeg_path_sync();
@@ -490,6 +2156,7 @@ pub async fn generate_preview_frame(
     // Wait, if we use fitpad filter, it modifies timestamps/frames? No.
 
     let output = TokioCommand::new(&ffmpeg_path)
+        .kill_on_drop(true)
         .arg("-ss")
         .arg(time_sec.to_string())
         .arg("-i")
@@ -508,8 +2175,10 @@ pub async fn generate_preview_frame(
         .map_err(|e| anyhow!("Failed to run ffmpeg: {}", e))?;
 
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow!("FFmpeg preview failed: {}", stderr));
+        return Err(anyhow!(crate::video::with_stderr_tail(
+            "FFmpeg preview failed",
+            &crate::video::stderr_tail(&output.stderr)
+        )));
     }
 
     use base64::{engine::general_purpose, Engine as _};
@@ -524,16 +2193,166 @@ pub async fn generate_preview_frame(
     })
 }
 
+const CLIP_DEFAULT_MS: u64 = 2000;
+const CLIP_MAX_MS: u64 = 10_000;
+const CLIP_DEFAULT_FPS: u32 = 12;
+const CLIP_MAX_SIDE: u32 = 480; // Longest side of a clip without explicit width/height
+
+// Clip window centered on `timestamp_ms`, shifted right rather than starting before 0
+fn clip_window(timestamp_ms: u64, duration_ms: Option<u64>) -> (u64, u64) {
+    let duration = duration_ms
+        .unwrap_or(CLIP_DEFAULT_MS)
+        .clamp(100, CLIP_MAX_MS);
+    (timestamp_ms.saturating_sub(duration / 2), duration)
+}
+
+// Shrink a canvas to fit CLIP_MAX_SIDE, keeping the aspect ratio and even dimensions
+fn clip_canvas(w: u32, h: u32) -> (u32, u32) {
+    let longest = w.max(h);
+    if longest <= CLIP_MAX_SIDE {
+        return (w, h);
+    }
+    let scale = |d: u32| {
+        crate::video::round_even((d as u64 * CLIP_MAX_SIDE as u64 / longest as u64) as u32)
+    };
+    (scale(w), scale(h))
+}
+
+// Filter tail and output args for the animated format, plus its MIME type
+fn clip_encoding(
+    format: Option<&str>,
+    fps: u32,
+) -> Result<(String, Vec<&'static str>, &'static str)> {
+    // Timestamps stay absolute through the caption filter (-copyts), then restart at 0
+    let timing = format!("fps={},setpts=PTS-STARTPTS", fps);
+    match format.unwrap_or("webp") {
+        "webp" => Ok((
+            timing,
+            vec![
+                "-c:v",
+                "libwebp",
+                "-lossless",
+                "0",
+                "-q:v",
+                "70",
+                "-loop",
+                "0",
+                "-f",
+                "webp",
+            ],
+            "image/webp",
+        )),
+        "gif" => Ok((
+            // One palette per clip keeps text edges clean
+            format!("{},split[a][b];[a]palettegen[p];[b][p]paletteuse", timing),
+            vec!["-loop", "0", "-f", "gif"],
+            "image/gif",
+        )),
+        other => Err(anyhow!(
+            "Unsupported clip format '{}': use webp or gif",
+            other
+        )),
+    }
+}
+
+/// Render a short animated clip around `timestamp_ms` with captions burned in,
+/// so the editor can show karaoke motion without a full export
+pub async fn generate_preview_clip(
+    id: &str,
+    params: crate::types::PreviewClipParams,
+    emit: &mut impl FnMut(RpcEvent),
+) -> Result<crate::types::PreviewClipResult> {
+    if params.frame.transparent {
+        return Err(anyhow!(
+            "Transparent rendering is only supported for single preview frames"
+        ));
+    }
+    let fps = params.fps.unwrap_or(CLIP_DEFAULT_FPS).clamp(1, 30);
+    let (filter_tail, output_args, mime) = clip_encoding(params.clip_format.as_deref(), fps)?;
+    let frame = &params.frame;
+    let (start_ms, duration_ms) = clip_window(frame.timestamp_ms, params.duration_ms);
+
+    let probe_result = probe(id, &frame.input_video, |_| {}).await?;
+    let (src_w, src_h) = probe_result.display_dimensions().unwrap_or((1920, 1080));
+    let (target_w, target_h) = match (frame.width, frame.height) {
+        (Some(_), Some(_)) => preview_frame_size(frame, src_w, src_h)?,
+        _ => {
+            let (w, h) = preview_frame_size(frame, src_w, src_h)?;
+            clip_canvas(w, h)
+        }
+    };
+
+    let temp_dir = job_dir(
+        frame.work_dir.as_deref(),
+        format!("capslap_clip_{}_{}", id, uuid::Uuid::new_v4()),
+    )?;
+    let result = async {
+        let vf = preview_filter(
+            id,
+            frame,
+            (target_w, target_h),
+            &probe_result,
+            &temp_dir,
+            emit,
+        )?;
+        emit(RpcEvent::Progress {
+            id: id.into(),
+            status: "Rendering preview clip…".into(),
+            progress: 0.5,
+        });
+        let output = TokioCommand::new(crate::video::get_ffmpeg_path_sync())
+            .kill_on_drop(true)
+            .arg("-ss")
+            .arg(format!("{:.3}", start_ms as f64 / 1000.0))
+            .arg("-t")
+            .arg(format!("{:.3}", duration_ms as f64 / 1000.0))
+            .arg("-copyts")
+            .arg("-i")
+            .arg(&frame.input_video)
+            .arg("-an")
+            .arg("-vf")
+            .arg(format!("{},{}", vf, filter_tail))
+            .args(&output_args)
+            .arg("-")
+            .output()
+            .await
+            .map_err(|e| anyhow!("Failed to run ffmpeg: {}", e))?;
+        if !output.status.success() {
+            return Err(anyhow!(crate::video::with_stderr_tail(
+                "FFmpeg preview clip failed",
+                &crate::video::stderr_tail(&output.stderr)
+            )));
+        }
+        Ok(output.stdout)
+    }
+    .await;
+    let _ = fs::remove_dir_all(&temp_dir);
+
+    use base64::{engine::general_purpose, Engine as _};
+    Ok(crate::types::PreviewClipResult {
+        image_data: format!(
+            "data:{};base64,{}",
+            mime,
+            general_purpose::STANDARD.encode(result?)
+        ),
+        start_ms,
+        duration_ms,
+        width: target_w,
+        height: target_h,
+    })
+}
+
 #[cfg(test)]
 #[cfg(test)]
 #[cfg(test)]
 mod tests_persistence {
     use super::*;
+    use crate::types::StylePreset;
     use std::fs;
     use tempfile::NamedTempFile;
 
-    #[test]
-    fn test_save_and_load_captions() -> Result<()> {
+    #[tokio::test]
+    async fn test_save_and_load_captions() -> Result<()> {
         let video_file = NamedTempFile::new()?;
         let video_path = video_file.path().to_string_lossy().to_string();
 
@@ -543,12 +2362,16 @@ mod tests_persistence {
                 end_ms: 2000,
                 text: "Hello world".to_string(),
                 words: vec![],
+                no_speech_prob: None,
+                position: None,
             },
             CaptionSegment {
                 start_ms: 2500,
                 end_ms: 3500,
                 text: "Testing save load".to_string(),
                 words: vec![],
+                no_speech_prob: None,
+                position: None,
             },
         ];
 
@@ -556,7 +2379,8 @@ mod tests_persistence {
         save_captions(SaveCaptionsParams {
             video_path: video_path.clone(),
             segments: segments.clone(),
-        })?;
+        })
+        .await?;
 
         // Check file exists
         let json_path = format!("{}.capslap.json", video_path);
@@ -578,6 +2402,137 @@ mod tests_persistence {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_save_captions_keeps_a_backup() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let video_path = dir.path().join("clip.mp4").to_string_lossy().to_string();
+        let save = |text: &str| {
+            save_captions(SaveCaptionsParams {
+                video_path: video_path.clone(),
+                segments: vec![CaptionSegment {
+                    start_ms: 0,
+                    end_ms: 1000,
+                    text: text.to_string(),
+                    words: vec![],
+                    no_speech_prob: Some(0.25),
+                    position: None,
+                }],
+            })
+        };
+        let json_path = format!("{}.capslap.json", video_path);
+        let backup_path = format!("{}.bak", json_path);
+
+        save("first").await?;
+        assert!(!Path::new(&backup_path).exists());
+        save("second").await?;
+        let backup: Vec<CaptionSegment> = serde_json::from_str(&fs::read_to_string(&backup_path)?)?;
+        assert_eq!(backup[0].text, "first");
+        let saved = load_captions(LoadCaptionsParams {
+            video_path: video_path.clone(),
+        })?;
+        assert_eq!(saved.segments.unwrap()[0].text, "second");
+
+        // No temp files are left behind
+        let names: Vec<String> = fs::read_dir(dir.path())?
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names.len(), 2, "{:?}", names);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_load_captions_falls_back_to_subtitle_sidecar() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let video = dir.path().join("clip.mp4");
+        fs::write(&video, b"")?;
+        let video_path = video.to_string_lossy().to_string();
+        let load = || {
+            load_captions(LoadCaptionsParams {
+                video_path: video_path.clone(),
+            })
+        };
+
+        let none = load()?;
+        assert!(none.segments.is_none() && none.source.is_none());
+
+        fs::write(
+            dir.path().join("clip.vtt"),
+            "WEBVTT\n\n00:00:01.000 --> 00:00:02.000\nFrom vtt\n",
+        )?;
+        fs::write(
+            dir.path().join("clip.srt"),
+            "1\n00:00:01,000 --> 00:00:02,500\nFrom srt\n",
+        )?;
+        let srt = load()?;
+        assert_eq!(srt.source.as_deref(), Some("srt"));
+        assert_eq!(srt.segments.unwrap()[0].text, "From srt");
+
+        // Our own JSON wins over any sidecar
+        save_captions(SaveCaptionsParams {
+            video_path: video_path.clone(),
+            segments: Vec::new(),
+        })
+        .await?;
+        let saved = load()?;
+        assert_eq!(saved.source.as_deref(), Some("capslap"));
+        assert_eq!(saved.path, Some(format!("{}.capslap.json", video_path)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_load_and_list_style_presets() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let preset = StylePreset {
+            font_name: Some("Montserrat Black".into()),
+            font_size: Some(72),
+            text_color: Some("#ffffff".into()),
+            highlight_word_color: Some("#ffd700".into()),
+            glow_effect: true,
+            position: Some("center".into()),
+            crop_strategy: Some("fit".into()),
+            style_options: CaptionStyleOptions {
+                max_words_per_cue: Some(3),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        save_style_preset_in(
+            dir.path(),
+            SaveStylePresetParams {
+                name: "Bold Yellow".into(),
+                preset: preset.clone(),
+            },
+        )?;
+
+        let loaded = load_style_preset_in(
+            dir.path(),
+            LoadStylePresetParams {
+                name: "Bold Yellow".into(),
+            },
+        )?;
+        assert_eq!(
+            serde_json::to_value(loaded.preset.unwrap())?,
+            serde_json::to_value(&preset)?
+        );
+
+        let missing = load_style_preset_in(
+            dir.path(),
+            LoadStylePresetParams {
+                name: "nope".into(),
+            },
+        )?;
+        assert!(missing.preset.is_none());
+        assert_eq!(
+            list_style_presets_in(dir.path())?.names,
+            vec!["Bold Yellow"]
+        );
+
+        // Names that could escape the presets directory are rejected
+        assert!(preset_path(dir.path(), "../evil").is_err());
+        assert!(preset_path(dir.path(), "").is_err());
+        Ok(())
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -599,8 +2554,37 @@ async fn optimized_multi_format_encode(
     position: Option<String>,
     output_size: Option<String>,
     crop_strategy: Option<String>,
+    style_options: &CaptionStyleOptions,
+    encode_options: &EncodeOptions,
+    overlay: Option<crate::video::AlphaCodec>,
     emit: &mut impl FnMut(RpcEvent),
 ) -> Result<Vec<CaptionedVideoResult>> {
+    validate_encode_options(encode_options)?;
+    validate_caption_style(
+        text_color.as_deref(),
+        highlight_word_color.as_deref(),
+        outline_color.as_deref(),
+        style_options,
+    )?;
+    // A trimmed encode starts at the trim point: cues move onto the clip's timeline
+    // and every duration-based calculation sees the clip's length
+    let (trimmed, trimmed_probe);
+    let (segments, probe_result) = if encode_options.is_trimmed() {
+        let (start, end) = (encode_options.trim_start_ms, encode_options.trim_end_ms);
+        trimmed_probe = trim_probe(probe_result, start, end)?;
+        trimmed = trim_segments(segments, start.unwrap_or(0), end);
+        (&trimmed[..], &trimmed_probe)
+    } else {
+        (segments, probe_result)
+    };
+    // Captions follow the picture past a frozen intro
+    let held;
+    let segments = if encode_options.intro_hold_ms > 0 {
+        held = offset_segments(segments, encode_options.intro_hold_ms as i64);
+        &held[..]
+    } else {
+        segments
+    };
     // Fail fast if libass is not available (required for burning subtitles)
     if !crate::video::is_libass_available().await {
         return Err(anyhow!("The installed FFmpeg version does not support burning subtitles (missing 'ass' filter). Please install a version of FFmpeg with libass support (e.g. via homebrew: 'brew install ffmpeg')."));
@@ -623,6 +2607,14 @@ async fn optimized_multi_format_encode(
         .to_string_lossy()
         .to_string();
 
+    let (font_family, fonts_dir) = caption_font(id, font_name.as_deref(), emit)?;
+    // An overlay covers the whole (held) video, or at least every caption
+    let hold_ms = encode_options.intro_hold_ms + encode_options.outro_hold_ms;
+    let overlay_duration = probe_result
+        .duration
+        .unwrap_or_else(|| segments.iter().map(|s| s.end_ms).max().unwrap_or(0) as f64 / 1000.0)
+        + hold_ms as f64 / 1000.0;
+
     // Pre-generate shared ASS files for each format (avoiding redundant subtitle processing)
     let mut format_ass_files = Vec::new();
     for format in export_formats {
@@ -631,54 +2623,27 @@ async fn optimized_multi_format_encode(
             message: format!("Processing format loop for: {}", format),
         });
         let target_ar = crate::video::parse_target_ar(format)?;
-        let src_w = probe_result.width.unwrap_or(1920) as u32;
-        let src_h = probe_result.height.unwrap_or(1080) as u32;
+        let (src_w, src_h) = probe_result.display_dimensions().unwrap_or((1920, 1080));
 
         // Determine target dimensions based on output_size or aspect ratio
-        let (target_w, target_h) = if let Some(size) = &output_size {
-            let (base_w, base_h) = crate::video::ar_wh(target_ar);
-            let ar = base_w as f64 / base_h as f64;
-
-            match size.as_str() {
-                "1080p" => {
-                    // Logic:
-                    // If Landscape (w > h): H=1080, W=1080*AR
-                    // If Portrait (h > w):  W=1080, H=1080/AR
-                    // If Square: 1080x1080
-                    if base_w > base_h {
-                        let w = (1080.0 * ar).round() as u32;
-                        (crate::video::round_even(w), 1080)
-                    } else {
-                        let h = (1080.0 / ar).round() as u32;
-                        (1080, crate::video::round_even(h))
-                    }
-                }
-                "720p" => {
-                    if base_w > base_h {
-                        let w = (720.0 * ar).round() as u32;
-                        (crate::video::round_even(w), 720)
-                    } else {
-                        let h = (720.0 / ar).round() as u32;
-                        (720, crate::video::round_even(h))
-                    }
-                }
-                "4k" | "2160p" => {
-                    // 4K usually refers to 3840x2160 (UHD)
-                    // Landscape: H=2160
-                    // Portrait: W=2160
-                    if base_w > base_h {
-                        let w = (2160.0 * ar).round() as u32;
-                        (crate::video::round_even(w), 2160)
-                    } else {
-                        let h = (2160.0 / ar).round() as u32;
-                        (2160, crate::video::round_even(h))
-                    }
-                }
-                _ => crate::video::canvas_no_downscale(src_w, src_h, target_ar),
+        let (mut target_w, mut target_h) =
+            crate::video::export_dimensions(target_ar, output_size.as_deref(), src_w, src_h);
+
+        // A size preset above the source resolution only adds blur and bytes
+        if !encode_options.allow_upscale && probe_result.display_dimensions().is_some() {
+            if let Some((w, h)) =
+                crate::video::clamp_to_source((target_w, target_h), (src_w, src_h))
+            {
+                emit(RpcEvent::Log {
+                    id: id.into(),
+                    message: format!(
+                        "{}: {}x{} would upscale the {}x{} source, encoding at {}x{} instead (set allowUpscale to override)",
+                        format, target_w, target_h, src_w, src_h, w, h
+                    ),
+                });
+                (target_w, target_h) = (w, h);
             }
-        } else {
-            crate::video::canvas_no_downscale(src_w, src_h, target_ar)
-        };
+        }
 
         // Build ASS subtitle file optimized for this format
         emit(RpcEvent::Log {
@@ -688,13 +2653,14 @@ async fn optimized_multi_format_encode(
         let style = default_ass_style(
             target_w,
             target_h,
-            font_name.as_deref(),
+            Some(&font_family),
             text_color.as_deref(),
             highlight_word_color.as_deref(),
             outline_color.as_deref(),
             glow_effect,
             position.as_deref(),
             font_size,
+            style_options,
         );
         emit(RpcEvent::Log {
             id: id.into(),
@@ -726,8 +2692,18 @@ async fn optimized_multi_format_encode(
         format_ass_files.push((format.clone(), ass_path, target_w, target_h));
     }
 
-    // Process formats with limited concurrency (2 at a time for optimal resource usage)
-    let semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(2));
+    // Encode formats concurrently, as many at a time as the machine has cores for
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let parallel = format_parallelism(cpus, format_ass_files.len());
+    emit(RpcEvent::Log {
+        id: id.into(),
+        message: format!(
+            "Encoding {} formats, {} at a time",
+            format_ass_files.len(),
+            parallel
+        ),
+    });
+    let semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(parallel));
     let mut tasks = tokio::task::JoinSet::new();
     let (tx, mut rx) = mpsc::unbounded_channel::<InternalUpdate>();
 
@@ -750,17 +2726,53 @@ async fn optimized_multi_format_encode(
         let task_id = format!("{}_{}", id, idx);
         let input_path = input_path.clone();
         let crop_strat = crop_strategy.clone().unwrap_or_else(|| "fit".to_string());
+        let fonts_dir = fonts_dir.clone();
+        let encode_options = encode_options.clone();
         let tx = tx.clone();
+        let ffmpeg_path = crate::whisper::ffmpeg_override();
 
-        tasks.spawn(async move {
+        tasks.spawn(crate::whisper::with_ffmpeg_path(ffmpeg_path, async move {
             // Acquire semaphore permit for bounded concurrency
             let _permit = semaphore.acquire().await.unwrap();
 
             let safe_format = format.replace(':', "x");
+            if let Some(codec) = overlay {
+                let overlay_path = format!(
+                    "{}_{}_overlay.{}",
+                    input_path,
+                    safe_format,
+                    codec.extension()
+                );
+                let commands = overlay_encode(
+                    &task_id,
+                    &ass_path,
+                    &overlay_path,
+                    (target_w, target_h),
+                    &fonts_dir,
+                    &probe_result,
+                    overlay_duration,
+                    codec,
+                    encode_options.dry_run,
+                    tx,
+                    idx,
+                )
+                .await?;
+                return Ok((
+                    idx,
+                    CaptionedVideoResult {
+                        format,
+                        raw_video: "".to_string(),
+                        captioned_video: overlay_path,
+                        width: target_w,
+                        height: target_h,
+                        commands,
+                    },
+                ));
+            }
             let captioned_path = format!("{}_{}.mp4", input_path, safe_format);
 
             // Single-pass format conversion + caption burning with hardware acceleration
-            optimized_single_format_encode(
+            let commands = optimized_single_format_encode(
                 &task_id,
                 &input_video,
                 &ass_path,
@@ -768,20 +2780,26 @@ async fn optimized_multi_format_encode(
                 target_w,
                 target_h,
                 &crop_strat,
+                Some(&fonts_dir),
                 &probe_result,
+                &encode_options,
                 tx,
                 idx,
             )
             .await?;
 
-            Ok::<CaptionedVideoResult, anyhow::Error>(CaptionedVideoResult {
-                format,
-                raw_video: "".to_string(),
-                captioned_video: captioned_path,
-                width: target_w,
-                height: target_h,
-            })
-        });
+            Ok::<_, anyhow::Error>((
+                idx,
+                CaptionedVideoResult {
+                    format,
+                    raw_video: "".to_string(),
+                    captioned_video: captioned_path,
+                    width: target_w,
+                    height: target_h,
+                    commands,
+                },
+            ))
+        }));
     }
 
     // Drop original sender so receiver knows when all senders are done (after tasks finish)
@@ -795,7 +2813,17 @@ async fn optimized_multi_format_encode(
         tokio::select! {
             Some(res) = tasks.join_next() => {
                 match res {
-                    Ok(Ok(result)) => captioned_videos.push(result),
+                    Ok(Ok(result)) => {
+                        captioned_videos.push(result);
+                        emit(RpcEvent::Log {
+                            id: id.to_string(),
+                            message: format!(
+                                "Finished {} of {} formats",
+                                captioned_videos.len(),
+                                total_tasks
+                            ),
+                        });
+                    }
                     Ok(Err(e)) => return Err(e), // Task failed
                     Err(e) => return Err(anyhow!("Task join error: {}", e)),
                 }
@@ -810,7 +2838,15 @@ async fn optimized_multi_format_encode(
 
                         emit(RpcEvent::Progress {
                             id: id.to_string(),
-                            status: format!("Exporting... ({:.0}%)", avg_progress * 100.0),
+                            status: if total_tasks > 1 {
+                                format!(
+                                    "Exporting {} formats... ({:.0}%)",
+                                    total_tasks,
+                                    avg_progress * 100.0
+                                )
+                            } else {
+                                format!("Exporting... ({:.0}%)", avg_progress * 100.0)
+                            },
                             progress: avg_progress,
                         });
                     },
@@ -831,8 +2867,238 @@ async fn optimized_multi_format_encode(
         progress: 1.0,
     });
 
-    // Sort results to match input order if needed, but for now just returning collected results
-    Ok(captioned_videos)
+    // Formats finish in any order; report them in the order they were requested
+    captioned_videos.sort_by_key(|(idx, _)| *idx);
+    Ok(captioned_videos.into_iter().map(|(_, v)| v).collect())
+}
+
+// Formats encoded at once: each ffmpeg already uses several threads, so one per four
+// cores (2 to 4, fewer on a one- or two-core machine), never more than there are formats
+fn format_parallelism(cpus: usize, formats: usize) -> usize {
+    (cpus / 4).clamp(2, 4).min(cpus).min(formats).max(1)
+}
+
+// Subtitle codec and output extension for a soft-subtitle copy of `input_video`
+fn mux_target(input_video: &str) -> (&'static str, &'static str) {
+    let ext = Path::new(input_video)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match ext.as_deref() {
+        Some("mkv") => ("srt", "mkv"),
+        Some("webm") => ("webvtt", "webm"),
+        Some("mov") => ("mov_text", "mov"),
+        Some("m4v") => ("mov_text", "m4v"),
+        _ => ("mov_text", "mp4"),
+    }
+}
+
+/// Copy the video and audio untouched and add the captions as a selectable subtitle
+/// track. No re-encode, so styling, reframing and export formats don't apply.
+async fn mux_captions(
+    id: &str,
+    input_video: &str,
+    segments: &[CaptionSegment],
+    probe_result: &crate::video::ProbeResult,
+    encode_options: &EncodeOptions,
+    temp_dir: &Path,
+    emit: &mut impl FnMut(RpcEvent),
+) -> Result<CaptionedVideoResult> {
+    let (subtitle_codec, ext) = mux_target(input_video);
+    let srt_path = temp_dir.join(format!("captions_{}.srt", id));
+    fs::write(&srt_path, crate::subtitles::to_srt(segments))?;
+    let output_path = format!(
+        "{}_subtitled.{}",
+        Path::new(input_video).with_extension("").to_string_lossy(),
+        ext
+    );
+
+    emit(RpcEvent::Progress {
+        id: id.into(),
+        status: "Adding subtitle track...".into(),
+        progress: 0.1,
+    });
+    emit(RpcEvent::Log {
+        id: id.into(),
+        message: format!(
+            "Muxing {} cues as a {} track into {} (video copied; styling and export formats are not applied)",
+            segments.len(),
+            subtitle_codec,
+            output_path
+        ),
+    });
+
+    let ffmpeg_path = crate::whisper::find_ffmpeg_binary()
+        .await
+        .map_err(|e| anyhow!("FFmpeg not found: {}", e))?;
+    let mut cmd = TokioCommand::new(&ffmpeg_path);
+    cmd.kill_on_drop(true)
+        .arg("-y")
+        .arg("-i")
+        .arg(input_video)
+        .arg("-i")
+        .arg(&srt_path)
+        .args(["-map", "0:v:0", "-map", "0:a?", "-map", "1:0"])
+        .args(["-c", "copy", "-c:s", subtitle_codec])
+        .args(["-metadata:s:s:0", "title=Captions"])
+        .args(crate::video::metadata_args(&encode_options.metadata))
+        .arg(&output_path);
+    let (width, height) = probe_result.display_dimensions().unwrap_or((0, 0));
+    if encode_options.dry_run {
+        let command = crate::logging::command_argv(&cmd);
+        emit(RpcEvent::Log {
+            id: id.into(),
+            message: format!("Dry run, not running: {}", command.join(" ")),
+        });
+        return Ok(CaptionedVideoResult {
+            format: "original".to_string(),
+            raw_video: "".to_string(),
+            captioned_video: output_path,
+            width,
+            height,
+            commands: vec![command],
+        });
+    }
+    let output = cmd
+        .output()
+        .await
+        .map_err(|e| anyhow!("Failed to run ffmpeg: {}", e))?;
+    if !output.status.success() {
+        let tail = crate::video::stderr_tail(&output.stderr);
+        return Err(crate::video::ffmpeg_failure(
+            id,
+            "FFmpeg subtitle mux failed",
+            &tail,
+            emit,
+        ));
+    }
+
+    emit(RpcEvent::Progress {
+        id: id.into(),
+        status: "Export complete".into(),
+        progress: 1.0,
+    });
+    Ok(CaptionedVideoResult {
+        format: "original".to_string(),
+        raw_video: "".to_string(),
+        captioned_video: output_path,
+        width,
+        height,
+        commands: Vec::new(),
+    })
+}
+
+// ffmpeg arguments rendering the captions of `ass_path` alone over transparency for
+// `duration_sec`, at the source frame rate so the overlay lines up on an edit timeline
+fn overlay_encode_args(
+    ass_path: &Path,
+    fonts_dir: &Path,
+    output_path: &str,
+    (target_w, target_h): (u32, u32),
+    fps: Option<f64>,
+    duration_sec: f64,
+    codec: crate::video::AlphaCodec,
+) -> Vec<String> {
+    let rate = fps
+        .and_then(|fps| crate::timecode::FrameRate::from_fps(fps).ok())
+        .map_or_else(|| "30".to_string(), |r| format!("{}/{}", r.num, r.den));
+    let mut args: Vec<String> = [
+        "-y",
+        "-f",
+        "lavfi",
+        "-i",
+        &crate::video::transparent_canvas(target_w, target_h, &rate, duration_sec),
+        "-progress",
+        "pipe:1",
+        "-vf",
+        &crate::video::caption_overlay_filter(&ass_path.to_string_lossy(), Some(fonts_dir), 0.0),
+    ]
+    .iter()
+    .map(|a| a.to_string())
+    .collect();
+    args.extend(codec.encoder_args().iter().map(|a| a.to_string()));
+    args.extend(["-an".to_string(), output_path.to_string()]);
+    args
+}
+
+/// Caption-only export: the styled captions over a transparent canvas, encoded with
+/// an alpha codec instead of being burned into the video
+#[allow(clippy::too_many_arguments)]
+async fn overlay_encode(
+    id: &str,
+    ass_path: &Path,
+    output_path: &str,
+    target: (u32, u32),
+    fonts_dir: &Path,
+    probe_result: &crate::video::ProbeResult,
+    duration_sec: f64,
+    codec: crate::video::AlphaCodec,
+    dry_run: bool,
+    tx: mpsc::UnboundedSender<InternalUpdate>,
+    index: usize,
+) -> Result<Vec<Vec<String>>> {
+    let ffmpeg_path = crate::whisper::find_ffmpeg_binary()
+        .await
+        .map_err(|e| anyhow!("FFmpeg not found: {}", e))?;
+    let mut cmd = TokioCommand::new(&ffmpeg_path);
+    cmd.kill_on_drop(true).args(overlay_encode_args(
+        ass_path,
+        fonts_dir,
+        output_path,
+        target,
+        probe_result.fps,
+        duration_sec,
+        codec,
+    ));
+    cmd.stdout(std::process::Stdio::piped());
+    cmd.stderr(std::process::Stdio::piped());
+
+    if dry_run {
+        let command = crate::logging::command_argv(&cmd);
+        let _ = tx.send(InternalUpdate::Event(RpcEvent::Log {
+            id: id.into(),
+            message: format!("Dry run, not running: {}", command.join(" ")),
+        }));
+        return Ok(vec![command]);
+    }
+
+    crate::logging::log_command(&cmd);
+    let mut child = cmd.spawn()?;
+    let stderr_tail = crate::video::collect_stderr_tail(&mut child);
+    let total_us = (duration_sec * 1_000_000.0) as u64;
+    if let Some(stdout) = child.stdout.take() {
+        let mut lines = tokio::io::BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let Some(us) = line
+                .strip_prefix("out_time_us=")
+                .and_then(|us| us.trim().parse::<u64>().ok())
+            else {
+                continue;
+            };
+            if total_us > 0 {
+                let _ = tx.send(InternalUpdate::Progress {
+                    index,
+                    value: (us as f64 / total_us as f64).min(0.99) as f32,
+                });
+            }
+        }
+    }
+
+    if !child.wait().await?.success() {
+        let tail = stderr_tail.await.unwrap_or_default();
+        return Err(crate::video::ffmpeg_failure(
+            id,
+            format!(
+                "FFmpeg failed to encode the caption overlay for {} ({:?})",
+                id, codec
+            ),
+            &tail,
+            &mut |event| {
+                let _ = tx.send(InternalUpdate::Event(event));
+            },
+        ));
+    }
+    Ok(Vec::new())
 }
 
 /// Optimized single format encoding with hardware acceleration and modern FFmpeg flags
@@ -845,12 +3111,20 @@ async fn optimized_single_format_encode(
     target_w: u32,
     target_h: u32,
     crop_strategy: &str,
+    fonts_dir: Option<&Path>,
     probe_result: &crate::video::ProbeResult,
+    encode_options: &EncodeOptions,
     tx: mpsc::UnboundedSender<InternalUpdate>,
     index: usize,
-) -> Result<()> {
-    // Determine the best available hardware encoder for H.264 first (for filter optimization)
-    let hardware_encoder = crate::video::get_best_hardware_encoder().await;
+) -> Result<Vec<Vec<String>>> {
+    // Determine the best available hardware encoder for H.264 first (for filter optimization).
+    // Two-pass rate control is a libx264 feature, and hardware encoders aren't reproducible,
+    // so both run in software.
+    let hardware_encoder = if encode_options.two_pass || encode_options.bitexact {
+        crate::video::HardwareEncoder::Software
+    } else {
+        crate::video::get_best_hardware_encoder().await
+    };
 
     // Try with hardware encoder first, then fallback to software if it fails
     let result = try_encode_with_encoder(
@@ -861,8 +3135,10 @@ async fn optimized_single_format_encode(
         target_w,
         target_h,
         crop_strategy,
+        fonts_dir,
         probe_result,
         hardware_encoder,
+        encode_options,
         tx.clone(),
         index,
     )
@@ -878,8 +3154,10 @@ async fn optimized_single_format_encode(
             target_w,
             target_h,
             crop_strategy,
+            fonts_dir,
             probe_result,
             crate::video::HardwareEncoder::Software,
+            encode_options,
             tx,
             index,
         )
@@ -899,26 +3177,56 @@ async fn try_encode_with_encoder(
     target_w: u32,
     target_h: u32,
     crop_strategy: &str,
+    fonts_dir: Option<&Path>,
     probe_result: &crate::video::ProbeResult,
     hardware_encoder: crate::video::HardwareEncoder,
+    encode_options: &EncodeOptions,
     tx: mpsc::UnboundedSender<InternalUpdate>,
     index: usize,
-) -> Result<()> {
+) -> Result<Vec<Vec<String>>> {
     // Build optimized filter with format conversion AND subtitles in one pass
     // Use encoder-specific format optimization (NV12 for VideoToolbox/NVENC, yuv420p for software)
     let ass = ass_path.to_string_lossy().to_string();
     let is_hdr = crate::video::is_hdr(probe_result);
-    let vf = crate::video::build_fitpad_filter_with_options(
+    let hold_ms = encode_options.intro_hold_ms + encode_options.outro_hold_ms;
+    let progress_bar = match (&encode_options.progress_bar, probe_result.duration) {
+        (Some(spec), Some(duration)) => {
+            let duration = duration + hold_ms as f64 / 1000.0;
+            let color = spec
+                .color
+                .as_deref()
+                .map_or(Some(HexColor::WHITE), parse_hex_color);
+            color.map(|c| (spec, c.to_ffmpeg(), duration))
+        }
+        _ => None,
+    };
+    let vf = crate::video::build_fitpad_filter_with_effects(
         target_w,
         target_h,
         Some(&ass),
         hardware_encoder,
         crop_strategy,
         is_hdr,
+        fonts_dir,
+        &crate::video::FrameEffects {
+            intro_hold_ms: encode_options.intro_hold_ms,
+            outro_hold_ms: encode_options.outro_hold_ms,
+            watermark: encode_options.watermark.as_ref(),
+            progress_bar,
+        },
     );
 
     // Determine optimal audio codec and settings
-    let (audio_codec, audio_args) = crate::video::determine_audio_codec(Some(probe_result));
+    let (mut audio_codec, mut audio_args) = crate::video::determine_audio_codec(Some(probe_result));
+    let hold_af =
+        crate::video::hold_audio_filter(encode_options.intro_hold_ms, encode_options.outro_hold_ms);
+    if hold_af.is_some() && audio_codec == "copy" {
+        // Padding the audio means re-encoding it
+        (audio_codec, audio_args) = ("aac", Vec::new());
+    }
+
+    let trim_args =
+        crate::video::trim_input_args(encode_options.trim_start_ms, encode_options.trim_end_ms);
 
     // Calculate GOP size based on original video FPS for better seeking
     let gop_size = if let Some(fps) = probe_result.fps {
@@ -927,168 +3235,455 @@ async fn try_encode_with_encoder(
         48 // Default for 24fps content
     };
     let gop_size_str = gop_size.to_string();
+    // A target size turns into an average bitrate for the two-pass encode
+    let sized_options;
+    let encode_options = match two_pass_bitrate_kbps(encode_options, probe_result)? {
+        Some(kbps) => {
+            sized_options = EncodeOptions {
+                video_bitrate: Some(format!("{}k", kbps)),
+                ..encode_options.clone()
+            };
+            &sized_options
+        }
+        None => encode_options,
+    };
+    let quality_args = video_quality_args(hardware_encoder, encode_options, target_w, target_h);
+    let metadata_args = output_metadata_args(encode_options);
+    // x264's output depends on the thread count, so a reproducible encode pins it
+    let threads = if encode_options.bitexact { "1" } else { "0" };
 
     // Resolve FFmpeg path using unified async detector (bundled > project > system)
     let ffmpeg_path = crate::whisper::find_ffmpeg_binary()
         .await
         .map_err(|e| anyhow!("FFmpeg not found: {}", e))?;
 
-    let mut cmd = TokioCommand::new(&ffmpeg_path);
-    cmd.kill_on_drop(true);
+    let duration_us = probe_result
+        .duration
+        .map(|s| (s * 1_000_000.0) as u64 + hold_ms * 1000);
+    let pass_count: u8 = if encode_options.two_pass { 2 } else { 1 };
+    let passlog = ass_path
+        .with_file_name(format!("x264_{}_{}", id, index))
+        .to_string_lossy()
+        .to_string();
 
-    let duration_us = probe_result.duration.map(|s| (s * 1_000_000.0) as u64);
+    let mut dry_run_commands = Vec::new();
+    for pass in 1..=pass_count {
+        let pass_str = pass.to_string();
+        // Pass 1 of a two-pass encode only writes the rate log; its output is discarded
+        let analysis_pass = pass_count == 2 && pass == 1;
+        let mut cmd = TokioCommand::new(&ffmpeg_path);
+        cmd.kill_on_drop(true);
+
+        cmd.args({
+            let mut args = vec!["-y"];
+            args.extend(trim_args.iter().map(String::as_str));
+            args.extend_from_slice(&[
+                "-i",
+                input_video,
+                "-progress",
+                "pipe:1", // Enable progress reporting
+                "-vf",
+                &vf,
+                "-fps_mode",
+                "passthrough", // Modern replacement for -vsync
+                "-threads",
+                threads, // All available CPU cores, unless bitexact
+                "-map",
+                "0:v:0", // Map first video stream
+                "-map",
+                "0:a?", // Map audio if present (optional)
+            ]);
+
+            // Add hardware-optimized encoding parameters
+            match hardware_encoder {
+                crate::video::HardwareEncoder::VideoToolbox => {
+                    // Quality comes from -b:v (see video_quality_args)
+                    args.extend_from_slice(&["-c:v", "h264_videotoolbox"]);
+                    args.extend(quality_args.iter().map(String::as_str));
+                    args.extend_from_slice(&[
+                        "-allow_sw",
+                        "1", // Allow software fallback
+                        "-g",
+                        &gop_size_str,
+                    ]);
+                }
+                crate::video::HardwareEncoder::Nvenc => {
+                    // Note: pix_fmt is already set in the filter (format=nv12), no need to duplicate
+                    args.extend_from_slice(&["-c:v", "h264_nvenc"]);
+                    args.extend(quality_args.iter().map(String::as_str));
+                    args.extend_from_slice(&[
+                        "-preset",
+                        "p5",
+                        "-tune",
+                        "hq",
+                        "-rc",
+                        "vbr",
+                        "-g",
+                        &gop_size_str,
+                    ]);
+                }
+                crate::video::HardwareEncoder::Software => {
+                    // Note: pix_fmt is already set in the filter (format=yuv420p), no need to duplicate
+                    args.extend_from_slice(&["-c:v", "libx264", "-preset", "medium"]);
+                    args.extend(quality_args.iter().map(String::as_str));
+                    args.extend_from_slice(&["-g", &gop_size_str]);
+                }
+            }
 
-    cmd.args({
-        let mut args = vec![
-            "-y",
-            "-i",
-            input_video,
-            "-progress",
-            "pipe:1", // Enable progress reporting
-            "-vf",
-            &vf,
-            "-fps_mode",
-            "passthrough", // Modern replacement for -vsync
-            "-threads",
-            "0", // Use all available CPU cores
-            "-map",
-            "0:v:0", // Map first video stream
-            "-map",
-            "0:a?", // Map audio if present (optional)
-        ];
-
-        // Add hardware-optimized encoding parameters
-        match hardware_encoder {
-            crate::video::HardwareEncoder::VideoToolbox => {
-                // VideoToolbox in this ffmpeg build doesn't support -q:v
-                // We use -b:v (bitrate) instead.
-                // CRF 16 equivalent is roughly usually 10-12Mbps for 1080p, scaling accordingly.
-                // Since specific bitrate control is robust, we use a high bitrate.
-                args.extend_from_slice(&[
-                    "-c:v",
-                    "h264_videotoolbox",
-                    "-b:v",
-                    "12M", // High quality target (matching software CRF 16 intent)
-                    "-allow_sw",
-                    "1", // Allow software fallback
-                    "-g",
-                    &gop_size_str,
-                ]);
+            if pass_count == 2 {
+                args.extend_from_slice(&["-pass", &pass_str, "-passlogfile", &passlog]);
             }
-            crate::video::HardwareEncoder::Nvenc => {
-                // Note: pix_fmt is already set in the filter (format=nv12), no need to duplicate
+            if analysis_pass {
+                args.extend_from_slice(&["-an", "-f", "null", NULL_OUTPUT]);
+            } else {
+                if let Some(af) = &hold_af {
+                    args.extend_from_slice(&["-af", af]);
+                }
+                args.push("-c:a");
+                args.push(audio_codec);
+
+                // Add audio-specific args
+                args.extend(audio_args.iter().copied());
+
+                // Add explicit bitrate for re-encoded audio if not using copy
+                if audio_codec != "copy" && audio_codec == "aac" && audio_args.is_empty() {
+                    args.extend_from_slice(&["-b:a", "160k"]);
+                }
+
+                args.extend(metadata_args.iter().map(String::as_str));
                 args.extend_from_slice(&[
-                    "-c:v",
-                    "h264_nvenc",
-                    "-cq",
-                    "16",
-                    "-preset",
-                    "p5",
-                    "-tune",
-                    "hq",
-                    "-rc",
-                    "vbr",
-                    "-g",
-                    &gop_size_str,
+                    "-movflags",
+                    crate::video::movflags(&encode_options.metadata), // Fast web playback
+                    output_path,
                 ]);
             }
-            crate::video::HardwareEncoder::Software => {
-                // Note: pix_fmt is already set in the filter (format=yuv420p), no need to duplicate
-                args.extend_from_slice(&[
-                    "-c:v",
-                    "libx264",
-                    "-preset",
-                    "medium",
-                    "-crf",
-                    "16",
-                    "-g",
-                    &gop_size_str,
-                ]);
+            args
+        });
+
+        cmd.stdout(std::process::Stdio::piped());
+        cmd.stderr(std::process::Stdio::piped());
+
+        if encode_options.dry_run {
+            let command = crate::logging::command_argv(&cmd);
+            let _ = tx.send(InternalUpdate::Event(RpcEvent::Log {
+                id: id.into(),
+                message: format!("Dry run, not running: {}", command.join(" ")),
+            }));
+            dry_run_commands.push(command);
+            continue;
+        }
+
+        // Log intent
+        let _ = tx.send(InternalUpdate::Event(RpcEvent::Log {
+            id: id.into(),
+            message: format!("Starting encoder: {:?}", hardware_encoder),
+        }));
+
+        crate::logging::log_command(&cmd);
+        let mut child = cmd.spawn()?;
+        let stderr_tail = crate::video::collect_stderr_tail(&mut child);
+
+        // Process stdout for progress
+        if let Some(stdout) = child.stdout.take() {
+            let reader = tokio::io::BufReader::new(stdout);
+            let mut lines = reader.lines();
+
+            while let Ok(Some(line)) = lines.next_line().await {
+                if let Some(stripped) = line.strip_prefix("out_time_us=") {
+                    if let Ok(us) = stripped.trim().parse::<u64>() {
+                        let pass_progress = if let Some(total) = duration_us {
+                            if total > 0 {
+                                (us as f64 / total as f64).min(0.99) as f32
+                            } else {
+                                0.0
+                            }
+                        } else {
+                            0.0
+                        };
+                        // Each pass gets an equal share: 0-0.5 and 0.5-1.0 for two passes
+                        let progress = (pass - 1) as f32 / pass_count as f32
+                            + pass_progress / pass_count as f32;
+
+                        // Send progress update
+                        let _ = tx.send(InternalUpdate::Progress {
+                            index,
+                            value: progress,
+                        });
+                    }
+                }
             }
         }
 
-        args.push("-c:a");
-        args.push(audio_codec);
+        let status = child.wait().await?;
 
-        // Add audio-specific args
-        args.extend(audio_args.iter().copied());
+        if !status.success() {
+            let tail = stderr_tail.await.unwrap_or_default();
+            let encoder_name = match hardware_encoder {
+                crate::video::HardwareEncoder::VideoToolbox => "h264_videotoolbox",
+                crate::video::HardwareEncoder::Nvenc => "h264_nvenc",
+                crate::video::HardwareEncoder::Software => "libx264",
+            };
+            let message = format!(
+                "FFmpeg failed to encode format for {} with encoder {}{}",
+                id,
+                encoder_name,
+                if pass_count == 2 {
+                    format!(" (pass {} of 2)", pass)
+                } else {
+                    String::new()
+                }
+            );
+            return Err(crate::video::ffmpeg_failure(
+                id,
+                message,
+                &tail,
+                &mut |event| {
+                    let _ = tx.send(InternalUpdate::Event(event));
+                },
+            ));
+        }
+    }
 
-        // Add explicit bitrate for re-encoded audio if not using copy
-        if audio_codec != "copy" && audio_codec == "aac" && audio_args.is_empty() {
-            args.extend_from_slice(&["-b:a", "160k"]);
+    if pass_count == 2 {
+        // x264 writes <passlog>-0.log and its .mbtree next to the ASS file
+        for suffix in ["-0.log", "-0.log.mbtree"] {
+            let _ = fs::remove_file(format!("{}{}", passlog, suffix));
         }
+    }
 
-        args.extend_from_slice(&[
-            "-movflags",
-            "+faststart", // Fast web playback
-            output_path,
-        ]);
-        args
-    });
+    Ok(dry_run_commands)
+}
 
-    cmd.stdout(std::process::Stdio::piped());
-    cmd.stderr(std::process::Stdio::inherit());
+#[cfg(windows)]
+const NULL_OUTPUT: &str = "NUL";
+#[cfg(not(windows))]
+const NULL_OUTPUT: &str = "/dev/null";
 
-    // Log intent
-    let _ = tx.send(InternalUpdate::Event(RpcEvent::Log {
-        id: id.into(),
-        message: format!("Starting encoder: {:?}", hardware_encoder),
-    }));
+// Default constant quality for exports (visually lossless for captions on social video)
+const DEFAULT_CRF: u8 = 16;
+// VideoToolbox has no CRF mode here; this bitrate matches CRF 16 at 1080p
+const VIDEOTOOLBOX_BASE_BITRATE: f64 = 12_000_000.0;
 
-    let mut child = cmd.spawn()?;
+/// Average video bitrate that lands a two-pass encode just under `target_size_mb`,
+/// after reserving room for the audio track and ~2% container overhead.
+fn two_pass_bitrate_kbps(
+    options: &EncodeOptions,
+    probe_result: &crate::video::ProbeResult,
+) -> Result<Option<u64>> {
+    let (true, Some(target_mb)) = (options.two_pass, options.target_size_mb) else {
+        return Ok(None);
+    };
+    let duration = probe_result
+        .duration
+        .filter(|d| *d > 0.0)
+        .ok_or_else(|| anyhow!("targetSizeMb needs a known video duration"))?;
+    let audio_kbps = if probe_result.audio {
+        probe_result
+            .audio_bitrate
+            .map_or(192.0, |b| b as f64 / 1000.0)
+    } else {
+        0.0
+    };
+    let total_kbps = target_mb * 1_000_000.0 * 8.0 * 0.98 / duration / 1000.0;
+    let video_kbps = total_kbps - audio_kbps;
+    if video_kbps < MIN_TWO_PASS_KBPS {
+        return Err(anyhow!(
+            "targetSizeMb {} is too small for a {:.1}s video (needs at least {:.1} MB)",
+            target_mb,
+            duration,
+            (MIN_TWO_PASS_KBPS + audio_kbps) * 1000.0 * duration / 8.0 / 0.98 / 1_000_000.0
+        ));
+    }
+    Ok(Some(video_kbps.floor() as u64))
+}
 
-    // Process stdout for progress
-    if let Some(stdout) = child.stdout.take() {
-        let reader = tokio::io::BufReader::new(stdout);
-        let mut lines = reader.lines();
+// Container tags for an export. A bitexact encode drops everything copied from the source
+// (creation times, device info) and keeps libavformat/libavcodec from stamping their versions.
+fn output_metadata_args(options: &EncodeOptions) -> Vec<String> {
+    if !options.bitexact {
+        return crate::video::metadata_args(&options.metadata);
+    }
+    let stripped = crate::types::MetadataOptions {
+        strip_metadata: true,
+        ..options.metadata.clone()
+    };
+    let mut args = crate::video::metadata_args(&stripped);
+    args.extend(["-flags:v", "+bitexact", "-flags:a", "+bitexact"].map(String::from));
+    args
+}
 
-        while let Ok(Some(line)) = lines.next_line().await {
-            if let Some(stripped) = line.strip_prefix("out_time_us=") {
-                if let Ok(us) = stripped.trim().parse::<u64>() {
-                    let progress = if let Some(total) = duration_us {
-                        if total > 0 {
-                            (us as f64 / total as f64).min(0.99) as f32
-                        } else {
-                            0.0
-                        }
-                    } else {
-                        0.0
-                    };
+// Below this the captions turn to mush; better to fail than ship it
+const MIN_TWO_PASS_KBPS: f64 = 150.0;
 
-                    // Send progress update
-                    let _ = tx.send(InternalUpdate::Progress {
-                        index,
-                        value: progress,
-                    });
-                }
+/// Longest intro/outro freeze accepted
+const MAX_HOLD_MS: u64 = 60_000;
+
+fn validate_encode_options(options: &EncodeOptions) -> Result<()> {
+    if let Some(target) = options.target_size_mb {
+        if !options.two_pass {
+            return Err(anyhow!("targetSizeMb requires twoPass"));
+        }
+        if !target.is_finite() || target <= 0.0 {
+            return Err(anyhow!("targetSizeMb must be positive, got {}", target));
+        }
+    }
+    if options.two_pass && options.target_size_mb.is_none() && options.video_bitrate.is_none() {
+        return Err(anyhow!("twoPass needs targetSizeMb or videoBitrate"));
+    }
+    if let Some(crf) = options.crf {
+        if crf > 51 {
+            return Err(anyhow!("crf must be between 0 and 51, got {}", crf));
+        }
+    }
+    if let Some(watermark) = &options.watermark {
+        if !Path::new(&watermark.path).is_file() {
+            return Err(anyhow!("Watermark image not found: {}", watermark.path));
+        }
+        if !(watermark.scale > 0.0 && watermark.scale <= 1.0) {
+            return Err(anyhow!(
+                "watermark.scale must be between 0 and 1, got {}",
+                watermark.scale
+            ));
+        }
+        if !(0.0..=1.0).contains(&watermark.opacity) {
+            return Err(anyhow!(
+                "watermark.opacity must be between 0 and 1, got {}",
+                watermark.opacity
+            ));
+        }
+        if !(0.0..0.5).contains(&watermark.margin_x) || !(0.0..0.5).contains(&watermark.margin_y) {
+            return Err(anyhow!("watermark margins must be between 0 and 0.5"));
+        }
+        if let Some(position) = &watermark.position {
+            if !crate::video::WATERMARK_POSITIONS.contains(&position.as_str()) {
+                return Err(anyhow!(
+                    "Unknown watermark.position \"{}\" (expected one of {})",
+                    position,
+                    crate::video::WATERMARK_POSITIONS.join(", ")
+                ));
             }
         }
     }
-
-    let status = child.wait().await?;
-
-    if !status.success() {
-        let encoder_name = match hardware_encoder {
-            crate::video::HardwareEncoder::VideoToolbox => "h264_videotoolbox",
-            crate::video::HardwareEncoder::Nvenc => "h264_nvenc",
-            crate::video::HardwareEncoder::Software => "libx264",
-        };
-        return Err(anyhow!(
-            "FFmpeg failed to encode format for {} with encoder {}",
-            id,
-            encoder_name
-        ));
+    if let (Some(start), Some(end)) = (options.trim_start_ms, options.trim_end_ms) {
+        if end <= start {
+            return Err(anyhow!(
+                "trimEndMs ({}) must be after trimStartMs ({})",
+                end,
+                start
+            ));
+        }
+    }
+    for (name, ms) in [
+        ("introHoldMs", options.intro_hold_ms),
+        ("outroHoldMs", options.outro_hold_ms),
+    ] {
+        if ms > MAX_HOLD_MS {
+            return Err(anyhow!(
+                "{} must be at most {} ms, got {}",
+                name,
+                MAX_HOLD_MS,
+                ms
+            ));
+        }
+    }
+    if let Some(bar) = &options.progress_bar {
+        if let Some(color) = &bar.color {
+            if parse_hex_color(color).is_none() {
+                return Err(CodedError::new(
+                    "INVALID_COLOR",
+                    format!(
+                        "Invalid progressBar.color \"{}\": expected #rrggbb or #rrggbbaa",
+                        color
+                    ),
+                )
+                .into());
+            }
+        }
+        if !(bar.height > 0.0 && bar.height <= 0.2) {
+            return Err(anyhow!(
+                "progressBar.height must be between 0 and 0.2, got {}",
+                bar.height
+            ));
+        }
+        if !matches!(bar.position.as_deref(), None | Some("top") | Some("bottom")) {
+            return Err(anyhow!(
+                "progressBar.position must be \"top\" or \"bottom\""
+            ));
+        }
+    }
+    if let Some(bitrate) = &options.video_bitrate {
+        let digits = bitrate
+            .strip_suffix(['k', 'K', 'm', 'M'])
+            .unwrap_or(bitrate);
+        if digits.parse::<f64>().map_or(true, |n| n <= 0.0) {
+            return Err(anyhow!(
+                "videoBitrate must look like \"6M\" or \"800k\", got \"{}\"",
+                bitrate
+            ));
+        }
     }
-
     Ok(())
 }
 
+/// Rate-control flags for the chosen encoder. A bitrate wins over crf; crf maps to
+/// x264 -crf, NVENC -cq, and for VideoToolbox to a bitrate (x264 rule of thumb:
+/// +6 crf halves the size) scaled by frame area.
+fn video_quality_args(
+    encoder: crate::video::HardwareEncoder,
+    options: &EncodeOptions,
+    width: u32,
+    height: u32,
+) -> Vec<String> {
+    use crate::video::HardwareEncoder;
+    if let Some(bitrate) = &options.video_bitrate {
+        return vec!["-b:v".into(), bitrate.clone()];
+    }
+    match (encoder, options.crf) {
+        (HardwareEncoder::Software, crf) => {
+            vec!["-crf".into(), crf.unwrap_or(DEFAULT_CRF).to_string()]
+        }
+        (HardwareEncoder::Nvenc, crf) => {
+            vec!["-cq".into(), crf.unwrap_or(DEFAULT_CRF).to_string()]
+        }
+        (HardwareEncoder::VideoToolbox, None) => vec!["-b:v".into(), "12M".into()],
+        (HardwareEncoder::VideoToolbox, Some(crf)) => {
+            let area = (width as f64 * height as f64) / (1920.0 * 1080.0);
+            let scale = 2f64.powf((DEFAULT_CRF as f64 - crf as f64) / 6.0);
+            let kbps = (VIDEOTOOLBOX_BASE_BITRATE * scale * area / 1000.0).clamp(500.0, 80_000.0);
+            vec!["-b:v".into(), format!("{}k", kbps.round() as u64)]
+        }
+    }
+}
+
 // ---- Constants for horizontal stretch animation ----
 const STRETCH_X_PEAK: f32 = 1.03; // 1.08–1.15 looks right
 const STRETCH_UP_MIN_MS: i64 = 0;
 const STRETCH_UP_MAX_MS: i64 = 150;
 const BIG_FONT_SIZE_MULTIPLIER: f32 = 1.1;
 
+// ---- Constants for per-word karaoke animation ----
+const POP_SCALE_PEAK: f32 = 1.25; // highlighted word grows to 125%
+const POP_UP_MAX_MS: i64 = 120; // cap on time to reach the peak
+
+// Black stroke width used by every style
+const OUTLINE_W: u32 = 4;
+
+// Right edge for right-aligned (RTL) captions; matches MarginR in the style line
+const RTL_MARGIN_R: i32 = 60;
+
+// Vertical captions sit in a column near the right edge, like vertical Japanese text
+const VERTICAL_COLUMN_X_PCT: f32 = 88.0;
+
+// ---- Constants for the background box (multiples of the font size) ----
+const BOX_CHAR_W: f32 = 0.62; // estimated advance of one uppercase glyph
+const BOX_LINE_H: f32 = 1.2; // line height
+const BOX_PAD_X: f32 = 0.4; // horizontal padding on each side
+const BOX_PAD_Y: f32 = 0.2; // vertical padding above and below
+const BOX_DEFAULT_OPACITY: f32 = 0.6;
+
+// Gradient fills are approximated by this many horizontal bands of solid color
+const GRADIENT_BANDS: usize = 8;
+
 // ---- Constants for bounce animation (non-karaoke) ----
 const BOUNCE_START: f32 = 0.85; // 95%
 const BOUNCE_PEAK: f32 = 1.05; // 103%
@@ -1105,9 +3700,8 @@ const HL_RECENT_WINDOW_MS: u64 = 5000; // window for repetition penalty
 
 #[allow(clippy::too_many_arguments)]
 fn push_glow_and_stroke(
-    lines: &mut String,
-    start: &str,
-    end: &str,
+    cue: &mut CueEvents,
+    times: (i64, i64),
     text_body: &str, // ONLY \1c, \fs, \t(...). No \bord/\blur/\shad here.
     x: i32,
     y: i32,
@@ -1127,10 +3721,7 @@ fn push_glow_and_stroke(
             "{}{{\\1a&HFF\\bord{:.2}\\3c&HFFFFFF&\\3a{}\\blur{:.2}\\shad0}}",
             common, glow_w, glow_alpha_hex, glow_blur
         );
-        lines.push_str(&format!(
-            "Dialogue: 0,{},{},TikTok,,0,0,0,,{}{}\n",
-            start, end, glow, text_body
-        ));
+        cue.push(0, times, None, &format!("{}{}", glow, text_body));
     }
 
     // LAYER 1 (or 0 if no glow) — sharp black stroke + visible fill
@@ -1139,10 +3730,315 @@ fn push_glow_and_stroke(
         "{}{{\\1a&H00\\bord{:.2}\\3c&H000000&\\3a&H00\\blur0\\shad0}}",
         common, stroke_w
     );
-    lines.push_str(&format!(
-        "Dialogue: {},{},{},TikTok,,0,0,0,,{}{}\n",
-        layer, start, end, stroke_fill, text_body
-    ));
+    cue.push(layer, times, None, &format!("{}{}", stroke_fill, text_body));
+}
+
+// Shadow-only layer drawn beneath glow/stroke (fill and border hidden by the tags)
+#[allow(clippy::too_many_arguments)]
+fn push_shadow_layer(
+    cue: &mut CueEvents,
+    times: (i64, i64),
+    text_body: &str,
+    x: i32,
+    y: i32,
+    alignment: u32,
+    shadow_tags: &str,
+) {
+    cue.push(
+        0,
+        times,
+        None,
+        &format!(
+            "{{\\an{}\\q2\\pos({},{})}}{}{}",
+            alignment, x, y, shadow_tags, text_body
+        ),
+    );
+}
+
+// Override tags for the drop shadow layer. Fill and border are transparent so the
+// shadow's blur never softens the sharp outline drawn on the layers above.
+fn shadow_layer_tags(shadow: &ShadowSpec, outline_w: u32) -> String {
+    let color = shadow
+        .color
+        .as_deref()
+        .map(hex_to_ass_color)
+        .unwrap_or_else(|| "&H00000000".into());
+    let alpha = &color.trim_start_matches("&H")[..2];
+    format!(
+        "{{\\1a&HFF\\3a&HFF\\bord{}\\blur{:.1}\\xshad{:.1}\\yshad{:.1}\\4c&H{}&\\4a&H{}}}",
+        outline_w,
+        shadow.blur.max(0.0),
+        shadow.x,
+        shadow.y,
+        bgr_from_aa_bgrr(&color),
+        alpha
+    )
+}
+
+// CSS text-shadow equivalent of the burned drop shadow, for the editor preview
+fn css_text_shadow(shadow: &ShadowSpec) -> String {
+    format!(
+        "{}px {}px {}px {}",
+        shadow.x,
+        shadow.y,
+        shadow.blur.max(0.0),
+        shadow.color.as_deref().unwrap_or("#000000")
+    )
+}
+
+struct BackgroundBox {
+    color: String, // #rrggbb; any alpha in the caller's color is folded into opacity
+    opacity: f32,  // 0.0-1.0
+    radius: f32,   // corner radius in pixels
+}
+
+// Box rectangle in frame pixels
+struct BoxRect {
+    left: f32,
+    top: f32,
+    width: f32,
+    height: f32,
+}
+
+fn background_box(options: &CaptionStyleOptions) -> Option<BackgroundBox> {
+    options.background_color.as_ref().map(|color| {
+        let opacity = options
+            .background_opacity
+            .unwrap_or(BOX_DEFAULT_OPACITY)
+            .clamp(0.0, 1.0);
+        // A #rrggbbaa color's own alpha multiplies backgroundOpacity
+        let (color, opacity) = match parse_hex_color(color) {
+            Some(c) => (
+                format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b),
+                opacity * c.a as f32 / 255.0,
+            ),
+            None => (color.clone(), opacity),
+        };
+        BackgroundBox {
+            color,
+            opacity,
+            radius: options.background_radius.unwrap_or(0.0).max(0.0),
+        }
+    })
+}
+
+// Characters as a reader sees them: a flag, an emoji family or a letter with its
+// combining marks is one, however many code points it takes
+fn grapheme_count(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+// Characters on a rendered line, counting the joining spaces
+fn joined_chars(tokens: &[String]) -> usize {
+    tokens.iter().map(|t| grapheme_count(t)).sum::<usize>() + tokens.len().saturating_sub(1)
+}
+
+// Rows of a vertical column: one per non-blank grapheme
+fn column_glyphs(tokens: &[String]) -> usize {
+    tokens
+        .iter()
+        .flat_map(|t| t.graphemes(true))
+        .filter(|g| !g.trim().is_empty())
+        .count()
+}
+
+// Token indices of the (at most two) lines produced by a line-break index
+fn split_token_lines(count: usize, split_idx: usize) -> Vec<Vec<usize>> {
+    if split_idx > 0 && split_idx < count {
+        vec![(0..split_idx).collect(), (split_idx..count).collect()]
+    } else {
+        vec![(0..count).collect()]
+    }
+}
+
+// Character counts of the (at most two) lines produced by a line-break index
+fn split_line_chars(tokens: &[String], split_idx: usize) -> Vec<usize> {
+    if split_idx > 0 && split_idx < tokens.len() {
+        vec![
+            joined_chars(&tokens[..split_idx]),
+            joined_chars(&tokens[split_idx..]),
+        ]
+    } else {
+        vec![joined_chars(tokens)]
+    }
+}
+
+// Estimated extent of wrapped text anchored at (x, y) with ASS alignment `align`.
+// Widths are estimated from character counts; vertical text is one glyph per row.
+fn text_rect(
+    line_chars: &[usize],
+    font_size: u32,
+    x: f32,
+    y: f32,
+    align: u32,
+    vertical: bool,
+) -> BoxRect {
+    let fs = font_size as f32;
+    let (width, height) = if vertical {
+        (
+            fs,
+            line_chars.iter().sum::<usize>() as f32 * fs * BOX_LINE_H,
+        )
+    } else {
+        let widest = line_chars.iter().copied().max().unwrap_or(0);
+        (
+            widest as f32 * fs * BOX_CHAR_W,
+            line_chars.len().max(1) as f32 * fs * BOX_LINE_H,
+        )
+    };
+    anchored_rect(width, height, x, y, align)
+}
+
+// Rectangle of the given size placed at (x, y) with ASS alignment `align`
+fn anchored_rect(width: f32, height: f32, x: f32, y: f32, align: u32) -> BoxRect {
+    let left = match align % 3 {
+        1 => x,               // left aligned
+        2 => x - width / 2.0, // centered
+        _ => x - width,       // right aligned
+    };
+    let top = match align {
+        7..=9 => y,
+        4..=6 => y - height / 2.0,
+        _ => y - height,
+    };
+    BoxRect {
+        left,
+        top,
+        width,
+        height,
+    }
+}
+
+// How far the outline (plus its blur) and the glow layer reach past the glyph edges
+fn stroke_pad(style: &AssStyle, glow: bool) -> f32 {
+    if glow {
+        style.outline_w as f32 * 2.0 + 6.0
+    } else {
+        style.outline_w as f32 + 2.0
+    }
+}
+
+// Padded box around the estimated text extent
+fn background_rect(
+    line_chars: &[usize],
+    font_size: u32,
+    x: f32,
+    y: f32,
+    align: u32,
+    vertical: bool,
+) -> BoxRect {
+    let text = text_rect(line_chars, font_size, x, y, align, vertical);
+    let fs = font_size as f32;
+    let (pad_x, pad_y) = (fs * BOX_PAD_X, fs * BOX_PAD_Y);
+    BoxRect {
+        left: text.left - pad_x,
+        top: text.top - pad_y,
+        width: text.width + 2.0 * pad_x,
+        height: text.height + 2.0 * pad_y,
+    }
+}
+
+// ASS vector drawing (\p1) of a rectangle with rounded corners, origin at its top-left
+fn rounded_rect_drawing(w: f32, h: f32, radius: f32) -> String {
+    let r = radius.min(w.min(h) / 2.0).max(0.0);
+    let (w, h) = (w.round() as i32, h.round() as i32);
+    if r < 1.0 {
+        return format!("m 0 0 l {w} 0 {w} {h} 0 {h}");
+    }
+    // Bezier control points sit ~45% of the radius in from each corner
+    let c = (r * 0.45).round() as i32;
+    let r = r.round() as i32;
+    format!(
+        "m {r} 0 l {} 0 b {} 0 {w} {c} {w} {r} l {w} {} b {w} {} {} {h} {} {h} \
+         l {r} {h} b {c} {h} 0 {} 0 {} l 0 {r} b 0 {c} {c} 0 {r} 0",
+        w - r,
+        w - c,
+        h - r,
+        h - c,
+        w - c,
+        w - r,
+        h - c,
+        h - r,
+    )
+}
+
+// Background box drawn beneath every other layer for the cue's duration
+fn push_background_box(cue: &mut CueEvents, times: (i64, i64), bg: &BackgroundBox, rect: &BoxRect) {
+    let alpha = ((1.0 - bg.opacity) * 255.0).round() as u8;
+    cue.push_box(
+        times,
+        &format!(
+            "{{\\an7\\pos({},{})\\bord0\\shad0\\blur0\\1c&H{}&\\1a&H{:02X}\\p1}}{}",
+            rect.left.round() as i32,
+            rect.top.round() as i32,
+            bgr_from_aa_bgrr(&hex_to_ass_color(&bg.color)),
+            alpha,
+            rounded_rect_drawing(rect.width, rect.height, bg.radius)
+        ),
+    );
+}
+
+// Linear blend between two BBGGRR colors, t in 0.0..=1.0
+fn lerp_bgr(a: &str, b: &str, t: f32) -> String {
+    let channel =
+        |s: &str, i: usize| u8::from_str_radix(s.get(i..i + 2).unwrap_or("FF"), 16).unwrap_or(255);
+    (0..3)
+        .map(|k| {
+            let (ca, cb) = (channel(a, k * 2) as f32, channel(b, k * 2) as f32);
+            format!("{:02X}", (ca + (cb - ca) * t).round() as u8)
+        })
+        .collect()
+}
+
+// Gradient fill: ASS has no gradients, so the fill is redrawn on top of the stroke layers
+// in horizontal bands clipped to slices of the estimated text extent, each recolored with
+// the blended stop. The outer bands extend to the frame edges to cover estimation error.
+#[allow(clippy::too_many_arguments)]
+fn push_gradient_bands(
+    cue: &mut CueEvents,
+    times: (i64, i64),
+    text_body: &str,
+    text_fill: &FillColor,
+    stops: &(String, String),
+    text: &BoxRect,
+    frame: (u32, u32),
+    x: i32,
+    y: i32,
+    alignment: u32,
+) {
+    let base = format!("\\1c&H{}&", text_fill.bgr);
+    let band_h = text.height / GRADIENT_BANDS as f32;
+    for band in 0..GRADIENT_BANDS {
+        let top = if band == 0 {
+            0
+        } else {
+            (text.top + band as f32 * band_h).round() as i32
+        };
+        let bottom = if band + 1 == GRADIENT_BANDS {
+            frame.1 as i32
+        } else {
+            (text.top + (band + 1) as f32 * band_h).round() as i32
+        };
+        let t = (band as f32 + 0.5) / GRADIENT_BANDS as f32;
+        let color = format!("\\1c&H{}&", lerp_bgr(&stops.0, &stops.1, t));
+        cue.push(
+            2,
+            times,
+            Some([0, top, frame.0 as i32, bottom]),
+            &format!(
+                "{{\\an{}\\q2\\pos({},{})\\bord0\\shad0\\blur0}}{}",
+                alignment,
+                x,
+                y,
+                text_body.replace(&base, &color)
+            ),
+        );
+    }
+}
+
+// CSS equivalent of the burned gradient fill, for the editor preview
+fn css_text_gradient(stops: &(String, String)) -> String {
+    format!("linear-gradient(to bottom, {}, {})", stops.0, stops.1)
 }
 
 #[derive(Clone)]
@@ -1150,8 +4046,9 @@ fn push_glow_and_stroke(
 struct Phrase {
     start_ms: u64,
     end_ms: u64,
-    tokens: Vec<String>,  // plain words for layout
-    spans: Vec<WordSpan>, // timings per token (same length as tokens)
+    tokens: Vec<String>,               // plain words for layout
+    spans: Vec<WordSpan>,              // timings per token (same length as tokens)
+    position: Option<SegmentPosition>, // manual placement of the segments it came from
 }
 
 // Heuristics: new phrase if punctuation on previous token or gap > 350ms or length > 3 words
@@ -1161,6 +4058,7 @@ fn coalesce_phrases(segments: &[CaptionSegment]) -> Vec<Phrase> {
         segments.len()
     );
     let mut all: Vec<WordSpan> = Vec::new();
+    let mut positions: Vec<Option<SegmentPosition>> = Vec::new();
     for s in segments {
         for w in &s.words {
             let t = w.text.trim();
@@ -1189,103 +4087,768 @@ fn coalesce_phrases(segments: &[CaptionSegment]) -> Vec<Phrase> {
                 });
             }
         }
+        positions.resize(all.len(), s.position);
     }
 
     let mut out: Vec<Phrase> = Vec::new();
     let mut cur: Vec<WordSpan> = Vec::new();
-    for w in all.into_iter() {
+    let mut cur_position = None;
+    for (w, position) in all.into_iter().zip(positions) {
         if cur.is_empty() {
             cur.push(w);
+            cur_position = position;
             continue;
         }
         let prev = cur.last().unwrap();
         let gap = w.start_ms.saturating_sub(prev.end_ms);
+        // Segments placed differently never share a cue
         let hard_break = [".", "!", "?"].iter().any(|p| prev.text.ends_with(p))
             || gap > 2000
-            || cur.len() >= 100;
+            || cur.len() >= 100
+            || position != cur_position;
         if hard_break {
-            let tokens = cur.iter().map(|x| x.text.clone()).collect::<Vec<_>>();
-            out.push(Phrase {
-                start_ms: cur.first().unwrap().start_ms,
-                end_ms: cur.last().unwrap().end_ms,
-                tokens,
-                spans: cur.clone(),
-            });
-            cur = vec![w];
+            out.push(phrase_from_spans(
+                std::mem::replace(&mut cur, vec![w]),
+                cur_position,
+            ));
+            cur_position = position;
         } else {
             cur.push(w);
         }
     }
     if !cur.is_empty() {
-        let tokens = cur.iter().map(|x| x.text.clone()).collect::<Vec<_>>();
-        out.push(Phrase {
-            start_ms: cur.first().unwrap().start_ms,
-            end_ms: cur.last().unwrap().end_ms,
-            tokens,
-            spans: cur.clone(),
-        });
+        out.push(phrase_from_spans(cur, cur_position));
     }
     out
 }
 
-// ---- time quantization (ASS is 1/100s) ----
-fn ms_to_cs(ms: u64) -> i64 {
-    (ms / 10) as i64
-}
-fn cs_to_ass(cs: i64) -> String {
-    let total = cs.max(0);
-    let h = total / 360000; // 3600*100
-    let m = (total % 360000) / 6000;
-    let s = (total % 6000) / 100;
-    let c = total % 100;
-    format!("{:01}:{:02}:{:02}.{:02}", h, m, s, c)
+// Karaoke: split phrases so at most max_words words are on screen at once
+fn limit_phrase_words(phrases: Vec<Phrase>, max_words: Option<usize>) -> Vec<Phrase> {
+    let Some(max_words) = max_words else {
+        return phrases;
+    };
+    let mut out = Vec::new();
+    for ph in phrases {
+        for chunk in ph.spans.chunks(max_words.max(1)) {
+            out.push(phrase_from_spans(chunk.to_vec(), ph.position));
+        }
+    }
+    out
 }
 
-// Contiguous, non-overlapping windows in cs
-fn contiguous_cs_windows(words: &[WordSpan]) -> Vec<(i64, i64)> {
-    let mut out = Vec::with_capacity(words.len());
-    for (i, w) in words.iter().enumerate() {
-        let s = ms_to_cs(w.start_ms);
-        let e = if i + 1 < words.len() {
-            ms_to_cs(words[i + 1].start_ms) // [s, next_s)
-        } else {
-            ms_to_cs(w.end_ms) // last word keeps its end
+// Split phrases longer than max_ms at word boundaries, then extend phrases shorter than
+// min_ms into the gap before the next one (never overlapping it)
+fn limit_phrase_durations(
+    phrases: Vec<Phrase>,
+    min_ms: Option<u64>,
+    max_ms: Option<u64>,
+) -> Vec<Phrase> {
+    let mut out: Vec<Phrase> = Vec::new();
+    for ph in phrases {
+        let Some(max_ms) = max_ms else {
+            out.push(ph);
+            continue;
         };
-        out.push((s, (e.max(s + 1)))); // at least 1 cs
+        let mut chunk: Vec<WordSpan> = Vec::new();
+        for span in ph.spans {
+            if let Some(first) = chunk.first() {
+                if span.end_ms.saturating_sub(first.start_ms) > max_ms {
+                    out.push(phrase_from_spans(std::mem::take(&mut chunk), ph.position));
+                }
+            }
+            chunk.push(span);
+        }
+        if !chunk.is_empty() {
+            out.push(phrase_from_spans(chunk, ph.position));
+        }
+    }
+
+    if let Some(min_ms) = min_ms {
+        for i in 0..out.len() {
+            let next_start = out.get(i + 1).map_or(u64::MAX, |n| n.start_ms);
+            let ph = &mut out[i];
+            let target = (ph.start_ms + min_ms).min(next_start);
+            if target > ph.end_ms {
+                ph.end_ms = target;
+                ph.spans.last_mut().unwrap().end_ms = target;
+            }
+        }
     }
     out
 }
 
-// Block stretch tag: X goes from peak -> 100%, Y stays 100%
-fn stretch_tag_ms(dur_ms: i64) -> String {
-    let up = dur_ms.clamp(STRETCH_UP_MIN_MS, STRETCH_UP_MAX_MS);
-    let px = (STRETCH_X_PEAK * 100.0).round() as u32;
-    format!(r"{{\fscx{px}\fscy100\t(0,{up},\fscx100)}}")
+fn phrase_from_spans(spans: Vec<WordSpan>, position: Option<SegmentPosition>) -> Phrase {
+    Phrase {
+        start_ms: spans.first().unwrap().start_ms,
+        end_ms: spans.last().unwrap().end_ms,
+        tokens: spans.iter().map(|x| x.text.clone()).collect(),
+        spans,
+        position,
+    }
 }
 
-// Bounce animation: 95% → 103% → 100% (nice entrance effect)
-fn bounce_tag() -> String {
-    let start = (BOUNCE_START * 100.0).round() as u32;
-    let peak = (BOUNCE_PEAK * 100.0).round() as u32;
-    let end_val = (BOUNCE_END * 100.0).round() as u32;
-    format!(
-        r"{{\fscx{start}\fscy{start}\t(0,{},\fscx{peak}\fscy{peak})\t({},{},\fscx{end_val}\fscy{end_val})}}",
-        BOUNCE_UP_MS,
-        BOUNCE_UP_MS,
-        BOUNCE_UP_MS + BOUNCE_DOWN_MS
-    )
+// Phrases for rendering, with the style's cue duration limits applied
+fn timed_phrases(segments: &[CaptionSegment], style: &AssStyle) -> Vec<Phrase> {
+    let replaced;
+    let segments = if style.replacements.is_empty() {
+        segments
+    } else {
+        replaced = apply_replacements(segments, &style.replacements);
+        &replaced[..]
+    };
+    let estimated;
+    let segments = if style.estimate_word_timing {
+        estimated = segments
+            .iter()
+            .map(|s| CaptionSegment {
+                words: estimate_word_spans(s),
+                ..s.clone()
+            })
+            .collect::<Vec<_>>();
+        &estimated[..]
+    } else {
+        segments
+    };
+    let phrases = limit_phrase_durations(
+        coalesce_phrases(segments),
+        style.min_cue_ms,
+        style.max_cue_ms,
+    );
+    match &style.profanity {
+        Some(filter) => filter_phrases(phrases, filter),
+        None => phrases,
+    }
 }
 
-// Uppercase + sanitize tokens (keeps punctuation)
-fn normalize_tokens(words: &[WordSpan]) -> Vec<String> {
-    words
+// Compiled `TextReplacement`: case-insensitive pattern and its replacement
+struct Replacement {
+    pattern: regex::Regex,
+    replace: String,
+    expand: bool, // regex rules may use $1-style capture references
+}
+
+fn compile_replacements(rules: &[crate::types::TextReplacement]) -> Result<Vec<Replacement>> {
+    rules
+        .iter()
+        .filter(|r| !r.find.is_empty())
+        .map(|r| {
+            let mut pattern = if r.regex {
+                r.find.clone()
+            } else {
+                regex::escape(&r.find)
+            };
+            if r.whole_word {
+                pattern = format!(r"\b(?:{})\b", pattern);
+            }
+            let pattern = regex::RegexBuilder::new(&pattern)
+                .case_insensitive(true)
+                .build()
+                .with_context(|| format!("Invalid replacement pattern \"{}\"", r.find))?;
+            Ok(Replacement {
+                pattern,
+                replace: r.replace.clone(),
+                expand: r.regex,
+            })
+        })
+        .collect()
+}
+
+impl Replacement {
+    fn replace_all(&self, text: &str) -> String {
+        if self.expand {
+            self.pattern
+                .replace_all(text, self.replace.as_str())
+                .into_owned()
+        } else {
+            self.pattern
+                .replace_all(text, regex::NoExpand(&self.replace))
+                .into_owned()
+        }
+    }
+}
+
+// Apply replacements to each segment's text and its word spans. Word spans are matched
+// as one space-joined line, so a rule can span words ("open ai" -> "OpenAI"); the words a
+// match covers merge into a single span from the first word's start to the last's end,
+// which keeps karaoke highlighting in step with the new text.
+fn apply_replacements(segments: &[CaptionSegment], rules: &[Replacement]) -> Vec<CaptionSegment> {
+    segments
+        .iter()
+        .map(|segment| {
+            let mut out = segment.clone();
+            for rule in rules {
+                out.text = rule.replace_all(&out.text);
+                out.words = replace_in_words(out.words, rule);
+            }
+            out
+        })
+        .collect()
+}
+
+fn replace_in_words(words: Vec<WordSpan>, rule: &Replacement) -> Vec<WordSpan> {
+    // Byte range of each word in the joined line
+    let mut line = String::new();
+    let mut ranges = Vec::with_capacity(words.len());
+    for w in &words {
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        ranges.push(line.len()..line.len() + w.text.len());
+        line.push_str(&w.text);
+    }
+
+    let mut words = words;
+    let matches: Vec<_> = rule.pattern.captures_iter(&line).collect();
+    // Right to left, so earlier word indices stay valid
+    for caps in matches.iter().rev() {
+        let m = caps.get(0).unwrap();
+        let Some(first) = ranges
+            .iter()
+            .position(|r| r.end > m.start() || (m.is_empty() && r.end >= m.start()))
+        else {
+            continue;
+        };
+        let last = ranges
+            .iter()
+            .rposition(|r| r.start < m.end())
+            .unwrap_or(first)
+            .max(first);
+        let mut replacement = String::new();
+        if rule.expand {
+            caps.expand(&rule.replace, &mut replacement);
+        } else {
+            replacement.push_str(&rule.replace);
+        }
+        // The tail comes from the word as it is now: a match further right in the same
+        // word has already been applied to it (only what's before that match is original)
+        let tail = m.end().clamp(ranges[last].start, ranges[last].end) - ranges[last].start;
+        let text = format!(
+            "{}{}{}",
+            &line[ranges[first].start..m.start().max(ranges[first].start)],
+            replacement,
+            &words[last].text[tail..]
+        );
+        let end_ms = words[last].end_ms;
+        words.drain(first + 1..=last);
+        words[first].text = text;
+        words[first].end_ms = end_ms;
+    }
+    // A rule that replaced a word with nothing leaves no empty span behind
+    words.retain(|w| !w.text.trim().is_empty());
+    words
+}
+
+static PROFANITY_WORDS: &str = include_str!("profanity_words.txt");
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ProfanityMode {
+    Mask,
+    Remove,
+}
+
+// Display-only profanity filter; segments (and the saved JSON) keep the original words
+struct ProfanityFilter {
+    mode: ProfanityMode,
+    words: HashSet<String>,
+}
+
+impl ProfanityFilter {
+    fn new(mode: Option<&str>, extra: &[String]) -> Option<Self> {
+        let mode = match mode? {
+            "mask" => ProfanityMode::Mask,
+            "remove" => ProfanityMode::Remove,
+            _ => return None, // "off"
+        };
+        let words = PROFANITY_WORDS
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(str::to_string)
+            .chain(extra.iter().map(|w| w.trim().to_lowercase()))
+            .filter(|w| !w.is_empty())
+            .collect();
+        Some(Self { mode, words })
+    }
+
+    fn matches(&self, word: &str) -> bool {
+        let bare = word.trim_matches(|c: char| !c.is_alphanumeric());
+        !bare.is_empty() && self.words.contains(&bare.to_lowercase())
+    }
+}
+
+// "fuck," -> "f***,": keep the first letter and any punctuation around the word
+fn mask_word(word: &str) -> String {
+    let mut seen_letter = false;
+    word.chars()
+        .map(|c| {
+            if !c.is_alphanumeric() {
+                c
+            } else if seen_letter {
+                '*'
+            } else {
+                seen_letter = true;
+                c
+            }
+        })
+        .collect()
+}
+
+fn filter_phrases(phrases: Vec<Phrase>, filter: &ProfanityFilter) -> Vec<Phrase> {
+    phrases
+        .into_iter()
+        .filter_map(|mut phrase| {
+            let spans = std::mem::take(&mut phrase.spans);
+            phrase.spans = spans
+                .into_iter()
+                .filter_map(|mut span| {
+                    if !filter.matches(&span.text) {
+                        return Some(span);
+                    }
+                    match filter.mode {
+                        ProfanityMode::Mask => {
+                            span.text = mask_word(&span.text);
+                            Some(span)
+                        }
+                        ProfanityMode::Remove => None,
+                    }
+                })
+                .collect();
+            phrase.tokens = phrase.spans.iter().map(|s| s.text.clone()).collect();
+            // A phrase made only of removed words disappears; the others keep their timing
+            (!phrase.spans.is_empty()).then_some(phrase)
+        })
+        .collect()
+}
+
+// Word timings guessed from the segment's own timing: each word gets a share of the
+// segment proportional to its length, so karaoke works without word-level transcription
+fn estimate_word_spans(segment: &CaptionSegment) -> Vec<WordSpan> {
+    let words: Vec<&str> = segment.text.split_whitespace().collect();
+    let weights: Vec<u64> = words
+        .iter()
+        .map(|w| grapheme_count(w).max(1) as u64)
+        .collect();
+    let total_weight: u64 = weights.iter().sum();
+    let duration = segment.end_ms.saturating_sub(segment.start_ms);
+    let mut done = 0;
+    words
+        .iter()
+        .zip(&weights)
+        .map(|(word, weight)| {
+            // Cumulative rounding so the last word ends exactly at the segment end
+            let start_ms = segment.start_ms + duration * done / total_weight;
+            done += weight;
+            WordSpan {
+                start_ms,
+                end_ms: segment.start_ms + duration * done / total_weight,
+                text: word.to_string(),
+            }
+        })
+        .collect()
+}
+
+// ---- time quantization (ASS is 1/100s) ----
+fn ms_to_cs(ms: u64) -> i64 {
+    (ms / 10) as i64
+}
+fn cs_to_ass(cs: i64) -> String {
+    let total = cs.max(0);
+    let h = total / 360000; // 3600*100
+    let m = (total % 360000) / 6000;
+    let s = (total % 6000) / 100;
+    let c = total % 100;
+    format!("{:01}:{:02}:{:02}.{:02}", h, m, s, c)
+}
+
+// Fade-in/out (ms) for one event of a cue. Only the event that starts the cue fades
+// in and only the one that ends it fades out, so karaoke windows don't flicker; fades
+// are clamped to half the cue and to the event's own length.
+fn cue_fades(cue_ms: (i64, i64), event_ms: (i64, i64), fade_ms: u32) -> (u32, u32) {
+    let limit = ((cue_ms.1 - cue_ms.0) / 2).min(event_ms.1 - event_ms.0);
+    let fade = (fade_ms as i64).min(limit).max(0) as u32;
+    (
+        if event_ms.0 == cue_ms.0 { fade } else { 0 },
+        if event_ms.1 == cue_ms.1 { fade } else { 0 },
+    )
+}
+
+// When each character (grapheme, spaces skipped) of a cue is typed: a word's characters
+// are spread evenly over the time it is spoken
+fn typewriter_times(tokens: &[String], spans: &[WordSpan]) -> Vec<u64> {
+    tokens
+        .iter()
+        .zip(spans)
+        .flat_map(|(token, span)| {
+            let n = grapheme_count(token).max(1) as u64;
+            let duration = span.end_ms.saturating_sub(span.start_ms);
+            (0..grapheme_count(token) as u64).map(move |k| span.start_ms + duration * k / n)
+        })
+        .collect()
+}
+
+// Characters of a cue typed by the playhead; with no playhead, all of them
+fn visible_chars_at(tokens: &[String], spans: &[WordSpan], timestamp_ms: Option<u64>) -> u32 {
+    let times = typewriter_times(tokens, spans);
+    timestamp_ms.map_or(times.len(), |t| times.iter().filter(|&&at| at <= t).count()) as u32
+}
+
+// One rendered line of a typewriter cue: the band of the frame it occupies, how far
+// right the clip opens before anything is typed, and where it opens to as each
+// character is typed
+struct RevealBand {
+    top: i32,
+    bottom: i32,
+    hidden_right: i32,
+    steps: Vec<(u64, i32)>, // (ms, clip right edge)
+}
+
+// Reveal bands for a cue whose tokens are laid out as `lines` (token indices per line)
+#[allow(clippy::too_many_arguments)]
+fn typewriter_bands(
+    tokens: &[String],
+    spans: &[WordSpan],
+    lines: &[Vec<usize>],
+    metrics: &FontMetrics,
+    style: &AssStyle,
+    (x, y): (i32, i32),
+    align: u32,
+    frame: (u32, u32),
+) -> Vec<RevealBand> {
+    let line_texts: Vec<Vec<String>> = lines
+        .iter()
+        .map(|l| l.iter().map(|&i| tokens[i].clone()).collect())
+        .collect();
+    let line_chars: Vec<usize> = line_texts.iter().map(|l| joined_chars(l)).collect();
+    let rect = text_rect(
+        &line_chars,
+        style.font_size,
+        x as f32,
+        y as f32,
+        align,
+        false,
+    );
+    let line_h = rect.height / lines.len().max(1) as f32;
+    // Keeps the untyped first glyph's outline and glow out of view
+    let pad = (style.outline_w * 2 + 8) as f32;
+    let times = typewriter_times(tokens, spans);
+    let first_char =
+        |token: usize| -> usize { tokens[..token].iter().map(|t| grapheme_count(t)).sum() };
+
+    lines
+        .iter()
+        .zip(&line_texts)
+        .enumerate()
+        .map(|(li, (line, texts))| {
+            let width = metrics.text_width(&texts.join(" "), style.font_size);
+            let left = match align % 3 {
+                1 => x as f32,
+                2 => x as f32 - width / 2.0,
+                _ => x as f32 - width,
+            };
+            let mut typed = String::new();
+            let mut steps = Vec::new();
+            for (j, &ti) in line.iter().enumerate() {
+                if j > 0 {
+                    typed.push(' ');
+                }
+                for (k, g) in tokens[ti].graphemes(true).enumerate() {
+                    typed.push_str(g);
+                    let right = left + metrics.text_width(&typed, style.font_size);
+                    steps.push((times[first_char(ti) + k], right.round() as i32));
+                }
+            }
+            // The last character opens the whole line, so trailing outline isn't cut
+            if let Some(last) = steps.last_mut() {
+                last.1 = frame.0 as i32;
+            }
+            RevealBand {
+                top: if li == 0 {
+                    0
+                } else {
+                    (rect.top + li as f32 * line_h).round() as i32
+                },
+                bottom: if li + 1 == lines.len() {
+                    frame.1 as i32
+                } else {
+                    (rect.top + (li + 1) as f32 * line_h).round() as i32
+                },
+                hidden_right: (left - pad).max(0.0).round() as i32,
+                steps,
+            }
+        })
+        .collect()
+}
+
+// The events (every layer) of one cue, written with the cue's fade. With a typewriter
+// reveal each text event becomes one copy per line of text, clipped to the line's band
+// and opened to the right in a step per character.
+struct CueEvents<'a> {
+    lines: &'a mut String,
+    cue_cs: (i64, i64),
+    fade_ms: u32,
+    reveal: Vec<RevealBand>,
+    frame_w: u32,
+}
+
+impl<'a> CueEvents<'a> {
+    fn new(lines: &'a mut String, cue_cs: (i64, i64), fade_ms: u32, frame_w: u32) -> Self {
+        Self {
+            lines,
+            cue_cs,
+            fade_ms,
+            reveal: Vec::new(),
+            frame_w,
+        }
+    }
+
+    fn with_reveal(mut self, bands: Vec<RevealBand>) -> Self {
+        self.reveal = bands;
+        self
+    }
+
+    // Only the event that starts the cue fades in and only the one that ends it fades out
+    fn fade_tag(&self, (start_cs, end_cs): (i64, i64)) -> String {
+        let (fade_in, fade_out) = cue_fades(
+            (self.cue_cs.0 * 10, self.cue_cs.1 * 10),
+            (start_cs * 10, end_cs * 10),
+            self.fade_ms,
+        );
+        if fade_in > 0 || fade_out > 0 {
+            format!("{{\\fad({},{})}}", fade_in, fade_out)
+        } else {
+            String::new()
+        }
+    }
+
+    fn write(&mut self, layer: u32, (start_cs, end_cs): (i64, i64), text: &str) {
+        self.lines.push_str(&format!(
+            "Dialogue: {},{},{},TikTok,,0,0,0,,{}\n",
+            layer,
+            cs_to_ass(start_cs),
+            cs_to_ass(end_cs),
+            text
+        ));
+    }
+
+    // Background box: fades with the cue, but shows from the start of a reveal
+    fn push_box(&mut self, times: (i64, i64), text: &str) {
+        let fade = self.fade_tag(times);
+        self.write(0, times, &format!("{}{}", fade, text));
+    }
+
+    // A text event; `clip` is a rect (x1, y1, x2, y2) it is confined to anyway
+    fn push(&mut self, layer: u32, times: (i64, i64), clip: Option<[i32; 4]>, text: &str) {
+        let fade = self.fade_tag(times);
+        let start_ms = times.0 as u64 * 10;
+        let end_ms = times.1 as u64 * 10;
+        // No reveal, or fully typed before this event starts (later karaoke windows)
+        if self
+            .reveal
+            .iter()
+            .all(|b| b.steps.iter().all(|&(at, _)| at <= start_ms))
+        {
+            let clip = clip.map_or(String::new(), |[x1, y1, x2, y2]| {
+                format!("{{\\clip({},{},{},{})}}", x1, y1, x2, y2)
+            });
+            self.write(layer, times, &format!("{}{}{}", clip, fade, text));
+            return;
+        }
+        let outer = clip.unwrap_or([0, 0, self.frame_w as i32, i32::MAX]);
+        let mut events = Vec::new();
+        for band in &self.reveal {
+            let (top, bottom) = (band.top.max(outer[1]), band.bottom.min(outer[3]));
+            if top >= bottom {
+                continue;
+            }
+            let clip = |right: i32| {
+                format!(
+                    "\\clip({},{},{},{})",
+                    outer[0],
+                    top,
+                    right.clamp(outer[0], outer[2]),
+                    bottom
+                )
+            };
+            let opened = band
+                .steps
+                .iter()
+                .filter(|&&(at, _)| at <= start_ms)
+                .map(|&(_, right)| right)
+                .next_back()
+                .unwrap_or(band.hidden_right);
+            let mut tags = clip(opened);
+            for &(at, right) in band
+                .steps
+                .iter()
+                .filter(|&&(at, _)| at > start_ms && at < end_ms)
+            {
+                let dt = at - start_ms;
+                tags.push_str(&format!("\\t({},{},{})", dt, dt, clip(right)));
+            }
+            events.push(format!("{{{}}}{}{}", tags, fade, text));
+        }
+        for event in events {
+            self.write(layer, times, &event);
+        }
+    }
+}
+
+// Contiguous, non-overlapping windows in cs
+fn contiguous_cs_windows(words: &[WordSpan]) -> Vec<(i64, i64)> {
+    let mut out = Vec::with_capacity(words.len());
+    for (i, w) in words.iter().enumerate() {
+        let s = ms_to_cs(w.start_ms);
+        let e = if i + 1 < words.len() {
+            ms_to_cs(words[i + 1].start_ms) // [s, next_s)
+        } else {
+            ms_to_cs(w.end_ms) // last word keeps its end
+        };
+        out.push((s, (e.max(s + 1)))); // at least 1 cs
+    }
+    out
+}
+
+// Block stretch tag: X goes from peak -> 100%, Y stays 100%
+fn stretch_tag_ms(dur_ms: i64) -> String {
+    let up = dur_ms.clamp(STRETCH_UP_MIN_MS, STRETCH_UP_MAX_MS);
+    let px = (STRETCH_X_PEAK * 100.0).round() as u32;
+    format!(r"{{\fscx{px}\fscy100\t(0,{up},\fscx100)}}")
+}
+
+// Bounce animation: 95% → 103% → 100% (nice entrance effect)
+fn bounce_tag() -> String {
+    let start = (BOUNCE_START * 100.0).round() as u32;
+    let peak = (BOUNCE_PEAK * 100.0).round() as u32;
+    let end_val = (BOUNCE_END * 100.0).round() as u32;
+    format!(
+        r"{{\fscx{start}\fscy{start}\t(0,{},\fscx{peak}\fscy{peak})\t({},{},\fscx{end_val}\fscy{end_val})}}",
+        BOUNCE_UP_MS,
+        BOUNCE_UP_MS,
+        BOUNCE_UP_MS + BOUNCE_DOWN_MS
+    )
+}
+
+// Uppercase + sanitize tokens (keeps punctuation)
+fn normalize_tokens(words: &[WordSpan], style: &AssStyle) -> Vec<String> {
+    words
         .iter()
         .map(|w| w.text.trim())
         .filter(|t| !t.is_empty())
-        .map(|t| t.to_uppercase())
+        .map(|t| {
+            let t = if style.strip_punctuation {
+                strip_word_punctuation(t)
+            } else {
+                t
+            };
+            style.text_transform.apply(t)
+        })
         .collect()
 }
 
+// Sentence punctuation and quotes, including CJK and Spanish forms
+fn is_sentence_punctuation(c: char) -> bool {
+    matches!(
+        c,
+        '.' | ','
+            | '!'
+            | '?'
+            | ';'
+            | ':'
+            | '…'
+            | '"'
+            | '“'
+            | '”'
+            | '„'
+            | '«'
+            | '»'
+            | '¿'
+            | '¡'
+            | '。'
+            | '、'
+            | '，'
+            | '！'
+            | '？'
+            | '：'
+            | '；'
+            | '「'
+            | '」'
+            | '『'
+            | '』'
+    )
+}
+
+// Trim punctuation off one word. Apostrophes only go when they wrap the whole word
+// ('quoted'), so "don't", "'cause" and "goin'" keep theirs. A word that is nothing but
+// punctuation stays as-is so tokens keep lining up with their word timings.
+fn strip_word_punctuation(word: &str) -> &str {
+    let mut t = word.trim_matches(is_sentence_punctuation);
+    let is_apostrophe = |c: char| matches!(c, '\'' | '‘' | '’');
+    if t.chars().count() > 1 && t.starts_with(is_apostrophe) && t.ends_with(is_apostrophe) {
+        t = t
+            .trim_matches(is_apostrophe)
+            .trim_matches(is_sentence_punctuation);
+    }
+    if t.is_empty() {
+        word
+    } else {
+        t
+    }
+}
+
+// Display case for caption words; segment text itself is never rewritten
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TextTransform {
+    None,
+    Upper,
+    Lower,
+    Title,
+}
+
+impl TextTransform {
+    fn parse(value: Option<&str>) -> Result<Self> {
+        match value {
+            None | Some("upper") => Ok(Self::Upper), // captions have always been all-caps
+            Some("none") => Ok(Self::None),
+            Some("lower") => Ok(Self::Lower),
+            Some("title") => Ok(Self::Title),
+            Some(other) => Err(anyhow!(
+                "Unknown textTransform '{}': use upper, lower, title or none",
+                other
+            )),
+        }
+    }
+
+    // Full Unicode case mapping: "ß" uppercases to "SS", "İ" lowercases to "i̇"
+    fn apply(self, word: &str) -> String {
+        match self {
+            Self::None => word.to_string(),
+            Self::Upper => word.to_uppercase(),
+            Self::Lower => word.to_lowercase(),
+            Self::Title => title_case_word(word),
+        }
+    }
+}
+
+// Uppercase the first letter (skipping leading punctuation like quotes) and lowercase
+// the rest. A letter whose uppercase is several chars ("ß" -> "SS") gets "Ss".
+fn title_case_word(word: &str) -> String {
+    let mut out = String::with_capacity(word.len());
+    let mut seen_letter = false;
+    for c in word.chars() {
+        if !seen_letter && c.is_alphabetic() {
+            seen_letter = true;
+            let mut upper = c.to_uppercase();
+            out.extend(upper.next());
+            out.extend(upper.flat_map(char::to_lowercase));
+        } else if seen_letter {
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
 // Split tokens containing hyphens into sub-tokens to allow wrapping
 // e.g. "FOO-BAR" -> ["FOO-", "BAR"]
 fn preprocess_hyphenated_tokens(
@@ -1404,73 +4967,881 @@ fn split_phrase_for_width(
     segments
 }
 
-// Color tags use BBGGRR (no alpha) for \1c
-fn bgr_from_aa_bgrr(aa_bgrr: &str) -> String {
-    aa_bgrr.trim_start_matches("&H").chars().skip(2).collect() // drop AA
+// Greedy word wrap: token indices (after the first) where a new line begins.
+// Breaks only between tokens; a token longer than max_chars gets a line of its own.
+fn wrap_line_starts(tokens: &[String], max_chars: usize) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut line_len = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        let t_len = grapheme_count(token);
+        if line_len > 0 && line_len + 1 + t_len > max_chars {
+            starts.push(i);
+            line_len = 0;
+        } else if line_len > 0 {
+            line_len += 1; // space
+        }
+        line_len += t_len;
+    }
+    starts
 }
 
-fn assemble_colored_two_lines(
-    tokens: &[String],
-    hi: usize,
-    white_bgr: &str,
-    hi_bgr: &str,
-    line1_count: usize,
-    header: &str,
-    font_size: u32,
-) -> String {
-    let white = format!("{{\\1c&H{}&\\fs{}}}", white_bgr, font_size);
-    // Only create bigger font style if we're actually highlighting something
-    let has_highlighting = hi != usize::MAX;
-    let hi_style = if has_highlighting {
-        let big_font_size = (font_size as f32 * BIG_FONT_SIZE_MULTIPLIER) as u32;
-        format!("{{\\1c&H{}&\\fs{}}}", hi_bgr, big_font_size)
-    } else {
-        format!("{{\\1c&H{}&\\fs{}}}", hi_bgr, font_size) // Same size, just different color
-    };
+// Line starts for a cue: the greedy wrap, or with `balance` the same number of lines
+// broken so their lengths are as even as possible
+fn line_starts(tokens: &[String], max_chars: usize, balance: bool) -> Vec<usize> {
+    let greedy = wrap_line_starts(tokens, max_chars);
+    if !balance || greedy.is_empty() {
+        return greedy;
+    }
+    balanced_line_starts(tokens, max_chars, greedy.len() + 1).unwrap_or(greedy)
+}
 
-    let mut s = String::from(header); // will include \an2 \pos \q2 and stretch
-    for i in 0..tokens.len() {
-        if i == line1_count {
-            s.push_str(r"\N");
-        }
-        // Only highlight if hi is a valid index (not usize::MAX)
-        let should_highlight = has_highlighting && i == hi;
-        s.push_str(if should_highlight { &hi_style } else { &white });
-        let t = tokens[i]
-            .replace('\\', r"\\")
-            .replace('{', r"\{")
-            .replace('}', r"\}");
-        s.push_str(&t); // Moved s.push_str(&t) earlier to use t for check? No wait.
-
-        // original was:
-        // let t = ...
-        // s.push_str(&t);
-        // if i + 1 < tokens.len() { s.push(' '); }
-
-        // New logic:
-        // Check if CURRENT token (not t, but tokens[i]) ends with '-'
-        // Note: tokens[i] might be raw string.
-        if i + 1 < tokens.len() {
-            let ends_with_hyphen = tokens[i].ends_with('-') && tokens[i].len() > 1;
-            if !ends_with_hyphen {
-                s.push(' ');
+// Breaks into exactly `lines` lines of at most max_chars (a lone overlong token may
+// exceed it) minimizing the sum of squared line lengths, which evens them out. Ties
+// go to the shorter top line, the bottom-heavy shape subtitlers prefer.
+fn balanced_line_starts(tokens: &[String], max_chars: usize, lines: usize) -> Option<Vec<usize>> {
+    let n = tokens.len();
+    if lines == 0 || lines > n {
+        return None;
+    }
+    let lens: Vec<usize> = tokens.iter().map(|t| grapheme_count(t)).collect();
+    let line_len = |a: usize, b: usize| lens[a..b].iter().sum::<usize>() + (b - a - 1);
+    let fits = |a: usize, b: usize| b - a == 1 || line_len(a, b) <= max_chars;
+    // best[j][i]: (cost, start of the last line) for the first i tokens on j lines
+    let mut best = vec![vec![None::<(usize, usize)>; n + 1]; lines + 1];
+    best[0][0] = Some((0, 0));
+    for j in 1..=lines {
+        for i in j..=n {
+            for p in (j - 1)..i {
+                let Some((cost, _)) = best[j - 1][p] else {
+                    continue;
+                };
+                if !fits(p, i) {
+                    continue;
+                }
+                let cost = cost + line_len(p, i).pow(2);
+                if best[j][i].is_none_or(|(c, _)| cost < c) {
+                    best[j][i] = Some((cost, p));
+                }
             }
         }
     }
-    s
+    best[lines][n]?;
+    let mut starts = Vec::with_capacity(lines - 1);
+    let mut end = n;
+    for j in (2..=lines).rev() {
+        let (_, p) = best[j][end]?;
+        starts.push(p);
+        end = p;
+    }
+    starts.reverse();
+    Some(starts)
 }
 
-struct AssStyle {
-    font_name: String,
-    font_size: u32,
-    primary: String,   // base (white)
-    secondary: String, // unused here
-    outline: String,
-    outline_w: u32,
-    shadow: u32,
-    align: u32,        // 1..9 grid; 2 = bottom-center
-    margin_v: u32,     // pixels
-    highlight: String, // green for current word
+// Index of the second line's first token, or usize::MAX if everything fits on one line
+fn first_line_break(tokens: &[String], max_chars: usize, balance: bool) -> usize {
+    line_starts(tokens, max_chars, balance)
+        .first()
+        .copied()
+        .unwrap_or(usize::MAX)
+}
+
+// Hard-split tokens that alone exceed max_chars, sharing the span's time by length.
+// Pieces break between grapheme clusters, never inside an emoji or accented letter.
+fn split_overlong_tokens(
+    tokens: &[String],
+    spans: &[WordSpan],
+    max_chars: usize,
+) -> (Vec<String>, Vec<WordSpan>) {
+    let mut new_tokens = Vec::new();
+    let mut new_spans = Vec::new();
+
+    for (token, span) in tokens.iter().zip(spans.iter()) {
+        let chars: Vec<&str> = token.graphemes(true).collect();
+        if max_chars == 0 || chars.len() <= max_chars {
+            new_tokens.push(token.clone());
+            new_spans.push(span.clone());
+            continue;
+        }
+
+        let pieces: Vec<String> = chars.chunks(max_chars).map(|c| c.concat()).collect();
+        let total_dur = span.end_ms.saturating_sub(span.start_ms);
+        let total_len = chars.len() as u64;
+        let mut consumed = 0u64;
+        for (i, piece) in pieces.iter().enumerate() {
+            let s_ms = span.start_ms + total_dur * consumed / total_len;
+            consumed += grapheme_count(piece) as u64;
+            let e_ms = if i == pieces.len() - 1 {
+                span.end_ms // Ensure last one aligns exactly with end
+            } else {
+                span.start_ms + total_dur * consumed / total_len
+            };
+            new_tokens.push(piece.clone());
+            new_spans.push(WordSpan {
+                start_ms: s_ms,
+                end_ms: e_ms,
+                text: piece.clone(),
+            });
+        }
+    }
+    (new_tokens, new_spans)
+}
+
+// Pack tokens into screens of at most max_lines lines, each wrapped at max_chars
+fn split_phrase_max_chars(
+    tokens: &[String],
+    spans: &[WordSpan],
+    max_chars: usize,
+    max_lines: usize,
+) -> Vec<(Vec<String>, Vec<WordSpan>)> {
+    let (tokens, spans) = preprocess_hyphenated_tokens(tokens, spans); // Handle hyphens first
+    let (tokens, spans) = split_overlong_tokens(&tokens, &spans, max_chars);
+
+    let mut segments = Vec::new();
+    let mut start = 0;
+    while start < tokens.len() {
+        let line_starts = wrap_line_starts(&tokens[start..], max_chars);
+        let end = line_starts
+            .get(max_lines.max(1) - 1)
+            .map_or(tokens.len(), |i| start + i);
+        segments.push((tokens[start..end].to_vec(), spans[start..end].to_vec()));
+        start = end;
+    }
+    segments
+}
+
+// Group a segment's tokens into preview lines starting at the given token indices
+fn preview_lines(
+    tokens: &[String],
+    hi_idx: usize,
+    line_starts: &[usize],
+) -> Vec<crate::types::PreviewLine> {
+    let mut lines = Vec::new();
+    let mut words = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if line_starts.contains(&i) && !words.is_empty() {
+            lines.push(crate::types::PreviewLine {
+                words,
+                est_width_px: 0.0,
+            });
+            words = Vec::new();
+        }
+        words.push(crate::types::PreviewWord {
+            text: token.clone(),
+            is_highlighted: i == hi_idx,
+            animation_phase: None,
+            fill_pct: None,
+            emoji: false,
+            attached: false,
+        });
+    }
+    if !words.is_empty() {
+        lines.push(crate::types::PreviewLine {
+            words,
+            est_width_px: 0.0,
+        });
+    }
+    lines
+}
+
+// ---- Emoji ----
+
+// Pictographic codepoints that caption fonts don't carry
+fn is_emoji_base(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0x2300..=0x23FF
+    )
+}
+
+// Codepoints that extend the preceding emoji: ZWJ, variation selector, keycap,
+// skin tone modifiers and tag characters (subdivision flags)
+fn is_emoji_modifier(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x200D | 0xFE0F | 0x20E3 | 0x1F3FB..=0x1F3FF | 0xE0020..=0xE007F
+    )
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c as u32, 0x1F1E6..=0x1F1FF)
+}
+
+// Split text into alternating plain and emoji pieces; every emoji cluster (ZWJ
+// sequences, skin tones, flag pairs) is its own piece. Returns (text, is_emoji).
+fn split_emoji_runs(text: &str) -> Vec<(String, bool)> {
+    let mut pieces: Vec<(String, bool)> = Vec::new();
+    let mut after_zwj = false;
+    let mut open_flag = false; // one regional indicator waiting for its pair
+    for c in text.chars() {
+        match pieces.last_mut() {
+            Some((s, true)) if is_emoji_modifier(c) => {
+                s.push(c);
+                after_zwj = c == '\u{200D}';
+            }
+            Some((s, true))
+                if is_emoji_base(c) && (after_zwj || (open_flag && is_regional_indicator(c))) =>
+            {
+                s.push(c);
+                after_zwj = false;
+                open_flag = false;
+            }
+            _ if is_emoji_base(c) => {
+                pieces.push((c.to_string(), true));
+                after_zwj = false;
+                open_flag = is_regional_indicator(c);
+            }
+            Some((s, false)) => s.push(c),
+            _ => pieces.push((c.to_string(), false)),
+        }
+    }
+    pieces
+}
+
+// Emoji fonts libass can draw. It renders outline glyphs only, in the caption's fill
+// color: color bitmap fonts (Noto Color Emoji, Apple Color Emoji) come out blank, so
+// burned emoji are monochrome.
+const OUTLINE_EMOJI_FONTS: [&str; 4] =
+    ["Noto Emoji", "Segoe UI Emoji", "Twemoji Mozilla", "Symbola"];
+
+// The emoji font to burn with: the configured one, else the first installed outline
+// emoji font. None when it isn't installed, so emoji are left out rather than drawn
+// as tofu boxes.
+fn emoji_burn_font(configured: Option<&str>) -> Option<String> {
+    let found = match configured {
+        Some(name) => find_font(name).map(|_| name.to_string()),
+        None => OUTLINE_EMOJI_FONTS
+            .iter()
+            .find(|name| find_font(name).is_some())
+            .map(|name| name.to_string()),
+    };
+    if found.is_none() {
+        tracing::warn!(
+            font = configured.unwrap_or(OUTLINE_EMOJI_FONTS[0]),
+            "no emoji font installed; emoji are left out of burned captions"
+        );
+    }
+    found
+}
+
+// Switch to the emoji font for every emoji cluster in dialogue text (outside
+// override blocks), then back to the caption font. Without an emoji font the
+// clusters are dropped, along with a space they leave doubled.
+fn wrap_emoji_runs(events: &str, emoji_font: Option<&str>, font_name: &str) -> String {
+    if !events.chars().any(is_emoji_base) {
+        return events.to_string();
+    }
+    let mut out = String::with_capacity(events.len());
+    let mut text = String::new();
+    let flush = |text: &mut String, out: &mut String| {
+        for (piece, emoji) in split_emoji_runs(text) {
+            match emoji_font {
+                Some(emoji_font) if emoji => out.push_str(&format!(
+                    "{{\\fn{}}}{}{{\\fn{}}}",
+                    emoji_font, piece, font_name
+                )),
+                None if emoji => {}
+                _ if out.ends_with(' ') && piece.starts_with(' ') => out.push_str(&piece[1..]),
+                _ => out.push_str(&piece),
+            }
+        }
+        text.clear();
+    };
+    let mut in_block = false;
+    for c in events.chars() {
+        match c {
+            '{' if !in_block => {
+                flush(&mut text, &mut out);
+                in_block = true;
+                out.push(c);
+            }
+            '}' if in_block => {
+                in_block = false;
+                out.push(c);
+            }
+            _ if in_block => out.push(c),
+            _ => text.push(c),
+        }
+    }
+    flush(&mut text, &mut out);
+    out
+}
+
+// ---- Bidirectional text (Arabic/Hebrew mixed with Latin) ----
+
+fn has_rtl_chars(tokens: &[String]) -> bool {
+    tokens
+        .iter()
+        .flat_map(|t| t.chars())
+        .any(|c| matches!(bidi_class(c), BidiClass::R | BidiClass::AL))
+}
+
+// Base direction of a cue, from its first strong character (Unicode bidi rules P2/P3)
+fn is_rtl_tokens(tokens: &[String]) -> bool {
+    has_rtl_chars(tokens)
+        && ParagraphBidiInfo::new(&tokens.join(" "), None)
+            .paragraph_level
+            .is_rtl()
+}
+
+// Display order of one line of tokens given in logical order. Text stays logical
+// (CaptionSegment.text is never reordered); only the word order on screen changes.
+fn bidi_visual_order(tokens: &[String], base_rtl: bool) -> Vec<usize> {
+    if !has_rtl_chars(tokens) && !base_rtl {
+        return (0..tokens.len()).collect();
+    }
+    let text = tokens.join(" ");
+    let base = if base_rtl { Level::rtl() } else { Level::ltr() };
+    let info = ParagraphBidiInfo::new(&text, Some(base));
+
+    // A token takes the embedding level resolved for its first character
+    let mut levels = Vec::with_capacity(tokens.len());
+    let mut offset = 0;
+    for token in tokens {
+        levels.push(info.levels.get(offset).copied().unwrap_or(base));
+        offset += token.len() + 1;
+    }
+    ParagraphBidiInfo::reorder_visual(&levels)
+}
+
+// RTL cues are right-aligned: move the anchor to the right column of the ASS numpad grid
+fn rtl_anchor(style: &AssStyle, frame_w: u32) -> (u32, i32) {
+    let align = if style.align.is_multiple_of(3) {
+        style.align
+    } else {
+        style.align - style.align % 3 + 3
+    };
+    (
+        align,
+        frame_w as i32 - RTL_MARGIN_R.max(style.safe_x as i32),
+    )
+}
+
+// ---- Vertical writing mode (top-to-bottom columns) ----
+
+// Y anchor for the style's alignment row (top / middle / bottom); the middle row is
+// centered in the safe area
+fn anchor_y(style: &AssStyle, frame_h: u32) -> i32 {
+    match style.align {
+        7..=9 => style.margin_v as i32,
+        4..=6 => ((style.safe_top + frame_h.saturating_sub(style.safe_bottom)) / 2) as i32,
+        _ => (frame_h as i32 - style.margin_v as i32).max(0),
+    }
+}
+
+// Cue anchor as percentages of the frame, the same point the burn positions the text at
+fn preview_anchor(
+    style: &AssStyle,
+    position: Option<SegmentPosition>,
+    tokens: &[String],
+    (frame_w, frame_h): (u32, u32),
+) -> (f32, f32) {
+    let (_, x, y) = cue_anchor(style, position, is_rtl_tokens(tokens), (frame_w, frame_h));
+    (
+        x as f32 / frame_w as f32 * 100.0,
+        y as f32 / frame_h as f32 * 100.0,
+    )
+}
+
+// Alignment and anchor point of a horizontal cue: the style's placement (right aligned for
+// RTL text), with a segment's manual position replacing either coordinate
+fn cue_anchor(
+    style: &AssStyle,
+    position: Option<SegmentPosition>,
+    rtl: bool,
+    (frame_w, frame_h): (u32, u32),
+) -> (u32, i32, i32) {
+    let (align, x) = if rtl {
+        rtl_anchor(style, frame_w)
+    } else {
+        (style.align, (frame_w / 2) as i32)
+    };
+    let position = position.unwrap_or_default();
+    let pct = |pct: f32, size: u32| (pct.clamp(0.0, 100.0) / 100.0 * size as f32).round() as i32;
+    (
+        align,
+        position.x_pct.map_or(x, |p| pct(p, frame_w)),
+        position
+            .y_pct
+            .map_or_else(|| anchor_y(style, frame_h), |p| pct(p, frame_h)),
+    )
+}
+
+// Width available to caption lines once the safe area's sides are taken off
+fn layout_width(style: &AssStyle, frame_w: u32) -> u32 {
+    frame_w.saturating_sub(style.safe_x * 2).max(1)
+}
+
+// Anchor point of a vertical column: near the right edge at the style's height, with a
+// segment's manual position replacing either coordinate (as `cue_anchor` does)
+fn vertical_anchor(
+    style: &AssStyle,
+    position: Option<SegmentPosition>,
+    (frame_w, frame_h): (u32, u32),
+) -> (i32, i32) {
+    let position = position.unwrap_or_default();
+    let pct = |pct: f32, size: u32| (pct.clamp(0.0, 100.0) / 100.0 * size as f32).round() as i32;
+    (
+        pct(position.x_pct.unwrap_or(VERTICAL_COLUMN_X_PCT), frame_w),
+        position
+            .y_pct
+            .map_or_else(|| anchor_y(style, frame_h), |p| pct(p, frame_h)),
+    )
+}
+
+// One column per cue: phrases packed so the stacked characters fit the frame height,
+// each with the manual position of the segments it came from
+fn vertical_columns(
+    segments: &[CaptionSegment],
+    style: &AssStyle,
+    frame_h: u32,
+) -> Vec<(Vec<String>, Vec<WordSpan>, Option<SegmentPosition>)> {
+    let fit = ((frame_h as f32 * 0.8) / (style.font_size as f32 * 1.1)).floor() as usize;
+    let per_column = style.max_chars_per_line.unwrap_or(fit).max(1);
+
+    let mut columns = Vec::new();
+    for ph in limit_phrase_words(timed_phrases(segments, style), style.max_words_per_cue) {
+        let tokens = normalize_tokens(&ph.spans, style);
+        columns.extend(
+            split_phrase_max_chars(&tokens, &ph.spans, per_column, 1)
+                .into_iter()
+                .map(|(tokens, spans)| (tokens, spans, ph.position)),
+        );
+    }
+    columns
+}
+
+// Stack every grapheme on its own line, coloring the highlighted token
+fn assemble_vertical_column(
+    tokens: &[String],
+    hi: usize,
+    text_fill: &FillColor,
+    hi_fill: &FillColor,
+    font_size: u32,
+) -> String {
+    let mut s = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let fill = if i == hi { hi_fill } else { text_fill };
+        s.push_str(&format!("{{{}\\fs{}}}", fill.tags(), font_size));
+        for (j, g) in token
+            .graphemes(true)
+            .filter(|g| !g.trim().is_empty())
+            .enumerate()
+        {
+            if i > 0 || j > 0 {
+                s.push_str(r"\N");
+            }
+            for c in g.chars() {
+                match c {
+                    '\\' => s.push_str(r"\\"),
+                    '{' => s.push_str(r"\{"),
+                    '}' => s.push_str(r"\}"),
+                    _ => s.push(c),
+                }
+            }
+        }
+    }
+    s
+}
+
+fn push_vertical_events(
+    lines: &mut String,
+    w: u32,
+    h: u32,
+    style: &AssStyle,
+    segments: &[CaptionSegment],
+    karaoke: bool,
+    glow_effect: bool,
+) {
+    let (text_fill, hi_fill) = fill_colors(style);
+    // Gradient fills aren't banded in columns; the top stop (style.primary) is used instead
+
+    for (tokens, spans, position) in vertical_columns(segments, style, h) {
+        let (x, y) = vertical_anchor(style, position, (w, h));
+        // Karaoke: one event per word window; otherwise the column shows as a whole
+        let windows = if karaoke {
+            contiguous_cs_windows(&spans)
+        } else {
+            vec![(
+                ms_to_cs(spans.first().unwrap().start_ms),
+                ms_to_cs(spans.last().unwrap().end_ms),
+            )]
+        };
+        let cue_cs = (windows[0].0, windows[windows.len() - 1].1);
+        let mut cue = CueEvents::new(lines, cue_cs, style.fade_ms, w);
+        if let Some(bg) = &style.background {
+            let rect = background_rect(
+                &[column_glyphs(&tokens)],
+                style.font_size,
+                x as f32,
+                y as f32,
+                style.align,
+                true,
+            );
+            push_background_box(&mut cue, cue_cs, bg, &rect);
+        }
+        for (i, (cs0, cs1)) in windows.iter().enumerate() {
+            let hi = if karaoke { i } else { usize::MAX };
+            let body = assemble_vertical_column(&tokens, hi, &text_fill, &hi_fill, style.font_size);
+            if let Some(tags) = &style.drop_shadow {
+                push_shadow_layer(&mut cue, (*cs0, *cs1), &body, x, y, style.align, tags);
+            }
+            push_glow_and_stroke(
+                &mut cue,
+                (*cs0, *cs1),
+                &body,
+                x,
+                y,
+                style.outline_w as f32,
+                glow_effect,
+                style.outline_w as f32 * 2.0,
+                6.0,
+                "&H80",
+                style.align,
+            );
+        }
+    }
+}
+
+fn vertical_preview_cues(
+    segments: &[CaptionSegment],
+    style: &AssStyle,
+    (frame_w, frame_h): (u32, u32),
+    karaoke: bool,
+) -> Vec<crate::types::PreviewCue> {
+    let mut cues = Vec::new();
+    for (tokens, spans, position) in vertical_columns(segments, style, frame_h) {
+        // Anchor as percentages of the frame, the same point the burn positions the column at
+        let (x, y) = vertical_anchor(style, position, (frame_w, frame_h));
+        let x_pct = x as f32 / frame_w as f32 * 100.0;
+        let y_pct = y as f32 / frame_h as f32 * 100.0;
+        let windows: Vec<(u64, u64, usize)> = if karaoke {
+            contiguous_cs_windows(&spans)
+                .into_iter()
+                .enumerate()
+                .map(|(i, (cs0, cs1))| (cs0 as u64 * 10, cs1 as u64 * 10, i))
+                .collect()
+        } else {
+            vec![(
+                spans.first().unwrap().start_ms,
+                spans.last().unwrap().end_ms,
+                usize::MAX,
+            )]
+        };
+        let column_ms = (windows[0].0 as i64, windows[windows.len() - 1].1 as i64);
+        for (start_ms, end_ms, hi) in windows {
+            let (fade_in_ms, fade_out_ms) =
+                cue_fades(column_ms, (start_ms as i64, end_ms as i64), style.fade_ms);
+            cues.push(crate::types::PreviewCue {
+                start_ms,
+                end_ms,
+                lines: preview_lines(&tokens, hi, &[]),
+                y_pct,
+                x_pct,
+                rtl: false,
+                vertical: true,
+                background: None,
+                fade_in_ms,
+                fade_out_ms,
+                visible_chars: None,
+                bounds: None,
+            });
+        }
+    }
+    cues
+}
+
+// Color tags use BBGGRR (no alpha) for \1c
+fn bgr_from_aa_bgrr(aa_bgrr: &str) -> String {
+    aa_bgrr.trim_start_matches("&H").chars().skip(2).collect() // drop AA
+}
+
+// Fill of a text run: BBGGRR for `\1c`, plus the AA for `\1a` when it carries one
+#[derive(Debug, Clone, PartialEq)]
+struct FillColor {
+    bgr: String,
+    alpha: Option<String>,
+}
+
+impl FillColor {
+    // Override tags that switch a run to this fill
+    fn tags(&self) -> String {
+        match &self.alpha {
+            Some(alpha) => format!("\\1c&H{}&\\1a&H{}&", self.bgr, alpha),
+            None => format!("\\1c&H{}&", self.bgr),
+        }
+    }
+}
+
+// Text and highlight fills. When either color is translucent both carry their alpha, so
+// switching runs also switches opacity; fully opaque styles keep plain `\1c` tags.
+fn fill_colors(style: &AssStyle) -> (FillColor, FillColor) {
+    let alpha = |aa_bgrr: &str| {
+        aa_bgrr
+            .trim_start_matches("&H")
+            .get(..2)
+            .unwrap_or("00")
+            .to_string()
+    };
+    let (text_alpha, hi_alpha) = (alpha(&style.primary), alpha(&style.highlight));
+    let opaque = text_alpha == "00" && hi_alpha == "00";
+    let with_alpha = |aa_bgrr: &str, a: &str| FillColor {
+        bgr: bgr_from_aa_bgrr(aa_bgrr),
+        alpha: (!opaque).then(|| a.to_string()),
+    };
+    (
+        with_alpha(&style.primary, &text_alpha),
+        with_alpha(&style.highlight, &hi_alpha),
+    )
+}
+
+fn assemble_colored_two_lines(
+    tokens: &[String],
+    hi: usize,
+    text_fill: &FillColor,
+    hi_fill: &FillColor,
+    line1_count: usize,
+    header: &str,
+    font_size: u32,
+) -> String {
+    let white = format!("{{{}\\fs{}}}", text_fill.tags(), font_size);
+    // Only create bigger font style if we're actually highlighting something
+    let has_highlighting = hi != usize::MAX;
+    let hi_style = if has_highlighting {
+        let big_font_size = (font_size as f32 * BIG_FONT_SIZE_MULTIPLIER) as u32;
+        format!("{{{}\\fs{}}}", hi_fill.tags(), big_font_size)
+    } else {
+        format!("{{{}\\fs{}}}", hi_fill.tags(), font_size) // Same size, just different color
+    };
+    assemble_styled_lines(tokens, hi, &white, &hi_style, line1_count, header)
+}
+
+// Same as assemble_colored_two_lines, with the per-run override tags supplied by the caller
+fn assemble_styled_lines(
+    tokens: &[String],
+    hi: usize,
+    white: &str,
+    hi_style: &str,
+    line1_count: usize,
+    header: &str,
+) -> String {
+    let has_highlighting = hi != usize::MAX;
+    let rtl = is_rtl_tokens(tokens);
+    let split = line1_count.min(tokens.len());
+    let mut s = String::from(header); // will include \an2 \pos \q2 and stretch
+    for (line_no, range) in [0..split, split..tokens.len()].into_iter().enumerate() {
+        if range.is_empty() {
+            continue;
+        }
+        if line_no == 1 {
+            s.push_str(r"\N");
+        }
+        // libass lays out each override run on its own, so runs go out in display order
+        let order = bidi_visual_order(&tokens[range.clone()], rtl);
+        for (pos, &k) in order.iter().enumerate() {
+            let i = range.start + k;
+            // Only highlight if hi is a valid index (not usize::MAX)
+            let should_highlight = has_highlighting && i == hi;
+            s.push_str(if should_highlight { hi_style } else { white });
+            let t = tokens[i]
+                .replace('\\', r"\\")
+                .replace('{', r"\{")
+                .replace('}', r"\}");
+            s.push_str(&t);
+
+            // No space after a hyphenated fragment ("FOO-" + "BAR")
+            if pos + 1 < order.len() {
+                let ends_with_hyphen = tokens[i].ends_with('-') && tokens[i].len() > 1;
+                if !ends_with_hyphen {
+                    s.push(' ');
+                }
+            }
+        }
+    }
+    s
+}
+
+// Animation applied to the active karaoke word
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum WordAnimation {
+    Static, // "none": color change only, no scaling
+    Pop,    // scale up and back while the word is spoken
+    Fade,   // cross-fade from the base color into the highlight color
+    Fill,   // highlight sweeps left-to-right across the word as it is spoken (\kf)
+}
+
+// Whether karaokeTiming asks to spread each segment over its words instead of using the
+// transcribed word timings
+fn parse_karaoke_timing(timing: Option<&str>) -> Result<bool> {
+    match timing {
+        None | Some("words") => Ok(false),
+        Some("estimate") => Ok(true),
+        Some(other) => Err(anyhow!(
+            "Unknown karaokeTiming '{}': use words or estimate",
+            other
+        )),
+    }
+}
+
+// Whether `reveal` asks for the typewriter reveal ("typewriter") or none ("none", the default)
+fn parse_reveal(reveal: Option<&str>) -> Result<bool> {
+    match reveal {
+        None | Some("none") => Ok(false),
+        Some("typewriter") => Ok(true),
+        Some(other) => Err(anyhow!(
+            "Unknown reveal '{}': use none or typewriter",
+            other
+        )),
+    }
+}
+
+// No animation keeps the classic stretch + size bump
+fn parse_word_animation(animation: Option<&str>) -> Result<Option<WordAnimation>> {
+    let Some(animation) = animation else {
+        return Ok(None);
+    };
+    Ok(Some(match animation {
+        "none" => WordAnimation::Static,
+        "pop" => WordAnimation::Pop,
+        "fade" => WordAnimation::Fade,
+        "fill" => WordAnimation::Fill,
+        other => {
+            return Err(anyhow!(
+                "Unknown animation '{}': use none, pop, fade or fill",
+                other
+            ))
+        }
+    }))
+}
+
+// (peak, settle) in ms relative to the word start, bounded by the word's own span
+fn word_anim_timing(word: &WordSpan, window_ms: i64) -> (i64, i64) {
+    let word_ms = (word.end_ms.saturating_sub(word.start_ms) as i64).clamp(1, window_ms.max(1));
+    let peak_ms = (word_ms / 3).clamp(1, POP_UP_MAX_MS);
+    (peak_ms, word_ms)
+}
+
+// Override tags for (other words, highlighted word) in one karaoke window
+fn karaoke_run_styles(
+    style: &AssStyle,
+    text_fill: &FillColor,
+    hi_fill: &FillColor,
+    word: &WordSpan,
+    window_ms: i64,
+) -> (String, String) {
+    let fs = style.font_size;
+    let Some(animation) = style.animation else {
+        let big = (fs as f32 * BIG_FONT_SIZE_MULTIPLIER) as u32;
+        return (
+            format!("{{{}\\fs{}}}", text_fill.tags(), fs),
+            format!("{{{}\\fs{}}}", hi_fill.tags(), big),
+        );
+    };
+
+    let (text_tags, hi_tags) = (text_fill.tags(), hi_fill.tags());
+    // Reset scale on every run so the animated word doesn't leak into the rest of the line
+    let white = format!("{{{text_tags}\\fs{fs}\\fscx100\\fscy100}}");
+    let (peak_ms, settle_ms) = word_anim_timing(word, window_ms);
+    if animation == WordAnimation::Fill {
+        // \kf sweeps \2c -> \1c over the word. A karaoke syllable runs to the next \k tag,
+        // so the other words get a zero-length \k0 to keep them out of the sweep.
+        let base_bgr = &text_fill.bgr;
+        let fill_cs = (fill_duration_ms(word, window_ms) / 10).max(1);
+        return (
+            format!("{{{text_tags}\\fs{fs}\\fscx100\\fscy100\\k0}}"),
+            format!("{{{hi_tags}\\2c&H{base_bgr}&\\fs{fs}\\fscx100\\fscy100\\kf{fill_cs}}}"),
+        );
+    }
+    let hi = match animation {
+        WordAnimation::Static => format!("{{{hi_tags}\\fs{fs}\\fscx100\\fscy100}}"),
+        WordAnimation::Pop => {
+            let peak = (POP_SCALE_PEAK * 100.0).round() as u32;
+            format!(
+                "{{{hi_tags}\\fs{fs}\\fscx100\\fscy100\\t(0,{peak_ms},\\fscx{peak}\\fscy{peak})\\t({peak_ms},{settle_ms},\\fscx100\\fscy100)}}"
+            )
+        }
+        WordAnimation::Fade | WordAnimation::Fill => {
+            format!("{{{text_tags}\\fs{fs}\\fscx100\\fscy100\\t(0,{peak_ms},{hi_tags})}}")
+        }
+    };
+    (white, hi)
+}
+
+// A fill sweeps across the whole spoken word, bounded by its karaoke window
+fn fill_duration_ms(word: &WordSpan, window_ms: i64) -> i64 {
+    (word.end_ms.saturating_sub(word.start_ms) as i64).clamp(1, window_ms.max(1))
+}
+
+// Percentage of a "fill" word swept at `timestamp_ms` (the cue start when absent)
+fn fill_pct_at(phase: &crate::types::AnimationPhase, timestamp_ms: Option<u64>) -> f32 {
+    let now = timestamp_ms.unwrap_or(phase.start_ms);
+    let span = phase.end_ms.saturating_sub(phase.start_ms).max(1);
+    (now.saturating_sub(phase.start_ms) as f32 / span as f32).clamp(0.0, 1.0) * 100.0
+}
+
+// Editor hint mirroring karaoke_run_styles for the highlighted word of a preview cue
+fn word_animation_phase(
+    animation: Option<WordAnimation>,
+    word: &WordSpan,
+    cue_start_ms: u64,
+    window_ms: i64,
+) -> Option<crate::types::AnimationPhase> {
+    let (kind, peak_scale) = match animation? {
+        WordAnimation::Static => return None,
+        WordAnimation::Pop => ("pop", POP_SCALE_PEAK),
+        WordAnimation::Fade => ("fade", 1.0),
+        WordAnimation::Fill => {
+            let fill_ms = fill_duration_ms(word, window_ms) as u64;
+            return Some(crate::types::AnimationPhase {
+                kind: "fill".to_string(),
+                start_ms: cue_start_ms,
+                peak_ms: cue_start_ms + fill_ms,
+                end_ms: cue_start_ms + fill_ms,
+                peak_scale: 1.0,
+            });
+        }
+    };
+    let (peak_ms, settle_ms) = word_anim_timing(word, window_ms);
+    Some(crate::types::AnimationPhase {
+        kind: kind.to_string(),
+        start_ms: cue_start_ms,
+        peak_ms: cue_start_ms + peak_ms as u64,
+        end_ms: cue_start_ms + settle_ms as u64,
+        peak_scale,
+    })
+}
+
+struct AssStyle {
+    font_name: String,
+    font_size: u32,
+    primary: String,   // base (white)
+    secondary: String, // unused here
+    outline: String,
+    outline_w: u32,
+    shadow: u32,
+    align: u32,                         // 1..9 grid; 2 = bottom-center
+    margin_v: u32,                      // pixels
+    highlight: String,                  // green for current word
+    max_chars_per_line: Option<usize>,  // user wrap width; overrides the font-size estimate
+    max_words_per_cue: Option<usize>,   // karaoke: max words visible at a time
+    animation: Option<WordAnimation>,   // karaoke: active word animation (None = classic stretch)
+    estimate_word_timing: bool,         // karaoke: derive word timings from segment timing
+    text_transform: TextTransform,      // display case applied to every word
+    strip_punctuation: bool,            // trim sentence punctuation off displayed words
+    profanity: Option<ProfanityFilter>, // mask or drop listed words at display time
+    replacements: Vec<Replacement>,     // find/replace rules applied before layout
+    vertical: bool,                     // top-to-bottom column layout (CJK)
+    drop_shadow: Option<String>,        // override tags for the offset shadow layer
+    background: Option<BackgroundBox>,  // padded box behind each cue
+    gradient: Option<(String, String)>, // fill stops (BBGGRR, top then bottom); primary = top stop
+    emoji_font: Option<String>,         // family switched to for emoji clusters; None drops them
+    fade_ms: u32,                       // per-cue fade in/out (0 = hard cut)
+    min_cue_ms: Option<u64>,            // shorter phrases are extended into the next gap
+    max_cue_ms: Option<u64>,            // longer phrases are split at word boundaries
+    typewriter: bool,                   // reveal each cue a character at a time
+    safe_top: u32,                      // pixels kept clear at the top (safe area)
+    safe_bottom: u32,                   // pixels kept clear at the bottom (safe area)
+    safe_x: u32,                        // pixels kept clear at each side (safe area)
+    balance_lines: bool,                // even out the lengths of a wrapped cue's lines
 }
 
 fn _pct_to_margin_v(frame_h: u32, y_pct_from_top: f32) -> u32 {
@@ -1825,7 +6196,7 @@ ScaledBorderAndShadow: yes
 
 [V4+ Styles]
 Format: Name,Fontname,Fontsize,PrimaryColour,SecondaryColour,OutlineColour,BackColour,Bold,Italic,Underline,StrikeOut,ScaleX,ScaleY,Spacing,Angle,BorderStyle,Outline,Shadow,Alignment,MarginL,MarginR,MarginV,Encoding
-Style: TikTok,{font},{size},{pri},{sec},{out},&H64000000,0,0,0,0,100,100,0,0,1,{ow},{sh},{al},60,60,{mv},1
+Style: TikTok,{font},{size},{pri},{sec},{out},&H64000000,0,0,0,0,100,100,0,0,1,{ow},{sh},{al},{mx},{mx},{mv},1
 
 [Events]
 Format: Layer,Start,End,Style,Name,MarginL,MarginR,MarginV,Effect,Text
@@ -1840,155 +6211,232 @@ Format: Layer,Start,End,Style,Name,MarginL,MarginR,MarginV,Effect,Text
         ow = style.outline_w,
         sh = style.shadow,
         al = style.align,
+        mx = style.safe_x.max(60),
         mv = style.margin_v
     );
 
     let mut lines = String::new();
+    let layout_w = layout_width(style, w);
+    let typewriter = style
+        .typewriter
+        .then(|| FontMetrics::load(&style.font_name));
 
-    if karaoke {
-        let phrases = coalesce_phrases(segments);
-        let white_bgr = bgr_from_aa_bgrr(&style.primary);
-        let hi_bgr = bgr_from_aa_bgrr(&style.highlight);
+    if style.vertical {
+        push_vertical_events(&mut lines, w, h, style, segments, karaoke, glow_effect);
+    } else if karaoke {
+        let phrases = limit_phrase_words(timed_phrases(segments, style), style.max_words_per_cue);
+        let (text_fill, hi_fill) = fill_colors(style);
 
         // Simple single-line karaoke: split phrases that are too wide, then process each segment
         for ph in phrases {
-            let tokens_upper = normalize_tokens(&ph.spans);
-            let segments = if multiline {
-                split_phrase_two_lines(&tokens_upper, &ph.spans, w, style.font_size)
-            } else {
-                split_phrase_for_width(&tokens_upper, &ph.spans, w, style.font_size)
+            let tokens_upper = normalize_tokens(&ph.spans, style);
+            let segments = if let Some(max_chars) = style.max_chars_per_line {
+                split_phrase_max_chars(&tokens_upper, &ph.spans, max_chars, 2)
+                    .into_iter()
+                    .map(|(t, s)| {
+                        let split = first_line_break(&t, max_chars, style.balance_lines);
+                        (t, s, split)
+                    })
+                    .collect()
+            } else if multiline {
+                split_phrase_two_lines(
+                    &tokens_upper,
+                    &ph.spans,
+                    layout_w,
+                    style.font_size,
+                    style.balance_lines,
+                )
+            } else {
+                split_phrase_for_width(&tokens_upper, &ph.spans, layout_w, style.font_size)
                     .into_iter()
                     .map(|(t, s)| (t, s, usize::MAX))
                     .collect()
             };
 
-            // Calculate Y position based on alignment
-            let y_pos = match style.align {
-                5 => (h / 2) as i32,                            // Middle center
-                _ => (h as i32 - style.margin_v as i32).max(0), // Bottom center
-            };
-
             // Process each width-appropriate segment
             for (segment_tokens, segment_spans, split_idx) in segments {
                 let windows = contiguous_cs_windows(&segment_spans);
+                let (align, x_pos, y_pos) =
+                    cue_anchor(style, ph.position, is_rtl_tokens(&segment_tokens), (w, h));
+
+                let line_chars = split_line_chars(&segment_tokens, split_idx);
+                let bands = typewriter
+                    .as_ref()
+                    .filter(|_| !is_rtl_tokens(&segment_tokens))
+                    .map(|metrics| {
+                        typewriter_bands(
+                            &segment_tokens,
+                            &segment_spans,
+                            &split_token_lines(segment_tokens.len(), split_idx),
+                            metrics,
+                            style,
+                            (x_pos, y_pos),
+                            align,
+                            (w, h),
+                        )
+                    })
+                    .unwrap_or_default();
+                let cue_cs = (windows[0].0, windows[windows.len() - 1].1);
+                let mut cue =
+                    CueEvents::new(&mut lines, cue_cs, style.fade_ms, w).with_reveal(bands);
+                if let Some(bg) = &style.background {
+                    let rect = background_rect(
+                        &line_chars,
+                        style.font_size,
+                        x_pos as f32,
+                        y_pos as f32,
+                        align,
+                        false,
+                    );
+                    push_background_box(&mut cue, cue_cs, bg, &rect);
+                }
 
                 for (i, (cs0, cs1)) in windows.iter().enumerate() {
                     let dur_ms = (cs1 - cs0) * 10;
                     let blur_value = if glow_effect { 6.0 } else { 2.0 };
+                    // Per-word animations replace the whole-line stretch
+                    let stretch = if style.animation.is_some() {
+                        String::new()
+                    } else {
+                        stretch_tag_ms(dur_ms)
+                    };
+                    let (white_style, hi_style) =
+                        karaoke_run_styles(style, &text_fill, &hi_fill, &segment_spans[i], dur_ms);
 
                     let header = format!(
                         "{{\\an{}\\q2\\pos({},{})\\bord{}\\blur{:.1}}}{}",
-                        style.align,
-                        (w / 2),
-                        y_pos,
-                        style.outline_w,
-                        blur_value,
-                        stretch_tag_ms(dur_ms)
+                        align, x_pos, y_pos, style.outline_w, blur_value, stretch
                     );
 
+                    if let Some(tags) = &style.drop_shadow {
+                        let body = assemble_styled_lines(
+                            &segment_tokens,
+                            i,
+                            &white_style,
+                            &hi_style,
+                            split_idx,
+                            &stretch,
+                        );
+                        push_shadow_layer(&mut cue, (*cs0, *cs1), &body, x_pos, y_pos, align, tags);
+                    }
+
                     if glow_effect {
                         // Glow layer
                         let glow_header = format!(
                         "{{\\an{}\\q2\\pos({},{})\\1a&HFF\\bord{}\\3c&HFFFFFF&\\3a&H80\\blur{:.1}\\shad0}}{}",
-                        style.align, (w/2), y_pos,
+                        align, x_pos, y_pos,
                         style.outline_w as f32 * 2.0,
                         6.0,
-                        stretch_tag_ms(dur_ms)
+                        stretch
                     );
-                        let glow_text = assemble_colored_two_lines(
+                        let glow_text = assemble_styled_lines(
                             &segment_tokens,
                             i,
-                            &white_bgr,
-                            &hi_bgr,
+                            &white_style,
+                            &hi_style,
                             split_idx,
                             &glow_header,
-                            style.font_size,
                         );
-                        lines.push_str(&format!(
-                            "Dialogue: 0,{},{},TikTok,,0,0,0,,{}\n",
-                            cs_to_ass(*cs0),
-                            cs_to_ass(*cs1),
-                            glow_text
-                        ));
+                        cue.push(0, (*cs0, *cs1), None, &glow_text);
 
                         // Main text layer
                         let main_header = format!(
                             "{{\\an{}\\q2\\pos({},{})\\bord{}\\blur0\\shad0}}{}",
-                            style.align,
-                            (w / 2),
-                            y_pos,
-                            style.outline_w,
-                            stretch_tag_ms(dur_ms)
+                            align, x_pos, y_pos, style.outline_w, stretch
                         );
-                        let main_text = assemble_colored_two_lines(
+                        let main_text = assemble_styled_lines(
                             &segment_tokens,
                             i,
-                            &white_bgr,
-                            &hi_bgr,
+                            &white_style,
+                            &hi_style,
                             split_idx,
                             &main_header,
-                            style.font_size,
                         );
-                        lines.push_str(&format!(
-                            "Dialogue: 1,{},{},TikTok,,0,0,0,,{}\n",
-                            cs_to_ass(*cs0),
-                            cs_to_ass(*cs1),
-                            main_text
-                        ));
+                        cue.push(1, (*cs0, *cs1), None, &main_text);
                     } else {
                         // Single layer
-                        let text = assemble_colored_two_lines(
+                        let text = assemble_styled_lines(
                             &segment_tokens,
                             i,
-                            &white_bgr,
-                            &hi_bgr,
+                            &white_style,
+                            &hi_style,
                             split_idx,
                             &header,
+                        );
+                        cue.push(0, (*cs0, *cs1), None, &text);
+                    }
+
+                    if let Some(stops) = &style.gradient {
+                        let body = assemble_styled_lines(
+                            &segment_tokens,
+                            i,
+                            &white_style,
+                            &hi_style,
+                            split_idx,
+                            &stretch,
+                        );
+                        let rect = text_rect(
+                            &line_chars,
                             style.font_size,
+                            x_pos as f32,
+                            y_pos as f32,
+                            align,
+                            false,
+                        );
+                        push_gradient_bands(
+                            &mut cue,
+                            (*cs0, *cs1),
+                            &body,
+                            &text_fill,
+                            stops,
+                            &rect,
+                            (w, h),
+                            x_pos,
+                            y_pos,
+                            align,
                         );
-                        lines.push_str(&format!(
-                            "Dialogue: 0,{},{},TikTok,,0,0,0,,{}\n",
-                            cs_to_ass(*cs0),
-                            cs_to_ass(*cs1),
-                            text
-                        ));
                     }
                 }
             }
         }
     } else {
-        let white_bgr = bgr_from_aa_bgrr(&style.primary);
-        let hi_bgr = bgr_from_aa_bgrr(&style.highlight);
-        let x = (w / 2) as i32;
-        // Calculate Y position based on alignment
-        let y = match style.align {
-            5 => (h / 2) as i32, // Middle center - use actual center of frame
-            _ => (h as i32 - style.margin_v as i32).max(0), // Bottom center - use margin
-        };
+        let (text_fill, hi_fill) = fill_colors(style);
 
-        let phrases = coalesce_phrases(segments);
+        let phrases = timed_phrases(segments, style);
 
         // NEW: state for smart highlighting
         let mut hl_state = HighlightState::new(segments);
 
         for (p_idx, phrase) in phrases.iter().enumerate() {
             eprintln!("DEBUG: Processing phrase {}/{}", p_idx, phrases.len());
-            let tokens_upper = normalize_tokens(&phrase.spans);
+            let tokens_upper = normalize_tokens(&phrase.spans, style);
 
             // Split phrase into segments suitable for the current style
-            let segments = if style.align == 5 {
+            let segments = match style.max_chars_per_line {
+                // Explicit wrap width: storyteller keeps up to 4 lines, standard up to 2
+                Some(max_chars) => split_phrase_max_chars(
+                    &tokens_upper,
+                    &phrase.spans,
+                    max_chars,
+                    if style.align == 5 { 4 } else { 2 },
+                ),
                 // Storyteller: multiple lines per segment
-                split_phrase_multiline(&tokens_upper, &phrase.spans, w, style.font_size)
-            } else {
+                None if style.align == 5 => {
+                    split_phrase_multiline(&tokens_upper, &phrase.spans, layout_w, style.font_size)
+                }
                 // Standard: 1-2 lines max
-                split_phrase_for_width(&tokens_upper, &phrase.spans, w, style.font_size)
+                None => {
+                    split_phrase_for_width(&tokens_upper, &phrase.spans, layout_w, style.font_size)
+                }
             };
 
             for (segment_tokens, segment_spans) in segments {
                 let segment_tokens_orig = original_tokens(&segment_spans);
 
-                let start = cs_to_ass(ms_to_cs(segment_spans.first().unwrap().start_ms));
-                let end = cs_to_ass(ms_to_cs(segment_spans.last().unwrap().end_ms));
+                let cue_cs = (
+                    ms_to_cs(segment_spans.first().unwrap().start_ms),
+                    ms_to_cs(segment_spans.last().unwrap().end_ms),
+                );
 
                 // Decide which single word (if any) to highlight in this segment
                 let hi_opt = choose_highlight_idx(
@@ -2001,40 +6449,53 @@ Format: Layer,Start,End,Style,Name,MarginL,MarginR,MarginV,Effect,Text
 
                 let is_storyteller = style.align == 5; // Safe-center / Storyteller mode
 
-                // Build text body
-                let text_body = if is_storyteller {
+                // Build text body, the character count of each rendered line and the
+                // tokens on each line
+                let (text_body, line_chars, token_lines) = if is_storyteller {
                     // For storyteller, use multiline assembly with dynamic width balancing
-                    let est_char_width = (style.font_size as f32 * 0.50).max(1.0);
-                    let max_chars = ((w as f32 * 0.85) / est_char_width).floor() as usize;
-
-                    let total_chars: usize = segment_tokens.iter().map(|t| t.len()).sum();
-                    // Target 3-5 lines for a nice block
-                    let soft_target = (total_chars as f32 / 3.5).ceil() as usize;
-                    // Clamp: at least 25 chars (for long words), at most max_chars
-                    let min_chars = 25.min(max_chars).max(1);
-                    let wrapping_width = soft_target.clamp(min_chars, max_chars);
+                    let wrapping_width = storyteller_wrap_width(&segment_tokens, layout_w, style);
 
                     // Prepend bounce tag for entrance
                     let mut body = bounce_tag();
                     body.push_str(&assemble_multiline(
                         &segment_tokens,
                         hi_idx,
-                        &white_bgr,
-                        &hi_bgr,
+                        &text_fill,
+                        &hi_fill,
                         style.font_size,
                         wrapping_width,
+                        style.balance_lines,
                     ));
-                    body
+                    let token_lines =
+                        wrap_multiline(&segment_tokens, wrapping_width, style.balance_lines);
+                    let line_chars = token_lines
+                        .iter()
+                        .map(|l| {
+                            let line: Vec<String> =
+                                l.iter().map(|&i| segment_tokens[i].clone()).collect();
+                            joined_chars(&line)
+                        })
+                        .collect();
+                    (body, line_chars, token_lines)
                 } else {
                     // Standard 1-2 line assembly
-                    assemble_colored_two_lines(
+                    // no line break forced unless a wrap width was requested
+                    let split = style.max_chars_per_line.map_or(usize::MAX, |m| {
+                        first_line_break(&segment_tokens, m, style.balance_lines)
+                    });
+                    let body = assemble_colored_two_lines(
                         &segment_tokens,
                         hi_idx,
-                        &white_bgr,
-                        &hi_bgr,
-                        usize::MAX, // no line break forced here, let it flow or use split logic
+                        &text_fill,
+                        &hi_fill,
+                        split,
                         &bounce_tag(), // entrance scale
                         style.font_size,
+                    );
+                    (
+                        body,
+                        split_line_chars(&segment_tokens, split),
+                        split_token_lines(segment_tokens.len(), split),
                     )
                 };
 
@@ -2042,11 +6503,48 @@ Format: Layer,Start,End,Style,Name,MarginL,MarginR,MarginV,Effect,Text
                 let glow_w = style.outline_w as f32 * 2.0;
                 let glow_blur = 6.0;
                 let stroke_w = style.outline_w as f32;
-
+                let (align, x, y) = cue_anchor(
+                    style,
+                    phrase.position,
+                    is_rtl_tokens(&segment_tokens),
+                    (w, h),
+                );
+                let bands = typewriter
+                    .as_ref()
+                    .filter(|_| !is_rtl_tokens(&segment_tokens))
+                    .map(|metrics| {
+                        typewriter_bands(
+                            &segment_tokens,
+                            &segment_spans,
+                            &token_lines,
+                            metrics,
+                            style,
+                            (x, y),
+                            align,
+                            (w, h),
+                        )
+                    })
+                    .unwrap_or_default();
+                let mut cue =
+                    CueEvents::new(&mut lines, cue_cs, style.fade_ms, w).with_reveal(bands);
+
+                if let Some(bg) = &style.background {
+                    let rect = background_rect(
+                        &line_chars,
+                        style.font_size,
+                        x as f32,
+                        y as f32,
+                        align,
+                        false,
+                    );
+                    push_background_box(&mut cue, cue_cs, bg, &rect);
+                }
+                if let Some(tags) = &style.drop_shadow {
+                    push_shadow_layer(&mut cue, cue_cs, &text_body, x, y, align, tags);
+                }
                 push_glow_and_stroke(
-                    &mut lines,
-                    &start,
-                    &end,
+                    &mut cue,
+                    cue_cs,
                     &text_body,
                     x,
                     y,
@@ -2054,14 +6552,36 @@ Format: Layer,Start,End,Style,Name,MarginL,MarginR,MarginV,Effect,Text
                     glow_effect, // Use the parameter to control glow
                     glow_w,
                     glow_blur,
-                    "&H80",      // ~50% white glow
-                    style.align, // Pass the alignment from style
+                    "&H80", // ~50% white glow
+                    align,  // Style alignment (right column for RTL)
                 );
+                if let Some(stops) = &style.gradient {
+                    let rect = text_rect(
+                        &line_chars,
+                        style.font_size,
+                        x as f32,
+                        y as f32,
+                        align,
+                        false,
+                    );
+                    push_gradient_bands(
+                        &mut cue,
+                        cue_cs,
+                        &text_body,
+                        &text_fill,
+                        stops,
+                        &rect,
+                        (w, h),
+                        x,
+                        y,
+                        align,
+                    );
+                }
             }
         }
     }
 
-    Ok(header + &lines)
+    Ok(header + &wrap_emoji_runs(&lines, style.emoji_font.as_deref(), &style.font_name))
 }
 
 /// Calculate proportional font size that maintains consistent appearance across different aspect ratios
@@ -2106,10 +6626,18 @@ fn default_ass_style(
     _glow_effect: bool,
     position: Option<&str>,
     font_size: Option<u32>,
+    options: &CaptionStyleOptions,
 ) -> AssStyle {
-    // Convert hex colors to ASS format (AABBGGRR), use defaults if None
-    let primary = text_color
-        .map(hex_to_ass_color)
+    // Convert hex colors to ASS format (AABBGGRR), use defaults if None.
+    // A gradient's top stop doubles as the solid fill where bands aren't drawn.
+    let gradient = options
+        .text_gradient
+        .as_ref()
+        .map(|(a, b)| (hex_to_ass_color(a), hex_to_ass_color(b)));
+    let primary = gradient
+        .as_ref()
+        .map(|(top, _)| top.clone())
+        .or_else(|| text_color.map(hex_to_ass_color))
         .unwrap_or_else(|| "&H00FFFFFF".into());
     let highlight = highlight_color
         .map(hex_to_ass_color)
@@ -2130,6 +6658,16 @@ fn default_ass_style(
         "safe-center" => (5, 0),              // Legacy support: Middle center
         _ => (2, pct_h(12.0)),                // Bottom center, 12% from bottom (default)
     };
+    // The safe area only ever moves captions inwards
+    let safe_pct = |p: Option<f32>| p.filter(|p| p.is_finite()).unwrap_or(0.0).clamp(0.0, 45.0);
+    let safe_top = pct_h(safe_pct(options.safe_margin_top_pct));
+    let safe_bottom = pct_h(safe_pct(options.safe_margin_bottom_pct));
+    let safe_x = (frame_w as f32 * safe_pct(options.safe_margin_x_pct) / 100.0).round() as u32;
+    let margin_v = match align {
+        8 => margin_v.max(safe_top),
+        2 => margin_v.max(safe_bottom),
+        _ => margin_v,
+    };
 
     AssStyle {
         font_name: font_name.unwrap_or("Montserrat Black").into(),
@@ -2137,11 +6675,42 @@ fn default_ass_style(
         primary: primary.clone(),
         secondary: primary,
         outline,
-        outline_w: 4,
+        outline_w: OUTLINE_W,
         shadow: 0,
         align,
         margin_v,
         highlight,
+        max_chars_per_line: options.max_chars_per_line.map(|n| n.max(1) as usize),
+        max_words_per_cue: options.max_words_per_cue.map(|n| n.max(1) as usize),
+        // Requests are validated first, so an unknown animation never gets here
+        animation: parse_word_animation(options.animation.as_deref()).unwrap_or_default(),
+        estimate_word_timing: parse_karaoke_timing(options.karaoke_timing.as_deref())
+            .unwrap_or_default(),
+        text_transform: TextTransform::parse(options.text_transform.as_deref())
+            .unwrap_or(TextTransform::Upper),
+        strip_punctuation: options.strip_punctuation,
+        // Requests are validated first, so a rule that doesn't compile never gets here
+        replacements: compile_replacements(&options.replacements).unwrap_or_default(),
+        profanity: ProfanityFilter::new(
+            options.profanity_filter.as_deref(),
+            &options.profanity_words,
+        ),
+        vertical: options.writing_mode.as_deref() == Some("vertical"),
+        drop_shadow: options
+            .shadow
+            .as_ref()
+            .map(|sh| shadow_layer_tags(sh, OUTLINE_W)),
+        background: background_box(options),
+        gradient: gradient.map(|(a, b)| (bgr_from_aa_bgrr(&a), bgr_from_aa_bgrr(&b))),
+        emoji_font: emoji_burn_font(options.emoji_font.as_deref()),
+        fade_ms: options.fade_ms.unwrap_or(0),
+        min_cue_ms: options.min_cue_ms.map(u64::from),
+        max_cue_ms: options.max_cue_ms.map(|ms| u64::from(ms.max(1))),
+        typewriter: parse_reveal(options.reveal.as_deref()).unwrap_or_default(),
+        safe_top,
+        safe_bottom,
+        safe_x,
+        balance_lines: options.balance_lines,
     }
 }
 
@@ -2246,229 +6815,2627 @@ fn split_phrase_multiline(
     segments
 }
 
+// Wrap width (in characters) for a storyteller block, shared by burn and preview
+// so both break lines at the same words
+fn storyteller_wrap_width(tokens: &[String], frame_w: u32, style: &AssStyle) -> usize {
+    let est_char_width = (style.font_size as f32 * 0.50).max(1.0);
+    let max_chars = ((frame_w as f32 * 0.85) / est_char_width).floor() as usize;
+
+    let total_chars: usize = tokens.iter().map(|t| t.len()).sum();
+    // Target 3-5 lines for a nice block
+    let soft_target = (total_chars as f32 / 3.5).ceil() as usize;
+    // Clamp: at least 25 chars (for long words), at most max_chars
+    let min_chars = 25.min(max_chars).max(1);
+    style
+        .max_chars_per_line
+        .unwrap_or_else(|| soft_target.clamp(min_chars, max_chars))
+}
+
+// Wrap for the storyteller block: lines of token indices in logical order.
+// Lengths are measured on the escaped text, as rendered by `assemble_multiline`;
+// with `balance` the greedy line count is kept and the breaks are evened out.
+fn wrap_multiline(tokens: &[String], max_chars_per_line: usize, balance: bool) -> Vec<Vec<usize>> {
+    let lines = greedy_wrap_multiline(tokens, max_chars_per_line);
+    if !balance || lines.len() < 2 {
+        return lines;
+    }
+    let Some(starts) = balanced_line_starts(tokens, max_chars_per_line, lines.len()) else {
+        return lines;
+    };
+    let bounds: Vec<usize> = std::iter::once(0)
+        .chain(starts)
+        .chain(std::iter::once(tokens.len()))
+        .collect();
+    bounds.windows(2).map(|w| (w[0]..w[1]).collect()).collect()
+}
+
+fn greedy_wrap_multiline(tokens: &[String], max_chars_per_line: usize) -> Vec<Vec<usize>> {
+    let ends_with_hyphen = |t: &str| t.ends_with('-') && t.len() > 1;
+    let mut lines: Vec<Vec<usize>> = vec![Vec::new()];
+    let mut line_len = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        let t_len = token.len() + token.matches(['\\', '{', '}']).count();
+        // Check if previous token ended with hyphen to suppress space
+        let prev_ended_with_hyphen = i > 0 && ends_with_hyphen(&tokens[i - 1]);
+
+        if line_len > 0 && line_len + t_len + 1 > max_chars_per_line {
+            lines.push(Vec::new());
+            line_len = 0;
+        } else if line_len > 0 && !prev_ended_with_hyphen {
+            line_len += 1;
+        }
+        lines.last_mut().unwrap().push(i);
+        line_len += t_len;
+    }
+    lines
+}
+
 // Assemble multi-line text with highlighting
 fn assemble_multiline(
     tokens: &[String],
     hi: usize,
-    white_bgr: &str,
-    hi_bgr: &str,
+    text_fill: &FillColor,
+    hi_fill: &FillColor,
     font_size: u32,
     max_chars_per_line: usize,
+    balance: bool,
 ) -> String {
     eprintln!("DEBUG: assemble_multiline start. tokens={}", tokens.len());
     // Similar to assemble_colored_two_lines but auto-wraps based on max_chars
-    let white = format!("{{\\1c&H{}&\\fs{}}}", white_bgr, font_size);
+    let white = format!("{{{}\\fs{}}}", text_fill.tags(), font_size);
     // Bigger font for highlight? Maybe not for block text, it might shift layout too much.
     // Let's keep same size for stability in 4-line blocks.
     let has_highlighting = hi != usize::MAX;
     let hi_style = if has_highlighting {
-        format!("{{\\1c&H{}&\\fs{}}}", hi_bgr, font_size)
+        format!("{{{}\\fs{}}}", hi_fill.tags(), font_size)
     } else {
         white.clone()
     };
 
-    let mut s = String::new();
-    let mut line_len = 0;
+    let escape = |t: &str| {
+        t.replace('\\', r"\\")
+            .replace('{', r"\{")
+            .replace('}', r"\}")
+    };
+    let ends_with_hyphen = |t: &str| t.ends_with('-') && t.len() > 1;
+
+    // First pass: wrap into lines of token indices (logical order)
+    let lines = wrap_multiline(tokens, max_chars_per_line, balance);
+
+    // Second pass: emit each line in display order
+    let rtl = is_rtl_tokens(tokens);
+    let mut s = String::new();
+    for (line_no, line) in lines.iter().filter(|l| !l.is_empty()).enumerate() {
+        if line_no > 0 {
+            s.push_str(r"\N");
+        }
+        let line_tokens: Vec<String> = line.iter().map(|&i| tokens[i].clone()).collect();
+        let order = bidi_visual_order(&line_tokens, rtl);
+        for (pos, &k) in order.iter().enumerate() {
+            let i = line[k];
+            if pos > 0 && !ends_with_hyphen(&tokens[line[order[pos - 1]]]) {
+                s.push(' ');
+            }
+            // Color logic
+            let should_highlight = has_highlighting && i == hi;
+            s.push_str(if should_highlight { &hi_style } else { &white });
+            s.push_str(&escape(&tokens[i]));
+        }
+    }
+    s
+}
+
+/// A parsed `#rgb`, `#rrggbb` or `#rrggbbaa` color (alpha 255 = opaque)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct HexColor {
+    r: u8,
+    g: u8,
+    b: u8,
+    a: u8,
+}
+
+impl HexColor {
+    const WHITE: HexColor = HexColor {
+        r: 255,
+        g: 255,
+        b: 255,
+        a: 255,
+    };
+
+    /// ffmpeg color syntax, `0xRRGGBB@alpha`
+    fn to_ffmpeg(self) -> String {
+        format!(
+            "0x{:02X}{:02X}{:02X}@{:.2}",
+            self.r,
+            self.g,
+            self.b,
+            self.a as f32 / 255.0
+        )
+    }
+}
+
+fn parse_hex_color(hex: &str) -> Option<HexColor> {
+    let hex = hex.trim();
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let byte = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).ok();
+    match digits.len() {
+        // #rgb doubles each digit: #f80 == #ff8800
+        3 => {
+            let nibble = |i: usize| {
+                u8::from_str_radix(&digits[i..i + 1], 16)
+                    .ok()
+                    .map(|n| n * 17)
+            };
+            Some(HexColor {
+                r: nibble(0)?,
+                g: nibble(1)?,
+                b: nibble(2)?,
+                a: 255,
+            })
+        }
+        6 | 8 => Some(HexColor {
+            r: byte(0)?,
+            g: byte(2)?,
+            b: byte(4)?,
+            a: if digits.len() == 8 { byte(6)? } else { 255 },
+        }),
+        _ => None,
+    }
+}
+
+/// Reject unparseable colors (naming the request field, instead of silently rendering
+/// them white) and replacement rules that don't compile
+fn validate_caption_style(
+    text_color: Option<&str>,
+    highlight_word_color: Option<&str>,
+    outline_color: Option<&str>,
+    style_options: &CaptionStyleOptions,
+) -> Result<()> {
+    let gradient = style_options.text_gradient.as_ref();
+    let fields = [
+        ("textColor", text_color),
+        ("highlightWordColor", highlight_word_color),
+        ("outlineColor", outline_color),
+        ("backgroundColor", style_options.background_color.as_deref()),
+        ("textGradient[0]", gradient.map(|(top, _)| top.as_str())),
+        (
+            "textGradient[1]",
+            gradient.map(|(_, bottom)| bottom.as_str()),
+        ),
+        (
+            "shadow.color",
+            style_options
+                .shadow
+                .as_ref()
+                .and_then(|s| s.color.as_deref()),
+        ),
+    ];
+    for (field, value) in fields {
+        if let Some(value) = value.filter(|v| parse_hex_color(v).is_none()) {
+            return Err(CodedError::new(
+                "INVALID_COLOR",
+                format!(
+                    "{} \"{}\" is not a valid color; use #rgb, #rrggbb or #rrggbbaa",
+                    field, value
+                ),
+            )
+            .into());
+        }
+    }
+    compile_replacements(&style_options.replacements)?;
+    parse_word_animation(style_options.animation.as_deref())?;
+    parse_karaoke_timing(style_options.karaoke_timing.as_deref())?;
+    TextTransform::parse(style_options.text_transform.as_deref())?;
+    parse_reveal(style_options.reveal.as_deref())?;
+    Ok(())
+}
+
+/// Convert hex color string (e.g., "#ffffff", "#ffffff80") to ASS color format (e.g., "&H00FFFFFF")
+fn hex_to_ass_color(hex: &str) -> String {
+    match parse_hex_color(hex) {
+        // ASS uses AABBGGRR format, and its alpha is inverted (00 = opaque, FF = invisible)
+        Some(c) => format!("&H{:02X}{:02X}{:02X}{:02X}", 255 - c.a, c.b, c.g, c.r),
+        None => "&H00FFFFFF".into(), // Default to white if invalid hex (requests are validated first)
+    }
+}
+
+// Split phrase into up to 2 lines for "Karaoke (Two Lines)" mode
+fn split_phrase_two_lines(
+    tokens: &[String],
+    spans: &[WordSpan],
+    frame_w: u32,
+    font_px: u32,
+    balance: bool,
+) -> Vec<(Vec<String>, Vec<WordSpan>, usize)> {
+    // Determine max chars per line
+    let est_char_width = (font_px as f32 * 0.7).max(1.0);
+    // Use slightly less width to be safe for 2 lines
+    let max_chars = ((frame_w as f32 * 0.9) / est_char_width).floor() as usize;
+
+    // Target total length for a "screenful" (2 lines)
+    // We want to fill 2 lines if possible, so max capacity = 2 * max_chars
+    let max_capacity_chars = max_chars * 2;
+
+    let split = |tokens: &[String]| {
+        let greedy = find_best_split(tokens, max_chars);
+        if balance && greedy < tokens.len() {
+            balanced_line_starts(tokens, max_chars, 2).map_or(greedy, |s| s[0])
+        } else {
+            greedy
+        }
+    };
+    let mut segments = Vec::new();
+    let mut current_tokens = Vec::new();
+    let mut current_spans = Vec::new();
+    let mut current_len = 0;
+
+    for (token, span) in tokens.iter().zip(spans.iter()) {
+        let token_len = token.len() + 1; // + space
+
+        if current_len > 0 && current_len + token_len > max_capacity_chars {
+            // Current 2-line block is full, push it
+            if !current_tokens.is_empty() {
+                let split_idx = split(&current_tokens);
+                segments.push((current_tokens.clone(), current_spans.clone(), split_idx));
+                current_tokens.clear();
+                current_spans.clear();
+                current_len = 0;
+            }
+        }
+
+        current_tokens.push(token.clone());
+        current_spans.push(span.clone());
+        current_len += token_len;
+    }
+
+    if !current_tokens.is_empty() {
+        let split_idx = split(&current_tokens);
+        segments.push((current_tokens, current_spans, split_idx));
+    }
+
+    segments
+}
+
+// Find best index to split tokens into 2 lines
+fn find_best_split(tokens: &[String], max_chars_per_line: usize) -> usize {
+    let mut current_len = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        let len = token.len() + 1;
+        if current_len + len > max_chars_per_line {
+            // This token makes it overflow, so split BEFORE this token
+            // i.e., line 1 ends at index i (0..i includes i items?)
+            // assemble_colored_two_lines takes line1_count.
+            // If we return i, line 1 has i items (0 to i-1). Item i starts line 2.
+            return i;
+        }
+        current_len += len;
+    }
+    // If it all fits in one line, return usize::MAX so it doesn't break
+    usize::MAX
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PreviewLayoutParams;
+
+    #[test]
+    fn test_preprocess_hyphenated_tokens_splits_time() {
+        let span = WordSpan {
+            start_ms: 0,
+            end_ms: 1000,
+            text: "FOO-BAR".to_string(),
+        };
+        let tokens = vec!["FOO-BAR".to_string()];
+        let spans = vec![span];
+
+        let (new_tokens, new_spans) = preprocess_hyphenated_tokens(&tokens, &spans);
+
+        assert_eq!(new_tokens.len(), 2);
+        assert_eq!(new_tokens[0], "FOO-");
+        assert_eq!(new_tokens[1], "BAR");
+
+        // "FOO-BAR" has 7 chars.
+        // "FOO-" is 4 chars.
+        // "BAR" is 3 chars.
+        // Duration 1000ms.
+        // Part 1: 1000 * 4/7 = 571ms.
+        // Part 2: 1000 * 3/7 = 428ms.
+
+        let s0 = new_spans[0].start_ms;
+        let e0 = new_spans[0].end_ms;
+        let s1 = new_spans[1].start_ms;
+        let e1 = new_spans[1].end_ms;
+
+        println!("Part 1 ({:?}): {} - {}", new_tokens[0], s0, e0);
+        println!("Part 2 ({:?}): {} - {}", new_tokens[1], s1, e1);
+
+        assert_eq!(s0, 0);
+        // We expect sequential times
+        assert!(e0 > 0);
+        assert_eq!(s1, e0); // Start of next = End of previous
+        assert_eq!(e1, 1000);
+
+        // Check approximate proportionality
+        let d0 = e0 - s0;
+        let d1 = e1 - s1;
+        assert!(d0 > d1); // 4 chars > 3 chars
+        assert!((d0 as i64 - 571).abs() < 5);
+    }
+
+    #[test]
+    fn test_assemble_colored_two_lines_hyphenation() {
+        let tokens = vec!["SAKSALAIS-".to_string(), "ROOMALAINEN".to_string()];
+
+        // We need to provide dummy args for assemble_colored_two_lines
+        // It requires: tokens, hi, text_fill, hi_fill, line1_count, header, font_size
+        let result = assemble_colored_two_lines(
+            &tokens,
+            usize::MAX, // no highlight
+            &opaque_fill("FFFFFF"),
+            &opaque_fill("0000FF"),
+            usize::MAX, // no break
+            "{\\an2}",
+            20,
+        );
+
+        println!("Result: {}", result);
+        assert!(
+            !result.contains("SAKSALAIS- "),
+            "Should not contain space after hyphen"
+        );
+    }
+
+    #[test]
+    fn test_assemble_multiline_hyphenation() {
+        let tokens = vec!["FOO-".to_string(), "BAR".to_string()];
+
+        let result = assemble_multiline(
+            &tokens,
+            usize::MAX,
+            &opaque_fill("FFFFFF"),
+            &opaque_fill("0000FF"),
+            20,
+            100,
+            false,
+        );
+
+        println!("Result: {}", result);
+        assert!(
+            !result.contains("FOO- "),
+            "Should not contain space after hyphen in multiline"
+        );
+    }
+
+    fn opaque_fill(bgr: &str) -> FillColor {
+        FillColor {
+            bgr: bgr.to_string(),
+            alpha: None,
+        }
+    }
+
+    fn spans_for(text: &str, word_ms: u64) -> Vec<WordSpan> {
+        text.split_whitespace()
+            .enumerate()
+            .map(|(i, w)| WordSpan {
+                start_ms: i as u64 * word_ms,
+                end_ms: (i as u64 + 1) * word_ms,
+                text: w.to_string(),
+            })
+            .collect()
+    }
+
+    fn preview_params(segments: Vec<CaptionSegment>, karaoke: bool) -> PreviewLayoutParams {
+        PreviewLayoutParams {
+            segments,
+            width: 1080,
+            height: 1920,
+            font_name: None,
+            font_size: None,
+            text_color: None,
+            highlight_word_color: None,
+            outline_color: None,
+            position: None,
+            karaoke,
+            multiline: false,
+            glow_effect: false,
+            timestamp_ms: None,
+            style_options: CaptionStyleOptions::default(),
+        }
+    }
+
+    #[test]
+    fn test_wrap_line_starts_breaks_at_spaces() {
+        let tokens: Vec<String> = ["THE", "QUICK", "BROWN", "FOX"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+        // "THE QUICK" = 9, "BROWN FOX" = 9
+        assert_eq!(wrap_line_starts(&tokens, 10), vec![2]);
+        assert_eq!(first_line_break(&tokens, 100, false), usize::MAX);
+    }
+
+    #[test]
+    fn test_balance_lines_evens_out_wrapped_lines() {
+        let tokens: Vec<String> = "A VERY LONG FIRST LINE HERE X"
+            .split(' ')
+            .map(|t| t.to_string())
+            .collect();
+        // Greedy fills the top line: "A VERY LONG FIRST LINE" (22) / "HERE X" (6)
+        assert_eq!(line_starts(&tokens, 22, false), vec![5]);
+        // Balanced: "A VERY LONG" (11) / "FIRST LINE HERE X" (17), bottom-heavy over 17 / 11
+        assert_eq!(line_starts(&tokens, 22, true), vec![3]);
+        assert_eq!(first_line_break(&tokens, 22, true), 3);
+        // Three lines keep three lines, just evened out
+        assert_eq!(wrap_line_starts(&tokens, 12), vec![3, 5]);
+        assert_eq!(line_starts(&tokens, 12, true), vec![2, 4]);
+        // Ties go to the shorter top line
+        let even: Vec<String> = ["AB", "CD", "EF"].iter().map(|t| t.to_string()).collect();
+        assert_eq!(line_starts(&even, 5, true), vec![1]);
+        // Nothing to balance when it fits
+        assert!(line_starts(&tokens, 100, true).is_empty());
+    }
+
+    #[test]
+    fn test_balance_lines_applies_to_storyteller_wrap() {
+        let tokens: Vec<String> = "A VERY LONG FIRST LINE HERE X"
+            .split(' ')
+            .map(|t| t.to_string())
+            .collect();
+        assert_eq!(
+            wrap_multiline(&tokens, 22, false),
+            vec![vec![0, 1, 2, 3, 4], vec![5, 6]]
+        );
+        assert_eq!(
+            wrap_multiline(&tokens, 22, true),
+            vec![vec![0, 1, 2], vec![3, 4, 5, 6]]
+        );
+        let body = assemble_multiline(
+            &tokens,
+            usize::MAX,
+            &opaque_fill("FFFFFF"),
+            &opaque_fill("0000FF"),
+            20,
+            22,
+            true,
+        );
+        let lines: Vec<&str> = body.split(r"\N").collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].contains("FIRST"));
+    }
+
+    #[test]
+    fn test_split_overlong_tokens_only_splits_words_wider_than_limit() {
+        let spans = spans_for("HI SUPERCALIFRAGILISTIC", 1000);
+        let tokens: Vec<String> = spans.iter().map(|s| s.text.clone()).collect();
+
+        let (new_tokens, new_spans) = split_overlong_tokens(&tokens, &spans, 8);
+        assert_eq!(new_tokens, vec!["HI", "SUPERCAL", "IFRAGILI", "STIC"]);
+        assert_eq!(new_spans[1].start_ms, 1000);
+        assert_eq!(new_spans[2].start_ms, new_spans[1].end_ms);
+        assert_eq!(new_spans[3].end_ms, 2000);
+    }
+
+    #[test]
+    fn test_preview_layout_wraps_to_max_chars_per_line() {
+        let text = "the quick brown fox jumps over the lazy dog";
+        let mut params = preview_params(
+            vec![CaptionSegment {
+                start_ms: 0,
+                end_ms: 4500,
+                text: text.to_string(),
+                words: spans_for(text, 500),
+                no_speech_prob: None,
+                position: None,
+            }],
+            false,
+        );
+        params.style_options.max_chars_per_line = Some(12);
+
+        let layout = generate_preview_layout(params).unwrap();
+        assert!(layout.cues.iter().any(|c| c.lines.len() == 2));
+        for cue in &layout.cues {
+            assert!(cue.lines.len() <= 2);
+            for line in &cue.lines {
+                let words: Vec<&str> = line.words.iter().map(|w| w.text.as_str()).collect();
+                assert!(words.join(" ").chars().count() <= 12, "{:?}", words);
+            }
+        }
+    }
+
+    #[test]
+    fn test_preview_layout_limits_karaoke_words_per_cue() {
+        let text = "one two three four five six seven";
+        let words = spans_for(text, 400);
+        let mut params = preview_params(
+            vec![CaptionSegment {
+                start_ms: 0,
+                end_ms: 2800,
+                text: text.to_string(),
+                words: words.clone(),
+                no_speech_prob: None,
+                position: None,
+            }],
+            true,
+        );
+        params.style_options.max_words_per_cue = Some(3);
+
+        let layout = generate_preview_layout(params).unwrap();
+        // One cue per word, each showing only its chunk of at most 3 words
+        assert_eq!(layout.cues.len(), words.len());
+        for (cue, word) in layout.cues.iter().zip(&words) {
+            let visible: usize = cue.lines.iter().map(|l| l.words.len()).sum();
+            assert!(visible <= 3);
+            assert_eq!(cue.start_ms, word.start_ms);
+        }
+        let last = layout.cues.last().unwrap();
+        assert_eq!(last.lines[0].words.len(), 1); // "SEVEN" alone
+        assert!(last.lines[0].words[0].is_highlighted);
+    }
+
+    #[test]
+    fn test_karaoke_pop_animation_scales_highlighted_word() {
+        let text = "pop goes the word";
+        let segments = vec![CaptionSegment {
+            start_ms: 0,
+            end_ms: 2000,
+            text: text.to_string(),
+            words: spans_for(text, 500),
+            no_speech_prob: None,
+            position: None,
+        }];
+        let options = CaptionStyleOptions {
+            animation: Some("pop".to_string()),
+            ..Default::default()
+        };
+        let style = default_ass_style(
+            1080, 1920, None, None, None, None, false, None, None, &options,
+        );
+
+        let doc = build_ass_document(1080, 1920, &style, &segments, true, false, false).unwrap();
+        // Word lasts 500ms: peaks at 1/3 of it, settles at its end; the line stretch is gone
+        assert!(doc.contains(r"\t(0,120,\fscx125\fscy125)\t(120,500,\fscx100\fscy100)"));
+        assert!(!doc.contains(r"\fscx103"));
+
+        let mut params = preview_params(segments, true);
+        params.style_options = options;
+        let layout = generate_preview_layout(params).unwrap();
+        let cue = &layout.cues[1];
+        let word = cue.lines[0]
+            .words
+            .iter()
+            .find(|w| w.is_highlighted)
+            .unwrap();
+        let phase = word.animation_phase.as_ref().unwrap();
+        assert_eq!(phase.kind, "pop");
+        assert_eq!(phase.start_ms, cue.start_ms);
+        assert_eq!(phase.end_ms, cue.start_ms + 500);
+    }
+
+    #[test]
+    fn test_bidi_visual_order_mixed_arabic_english() {
+        // Logical: "مرحبا HELLO WORLD عالم" in a right-to-left paragraph
+        let tokens: Vec<String> = ["مرحبا", "HELLO", "WORLD", "عالم"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+        assert!(is_rtl_tokens(&tokens));
+        // The Latin run keeps its own left-to-right order inside the RTL line
+        assert_eq!(bidi_visual_order(&tokens, true), vec![3, 1, 2, 0]);
+
+        let latin: Vec<String> = vec!["HELLO".into(), "WORLD".into()];
+        assert!(!is_rtl_tokens(&latin));
+        assert_eq!(bidi_visual_order(&latin, false), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_rtl_segment_layout_and_burn() {
+        let text = "مرحبا hello world عالم";
+        let segments = vec![CaptionSegment {
+            start_ms: 0,
+            end_ms: 2000,
+            text: text.to_string(),
+            words: spans_for(text, 500),
+            no_speech_prob: None,
+            position: None,
+        }];
+
+        // Small font so the whole segment fits on one line
+        let mut params = preview_params(segments.clone(), true);
+        params.font_size = Some(30);
+        let layout = generate_preview_layout(params).unwrap();
+        let first = &layout.cues[0];
+        assert!(first.rtl);
+        let shown: Vec<&str> = first.lines[0]
+            .words
+            .iter()
+            .map(|w| w.text.as_str())
+            .collect();
+        assert_eq!(shown, vec!["عالم", "HELLO", "WORLD", "مرحبا"]);
+        // First spoken word is highlighted even though it is displayed last
+        assert!(first.lines[0].words[3].is_highlighted);
+
+        let options = CaptionStyleOptions::default();
+        let style = default_ass_style(
+            1080,
+            1920,
+            None,
+            None,
+            None,
+            None,
+            false,
+            None,
+            Some(30),
+            &options,
+        );
+        let doc = build_ass_document(1080, 1920, &style, &segments, true, false, false).unwrap();
+        let event = doc.lines().find(|l| l.starts_with("Dialogue:")).unwrap();
+        assert!(event.contains(r"\an3"));
+        assert!(event.find("عالم").unwrap() < event.find("مرحبا").unwrap());
+        // Source text stays in logical order
+        assert_eq!(segments[0].text, text);
+    }
+
+    #[test]
+    fn test_vertical_writing_mode_stacks_characters() {
+        let segments = vec![CaptionSegment {
+            start_ms: 0,
+            end_ms: 1000,
+            text: "こんにちは 世界".to_string(),
+            words: spans_for("こんにちは 世界", 500),
+            no_speech_prob: None,
+            position: None,
+        }];
+        let options = CaptionStyleOptions {
+            writing_mode: Some("vertical".to_string()),
+            ..Default::default()
+        };
+
+        let mut params = preview_params(segments.clone(), true);
+        params.style_options = options.clone();
+        let layout = generate_preview_layout(params).unwrap();
+        assert_eq!(layout.cues.len(), 2);
+        assert!(layout.cues.iter().all(|c| c.vertical && c.x_pct > 50.0));
+        assert!(layout.cues[1].lines[0].words[1].is_highlighted);
+
+        let style = default_ass_style(
+            1080, 1920, None, None, None, None, false, None, None, &options,
+        );
+        let doc = build_ass_document(1080, 1920, &style, &segments, true, false, false).unwrap();
+        assert!(doc.contains(r"こ\Nん\Nに\Nち\Nは"));
+        assert!(doc.contains(r"\pos(950,1690)"));
+    }
+
+    #[test]
+    fn test_drop_shadow_layer_composes_with_outline() {
+        let segments = vec![CaptionSegment {
+            start_ms: 0,
+            end_ms: 1000,
+            text: "shadow test".to_string(),
+            words: spans_for("shadow test", 500),
+            no_speech_prob: None,
+            position: None,
+        }];
+        let options = CaptionStyleOptions {
+            shadow: Some(ShadowSpec {
+                x: 4.0,
+                y: 6.0,
+                blur: 3.0,
+                color: Some("#112233".to_string()),
+            }),
+            ..Default::default()
+        };
+        let style = default_ass_style(
+            1080, 1920, None, None, None, None, false, None, None, &options,
+        );
+
+        let doc = build_ass_document(1080, 1920, &style, &segments, false, false, false).unwrap();
+        let events: Vec<&str> = doc.lines().filter(|l| l.starts_with("Dialogue:")).collect();
+        // Shadow layer first, then the regular stroke + fill layer with its outline intact
+        assert!(events[0].contains(r"\xshad4.0\yshad6.0\4c&H332211&\4a&H00"));
+        assert!(events[0].contains(r"\1a&HFF\3a&HFF"));
+        assert!(events[1].contains(r"\bord4.00\3c&H000000&"));
+
+        let mut params = preview_params(segments, false);
+        params.style_options = options;
+        let layout = generate_preview_layout(params).unwrap();
+        assert_eq!(layout.text_shadow.as_deref(), Some("4px 6px 3px #112233"));
+    }
+
+    #[test]
+    fn test_background_box_sizes_to_wrapped_lines() {
+        let segments = vec![CaptionSegment {
+            start_ms: 0,
+            end_ms: 2000,
+            text: "the quick brown fox jumps".to_string(),
+            words: spans_for("the quick brown fox jumps", 400),
+            no_speech_prob: None,
+            position: None,
+        }];
+        let options = CaptionStyleOptions {
+            max_chars_per_line: Some(10),
+            background_color: Some("#102030".to_string()),
+            background_opacity: Some(0.5),
+            background_radius: Some(12.0),
+            ..Default::default()
+        };
+
+        // Burn: the rounded box is the first event, half transparent, under the text
+        let style = default_ass_style(
+            1080, 1920, None, None, None, None, false, None, None, &options,
+        );
+        let doc = build_ass_document(1080, 1920, &style, &segments, false, false, false).unwrap();
+        let events: Vec<&str> = doc.lines().filter(|l| l.starts_with("Dialogue:")).collect();
+        assert!(events[0].contains(r"\1c&H302010&\1a&H80\p1}m 12 0"));
+        assert!(events[1].contains(r"\bord4.00"));
+
+        // Preview: every cue carries a box, and a two-line cue is taller than a one-line one
+        let mut params = preview_params(segments, false);
+        params.style_options = options;
+        let layout = generate_preview_layout(params).unwrap();
+        let boxes: Vec<_> = layout
+            .cues
+            .iter()
+            .map(|c| (c.lines.len(), c.background.clone().unwrap()))
+            .collect();
+        let two = boxes.iter().find(|(n, _)| *n == 2).unwrap();
+        let one = boxes.iter().find(|(n, _)| *n == 1).unwrap();
+        assert!(two.1.height_pct > one.1.height_pct);
+        assert_eq!(two.1.color, "#102030");
+        assert_eq!(two.1.radius_px, 12.0);
+        // Bottom-anchored: the box is centered and ends just below the text anchor
+        assert!((two.1.x_pct + two.1.width_pct / 2.0 - 50.0).abs() < 0.01);
+        assert!(two.1.y_pct + two.1.height_pct > layout.cues[0].y_pct);
+    }
+
+    #[test]
+    fn test_text_gradient_bands_and_fallback() {
+        let segments = vec![CaptionSegment {
+            start_ms: 0,
+            end_ms: 1000,
+            text: "gradient fill".to_string(),
+            words: spans_for("gradient fill", 500),
+            no_speech_prob: None,
+            position: None,
+        }];
+        let options = CaptionStyleOptions {
+            text_gradient: Some(("#FFFFFF".to_string(), "#FFFF00".to_string())),
+            ..Default::default()
+        };
+        let style = default_ass_style(
+            1080, 1920, None, None, None, None, false, None, None, &options,
+        );
+        assert_eq!(style.primary, "&H00FFFFFF"); // solid fallback = top stop
+
+        let doc = build_ass_document(1080, 1920, &style, &segments, false, false, false).unwrap();
+        let bands: Vec<&str> = doc
+            .lines()
+            .filter(|l| l.starts_with("Dialogue: 2,"))
+            .collect();
+        assert_eq!(bands.len(), GRADIENT_BANDS);
+        // Top band stays near white, bottom band is near yellow (BGR 00FFFF)
+        assert!(bands[0].contains(r"\clip(0,0,1080,") && bands[0].contains(r"\1c&HEFFFFF&"));
+        assert!(bands[7].contains(",1080,1920)") && bands[7].contains(r"\1c&H10FFFF&"));
+
+        // Vertical columns use the top stop without bands
+        let vertical = CaptionStyleOptions {
+            writing_mode: Some("vertical".to_string()),
+            ..options.clone()
+        };
+        let style = default_ass_style(
+            1080, 1920, None, None, None, None, false, None, None, &vertical,
+        );
+        let doc = build_ass_document(1080, 1920, &style, &segments, false, false, false).unwrap();
+        assert!(!doc.contains("Dialogue: 2,"));
+
+        let mut params = preview_params(segments, false);
+        params.style_options = options;
+        let layout = generate_preview_layout(params).unwrap();
+        assert_eq!(
+            layout.text_gradient.as_deref(),
+            Some("linear-gradient(to bottom, #FFFFFF, #FFFF00)")
+        );
+    }
+
+    #[test]
+    fn test_emoji_clusters_split_and_font_switch() {
+        let pieces = split_emoji_runs("WOW😂👍🏽!🇫🇮");
+        let texts: Vec<&str> = pieces.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(texts, vec!["WOW", "😂", "👍🏽", "!", "🇫🇮"]);
+        assert_eq!(split_emoji_runs("👨\u{200D}👩\u{200D}👧").len(), 1);
+
+        let wrapped = wrap_emoji_runs(
+            r"{\1c&HFFFFFF&}HI 😂\NOK",
+            Some("Noto Emoji"),
+            "Montserrat Black",
+        );
+        assert_eq!(
+            wrapped,
+            r"{\1c&HFFFFFF&}HI {\fnNoto Emoji}😂{\fnMontserrat Black}\NOK"
+        );
+        // Without an emoji font they are dropped instead of burned as tofu
+        assert_eq!(
+            wrap_emoji_runs(r"{\1c&HFFFFFF&}SO 😂 FUNNY 👍🏽", None, "Montserrat Black"),
+            r"{\1c&HFFFFFF&}SO FUNNY "
+        );
+        assert_eq!(emoji_burn_font(Some("Definitely Not A Font")), None);
+
+        let segments = vec![CaptionSegment {
+            start_ms: 0,
+            end_ms: 1000,
+            text: "wow😂 nice".to_string(),
+            words: spans_for("wow😂 nice", 500),
+            no_speech_prob: None,
+            position: None,
+        }];
+        let layout = generate_preview_layout(preview_params(segments, true)).unwrap();
+        let words = &layout.cues[0].lines[0].words;
+        assert_eq!(words.len(), 3);
+        assert!(words[1].emoji && words[1].attached && words[1].text == "😂");
+        assert!(!words[2].attached);
+    }
+
+    #[test]
+    fn test_resolve_bundled_font_and_missing_font() {
+        let path = resolve_font("montserrat black").unwrap();
+        assert!(path.ends_with("Montserrat Black.ttf"));
+
+        let err = resolve_font("Definitely Not A Font").unwrap_err();
+        assert_eq!(crate::rpc::error_code(&err), Some("FONT_NOT_FOUND"));
+
+        let fonts = list_fonts().unwrap().fonts;
+        assert!(fonts
+            .iter()
+            .any(|f| f.bundled && f.full_name == "Montserrat Black"));
+    }
+
+    #[test]
+    fn test_caption_font_falls_back_to_embedded_default() {
+        let mut logs = Vec::new();
+        let (family, dir) =
+            caption_font("t", Some("Definitely Not A Font"), &mut |e| logs.push(e)).unwrap();
+        assert_eq!(family, DEFAULT_FONT_FAMILY);
+        assert!(dir.join("Montserrat Black.ttf").exists());
+        assert!(matches!(
+            &logs[0],
+            RpcEvent::Log { message, .. } if message.contains("using bundled Montserrat Black")
+        ));
+
+        // An installed font is used as-is, without a fallback log
+        logs.clear();
+        let (family, _) = caption_font("t", Some("Bangers"), &mut |e| logs.push(e)).unwrap();
+        assert_eq!(family, "Bangers");
+        assert!(logs.is_empty());
+    }
+
+    #[test]
+    fn test_fade_ms_clamped_per_cue() {
+        // Fades never exceed half the cue, and only the cue's outer events fade
+        assert_eq!(cue_fades((0, 1000), (0, 1000), 300), (300, 300));
+        assert_eq!(cue_fades((0, 400), (0, 400), 300), (200, 200));
+        assert_eq!(cue_fades((0, 1500), (500, 1000), 300), (0, 0));
+        assert_eq!(cue_fades((0, 1500), (0, 100), 300), (100, 0));
+
+        let segments = vec![CaptionSegment {
+            start_ms: 0,
+            end_ms: 1500,
+            text: "fade in out".to_string(),
+            words: spans_for("fade in out", 500),
+            no_speech_prob: None,
+            position: None,
+        }];
+        let options = CaptionStyleOptions {
+            fade_ms: Some(250),
+            ..Default::default()
+        };
+        let style = default_ass_style(
+            1080, 1920, None, None, None, None, false, None, None, &options,
+        );
+        let doc = build_ass_document(1080, 1920, &style, &segments, true, false, false).unwrap();
+        let events: Vec<&str> = doc.lines().filter(|l| l.starts_with("Dialogue:")).collect();
+        assert!(events[0].contains(r",{\fad(250,0)}{"));
+        assert!(!events[1].contains(r"\fad"));
+        assert!(events[2].contains(r",{\fad(0,250)}{"));
+
+        let mut params = preview_params(segments, true);
+        params.style_options = options;
+        let cues = generate_preview_layout(params).unwrap().cues;
+        let ramps: Vec<(u32, u32)> = cues.iter().map(|c| (c.fade_in_ms, c.fade_out_ms)).collect();
+        assert_eq!(ramps, vec![(250, 0), (0, 0), (0, 250)]);
+    }
+
+    #[test]
+    fn test_min_max_cue_duration() {
+        let words = vec![
+            WordSpan {
+                start_ms: 0,
+                end_ms: 150,
+                text: "Hi.".to_string(),
+            },
+            WordSpan {
+                start_ms: 1000,
+                end_ms: 2000,
+                text: "one".to_string(),
+            },
+            WordSpan {
+                start_ms: 2000,
+                end_ms: 3000,
+                text: "two".to_string(),
+            },
+            WordSpan {
+                start_ms: 3000,
+                end_ms: 4000,
+                text: "three".to_string(),
+            },
+        ];
+        let segments = vec![CaptionSegment {
+            start_ms: 0,
+            end_ms: 4000,
+            text: "Hi. one two three".to_string(),
+            words,
+            no_speech_prob: None,
+            position: None,
+        }];
+        let phrases = limit_phrase_durations(coalesce_phrases(&segments), Some(1500), Some(2500));
+        let ranges: Vec<(u64, u64)> = phrases.iter().map(|p| (p.start_ms, p.end_ms)).collect();
+        // Flash cue grows up to the next cue; the 3s run-on splits after "two"
+        assert_eq!(ranges, vec![(0, 1000), (1000, 3000), (3000, 4500)]);
+        assert_eq!(phrases[0].spans[0].end_ms, 1000);
+
+        let mut params = preview_params(segments, false);
+        params.style_options.min_cue_ms = Some(1500);
+        params.style_options.max_cue_ms = Some(2500);
+        let cues = generate_preview_layout(params).unwrap().cues;
+        assert_eq!(cues[0].end_ms, 1000);
+        assert!(cues.iter().all(|c| c.end_ms - c.start_ms <= 2500));
+    }
+
+    #[test]
+    fn test_offset_segments_shifts_and_clamps() {
+        let segments = vec![CaptionSegment {
+            start_ms: 100,
+            end_ms: 900,
+            text: "sync me".to_string(),
+            words: spans_for("sync me", 400),
+            no_speech_prob: None,
+            position: None,
+        }];
+        let later = offset_segments(&segments, 200);
+        assert_eq!((later[0].start_ms, later[0].end_ms), (300, 1100));
+        assert_eq!(later[0].words[1].start_ms, 600);
+
+        let earlier = offset_segments(&segments, -250);
+        assert_eq!((earlier[0].start_ms, earlier[0].end_ms), (0, 650));
+        assert_eq!(
+            (earlier[0].words[0].start_ms, earlier[0].words[0].end_ms),
+            (0, 150)
+        );
+    }
+
+    #[test]
+    fn test_trim_segments_moves_cues_onto_clip() {
+        let segment = |start_ms, end_ms, text: &str| CaptionSegment {
+            start_ms,
+            end_ms,
+            text: text.to_string(),
+            words: spans_for(text, 500)
+                .into_iter()
+                .map(|w| WordSpan {
+                    start_ms: w.start_ms + start_ms,
+                    end_ms: w.end_ms + start_ms,
+                    text: w.text,
+                })
+                .collect(),
+            no_speech_prob: None,
+            position: None,
+        };
+        let segments = vec![
+            segment(0, 1000, "before trim"),
+            segment(4000, 5000, "straddles start"),
+            segment(6000, 7000, "inside range"),
+            segment(9500, 10500, "straddles end"),
+            segment(12000, 13000, "after trim"),
+        ];
+        let clip = trim_segments(&segments, 4500, Some(10000));
+        let spans: Vec<_> = clip.iter().map(|s| (s.start_ms, s.end_ms)).collect();
+        assert_eq!(spans, vec![(0, 500), (1500, 2500), (5000, 5500)]);
+        // Words that ended before the cut are gone
+        assert_eq!(clip[0].words.len(), 1);
+        assert_eq!(clip[0].words[0].text, "start");
+        assert_eq!(clip[2].words.len(), 1);
+
+        let open_ended = trim_segments(&segments, 6000, None);
+        assert_eq!(open_ended.len(), 3);
+        assert_eq!(open_ended[2].start_ms, 6000);
+    }
+
+    #[test]
+    fn test_retime_segments_scales_after_offset() {
+        let segments = vec![CaptionSegment {
+            start_ms: 1000,
+            end_ms: 2000,
+            text: "faster".to_string(),
+            words: spans_for("faster", 1000),
+            no_speech_prob: None,
+            position: None,
+        }];
+        let mut logs = Vec::new();
+        let out = retime_segments("t", &segments, 1000, 0.8, &mut |e| logs.push(e)).unwrap();
+        // (1000 + 1000) * 0.8 = 1600, (2000 + 1000) * 0.8 = 2400
+        assert_eq!((out[0].start_ms, out[0].end_ms), (1600, 2400));
+        assert!(matches!(
+            &logs[0],
+            RpcEvent::Log { message, .. } if message.contains("end at 2400 ms (was 3000 ms)")
+        ));
+
+        assert!(retime_segments("t", &segments, 0, 0.0, &mut |_| {}).is_err());
+        assert!(retime_segments("t", &segments, 0, f64::NAN, &mut |_| {}).is_err());
+    }
+
+    fn segment_of(text: &str) -> Vec<CaptionSegment> {
+        let words = spans_for(text, 400);
+        vec![CaptionSegment {
+            start_ms: 0,
+            end_ms: words.last().unwrap().end_ms,
+            text: text.to_string(),
+            words,
+            no_speech_prob: None,
+            position: None,
+        }]
+    }
+
+    #[test]
+    fn test_segment_position_overrides_global_placement() {
+        let mut segments = segment_of("move me");
+        segments[0].position = Some(SegmentPosition {
+            x_pct: Some(25.0),
+            y_pct: Some(30.0),
+        });
+        let mut rest = segment_of("stay put");
+        for w in &mut rest[0].words {
+            w.start_ms += 800;
+            w.end_ms += 800;
+        }
+        rest[0].start_ms = 800;
+        rest[0].end_ms = 1600;
+        // Only x: y keeps the global bottom placement
+        rest[0].position = Some(SegmentPosition {
+            x_pct: Some(75.0),
+            y_pct: None,
+        });
+        segments.extend(rest);
+
+        let style = default_ass_style(
+            1080,
+            1920,
+            None,
+            None,
+            None,
+            None,
+            false,
+            None,
+            None,
+            &CaptionStyleOptions::default(),
+        );
+        let bottom = anchor_y(&style, 1920);
+        for karaoke in [false, true] {
+            let doc =
+                build_ass_document(1080, 1920, &style, &segments, karaoke, false, false).unwrap();
+            assert!(doc.contains(r"\pos(270,576)"), "{}", doc);
+            assert!(doc.contains(&format!(r"\pos(810,{})", bottom)), "{}", doc);
+            assert!(!doc.contains(&format!(r"\pos(540,{})", bottom)));
+        }
+
+        let layout = generate_preview_layout(preview_params(segments, false)).unwrap();
+        let first = &layout.cues[0];
+        assert!((first.x_pct - 25.0).abs() < 0.01 && (first.y_pct - 30.0).abs() < 0.01);
+        let second = layout.cues.last().unwrap();
+        assert!((second.x_pct - 75.0).abs() < 0.01);
+        assert!((second.y_pct - bottom as f32 / 1920.0 * 100.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_segment_position_moves_vertical_columns() {
+        let mut segments = segment_of("こんにちは");
+        segments[0].position = Some(SegmentPosition {
+            x_pct: Some(20.0),
+            y_pct: Some(40.0),
+        });
+        let options = CaptionStyleOptions {
+            writing_mode: Some("vertical".to_string()),
+            ..Default::default()
+        };
+        let style = default_ass_style(
+            1080, 1920, None, None, None, None, false, None, None, &options,
+        );
+        let doc = build_ass_document(1080, 1920, &style, &segments, false, false, false).unwrap();
+        assert!(doc.contains(r"\pos(216,768)"), "{}", doc);
+
+        let mut params = preview_params(segments, false);
+        params.style_options = options;
+        let layout = generate_preview_layout(params).unwrap();
+        let cue = &layout.cues[0];
+        assert!(cue.vertical);
+        assert!((cue.x_pct - 20.0).abs() < 0.01 && (cue.y_pct - 40.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_preview_lines_report_measured_widths() {
+        let metrics = FontMetrics::load(DEFAULT_FONT_FAMILY);
+        let one = metrics.text_width("HELLO", 80);
+        // Wider glyphs measure wider, and width scales with the font size
+        assert!(metrics.text_width("WWW", 80) > metrics.text_width("III", 80));
+        assert!((metrics.text_width("HELLO", 160) - one * 2.0).abs() < 0.01);
+
+        let mut params = preview_params(segment_of("hello world"), false);
+        params.font_size = Some(80);
+        let font_size = calculate_proportional_font_size(1080, 1920, Some(80));
+        let layout = generate_preview_layout(params).unwrap();
+        let line = &layout.cues[0].lines[0];
+        let words: Vec<&str> = line.words.iter().map(|w| w.text.as_str()).collect();
+        let expected = metrics.text_width(&words.join(" "), font_size);
+        assert!(line.est_width_px > 0.0);
+        assert!((line.est_width_px - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_preview_cue_without_x_pct_is_centered() {
+        // Layouts saved before xPct existed still load, centered as they were rendered
+        let cue: crate::types::PreviewCue = serde_json::from_value(serde_json::json!({
+            "startMs": 0,
+            "endMs": 1000,
+            "lines": [],
+            "yPct": 80.0,
+        }))
+        .unwrap();
+        assert_eq!(cue.x_pct, 50.0);
+    }
+
+    #[test]
+    fn test_preview_cue_bounds_cover_text_and_outline() {
+        let mut params = preview_params(segment_of("hello world"), false);
+        params.style_options.max_chars_per_line = Some(6);
+        let layout = generate_preview_layout(params).unwrap();
+        let cue = &layout.cues[0];
+        assert_eq!(cue.lines.len(), 2);
+        let b = cue.bounds.clone().unwrap();
+        let widest = cue.lines.iter().map(|l| l.est_width_px).fold(0.0, f32::max);
+        // Centered on the anchor, ending at it (bottom alignment), wider than the text
+        assert!((b.x_pct + b.width_pct / 2.0 - cue.x_pct).abs() < 0.01);
+        assert!((b.y_pct + b.height_pct - cue.y_pct).abs() < 0.01);
+        assert!(b.width_pct / 100.0 * 1080.0 > widest);
+
+        // The glow reaches further than the plain outline
+        let mut glow = preview_params(segment_of("hello world"), false);
+        glow.style_options.max_chars_per_line = Some(6);
+        glow.glow_effect = true;
+        let glow_bounds = generate_preview_layout(glow).unwrap().cues[0]
+            .bounds
+            .clone()
+            .unwrap();
+        assert!(glow_bounds.width_pct > b.width_pct);
+        assert!(glow_bounds.height_pct > b.height_pct);
+
+        // One line is shorter than two
+        let single =
+            generate_preview_layout(preview_params(segment_of("hello world"), false)).unwrap();
+        assert!(single.cues[0].bounds.as_ref().unwrap().height_pct < b.height_pct);
+    }
+
+    #[test]
+    fn test_storyteller_preview_breaks_match_burn_wrap() {
+        let text = (0..24)
+            .map(|i| format!("word{}", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut params = preview_params(segment_of(&text), false);
+        params.position = Some("center".into());
+        let style = default_ass_style(
+            1080,
+            1920,
+            None,
+            None,
+            None,
+            None,
+            false,
+            Some("center"),
+            None,
+            &params.style_options,
+        );
+        let layout = generate_preview_layout(params).unwrap();
+        assert!(layout.cues.iter().any(|c| c.lines.len() > 1));
+        for cue in &layout.cues {
+            let tokens: Vec<String> = cue
+                .lines
+                .iter()
+                .flat_map(|l| l.words.iter().map(|w| w.text.clone()))
+                .collect();
+            let width = storyteller_wrap_width(&tokens, 1080, &style);
+            let burned: Vec<usize> = wrap_multiline(&tokens, width, style.balance_lines)
+                .iter()
+                .map(|l| l.len())
+                .collect();
+            let previewed: Vec<usize> = cue.lines.iter().map(|l| l.words.len()).collect();
+            assert_eq!(previewed, burned);
+        }
+    }
+
+    fn frame_params(extra: serde_json::Value) -> crate::types::PreviewFrameParams {
+        let mut json = serde_json::json!({
+            "inputVideo": "in.mp4",
+            "segments": [],
+            "timestampMs": 0,
+            "exportFormat": "9:16",
+            "karaoke": false,
+            "fontName": null,
+        });
+        json.as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_preview_frame_size_uses_exact_overrides() {
+        let params = frame_params(serde_json::json!({ "width": 390, "height": 844 }));
+        assert_eq!(preview_frame_size(&params, 1920, 1080).unwrap(), (390, 844));
+
+        // Without overrides the export format's aspect ratio decides
+        let params = frame_params(serde_json::json!({ "outputSize": "1080p" }));
+        assert_eq!(
+            preview_frame_size(&params, 1920, 1080).unwrap(),
+            (1080, 1920)
+        );
+
+        let params = frame_params(serde_json::json!({ "width": 390 }));
+        assert!(preview_frame_size(&params, 1920, 1080).is_err());
+        let params = frame_params(serde_json::json!({ "width": 0, "height": 844 }));
+        assert!(preview_frame_size(&params, 1920, 1080).is_err());
+    }
+
+    #[test]
+    fn test_transparent_frame_draws_captions_on_alpha_canvas() {
+        let params = frame_params(serde_json::json!({ "transparent": true }));
+        assert!(params.transparent);
+        let args = transparent_frame_args(
+            Path::new("/tmp/job/preview.ass"),
+            Path::new("/fonts"),
+            (1080, 1920),
+            2500,
+        );
+        let arg_after = |flag: &str| {
+            let i = args.iter().position(|a| a == flag).unwrap();
+            args[i + 1].clone()
+        };
+        // No video input: a transparent canvas of the preview size
+        assert_eq!(arg_after("-f"), "lavfi");
+        assert_eq!(
+            arg_after("-i"),
+            "color=c=black@0.0:s=1080x1920:r=1:d=1.000,format=rgba"
+        );
+        // The canvas sits at the requested time so the right cue is drawn, with alpha
+        assert_eq!(
+            arg_after("-vf"),
+            "setpts=PTS+2.500/TB,ass='/tmp/job/preview.ass':fontsdir='/fonts':alpha=1"
+        );
+        assert_eq!(arg_after("-pix_fmt"), "rgba");
+        assert_eq!(arg_after("-c:v"), "png");
+    }
+
+    #[test]
+    fn test_overlay_export_encodes_captions_with_alpha() {
+        use crate::video::AlphaCodec;
+        assert_eq!(AlphaCodec::parse(None).unwrap(), AlphaCodec::ProRes4444);
+        assert_eq!(AlphaCodec::parse(Some("vp9")).unwrap(), AlphaCodec::Vp9);
+        assert!(AlphaCodec::parse(Some("h264")).is_err());
+
+        let args = overlay_encode_args(
+            Path::new("/tmp/job/captions.ass"),
+            Path::new("/fonts"),
+            "/videos/clip_9x16_overlay.mov",
+            (1080, 1920),
+            Some(29.97),
+            12.5,
+            AlphaCodec::ProRes4444,
+        );
+        let arg_after = |flag: &str| {
+            let i = args.iter().position(|a| a == flag).unwrap();
+            args[i + 1].clone()
+        };
+        // A transparent canvas the length of the video, at its exact NTSC rate
+        assert_eq!(
+            arg_after("-i"),
+            "color=c=black@0.0:s=1080x1920:r=30000/1001:d=12.500,format=rgba"
+        );
+        assert!(arg_after("-vf").ends_with(":alpha=1"));
+        assert_eq!(arg_after("-profile:v"), "4444");
+        assert_eq!(arg_after("-pix_fmt"), "yuva444p10le");
+        assert_eq!(args.last().unwrap(), "/videos/clip_9x16_overlay.mov");
+
+        let vp9 = overlay_encode_args(
+            Path::new("/tmp/job/captions.ass"),
+            Path::new("/fonts"),
+            "/videos/clip_9x16_overlay.webm",
+            (1080, 1920),
+            None,
+            3.0,
+            AlphaCodec::Vp9,
+        );
+        assert!(vp9.iter().any(|a| a.contains(":r=30:")));
+        assert!(vp9.windows(2).any(|w| w == ["-pix_fmt", "yuva420p"]));
+        assert_eq!(AlphaCodec::Vp9.extension(), "webm");
+    }
+
+    #[test]
+    fn test_format_parallelism_scales_with_cores() {
+        assert_eq!(format_parallelism(1, 3), 1);
+        assert_eq!(format_parallelism(4, 3), 2);
+        assert_eq!(format_parallelism(8, 3), 2);
+        assert_eq!(format_parallelism(12, 3), 3);
+        assert_eq!(format_parallelism(32, 6), 4);
+        // Never more slots than formats
+        assert_eq!(format_parallelism(32, 1), 1);
+        assert_eq!(format_parallelism(8, 0), 1);
+    }
+
+    #[test]
+    fn test_clip_window_centers_and_clamps() {
+        assert_eq!(clip_window(5000, None), (4000, 2000));
+        assert_eq!(clip_window(300, Some(2000)), (0, 2000));
+        assert_eq!(clip_window(60_000, Some(60_000)), (55_000, 10_000));
+    }
+
+    #[test]
+    fn test_clip_canvas_shrinks_to_even_dimensions() {
+        assert_eq!(clip_canvas(1080, 1920), (270, 480));
+        assert_eq!(clip_canvas(1920, 1080), (480, 270));
+        assert_eq!(clip_canvas(320, 240), (320, 240));
+        let (w, h) = clip_canvas(1000, 1999);
+        assert!(w.is_multiple_of(2) && h.is_multiple_of(2));
+    }
+
+    #[test]
+    fn test_clip_encoding_formats() {
+        let (tail, args, mime) = clip_encoding(None, 12).unwrap();
+        assert_eq!(mime, "image/webp");
+        assert!(tail.starts_with("fps=12,setpts=PTS-STARTPTS"));
+        assert!(args.contains(&"libwebp"));
+        let (tail, _, mime) = clip_encoding(Some("gif"), 10).unwrap();
+        assert_eq!(mime, "image/gif");
+        assert!(tail.contains("paletteuse"));
+        assert!(clip_encoding(Some("mp4"), 12).is_err());
+    }
+
+    #[test]
+    fn test_mux_target_matches_container() {
+        assert_eq!(mux_target("/v/clip.MP4"), ("mov_text", "mp4"));
+        assert_eq!(mux_target("/v/clip.mov"), ("mov_text", "mov"));
+        assert_eq!(mux_target("/v/clip.mkv"), ("srt", "mkv"));
+        assert_eq!(mux_target("/v/clip.webm"), ("webvtt", "webm"));
+        assert_eq!(mux_target("/v/clip"), ("mov_text", "mp4"));
+    }
+
+    #[test]
+    fn test_video_quality_args_per_encoder() {
+        use crate::video::HardwareEncoder;
+        let default = EncodeOptions::default();
+        assert_eq!(
+            video_quality_args(HardwareEncoder::Software, &default, 1080, 1920),
+            vec!["-crf", "16"]
+        );
+        assert_eq!(
+            video_quality_args(HardwareEncoder::VideoToolbox, &default, 1080, 1920),
+            vec!["-b:v", "12M"]
+        );
+
+        let crf = EncodeOptions {
+            crf: Some(22),
+            ..Default::default()
+        };
+        assert_eq!(
+            video_quality_args(HardwareEncoder::Nvenc, &crf, 1080, 1920),
+            vec!["-cq", "22"]
+        );
+        // +6 crf halves the VideoToolbox bitrate at 1080p
+        assert_eq!(
+            video_quality_args(HardwareEncoder::VideoToolbox, &crf, 1080, 1920),
+            vec!["-b:v", "6000k"]
+        );
+
+        let bitrate = EncodeOptions {
+            crf: Some(22),
+            video_bitrate: Some("4M".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            video_quality_args(HardwareEncoder::Software, &bitrate, 1080, 1920),
+            vec!["-b:v", "4M"]
+        );
+    }
+
+    #[test]
+    fn test_validate_encode_options() {
+        assert!(validate_encode_options(&EncodeOptions::default()).is_ok());
+        let bad_crf = EncodeOptions {
+            crf: Some(60),
+            ..Default::default()
+        };
+        assert!(validate_encode_options(&bad_crf).is_err());
+        let bad_rate = EncodeOptions {
+            video_bitrate: Some("fast".into()),
+            ..Default::default()
+        };
+        assert!(validate_encode_options(&bad_rate).is_err());
+        let missing_logo = EncodeOptions {
+            watermark: serde_json::from_value(serde_json::json!({ "path": "/no/such/logo.png" }))
+                .unwrap(),
+            ..Default::default()
+        };
+        let err = validate_encode_options(&missing_logo)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Watermark image not found"), "{}", err);
+    }
+
+    #[test]
+    fn test_two_pass_bitrate_fits_target_size() {
+        let probe = crate::video::parse_probe_json(&serde_json::json!({
+            "format": {"duration": "60.0"},
+            "streams": [
+                {"codec_type": "video", "width": 3840, "height": 2160},
+                {"codec_type": "audio", "codec_name": "aac", "bit_rate": "128000"}
+            ]
+        }));
+        let options = EncodeOptions {
+            two_pass: true,
+            target_size_mb: Some(50.0),
+            ..Default::default()
+        };
+        // 50 MB * 8 * 0.98 / 60 s = 6533 kbps total, minus 128k audio
+        assert_eq!(two_pass_bitrate_kbps(&options, &probe).unwrap(), Some(6405));
+        assert!(validate_encode_options(&options).is_ok());
+
+        let tiny = EncodeOptions {
+            target_size_mb: Some(0.5),
+            ..options.clone()
+        };
+        assert!(two_pass_bitrate_kbps(&tiny, &probe).is_err());
+
+        let single = EncodeOptions {
+            two_pass: false,
+            ..options
+        };
+        assert_eq!(two_pass_bitrate_kbps(&single, &probe).unwrap(), None);
+        assert!(validate_encode_options(&single).is_err());
+        let no_target = EncodeOptions {
+            two_pass: true,
+            ..Default::default()
+        };
+        assert!(validate_encode_options(&no_target).is_err());
+    }
+
+    #[test]
+    fn test_stage_progress_scales_into_band() {
+        let mut seen = Vec::new();
+        let mut sink = |e: RpcEvent| seen.push(e);
+        {
+            let mut burn = stage_progress(&mut sink, TRANSCRIBE_STAGE_END, 1.0);
+            for progress in [0.0, 0.5, 1.0, 1.5] {
+                burn(RpcEvent::Progress {
+                    id: "x".into(),
+                    status: "Exporting".into(),
+                    progress,
+                });
+            }
+            burn(RpcEvent::Log {
+                id: "x".into(),
+                message: "untouched".into(),
+            });
+        }
+        let values: Vec<f32> = seen
+            .iter()
+            .filter_map(|e| match e {
+                RpcEvent::Progress { progress, .. } => Some(*progress),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(values, vec![0.6, 0.8, 1.0, 1.0]);
+        assert!(matches!(seen.last(), Some(RpcEvent::Log { .. })));
+
+        // Nested bands compose: extraction inside transcribe inside generate
+        let mut outer_seen = Vec::new();
+        let mut outer = |e: RpcEvent| outer_seen.push(e);
+        let mut generate = stage_progress(&mut outer, 0.0, TRANSCRIBE_STAGE_END);
+        let mut extract =
+            stage_progress(&mut generate, 0.0, EXTRACT_STAGE_END / TRANSCRIBE_STAGE_END);
+        extract(RpcEvent::Progress {
+            id: "x".into(),
+            status: "done".into(),
+            progress: 1.0,
+        });
+        drop(extract);
+        drop(generate);
+        match &outer_seen[0] {
+            RpcEvent::Progress { progress, .. } => {
+                assert!((progress - EXTRACT_STAGE_END).abs() < 1e-6)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_generate_captions_progress_never_goes_backwards() {
+        let mut seen = Vec::new();
+        let mut sink = |e: RpcEvent| seen.push(e);
+        // Each stage reports its own 0 -> 1, as probe, extraction, transcription and
+        // the encode do
+        let run_stage = |emit: &mut dyn FnMut(RpcEvent)| {
+            for progress in [0.0, 0.5, 1.0] {
+                emit(RpcEvent::Progress {
+                    id: "x".into(),
+                    status: "stage".into(),
+                    progress,
+                });
+            }
+        };
+        {
+            let mut transcribe = stage_progress(&mut sink, 0.0, TRANSCRIBE_STAGE_END);
+            for (start, end) in transcribe_bands() {
+                run_stage(&mut stage_progress(&mut transcribe, start, end));
+            }
+        }
+        run_stage(&mut stage_progress(&mut sink, TRANSCRIBE_STAGE_END, 1.0));
+
+        let values: Vec<f32> = seen
+            .iter()
+            .filter_map(|e| match e {
+                RpcEvent::Progress { progress, .. } => Some(*progress),
+                _ => None,
+            })
+            .collect();
+        assert!(
+            values.windows(2).all(|w| w[0] <= w[1] + 1e-6),
+            "{:?}",
+            values
+        );
+        assert!((values[2] - PROBE_STAGE_END).abs() < 1e-6, "{:?}", values);
+        assert_eq!(values.last(), Some(&1.0));
+    }
+
+    #[test]
+    fn test_resumable_audio_path_tracks_input_and_preprocessing() {
+        let input = tempfile::NamedTempFile::new().unwrap();
+        fs::write(input.path(), b"video").unwrap();
+        let path = input.path().to_str().unwrap();
+        let plain = resumable_audio_path(path, false, false, None, None).unwrap();
+        assert_eq!(
+            plain,
+            resumable_audio_path(path, false, false, None, None).unwrap()
+        );
+        assert_ne!(
+            plain,
+            resumable_audio_path(path, true, false, None, None).unwrap()
+        );
+        assert_ne!(
+            plain,
+            resumable_audio_path(path, false, true, None, None).unwrap()
+        );
+        assert_ne!(
+            plain,
+            resumable_audio_path(path, false, false, Some(1000), None).unwrap()
+        );
+        assert!(!is_reusable_audio(&plain));
+
+        // Editing the source invalidates the stored audio
+        fs::write(input.path(), b"a different video").unwrap();
+        assert_ne!(
+            plain,
+            resumable_audio_path(path, false, false, None, None).unwrap()
+        );
+        assert!(resumable_audio_path("/no/such/video.mp4", false, false, None, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_audio_extraction_lock_serializes_same_audio() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.mp3"), dir.path().join("b.mp3"));
+        let first = audio_extraction_lock(&a);
+        let held = first.lock().await;
+        // The same audio shares the lock, so a second extraction waits for the first
+        let second = audio_extraction_lock(&a);
+        assert!(std::sync::Arc::ptr_eq(&first, &second));
+        assert!(second.try_lock().is_err());
+        // Other audio is not held up
+        assert!(audio_extraction_lock(&b).try_lock().is_ok());
+        drop(held);
+        assert!(second.try_lock().is_ok());
+    }
+
+    #[test]
+    fn test_dry_run_transcription_covers_the_processed_range() {
+        let probe: crate::video::ProbeResult = serde_json::from_value(serde_json::json!({
+            "duration": 120.0, "width": 1920, "height": 1080, "fps": 30.0,
+            "audio": true, "video": true, "audioCodec": "aac", "audioBitrate": null,
+            "colorSpace": null, "colorTransfer": null, "colorPrimaries": null,
+        }))
+        .unwrap();
+        let whole = dry_run_transcription(&probe, None, None).unwrap();
+        assert_eq!(whole.segments.len(), 1);
+        assert_eq!(
+            (whole.segments[0].start_ms, whole.segments[0].end_ms),
+            (0, 120_000)
+        );
+
+        // Segments stay on the source timeline
+        let trimmed = dry_run_transcription(&probe, Some(10_000), Some(40_000)).unwrap();
+        assert_eq!(
+            (trimmed.segments[0].start_ms, trimmed.segments[0].end_ms),
+            (10_000, 40_000)
+        );
+        assert_eq!(trimmed.duration, Some(30.0));
+    }
+
+    #[test]
+    fn test_estimate_prices_api_minutes_and_scales_with_pixels() {
+        let probe: crate::video::ProbeResult = serde_json::from_value(serde_json::json!({
+            "duration": 120.0, "width": 1920, "height": 1080, "fps": 30.0,
+            "audio": true, "video": true, "audioCodec": "aac", "audioBitrate": null,
+            "colorSpace": null, "colorTransfer": null, "colorPrimaries": null,
+        }))
+        .unwrap();
+        let formats = vec!["16:9".to_string()];
+
+        let api_route = |chain: &[&str]| {
+            crate::whisper::TranscriptionRoute::Api(chain.iter().map(|m| m.to_string()).collect())
+        };
+        let api = estimate_job(&probe, &api_route(&["whisper-1"]), &formats, None).unwrap();
+        assert_eq!(api.engine, "api");
+        assert!((api.transcription_cost_usd.unwrap() - 0.012).abs() < 1e-9);
+        assert!((api.export_sec - 120.0 * ENCODE_1080P_FACTOR).abs() < 1e-6);
+        assert_eq!(api.total_sec, api.transcribe_sec + api.export_sec);
+
+        let small = crate::whisper::TranscriptionRoute::Local("small".into());
+        let local = estimate_job(&probe, &small, &formats, None).unwrap();
+        assert_eq!(
+            (local.engine.as_str(), local.transcription_cost_usd),
+            ("local", Some(0.0))
+        );
+        assert!(local.transcribe_sec > api.transcribe_sec);
+
+        // A local model that isn't installed routes to the API, which is priced
+        let fallback = estimate_job(
+            &probe,
+            &api_route(&["whisper-1", "gpt-4o-mini-transcribe"]),
+            &formats,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            (fallback.model.as_str(), fallback.engine.as_str()),
+            ("whisper-1", "api")
+        );
+        assert_eq!(fallback.transcription_cost_usd, api.transcription_cost_usd);
+        assert_eq!(fallback.fallback_models, ["gpt-4o-mini-transcribe"]);
+
+        // 4K output costs four times the pixels of 1080p
+        let uhd = estimate_job(&probe, &api_route(&["whisper-1"]), &formats, Some("4k")).unwrap();
+        assert!((uhd.export_sec - 4.0 * api.export_sec).abs() < 1e-6);
+
+        let unknown = crate::video::ProbeResult {
+            duration: None,
+            ..probe
+        };
+        assert!(estimate_job(&unknown, &small, &formats, None).is_err());
+    }
+
+    #[test]
+    fn test_inline_audio_only_for_short_api_clips() {
+        let mut logs = Vec::new();
+        let mut emit = |e: RpcEvent| {
+            if let RpcEvent::Log { message, .. } = e {
+                logs.push(message);
+            }
+        };
+        assert!(inline_audio_fits(
+            Some("whisper-1"),
+            Some(60.0),
+            &mut emit,
+            "t"
+        ));
+        // Local engines need a file; unknown or long audio falls back to one
+        assert!(!inline_audio_fits(Some("base"), Some(60.0), &mut emit, "t"));
+        assert!(!inline_audio_fits(None, Some(60.0), &mut emit, "t"));
+        assert!(!inline_audio_fits(Some("whisper-1"), None, &mut emit, "t"));
+        assert!(!inline_audio_fits(
+            Some("whisper-1"),
+            Some(3600.0),
+            &mut emit,
+            "t"
+        ));
+        assert_eq!(logs.len(), 4);
+        assert!(logs[3].contains("too long"), "{}", logs[3]);
+
+        let p = transcription_audio_params("/v.mp4", None, true, false, Some(1000), None);
+        assert_eq!(p.out, None);
+        assert_eq!(p.bitrate.as_deref(), Some("48k"));
+        assert_eq!((p.sample_rate, p.channels), (Some(16_000), Some(1)));
+    }
+
+    #[test]
+    fn test_prune_resumable_audio_keeps_recent_and_partials() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = std::time::SystemTime::now() - std::time::Duration::from_secs(100);
+        for (i, name) in ["a.mp3", "b.mp3", "c.mp3", "d.x.partial.mp3"]
+            .iter()
+            .enumerate()
+        {
+            let path = dir.path().join(name);
+            fs::write(&path, b"mp3").unwrap();
+            fs::File::options()
+                .append(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(base + std::time::Duration::from_secs(i as u64))
+                .unwrap();
+        }
+        prune_resumable_audio(dir.path(), 2);
+        let mut left: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(left, vec!["b.mp3", "c.mp3", "d.x.partial.mp3"]);
+    }
+
+    #[test]
+    fn test_job_dir_uses_work_dir_override() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = job_dir(root.path().to_str(), "capslap_captions_job".into()).unwrap();
+        assert_eq!(dir, root.path().join("capslap_captions_job"));
+        assert!(dir.is_dir());
+        // Blank overrides fall through to the env var / OS temp dir
+        assert_ne!(work_root(Some("  ")), PathBuf::from("  "));
+    }
 
-    for (i, token) in tokens.iter().enumerate() {
-        if i % 10 == 0 {
-            eprintln!("DEBUG: assemble_multiline loop i={}", i);
+    #[test]
+    fn test_remove_job_files_covers_every_scratch_dir_of_the_job() {
+        let root = tempfile::tempdir().unwrap();
+        let work_dir = root.path().to_str();
+        for name in [
+            "capslap_captions_job",
+            "capslap_preview_job_1234",
+            "capslap_clip_job_5678",
+            "capslap_captions_other",
+            "capslap_clip_jobs_9999",
+        ] {
+            job_dir(work_dir, name.into()).unwrap();
         }
-        let t_clean = token
-            .replace('\\', r"\\")
-            .replace('{', r"\{")
-            .replace('}', r"\}");
-        let t_len = t_clean.len();
+        remove_job_files(work_dir, "job");
+        let mut left: Vec<String> = fs::read_dir(root.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["capslap_captions_other", "capslap_clip_jobs_9999"]);
+    }
 
-        // Simple wrapping check
-        // Simple wrapping check
-        // Check if previous token ended with hyphen to suppress space
-        let prev_ended_with_hyphen = if i > 0 {
-            let prev = &tokens[i - 1];
-            prev.ends_with('-') && prev.len() > 1
-        } else {
-            false
+    #[test]
+    fn test_parse_hex_color_forms() {
+        let orange = HexColor {
+            r: 0xff,
+            g: 0x88,
+            b: 0x00,
+            a: 255,
         };
+        assert_eq!(parse_hex_color("#f80"), Some(orange));
+        assert_eq!(parse_hex_color("#FF8800"), Some(orange));
+        assert_eq!(parse_hex_color("ff8800"), Some(orange));
+        assert_eq!(parse_hex_color("#ff880080").map(|c| c.a), Some(0x80));
+        assert_eq!(parse_hex_color("white"), None);
+        assert_eq!(parse_hex_color("#ffff"), None);
+        assert_eq!(parse_hex_color("#ggg"), None);
+        assert_eq!(hex_to_ass_color("#fff"), "&H00FFFFFF");
+        assert_eq!(hex_to_ass_color("#102030"), "&H00302010");
+    }
 
-        if line_len > 0 && line_len + t_len + 1 > max_chars_per_line {
-            s.push_str(r"\N");
-            line_len = 0;
-        } else if line_len > 0 && !prev_ended_with_hyphen {
-            s.push(' ');
-            line_len += 1;
-        }
+    #[test]
+    fn test_validate_caption_style_names_the_field() {
+        let style = CaptionStyleOptions::default();
+        assert!(validate_caption_style(Some("#fff"), Some("#ffd700cc"), None, &style).is_ok());
 
-        // Color logic
-        let should_highlight = has_highlighting && i == hi;
-        s.push_str(if should_highlight { &hi_style } else { &white });
-        s.push_str(&t_clean);
+        let err = validate_caption_style(Some("white"), None, None, &style).unwrap_err();
+        assert_eq!(crate::rpc::error_code(&err), Some("INVALID_COLOR"));
+        assert!(
+            err.to_string().starts_with("textColor \"white\""),
+            "{}",
+            err
+        );
 
-        line_len += t_len;
+        let style = CaptionStyleOptions {
+            text_gradient: Some(("#ffffff".into(), "blue".into())),
+            ..Default::default()
+        };
+        let err = validate_caption_style(None, None, None, &style).unwrap_err();
+        assert!(err.to_string().contains("textGradient[1]"), "{}", err);
     }
-    s
-}
 
-/// Convert hex color string (e.g., "#ffffff") to ASS color format (e.g., "&H00FFFFFF")
-fn hex_to_ass_color(hex: &str) -> String {
-    let hex = hex.trim_start_matches('#');
-    if hex.len() == 6 {
-        // Convert RGB hex to BGR hex for ASS format
-        let r = &hex[0..2];
-        let g = &hex[2..4];
-        let b = &hex[4..6];
-        format!("&H00{}{}{}", b, g, r) // ASS uses AABBGGRR format
-    } else {
-        "&H00FFFFFF".into() // Default to white if invalid hex
+    #[test]
+    fn test_unknown_style_values_are_rejected() {
+        let rejected = |style: CaptionStyleOptions| {
+            validate_caption_style(None, None, None, &style)
+                .unwrap_err()
+                .to_string()
+        };
+        let err = rejected(CaptionStyleOptions {
+            animation: Some("wiggle".into()),
+            ..Default::default()
+        });
+        assert_eq!(
+            err,
+            "Unknown animation 'wiggle': use none, pop, fade or fill"
+        );
+        let err = rejected(CaptionStyleOptions {
+            karaoke_timing: Some("guess".into()),
+            ..Default::default()
+        });
+        assert_eq!(err, "Unknown karaokeTiming 'guess': use words or estimate");
+        let err = rejected(CaptionStyleOptions {
+            text_transform: Some("shout".into()),
+            ..Default::default()
+        });
+        assert_eq!(
+            err,
+            "Unknown textTransform 'shout': use upper, lower, title or none"
+        );
+        let err = rejected(CaptionStyleOptions {
+            reveal: Some("typewritter".into()),
+            ..Default::default()
+        });
+        assert_eq!(err, "Unknown reveal 'typewritter': use none or typewriter");
     }
-}
-
-// Split phrase into up to 2 lines for "Karaoke (Two Lines)" mode
-fn split_phrase_two_lines(
-    tokens: &[String],
-    spans: &[WordSpan],
-    frame_w: u32,
-    font_px: u32,
-) -> Vec<(Vec<String>, Vec<WordSpan>, usize)> {
-    // Determine max chars per line
-    let est_char_width = (font_px as f32 * 0.7).max(1.0);
-    // Use slightly less width to be safe for 2 lines
-    let max_chars = ((frame_w as f32 * 0.9) / est_char_width).floor() as usize;
 
-    // Target total length for a "screenful" (2 lines)
-    // We want to fill 2 lines if possible, so max capacity = 2 * max_chars
-    let max_capacity_chars = max_chars * 2;
+    #[test]
+    fn test_translucent_colors_reach_the_ass_output() {
+        // ASS alpha is inverted: #..80 (50% opaque) becomes 7F
+        assert_eq!(hex_to_ass_color("#ff000080"), "&H7F0000FF");
+        assert_eq!(hex_to_ass_color("#00ff00"), "&H0000FF00");
+
+        let opaque = default_ass_style(
+            1080,
+            1920,
+            None,
+            Some("#ffffff"),
+            Some("#ffd700"),
+            None,
+            false,
+            None,
+            None,
+            &CaptionStyleOptions::default(),
+        );
+        let (text, hi) = fill_colors(&opaque);
+        assert_eq!(text.tags(), r"\1c&HFFFFFF&");
+        assert_eq!(hi.tags(), r"\1c&H00D7FF&");
 
-    let mut segments = Vec::new();
-    let mut current_tokens = Vec::new();
-    let mut current_spans = Vec::new();
-    let mut current_len = 0;
+        let style = default_ass_style(
+            1080,
+            1920,
+            None,
+            Some("#ffffff"),
+            Some("#ffd70080"),
+            Some("#00000040"),
+            false,
+            None,
+            None,
+            &CaptionStyleOptions::default(),
+        );
+        assert_eq!(style.outline, "&HBF000000");
+        let (text, hi) = fill_colors(&style);
+        assert_eq!(text.tags(), r"\1c&HFFFFFF&\1a&H00&");
+        assert_eq!(hi.tags(), r"\1c&H00D7FF&\1a&H7F&");
+        assert_eq!(hi.bgr, "00D7FF");
+    }
 
-    for (token, span) in tokens.iter().zip(spans.iter()) {
-        let token_len = token.len() + 1; // + space
+    #[test]
+    fn test_background_color_alpha_scales_opacity() {
+        let options = CaptionStyleOptions {
+            background_color: Some("#10203080".into()),
+            background_opacity: Some(1.0),
+            ..Default::default()
+        };
+        let bg = background_box(&options).unwrap();
+        assert_eq!(bg.color, "#102030");
+        assert!((bg.opacity - 128.0 / 255.0).abs() < 1e-6);
+    }
 
-        if current_len > 0 && current_len + token_len > max_capacity_chars {
-            // Current 2-line block is full, push it
-            if !current_tokens.is_empty() {
-                let split_idx = find_best_split(&current_tokens, max_chars);
-                segments.push((current_tokens.clone(), current_spans.clone(), split_idx));
-                current_tokens.clear();
-                current_spans.clear();
-                current_len = 0;
-            }
-        }
+    #[test]
+    fn test_karaoke_fill_sweeps_active_word() {
+        let text = "fill the word";
+        let segments = vec![CaptionSegment {
+            start_ms: 0,
+            end_ms: 1500,
+            text: text.to_string(),
+            words: spans_for(text, 500),
+            no_speech_prob: None,
+            position: None,
+        }];
+        let options = CaptionStyleOptions {
+            animation: Some("fill".to_string()),
+            ..Default::default()
+        };
+        let style = default_ass_style(
+            1080,
+            1920,
+            None,
+            Some("#ffffff"),
+            Some("#ffd700"),
+            None,
+            false,
+            None,
+            None,
+            &options,
+        );
+        let doc = build_ass_document(1080, 1920, &style, &segments, true, false, false).unwrap();
+        // Active word fills from white to the highlight over its 500ms; others sit out the sweep
+        assert!(doc.contains(r"{\1c&H00D7FF&\2c&HFFFFFF&\fs"), "{}", doc);
+        assert!(doc.contains(r"\kf50}"));
+        assert!(doc.contains(r"\fscy100\k0}"));
+
+        let mut params = preview_params(segments, true);
+        params.style_options = options;
+        params.timestamp_ms = Some(625);
+        let layout = generate_preview_layout(params).unwrap();
+        let word = |cue: &crate::types::PreviewCue| {
+            cue.lines[0]
+                .words
+                .iter()
+                .find(|w| w.is_highlighted)
+                .map(|w| (w.animation_phase.clone().unwrap(), w.fill_pct))
+                .unwrap()
+        };
+        let (phase, fill) = word(&layout.cues[1]);
+        assert_eq!(phase.kind, "fill");
+        assert_eq!((phase.start_ms, phase.end_ms), (500, 1000));
+        assert_eq!(fill, Some(25.0));
+        // Cues the playhead hasn't reached yet are unfilled
+        assert_eq!(word(&layout.cues[2]).1, Some(0.0));
+    }
 
-        current_tokens.push(token.clone());
-        current_spans.push(span.clone());
-        current_len += token_len;
+    #[test]
+    fn test_estimate_word_spans_by_length() {
+        let segment = CaptionSegment {
+            start_ms: 1_000,
+            end_ms: 2_000,
+            text: "a bbb cccc".into(),
+            words: Vec::new(),
+            no_speech_prob: None,
+            position: None,
+        };
+        let spans = estimate_word_spans(&segment);
+        let times: Vec<_> = spans.iter().map(|w| (w.start_ms, w.end_ms)).collect();
+        assert_eq!(times, vec![(1_000, 1_125), (1_125, 1_500), (1_500, 2_000)]);
+
+        // "estimate" replaces transcribed word timings with the length-based guess
+        let with_words = CaptionSegment {
+            words: vec![
+                WordSpan {
+                    start_ms: 1_000,
+                    end_ms: 1_900,
+                    text: "a".into(),
+                },
+                WordSpan {
+                    start_ms: 1_900,
+                    end_ms: 1_950,
+                    text: "bbb".into(),
+                },
+                WordSpan {
+                    start_ms: 1_950,
+                    end_ms: 2_000,
+                    text: "cccc".into(),
+                },
+            ],
+            ..segment
+        };
+        let options = CaptionStyleOptions {
+            karaoke_timing: Some("estimate".into()),
+            ..Default::default()
+        };
+        let style = default_ass_style(
+            1080, 1920, None, None, None, None, false, None, None, &options,
+        );
+        let phrases = timed_phrases(std::slice::from_ref(&with_words), &style);
+        assert_eq!(phrases[0].spans[1].start_ms, 1_125);
+
+        let words_style = default_ass_style(
+            1080,
+            1920,
+            None,
+            None,
+            None,
+            None,
+            false,
+            None,
+            None,
+            &CaptionStyleOptions::default(),
+        );
+        let phrases = timed_phrases(&[with_words], &words_style);
+        assert_eq!(phrases[0].spans[1].start_ms, 1_900);
     }
 
-    if !current_tokens.is_empty() {
-        let split_idx = find_best_split(&current_tokens, max_chars);
-        segments.push((current_tokens, current_spans, split_idx));
+    #[test]
+    fn test_text_transform_is_unicode_aware() {
+        assert_eq!(TextTransform::Upper.apply("straße"), "STRASSE");
+        assert_eq!(TextTransform::Upper.apply("ıi"), "II");
+        assert_eq!(TextTransform::Lower.apply("İSTANBUL"), "i\u{307}stanbul");
+        assert_eq!(TextTransform::Lower.apply("ÉCOLE"), "école");
+        assert_eq!(TextTransform::Title.apply("ÉCOLE"), "École");
+        assert_eq!(TextTransform::Title.apply("\"don't"), "\"Don't");
+        assert_eq!(TextTransform::Title.apply("ßig"), "Ssig");
+        assert_eq!(TextTransform::None.apply("MiXeD"), "MiXeD");
+        assert_eq!(TextTransform::parse(None).unwrap(), TextTransform::Upper);
+        assert_eq!(
+            TextTransform::parse(Some("title")).unwrap(),
+            TextTransform::Title
+        );
     }
 
-    segments
-}
+    #[test]
+    fn test_text_transform_applies_to_preview_not_segments() {
+        let text = "Hello wörld";
+        let segments = vec![CaptionSegment {
+            start_ms: 0,
+            end_ms: 1000,
+            text: text.to_string(),
+            words: spans_for(text, 500),
+            no_speech_prob: None,
+            position: None,
+        }];
+        let mut params = preview_params(segments.clone(), true);
+        params.style_options.text_transform = Some("lower".into());
+        let layout = generate_preview_layout(params).unwrap();
+        let shown: Vec<&str> = layout.cues[0].lines[0]
+            .words
+            .iter()
+            .map(|w| w.text.as_str())
+            .collect();
+        assert_eq!(shown, vec!["hello", "wörld"]);
 
-// Find best index to split tokens into 2 lines
-fn find_best_split(tokens: &[String], max_chars_per_line: usize) -> usize {
-    let mut current_len = 0;
-    for (i, token) in tokens.iter().enumerate() {
-        let len = token.len() + 1;
-        if current_len + len > max_chars_per_line {
-            // This token makes it overflow, so split BEFORE this token
-            // i.e., line 1 ends at index i (0..i includes i items?)
-            // assemble_colored_two_lines takes line1_count.
-            // If we return i, line 1 has i items (0 to i-1). Item i starts line 2.
-            return i;
-        }
-        current_len += len;
+        let layout = generate_preview_layout(preview_params(segments.clone(), true)).unwrap();
+        assert_eq!(layout.cues[0].lines[0].words[1].text, "WÖRLD");
+        assert_eq!(segments[0].text, text);
     }
-    // If it all fits in one line, return usize::MAX so it doesn't break
-    usize::MAX
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_strip_word_punctuation_keeps_contractions() {
+        assert_eq!(strip_word_punctuation("world."), "world");
+        assert_eq!(strip_word_punctuation("“Really?!”"), "Really");
+        assert_eq!(strip_word_punctuation("¿Qué?"), "Qué");
+        assert_eq!(strip_word_punctuation("don't,"), "don't");
+        assert_eq!(strip_word_punctuation("goin'"), "goin'");
+        assert_eq!(strip_word_punctuation("'cause"), "'cause");
+        assert_eq!(strip_word_punctuation("'quoted'."), "quoted");
+        assert_eq!(strip_word_punctuation("好。"), "好");
+        assert_eq!(strip_word_punctuation("..."), "...");
+        assert_eq!(strip_word_punctuation("well-known"), "well-known");
+    }
 
     #[test]
-    fn test_preprocess_hyphenated_tokens_splits_time() {
-        let span = WordSpan {
+    fn test_strip_punctuation_keeps_karaoke_words_aligned() {
+        let text = "Wait, “what” … okay";
+        let segments = vec![CaptionSegment {
             start_ms: 0,
-            end_ms: 1000,
-            text: "FOO-BAR".to_string(),
+            end_ms: 2000,
+            text: text.to_string(),
+            words: spans_for(text, 500),
+            no_speech_prob: None,
+            position: None,
+        }];
+        let mut params = preview_params(segments, true);
+        params.style_options.strip_punctuation = true;
+        params.font_size = Some(30); // keep the whole segment on one line
+        let layout = generate_preview_layout(params).unwrap();
+        let shown: Vec<&str> = layout.cues[0]
+            .lines
+            .iter()
+            .flat_map(|l| &l.words)
+            .map(|w| w.text.as_str())
+            .collect();
+        assert_eq!(shown, vec!["WAIT", "WHAT", "…", "OKAY"]);
+        // One cue per word, each starting at its own word
+        let starts: Vec<u64> = layout.cues.iter().map(|c| c.start_ms).collect();
+        assert_eq!(starts, vec![0, 500, 1000, 1500]);
+    }
+
+    #[test]
+    fn test_profanity_filter_masks_or_removes_words() {
+        let text = "Oh shit, that's Frobnicated damn good";
+        let segments = vec![CaptionSegment {
+            start_ms: 0,
+            end_ms: 3000,
+            text: text.to_string(),
+            words: spans_for(text, 500),
+            no_speech_prob: None,
+            position: None,
+        }];
+        let shown = |mode: &str| {
+            let options = CaptionStyleOptions {
+                profanity_filter: Some(mode.into()),
+                profanity_words: vec![" frobnicated ".into()],
+                ..Default::default()
+            };
+            let style = default_ass_style(
+                1080, 1920, None, None, None, None, false, None, None, &options,
+            );
+            timed_phrases(&segments, &style)
+                .into_iter()
+                .flat_map(|p| p.spans)
+                .map(|s| (s.text, s.start_ms))
+                .collect::<Vec<_>>()
         };
-        let tokens = vec!["FOO-BAR".to_string()];
-        let spans = vec![span];
 
-        let (new_tokens, new_spans) = preprocess_hyphenated_tokens(&tokens, &spans);
+        let masked = shown("mask");
+        let words: Vec<&str> = masked.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(
+            words,
+            vec!["Oh", "s***,", "that's", "F**********", "d***", "good"]
+        );
 
-        assert_eq!(new_tokens.len(), 2);
-        assert_eq!(new_tokens[0], "FOO-");
-        assert_eq!(new_tokens[1], "BAR");
+        let removed = shown("remove");
+        let kept: Vec<(&str, u64)> = removed.iter().map(|(t, ms)| (t.as_str(), *ms)).collect();
+        assert_eq!(kept, vec![("Oh", 0), ("that's", 1000), ("good", 2500)]);
 
-        // "FOO-BAR" has 7 chars.
-        // "FOO-" is 4 chars.
-        // "BAR" is 3 chars.
-        // Duration 1000ms.
-        // Part 1: 1000 * 4/7 = 571ms.
-        // Part 2: 1000 * 3/7 = 428ms.
+        assert_eq!(shown("off").len(), 6);
+        // The segments themselves are never censored
+        assert_eq!(segments[0].text, text);
+    }
 
-        let s0 = new_spans[0].start_ms;
-        let e0 = new_spans[0].end_ms;
-        let s1 = new_spans[1].start_ms;
-        let e1 = new_spans[1].end_ms;
+    #[test]
+    fn test_replacements_fix_text_and_word_spans() {
+        let segment = CaptionSegment {
+            start_ms: 0,
+            end_ms: 2500,
+            text: "We love open ai and Capslap, said Al".into(),
+            words: spans_for("We love open ai and capslap, said Al", 300),
+            no_speech_prob: None,
+            position: None,
+        };
+        let rules = compile_replacements(&[
+            crate::types::TextReplacement {
+                find: "open ai".into(),
+                replace: "OpenAI".into(),
+                ..Default::default()
+            },
+            crate::types::TextReplacement {
+                find: "capslap".into(),
+                replace: "CapSlap".into(),
+                whole_word: true,
+                ..Default::default()
+            },
+            crate::types::TextReplacement {
+                find: "al".into(),
+                replace: "Alan".into(),
+                whole_word: true,
+                ..Default::default()
+            },
+        ])
+        .unwrap();
+        let out = &apply_replacements(std::slice::from_ref(&segment), &rules)[0];
+        assert_eq!(out.text, "We love OpenAI and CapSlap, said Alan");
+        let words: Vec<(&str, u64, u64)> = out
+            .words
+            .iter()
+            .map(|w| (w.text.as_str(), w.start_ms, w.end_ms))
+            .collect();
+        assert_eq!(
+            words,
+            vec![
+                ("We", 0, 300),
+                ("love", 300, 600),
+                ("OpenAI", 600, 1200), // "open" + "ai" merged into one span
+                ("and", 1200, 1500),
+                ("CapSlap,", 1500, 1800),
+                ("said", 1800, 2100),
+                ("Alan", 2100, 2400),
+            ]
+        );
 
-        println!("Part 1 ({:?}): {} - {}", new_tokens[0], s0, e0);
-        println!("Part 2 ({:?}): {} - {}", new_tokens[1], s1, e1);
+        let regex_rule = compile_replacements(&[crate::types::TextReplacement {
+            find: r"(\d+) percent".into(),
+            replace: "$1%".into(),
+            regex: true,
+            ..Default::default()
+        }])
+        .unwrap();
+        let seg = CaptionSegment {
+            start_ms: 0,
+            end_ms: 600,
+            text: "50 Percent".into(),
+            words: spans_for("50 Percent", 300),
+            no_speech_prob: None,
+            position: None,
+        };
+        let out = &apply_replacements(&[seg], &regex_rule)[0];
+        assert_eq!((out.text.as_str(), out.words.len()), ("50%", 1));
+
+        // Two matches inside one word both land in the word, like in the text
+        let letter_rule = compile_replacements(&[crate::types::TextReplacement {
+            find: "l".into(),
+            replace: "L".into(),
+            ..Default::default()
+        }])
+        .unwrap();
+        let seg = CaptionSegment {
+            start_ms: 0,
+            end_ms: 600,
+            text: "hello all".into(),
+            words: spans_for("hello all", 300),
+            no_speech_prob: None,
+            position: None,
+        };
+        let out = &apply_replacements(&[seg], &letter_rule)[0];
+        assert_eq!(out.text, "heLLo aLL");
+        let words: Vec<&str> = out.words.iter().map(|w| w.text.as_str()).collect();
+        assert_eq!(words, ["heLLo", "aLL"]);
+
+        let bad = [crate::types::TextReplacement {
+            find: "(".into(),
+            regex: true,
+            ..Default::default()
+        }];
+        let style = CaptionStyleOptions {
+            replacements: bad.to_vec(),
+            ..Default::default()
+        };
+        assert!(validate_caption_style(None, None, None, &style).is_err());
+    }
 
-        assert_eq!(s0, 0);
-        // We expect sequential times
-        assert!(e0 > 0);
-        assert_eq!(s1, e0); // Start of next = End of previous
-        assert_eq!(e1, 1000);
+    #[test]
+    fn test_platform_preset_fills_only_unset_fields() {
+        let params = |extra: serde_json::Value| {
+            let mut json = serde_json::json!({
+                "inputVideo": "/v.mp4",
+                "karaoke": false,
+                "splitByWords": true,
+                "preset": "TikTok",
+            });
+            json.as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            serde_json::from_value::<GenerateCaptionsParams>(json).unwrap()
+        };
 
-        // Check approximate proportionality
-        let d0 = e0 - s0;
-        let d1 = e1 - s1;
-        assert!(d0 > d1); // 4 chars > 3 chars
-        assert!((d0 as i64 - 571).abs() < 5);
+        let mut plain = params(serde_json::json!({}));
+        apply_platform_preset(&mut plain).unwrap();
+        assert_eq!(plain.export_formats, vec!["9:16"]);
+        assert_eq!(plain.output_size.as_deref(), Some("1080p"));
+        assert_eq!(plain.crop_strategy.as_deref(), Some("fill"));
+        assert_eq!(plain.position.as_deref(), Some("center"));
+        assert_eq!(plain.encode_options.video_bitrate.as_deref(), Some("6M"));
+        assert_eq!(plain.style_options.safe_margin_bottom_pct, Some(20.0));
+
+        let mut explicit = params(serde_json::json!({
+            "exportFormats": ["1:1"],
+            "position": "bottom",
+            "crf": 20,
+            "safeMarginBottomPct": 5.0,
+        }));
+        apply_platform_preset(&mut explicit).unwrap();
+        assert_eq!(explicit.export_formats, vec!["1:1"]);
+        assert_eq!(explicit.position.as_deref(), Some("bottom"));
+        assert_eq!(explicit.crop_strategy.as_deref(), Some("fill"));
+        assert_eq!(explicit.encode_options.video_bitrate, None);
+        assert_eq!(explicit.style_options.safe_margin_bottom_pct, Some(5.0));
+        assert_eq!(explicit.style_options.safe_margin_x_pct, Some(12.0));
+
+        let mut unknown = params(serde_json::json!({ "preset": "myspace" }));
+        let err = apply_platform_preset(&mut unknown).unwrap_err().to_string();
+        assert!(err.contains("tiktok, reels, shorts, youtube"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_empty_export_formats_rejected_before_transcription() {
+        let params = serde_json::from_value::<GenerateCaptionsParams>(serde_json::json!({
+            "inputVideo": "/nonexistent/capslap_no_formats.mp4",
+            "karaoke": false,
+            "splitByWords": true,
+        }))
+        .unwrap();
+        let err = generate_captions_single_pass("no-formats", params, |_| {})
+            .await
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "No export formats specified");
     }
 
     #[test]
-    fn test_assemble_colored_two_lines_hyphenation() {
-        let tokens = vec!["SAKSALAIS-".to_string(), "ROOMALAINEN".to_string()];
+    fn test_bitexact_output_strips_source_metadata() {
+        let copying = EncodeOptions::default();
+        assert_eq!(output_metadata_args(&copying), vec!["-map_metadata", "0"]);
 
-        // We need to provide dummy args for assemble_colored_two_lines
-        // It requires: tokens, hi, white_bgr, hi_bgr, line1_count, header, font_size
-        let result = assemble_colored_two_lines(
-            &tokens,
-            usize::MAX, // no highlight
-            "FFFFFF",
-            "0000FF",
-            usize::MAX, // no break
-            "{\\an2}",
-            20,
+        let mut bitexact = EncodeOptions {
+            bitexact: true,
+            ..Default::default()
+        };
+        bitexact.metadata.metadata_title = Some("Clip".into());
+        let args = output_metadata_args(&bitexact).join(" ");
+        assert!(args.contains("-map_metadata -1"), "{}", args);
+        assert!(args.contains("-fflags +bitexact"));
+        assert!(args.contains("-flags:v +bitexact -flags:a +bitexact"));
+        // Explicit tags are deterministic, so they stay
+        assert!(args.contains("-metadata title=Clip"));
+    }
+
+    fn timed(start_ms: u64, end_ms: u64, text: &str) -> CaptionSegment {
+        CaptionSegment {
+            start_ms,
+            end_ms,
+            text: text.to_string(),
+            words: vec![],
+            no_speech_prob: None,
+            position: None,
+        }
+    }
+
+    #[test]
+    fn test_check_segments_names_the_bad_index() {
+        let segments = vec![
+            timed(0, 1000, "one"),
+            timed(900, 2000, "two"),
+            timed(2000, 3000, "three"),
+        ];
+        let err = check_segments("t", &segments, false, &mut |_| {}).unwrap_err();
+        assert_eq!(crate::rpc::error_code(&err), Some("INVALID_SEGMENT"));
+        assert_eq!(
+            err.to_string(),
+            "Segment 1 (900-2000 ms, \"two\") overlaps segment 0, which ends at 1000 ms"
         );
 
-        println!("Result: {}", result);
+        let backwards = vec![timed(0, 1000, "one"), timed(3000, 2000, "two")];
+        let err = check_segments("t", &backwards, false, &mut |_| {}).unwrap_err();
+        assert!(err.to_string().starts_with("Segment 1 "), "{}", err);
+        assert!(err.to_string().ends_with("ends before it starts"));
+
+        let unordered = vec![timed(2000, 3000, "late"), timed(0, 1000, "early")];
+        let err = check_segments("t", &unordered, false, &mut |_| {}).unwrap_err();
         assert!(
-            !result.contains("SAKSALAIS- "),
-            "Should not contain space after hyphen"
+            err.to_string().contains("starts before segment 0"),
+            "{}",
+            err
+        );
+
+        let err = check_segments("t", &[], true, &mut |_| {}).unwrap_err();
+        assert_eq!(crate::rpc::error_code(&err), Some("INVALID_SEGMENT"));
+        assert_eq!(err.to_string(), "No segments to burn");
+        let empty = vec![timed(1000, 1000, "blink")];
+        let err = check_segments("t", &empty, true, &mut |_| {}).unwrap_err();
+        assert_eq!(crate::rpc::error_code(&err), Some("INVALID_SEGMENT"));
+
+        // Back-to-back cues are fine and pass through untouched
+        let ok = vec![timed(0, 1000, "one"), timed(1000, 2000, "two")];
+        assert_eq!(
+            check_segments("t", &ok, false, &mut |_| {}).unwrap().len(),
+            2
         );
     }
 
+    #[tokio::test]
+    async fn test_subtitle_file_without_cues_is_an_invalid_segment() {
+        let dir = tempfile::tempdir().unwrap();
+        let srt = dir.path().join("empty.srt");
+        fs::write(&srt, "\n").unwrap();
+        let params =
+            serde_json::from_value::<crate::types::BurnFromSubtitleFileParams>(serde_json::json!({
+                "subtitlePath": srt.to_string_lossy(),
+                "inputVideo": "/nonexistent/capslap_empty_srt.mp4",
+                "exportFormats": ["9:16"],
+                "karaoke": false,
+            }))
+            .unwrap();
+        let err = burn_from_subtitle_file("t", params, |_| {})
+            .await
+            .unwrap_err();
+        assert_eq!(crate::rpc::error_code(&err), Some("INVALID_SEGMENT"));
+    }
+
     #[test]
-    fn test_assemble_multiline_hyphenation() {
-        let tokens = vec!["FOO-".to_string(), "BAR".to_string()];
+    fn test_check_segments_fix_sorts_and_clamps() {
+        let mut overlapping = timed(900, 2500, "two");
+        overlapping.words = vec![
+            WordSpan {
+                start_ms: 900,
+                end_ms: 1500,
+                text: "two".into(),
+            },
+            WordSpan {
+                start_ms: 1500,
+                end_ms: 2500,
+                text: "more".into(),
+            },
+        ];
+        let segments = vec![
+            timed(2000, 3000, "three"),
+            timed(0, 1000, "one"),
+            overlapping,
+            timed(2000, 2000, "empty"),
+        ];
+        let mut logs = vec![];
+        let fixed = check_segments("t", &segments, true, &mut |e| logs.push(e)).unwrap();
+        let times: Vec<(u64, u64)> = fixed.iter().map(|s| (s.start_ms, s.end_ms)).collect();
+        assert_eq!(times, vec![(0, 900), (900, 2000), (2000, 3000)]);
+        assert_eq!(fixed[1].words[1].start_ms, 1500);
+        assert_eq!(fixed[1].words[1].end_ms, 2000);
+        assert!(segment_problem(&fixed).is_none());
+        assert!(matches!(
+            &logs[0],
+            RpcEvent::Log { message, .. } if message.contains("3 segments kept of 4")
+        ));
+    }
 
-        let result = assemble_multiline(&tokens, usize::MAX, "FFFFFF", "0000FF", 20, 100);
+    #[test]
+    fn test_layout_counts_graphemes_not_code_points() {
+        // Finland flag (two regional indicators), a ZWJ family, and Hindi with vowel signs
+        let tokens: Vec<String> = ["🇫🇮🇫🇮", "👨‍👩‍👧", "नमस्ते"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(grapheme_count(&tokens[0]), 2);
+        assert_eq!(grapheme_count(&tokens[1]), 1);
+        assert!(grapheme_count(&tokens[2]) < tokens[2].chars().count());
+        // 2 + 1 + 1 spaces: the flags and family fit on a 4-character line together
+        assert_eq!(wrap_line_starts(&tokens[..2], 4), Vec::<usize>::new());
+
+        let metrics = FontMetrics::load(DEFAULT_FONT_FAMILY);
+        let family = metrics.text_width("👨‍👩‍👧", 80);
+        assert!((family - metrics.text_width("👨", 80)).abs() < 0.01);
+    }
 
-        println!("Result: {}", result);
-        assert!(
-            !result.contains("FOO- "),
-            "Should not contain space after hyphen in multiline"
+    #[test]
+    fn test_overlong_tokens_split_between_graphemes() {
+        let spans = |text: &str| {
+            vec![WordSpan {
+                start_ms: 0,
+                end_ms: 900,
+                text: text.into(),
+            }]
+        };
+        let flags = "🇫🇮🇸🇪🇳🇴".to_string();
+        let (pieces, timed) =
+            split_overlong_tokens(std::slice::from_ref(&flags), &spans(&flags), 2);
+        assert_eq!(pieces, vec!["🇫🇮🇸🇪", "🇳🇴"]);
+        assert_eq!(timed[1].start_ms, 600);
+
+        let hindi = "स्वागतम्".to_string();
+        let (pieces, _) = split_overlong_tokens(std::slice::from_ref(&hindi), &spans(&hindi), 2);
+        assert_eq!(pieces.concat(), hindi);
+        for piece in &pieces {
+            // No piece may start with a dependent vowel sign or virama
+            let first = piece.chars().next().unwrap();
+            assert!(!('\u{093E}'..='\u{094D}').contains(&first), "{:?}", pieces);
+        }
+
+        let column = assemble_vertical_column(
+            &["🇫🇮é".to_string()],
+            0,
+            &opaque_fill("FFFFFF"),
+            &opaque_fill("00FFFF"),
+            40,
+        );
+        assert!(column.ends_with("🇫🇮\\Né"), "{}", column);
+    }
+
+    #[test]
+    fn test_typewriter_reveal_clips_each_line() {
+        let segments = vec![CaptionSegment {
+            start_ms: 0,
+            end_ms: 1000,
+            text: "hi you".to_string(),
+            words: spans_for("hi you", 500),
+            no_speech_prob: None,
+            position: None,
+        }];
+        let options = CaptionStyleOptions {
+            reveal: Some("typewriter".into()),
+            ..Default::default()
+        };
+        let style = default_ass_style(
+            1080, 1920, None, None, None, None, false, None, None, &options,
+        );
+        let doc = build_ass_document(1080, 1920, &style, &segments, false, false, false).unwrap();
+        let events: Vec<&str> = doc.lines().filter(|l| l.starts_with("Dialogue:")).collect();
+        assert!(!events.is_empty());
+        for event in &events {
+            // "H" shows at once; "I", "Y", "O", "U" follow at 250, 500, 666 and 833 ms
+            assert_eq!(event.matches(r"\clip(").count(), 5, "{}", event);
+            assert!(event.contains(r"\t(250,250,\clip(0,0,"), "{}", event);
+            assert!(
+                event.contains(r"\t(833,833,\clip(0,0,1080,1920))"),
+                "{}",
+                event
+            );
+        }
+
+        let mut params = preview_params(segments, false);
+        params.style_options = options;
+        params.timestamp_ms = Some(600);
+        let cues = generate_preview_layout(params).unwrap().cues;
+        assert_eq!(cues[0].visible_chars, Some(3));
+    }
+
+    #[test]
+    fn test_reveal_keeps_gradient_band_clips() {
+        let band = RevealBand {
+            top: 0,
+            bottom: 1920,
+            hidden_right: 100,
+            steps: vec![(0, 300), (400, 1080)],
+        };
+        let mut lines = String::new();
+        let mut cue = CueEvents::new(&mut lines, (0, 100), 0, 1080).with_reveal(vec![band]);
+        cue.push(2, (0, 100), Some([0, 900, 1080, 950]), "{\\an2}HI");
+        cue.push(0, (50, 100), None, "{\\an2}HI");
+        let events: Vec<&str> = lines.lines().collect();
+        assert_eq!(
+            events[0],
+            "Dialogue: 2,0:00:00.00,0:00:01.00,TikTok,,0,0,0,,\
+             {\\clip(0,900,300,950)\\t(400,400,\\clip(0,900,1080,950))}{\\an2}HI"
+        );
+        // Fully typed before the event starts: left as it was
+        assert_eq!(
+            events[1],
+            "Dialogue: 0,0:00:00.50,0:00:01.00,TikTok,,0,0,0,,{\\an2}HI"
+        );
+        assert_eq!(
+            visible_chars_at(&["HI".into()], &spans_for("hi", 400), None),
+            2
         );
     }
+
+    #[test]
+    fn test_safe_area_moves_captions_inwards() {
+        let style_with = |position: &str, top: f32, bottom: f32, x: f32| {
+            let options = CaptionStyleOptions {
+                safe_margin_top_pct: Some(top),
+                safe_margin_bottom_pct: Some(bottom),
+                safe_margin_x_pct: Some(x),
+                ..Default::default()
+            };
+            default_ass_style(
+                1080,
+                1920,
+                None,
+                None,
+                None,
+                None,
+                false,
+                Some(position),
+                None,
+                &options,
+            )
+        };
+        // Bottom captions rise above a 20% caption bar; a smaller safe area changes nothing
+        assert_eq!(anchor_y(&style_with("bottom", 0.0, 20.0, 0.0), 1920), 1536);
+        assert_eq!(anchor_y(&style_with("bottom", 0.0, 5.0, 0.0), 1920), 1690);
+        assert_eq!(anchor_y(&style_with("top", 15.0, 0.0, 0.0), 1920), 288);
+        // The middle row is centered between the top and bottom margins
+        assert_eq!(anchor_y(&style_with("center", 10.0, 20.0, 0.0), 1920), 864);
+
+        let narrow = style_with("bottom", 0.0, 0.0, 12.0);
+        assert_eq!(layout_width(&narrow, 1080), 820);
+        assert_eq!(rtl_anchor(&narrow, 1080), (3, 950));
+
+        let segments = vec![CaptionSegment {
+            start_ms: 0,
+            end_ms: 1000,
+            text: "safe".to_string(),
+            words: spans_for("safe", 1000),
+            no_speech_prob: None,
+            position: None,
+        }];
+        let mut params = preview_params(segments, false);
+        params.style_options.safe_margin_bottom_pct = Some(20.0);
+        let cues = generate_preview_layout(params).unwrap().cues;
+        assert!((cues[0].y_pct - 80.0).abs() < 0.01, "{}", cues[0].y_pct);
+    }
 }
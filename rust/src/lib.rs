@@ -3,4 +3,7 @@ pub mod types;
 pub mod audio;
 pub mod video;
 pub mod captions;
-pub mod whisper;
\ No newline at end of file
+pub mod whisper;
+pub mod subtitles;
+pub mod timecode;
+pub mod logging;
\ No newline at end of file
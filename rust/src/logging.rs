@@ -0,0 +1,176 @@
+use serde_json::Value;
+use std::path::PathBuf;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::EnvFilter;
+
+/// Rotated log files kept in the log directory (one per day)
+const MAX_LOG_FILES: usize = 7;
+
+/// Start file logging when `CAPSLAP_LOG` is set, e.g. `debug` or `info,core=debug`.
+/// Logs go to `<cache root>/logs/core.<date>.log`; keep the guard alive so buffered
+/// lines are flushed on exit. Unset (or `off`) logs nothing.
+pub fn init() -> Option<WorkerGuard> {
+    let level = std::env::var("CAPSLAP_LOG").ok()?;
+    let level = level.trim();
+    if level.is_empty() || level.eq_ignore_ascii_case("off") {
+        return None;
+    }
+    let filter = EnvFilter::try_new(level).ok()?;
+
+    let dir = log_dir();
+    std::fs::create_dir_all(&dir).ok()?;
+    let appender = tracing_appender::rolling::Builder::new()
+        .rotation(tracing_appender::rolling::Rotation::DAILY)
+        .filename_prefix("core")
+        .filename_suffix("log")
+        .max_log_files(MAX_LOG_FILES)
+        .build(&dir)
+        .ok()?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(false)
+        .try_init()
+        .ok()?;
+    tracing::info!(dir = %dir.display(), "logging started");
+    Some(guard)
+}
+
+/// Where log files go: next to the transcription cache when a cache root is configured
+pub fn log_dir() -> PathBuf {
+    crate::whisper::cache_root_override()
+        .map(|(root, _)| root)
+        .unwrap_or_else(|| std::env::temp_dir().join("capslap"))
+        .join("logs")
+}
+
+/// Log an external command line (ffmpeg, ffprobe, whisper) at debug level
+pub fn log_command(cmd: &tokio::process::Command) {
+    tracing::debug!(command = ?cmd.as_std(), "running command");
+}
+
+/// Program and arguments of a command as one argv, e.g. for a dry run's result
+pub fn command_argv(cmd: &tokio::process::Command) -> Vec<String> {
+    let cmd = cmd.as_std();
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Copy of request params with API keys and other secrets blanked out, safe to log
+pub fn redact_params(params: &Value) -> Value {
+    match params {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        Value::String(s) if is_secret_key(key) => Value::String(mask_secret(s)),
+                        Value::Null => Value::Null,
+                        _ if is_secret_key(key) => Value::String("***".into()),
+                        Value::String(s) => Value::String(redact_secrets(s)),
+                        _ => redact_params(value),
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact_params).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Mask a secret for display: `sk-abc123...` becomes `sk-***`, anything else `***`
+pub fn mask_secret(secret: &str) -> String {
+    match secret.split_once('-') {
+        Some((prefix, _))
+            if prefix.len() <= 4 && prefix.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            format!("{}-***", prefix)
+        }
+        _ => "***".to_string(),
+    }
+}
+
+/// Mask anything shaped like an OpenAI key (`sk-...`, `sk-proj-...`) in free text,
+/// e.g. an API error body or an error message headed for the client
+pub fn redact_secrets(text: &str) -> String {
+    static KEY: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    KEY.get_or_init(|| regex::Regex::new(r"\bsk-[A-Za-z0-9_\-*.]{6,}").unwrap())
+        .replace_all(text, "sk-***")
+        .into_owned()
+}
+
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase().replace(['_', '-'], "");
+    ["apikey", "token", "secret", "password", "authorization"]
+        .iter()
+        .any(|s| key.contains(s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_params_hides_nested_secrets() {
+        let params = serde_json::json!({
+            "inputVideo": "/videos/a.mp4",
+            "apiKey": "sk-live-123",
+            "model": "whisper-1",
+            "jobs": [{ "api_key": "sk-2", "accessToken": null }],
+        });
+        let redacted = redact_params(&params);
+        assert_eq!(redacted["apiKey"], "sk-***");
+        assert_eq!(redacted["jobs"][0]["api_key"], "sk-***");
+        assert!(redacted["jobs"][0]["accessToken"].is_null());
+        assert_eq!(redacted["inputVideo"], "/videos/a.mp4");
+        assert!(!redacted.to_string().contains("live-123"));
+    }
+
+    #[test]
+    fn test_redact_secrets_in_free_text() {
+        let body = r#"{"error":{"message":"Incorrect API key provided: sk-proj-AbC1********************xyz9."}}"#;
+        let redacted = redact_secrets(body);
+        assert!(
+            redacted.contains("Incorrect API key provided: sk-***"),
+            "{}",
+            redacted
+        );
+        assert!(!redacted.contains("AbC1"));
+        assert_eq!(
+            redact_secrets("no keys in task-list here"),
+            "no keys in task-list here"
+        );
+        assert_eq!(mask_secret("sk-live-123"), "sk-***");
+        assert_eq!(mask_secret("hunter2"), "***");
+    }
+
+    #[test]
+    fn test_api_key_debug_is_masked() {
+        let p: crate::types::TranscribeSegmentsParams = serde_json::from_value(serde_json::json!({
+            "audio": "/a.mp3",
+            "splitByWords": true,
+            "apiKey": "sk-secret-value-123",
+        }))
+        .unwrap();
+        let key = p.api_key.as_ref().unwrap();
+        assert_eq!(key.expose(), "sk-secret-value-123");
+        let debug = format!("{:?}", p);
+        assert!(debug.contains("ApiKey(sk-***)"), "{}", debug);
+        assert!(!debug.contains("secret-value"));
+        // Round-trips as a plain string so clients are unaffected
+        assert_eq!(serde_json::to_value(key).unwrap(), "sk-secret-value-123");
+    }
+
+    #[test]
+    fn test_command_argv_keeps_each_argument() {
+        let mut cmd = tokio::process::Command::new("ffmpeg");
+        cmd.args(["-y", "-i", "/videos/my clip.mp4"]);
+        assert_eq!(
+            command_argv(&cmd),
+            vec!["ffmpeg", "-y", "-i", "/videos/my clip.mp4"]
+        );
+    }
+}
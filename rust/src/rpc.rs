@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+/// One request line. Any request's params may include `ffmpegPath`, the ffmpeg binary to
+/// use for everything that request runs (ahead of `FFMPEG_PATH` and the bundled/system search),
+/// and `timeoutSec`, its time limit (see [`request_timeout`]).
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RpcRequest {
     pub id: String,     // Unique identifier to match requests with responses
@@ -19,9 +22,42 @@ pub struct RpcResponse<T> {
 pub struct RpcError {
     pub id: String,    // Same ID as the request that failed
     pub error: String, // Human-readable error message explaining what went wrong
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>, // Machine-readable error code (e.g. "NO_AUDIO") the UI can branch on
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Error carrying a stable machine-readable code, surfaced to the client as `RpcError.code`.
+#[derive(Debug)]
+pub struct CodedError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl CodedError {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for CodedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CodedError {}
+
+/// Find the code of the first `CodedError` in an error chain, if any.
+pub fn error_code(err: &anyhow::Error) -> Option<&'static str> {
+    err.chain()
+        .find_map(|e| e.downcast_ref::<CodedError>())
+        .map(|e| e.code)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "event", rename_all = "camelCase")] // JSON will have an "event" field indicating the type
 pub enum RpcEvent {
     // Progress updates for long operations (0.0 to 1.0 completion)
@@ -35,12 +71,333 @@ pub enum RpcEvent {
         id: String,      // ID of the operation
         message: String, // The log message content
     },
+    // Sent every few seconds while a request runs, so silence means the core is stuck
+    Heartbeat {
+        id: String, // ID of the operation still running
+    },
+}
+
+/// Interval between `Heartbeat` events for a running request
+pub const HEARTBEAT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Default time limit for a method, generous enough for long videos on slow machines.
+/// Methods without one (quick or synchronous ones) run unbounded.
+pub fn default_timeout(method: &str) -> Option<std::time::Duration> {
+    let secs = match method {
+        "probe" | "estimate" => 60,
+        "extractThumbnails" | "generatePreviewClip" => 10 * 60,
+        "transcribe" | "downloadModel" => 60 * 60,
+        "generateCaptions" | "burn" | "burnFromSubtitleFile" | "concatVideos" => 3 * 60 * 60,
+        _ => return None,
+    };
+    Some(std::time::Duration::from_secs(secs))
+}
+
+/// Time limit for a request: its `timeoutSec` param, then config `requestTimeouts`,
+/// then the method's default. 0 turns the limit off. The config is only read for
+/// methods that have a default, so quick ones (ping, cancel, ...) never touch disk.
+pub fn request_timeout(method: &str, timeout_sec: Option<u64>) -> Option<std::time::Duration> {
+    let config = match timeout_sec {
+        Some(_) => Default::default(),
+        None if default_timeout(method).is_none() => return None,
+        None => crate::captions::core_config_or_default().request_timeouts,
+    };
+    resolve_timeout(method, timeout_sec, &config)
+}
+
+fn resolve_timeout(
+    method: &str,
+    timeout_sec: Option<u64>,
+    config: &std::collections::HashMap<String, u64>,
+) -> Option<std::time::Duration> {
+    match timeout_sec.or_else(|| config.get(method).copied()) {
+        Some(0) => None,
+        Some(secs) => Some(std::time::Duration::from_secs(secs)),
+        None => default_timeout(method),
+    }
+}
+
+/// Why a running request stopped early
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interruption {
+    Cancelled,
+    TimedOut(std::time::Duration),
+}
+
+/// Resolves when the request is cancelled or its time limit passes
+pub async fn interruption(
+    cancel: &mut tokio::sync::broadcast::Receiver<()>,
+    timeout: Option<std::time::Duration>,
+) -> Interruption {
+    let deadline = async {
+        match timeout {
+            Some(limit) => {
+                tokio::time::sleep(limit).await;
+                limit
+            }
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = cancel.recv() => Interruption::Cancelled,
+        limit = deadline => Interruption::TimedOut(limit),
+    }
+}
+
+/// Minimum gap between `Progress` events of one request
+pub const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Coalesces a request's `Progress` events to one per interval. A status change and
+/// the final 1.0 always go out right away; an update held back is sent ahead of the
+/// next event that isn't progress, so logs never overtake it. Each event comes paired
+/// with whether the transport may drop it: only routine updates that a later one
+/// supersedes.
+pub struct ProgressThrottle {
+    interval: std::time::Duration,
+    last_sent: Option<std::time::Instant>,
+    last_status: Option<String>,
+    pending: Option<RpcEvent>,
+}
+
+impl ProgressThrottle {
+    pub fn new(interval: std::time::Duration) -> Self {
+        Self {
+            interval,
+            last_sent: None,
+            last_status: None,
+            pending: None,
+        }
+    }
+
+    /// Events to send now for `ev` (none while throttled, two when a held update is flushed)
+    pub fn push(&mut self, ev: RpcEvent, now: std::time::Instant) -> Vec<(RpcEvent, bool)> {
+        let RpcEvent::Progress {
+            status, progress, ..
+        } = &ev
+        else {
+            let held = self.pending.take().map(|held| (held, true));
+            return held.into_iter().chain([(ev, false)]).collect();
+        };
+        let due = self
+            .last_sent
+            .is_none_or(|sent| now.duration_since(sent) >= self.interval);
+        let status_changed = self.last_status.as_deref() != Some(status.as_str());
+        let milestone = status_changed || *progress >= 1.0;
+        if due || milestone {
+            self.last_sent = Some(now);
+            self.last_status = Some(status.clone());
+            self.pending = None;
+            vec![(ev, !milestone)]
+        } else {
+            self.pending = Some(ev);
+            Vec::new()
+        }
+    }
 }
 
 pub fn new_id() -> String {
     Uuid::new_v4().to_string()
 }
 
+/// Methods that run ffmpeg or whisper and count against the concurrency limit.
+/// Everything else (ping, previewLayout, probe, ...) is answered right away.
+pub fn is_heavy_method(method: &str) -> bool {
+    matches!(
+        method,
+        "generateCaptions"
+            | "transcribe"
+            | "burn"
+            | "burnFromSubtitleFile"
+            | "generatePreviewClip"
+            | "generatePreviewFrame"
+            | "extractThumbnails"
+            | "concatVideos"
+    )
+}
+
+/// Each heavy job already runs a multi-threaded ffmpeg/whisper, so a couple at a time is plenty
+pub const DEFAULT_MAX_HEAVY_JOBS: usize = 2;
+
+/// How many heavy requests may run at once: `CAPSLAP_MAX_JOBS`, then config
+/// `maxConcurrentJobs`, then the CPU count capped at [`DEFAULT_MAX_HEAVY_JOBS`].
+pub fn max_heavy_jobs() -> usize {
+    let config = crate::captions::core_config_or_default();
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    resolve_max_heavy_jobs(
+        std::env::var("CAPSLAP_MAX_JOBS").ok().as_deref(),
+        config.max_concurrent_jobs,
+        cpus,
+    )
+}
+
+fn resolve_max_heavy_jobs(env: Option<&str>, config: Option<usize>, cpus: usize) -> usize {
+    env.and_then(|v| v.trim().parse::<usize>().ok())
+        .or(config)
+        .filter(|&n| n > 0)
+        .unwrap_or_else(|| cpus.clamp(1, DEFAULT_MAX_HEAVY_JOBS))
+}
+
+// ============================================
+// stdout transport
+// ============================================
+
+/// Lines queued for stdout before results wait (and routine progress starts being dropped)
+const OUTPUT_QUEUE: usize = 256;
+
+/// Longest line written to stdout; a bigger result is spilled to a file (see [`spill_result`])
+pub const MAX_LINE_BYTES: usize = 4 * 1024 * 1024;
+
+/// Longest log message sent as an event; the rest is cut off
+const MAX_LOG_BYTES: usize = 64 * 1024;
+
+/// A result too big for one stdout line: the client reads the JSON from `result_path`
+/// and deletes the file
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcSpilledResponse {
+    pub id: String,          // Same ID as the request
+    pub result_path: String, // JSON file holding the result
+}
+
+enum OutputLine {
+    // The slot, if the line took one, frees up once the line is written
+    Line(String, Option<tokio::sync::OwnedSemaphorePermit>),
+    Flush(tokio::sync::oneshot::Sender<()>),
+}
+
+// One queue for every line, so they reach stdout in the order they were sent. `slots`
+// bounds how many lines may wait: results await a slot, routine progress is dropped
+// without one, and the few events that must arrive from sync code skip the bound.
+struct Output {
+    tx: tokio::sync::mpsc::UnboundedSender<OutputLine>,
+    slots: std::sync::Arc<tokio::sync::Semaphore>,
+}
+
+impl Output {
+    async fn send(&self, line: String) {
+        let slot = self.slots.clone().acquire_owned().await.ok();
+        let _ = self.tx.send(OutputLine::Line(line, slot));
+    }
+
+    fn try_send(&self, line: String) -> bool {
+        match self.slots.clone().try_acquire_owned() {
+            Ok(slot) => self.tx.send(OutputLine::Line(line, Some(slot))).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn send_now(&self, line: String) {
+        let _ = self.tx.send(OutputLine::Line(line, None));
+    }
+}
+
+// Writes queued lines on its own thread, so a slow reader stalls the queue, not the runtime
+fn spawn_output_writer(mut out: impl std::io::Write + Send + 'static, capacity: usize) -> Output {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<OutputLine>();
+    std::thread::spawn(move || {
+        while let Some(line) = rx.blocking_recv() {
+            match line {
+                OutputLine::Line(line, _slot) => {
+                    let _ = writeln!(out, "{}", line);
+                    let _ = out.flush();
+                }
+                OutputLine::Flush(done) => {
+                    let _ = done.send(());
+                }
+            }
+        }
+    });
+    Output {
+        tx,
+        slots: std::sync::Arc::new(tokio::sync::Semaphore::new(capacity)),
+    }
+}
+
+fn output() -> &'static Output {
+    static OUTPUT: std::sync::OnceLock<Output> = std::sync::OnceLock::new();
+    OUTPUT.get_or_init(|| spawn_output_writer(std::io::stdout(), OUTPUT_QUEUE))
+}
+
+/// Queue one line for stdout, waiting while the queue is full (results and errors must arrive)
+pub async fn write_line(line: String) {
+    output().send(line).await;
+}
+
+/// Send an event from sync code without waiting. A `droppable` one (routine progress,
+/// see [`ProgressThrottle`]) is dropped when the reader is behind; anything else is
+/// queued regardless, so a status change or the final update always arrives.
+pub fn write_event(ev: &RpcEvent, droppable: bool) {
+    let line = match ev {
+        RpcEvent::Log { id, message } if message.len() > MAX_LOG_BYTES => {
+            serde_json::to_string(&RpcEvent::Log {
+                id: id.clone(),
+                message: truncate_message(message, MAX_LOG_BYTES),
+            })
+        }
+        _ => serde_json::to_string(ev),
+    }
+    .unwrap();
+    if droppable {
+        output().try_send(line);
+    } else {
+        output().send_now(line);
+    }
+}
+
+/// Send a result, spilling it to a file when its line would exceed [`MAX_LINE_BYTES`]
+pub async fn write_result(id: &str, result: serde_json::Value) {
+    let line = serde_json::to_string(&RpcResponse {
+        id: id.to_string(),
+        result,
+    })
+    .unwrap();
+    write_line(spill_result(
+        id,
+        line,
+        MAX_LINE_BYTES,
+        &std::env::temp_dir(),
+    ))
+    .await;
+}
+
+/// Wait (up to `timeout`) until everything queued so far has been written, e.g. before exiting
+pub async fn flush_output(timeout: std::time::Duration) {
+    let (done, wait) = tokio::sync::oneshot::channel();
+    if output().tx.send(OutputLine::Flush(done)).is_ok() {
+        let _ = tokio::time::timeout(timeout, wait).await;
+    }
+}
+
+// A response line over `max_bytes` has its result written to a file under `dir`; the
+// line sent instead only points at it. Falls back to the full line if the write fails.
+fn spill_result(id: &str, line: String, max_bytes: usize, dir: &std::path::Path) -> String {
+    if line.len() <= max_bytes {
+        return line;
+    }
+    let dir = dir.join("capslap").join("results");
+    let path = dir.join(format!("{}.json", Uuid::new_v4()));
+    let written = std::fs::create_dir_all(&dir).and_then(|_| {
+        let value: serde_json::Value = serde_json::from_str(&line)?;
+        std::fs::write(&path, serde_json::to_vec(&value["result"])?)
+    });
+    match written {
+        Ok(()) => serde_json::to_string(&RpcSpilledResponse {
+            id: id.to_string(),
+            result_path: path.to_string_lossy().to_string(),
+        })
+        .unwrap(),
+        Err(_) => line,
+    }
+}
+
+fn truncate_message(message: &str, max_bytes: usize) -> String {
+    let mut end = max_bytes;
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...[truncated]", &message[..end])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -162,6 +519,7 @@ mod tests {
         let err = RpcError {
             id: "err-123".to_string(),
             error: "File not found".to_string(),
+            code: None,
         };
 
         let json = serde_json::to_string(&err).unwrap();
@@ -176,6 +534,27 @@ mod tests {
 
         assert_eq!(err.id, "err-456");
         assert_eq!(err.error, "Invalid format");
+        assert_eq!(err.code, None);
+    }
+
+    #[test]
+    fn test_rpc_error_code_serialization() {
+        let err = RpcError {
+            id: "err-789".to_string(),
+            error: "No audio".to_string(),
+            code: Some("NO_AUDIO".to_string()),
+        };
+
+        let json = serde_json::to_string(&err).unwrap();
+        assert!(json.contains("\"code\":\"NO_AUDIO\""));
+    }
+
+    #[test]
+    fn test_error_code_from_chain() {
+        let err = anyhow::Error::new(CodedError::new("NO_AUDIO", "No audio track"))
+            .context("Transcription failed");
+        assert_eq!(error_code(&err), Some("NO_AUDIO"));
+        assert_eq!(error_code(&anyhow::anyhow!("plain")), None);
     }
 
     // ============================================
@@ -242,6 +621,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_rpc_event_heartbeat_round_trip() {
+        let json = serde_json::to_string(&RpcEvent::Heartbeat { id: "op-5".into() }).unwrap();
+        assert_eq!(json, r#"{"event":"heartbeat","id":"op-5"}"#);
+        match serde_json::from_str(&json).unwrap() {
+            RpcEvent::Heartbeat { id } => assert_eq!(id, "op-5"),
+            _ => panic!("Expected Heartbeat event"),
+        }
+    }
+
     // ============================================
     // new_id tests
     // ============================================
@@ -272,4 +661,182 @@ mod tests {
         let parsed = uuid::Uuid::parse_str(&id);
         assert!(parsed.is_ok());
     }
+
+    #[test]
+    fn test_heavy_methods_and_job_limit() {
+        assert!(is_heavy_method("generateCaptions"));
+        assert!(is_heavy_method("burn"));
+        assert!(is_heavy_method("concatVideos"));
+        assert!(is_heavy_method("extractThumbnails"));
+        assert!(is_heavy_method("generatePreviewFrame"));
+        assert!(!is_heavy_method("ping"));
+        assert!(!is_heavy_method("previewLayout"));
+
+        assert_eq!(resolve_max_heavy_jobs(Some("3"), Some(5), 8), 3);
+        assert_eq!(resolve_max_heavy_jobs(Some("lots"), Some(5), 8), 5);
+        assert_eq!(
+            resolve_max_heavy_jobs(None, None, 8),
+            DEFAULT_MAX_HEAVY_JOBS
+        );
+        assert_eq!(resolve_max_heavy_jobs(None, None, 1), 1);
+        // Zero would deadlock every heavy request; fall back to the default
+        assert_eq!(
+            resolve_max_heavy_jobs(Some("0"), None, 4),
+            DEFAULT_MAX_HEAVY_JOBS
+        );
+    }
+
+    #[test]
+    fn test_request_timeout_resolution() {
+        let mut config = std::collections::HashMap::new();
+        assert_eq!(
+            resolve_timeout("burn", None, &config),
+            default_timeout("burn")
+        );
+        assert_eq!(resolve_timeout("ping", None, &config), None);
+        config.insert("burn".to_string(), 60);
+        assert_eq!(
+            resolve_timeout("burn", None, &config),
+            Some(std::time::Duration::from_secs(60))
+        );
+        // The request's own limit wins, and 0 means no limit
+        assert_eq!(
+            resolve_timeout("burn", Some(5), &config),
+            Some(std::time::Duration::from_secs(5))
+        );
+        assert_eq!(resolve_timeout("burn", Some(0), &config), None);
+    }
+
+    #[tokio::test]
+    async fn test_interruption_times_out_or_cancels() {
+        let (tx, mut rx) = tokio::sync::broadcast::channel(1);
+        let limit = std::time::Duration::from_millis(20);
+        assert_eq!(
+            interruption(&mut rx, Some(limit)).await,
+            Interruption::TimedOut(limit)
+        );
+        tx.send(()).unwrap();
+        assert_eq!(interruption(&mut rx, None).await, Interruption::Cancelled);
+    }
+
+    #[test]
+    fn test_progress_throttle_coalesces_updates() {
+        let progress = |status: &str, progress: f32| RpcEvent::Progress {
+            id: "t".into(),
+            status: status.into(),
+            progress,
+        };
+        let mut throttle = ProgressThrottle::new(std::time::Duration::from_millis(100));
+        let t0 = std::time::Instant::now();
+        let ms = |n| t0 + std::time::Duration::from_millis(n);
+
+        // The first update names a new status, so it may not be dropped
+        assert_eq!(
+            throttle.push(progress("Encoding", 0.1), ms(0)),
+            [(progress("Encoding", 0.1), false)]
+        );
+        assert!(throttle.push(progress("Encoding", 0.2), ms(10)).is_empty());
+        assert!(throttle.push(progress("Encoding", 0.3), ms(50)).is_empty());
+        assert_eq!(
+            throttle.push(progress("Encoding", 0.4), ms(100)),
+            [(progress("Encoding", 0.4), true)]
+        );
+        // A new status goes out at once
+        assert_eq!(
+            throttle.push(progress("Muxing", 0.5), ms(110)),
+            [(progress("Muxing", 0.5), false)]
+        );
+        assert!(throttle.push(progress("Muxing", 0.6), ms(120)).is_empty());
+        // A held update goes out before a log
+        let log = RpcEvent::Log {
+            id: "t".into(),
+            message: "hi".into(),
+        };
+        assert_eq!(
+            throttle.push(log.clone(), ms(130)),
+            [(progress("Muxing", 0.6), true), (log, false)]
+        );
+        // The final update is never held back or dropped
+        assert_eq!(
+            throttle.push(progress("Muxing", 1.0), ms(140)),
+            [(progress("Muxing", 1.0), false)]
+        );
+    }
+
+    // Collects written output; blocks every write until the gate opens
+    #[derive(Clone)]
+    struct GatedWriter {
+        out: std::sync::Arc<std::sync::Mutex<Vec<u8>>>,
+        gate: std::sync::Arc<(std::sync::Mutex<bool>, std::sync::Condvar)>,
+    }
+
+    impl std::io::Write for GatedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let (open, cvar) = &*self.gate;
+            let _open = cvar
+                .wait_while(open.lock().unwrap(), |open| !*open)
+                .unwrap();
+            self.out.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_output_queue_is_bounded_and_ordered() {
+        let writer = GatedWriter {
+            out: Default::default(),
+            gate: Default::default(),
+        };
+        let output = spawn_output_writer(writer.clone(), 2);
+        // The writer thread takes one line and blocks on it; the second fills the queue
+        output.send("line 0".into()).await;
+        output.send("line 1".into()).await;
+        let wait = std::time::Duration::from_millis(50);
+        // A full queue makes a result wait (without blocking the runtime) and turns
+        // routine progress away
+        assert!(tokio::time::timeout(wait, output.send("late".into()))
+            .await
+            .is_err());
+        assert!(!output.try_send("progress".into()));
+        // An event that must arrive is queued anyway, in order
+        output.send_now("done".into());
+
+        let (open, cvar) = &*writer.gate;
+        *open.lock().unwrap() = true;
+        cvar.notify_all();
+        output.send("line 2".into()).await;
+        let (done, flushed) = tokio::sync::oneshot::channel();
+        output.tx.send(OutputLine::Flush(done)).unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), flushed)
+            .await
+            .unwrap()
+            .unwrap();
+        let out = String::from_utf8(writer.out.lock().unwrap().clone()).unwrap();
+        assert_eq!(out, "line 0\nline 1\ndone\nline 2\n");
+    }
+
+    #[test]
+    fn test_large_results_spill_to_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = r#"{"id":"a","result":{"ok":true}}"#.to_string();
+        assert_eq!(spill_result("a", small.clone(), 100, dir.path()), small);
+
+        let big = serde_json::to_string(&RpcResponse {
+            id: "b".to_string(),
+            result: serde_json::json!({ "imageData": "x".repeat(500) }),
+        })
+        .unwrap();
+        let line = spill_result("b", big, 100, dir.path());
+        assert!(line.len() <= 100 + 200, "{}", line);
+        let spilled: RpcSpilledResponse = serde_json::from_str(&line).unwrap();
+        assert_eq!(spilled.id, "b");
+        let result: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&spilled.result_path).unwrap()).unwrap();
+        assert_eq!(result["imageData"].as_str().unwrap().len(), 500);
+
+        assert_eq!(truncate_message("héllo", 2), "h...[truncated]");
+    }
 }
@@ -0,0 +1,919 @@
+use crate::timecode::{self, FrameRate};
+use crate::types::{
+    CaptionSegment, CaptionsExport, ExportSegment, ExportSubtitlesParams, ExportSubtitlesResult,
+    ExportWord,
+};
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+
+/// Load an SRT, WebVTT or CSV file into caption segments. The format is picked from
+/// the extension, falling back to sniffing the `WEBVTT` header.
+pub fn load_subtitle_file(path: &Path) -> Result<Vec<CaptionSegment>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read subtitle file {}", path.display()))?;
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    if ext.as_deref() == Some("csv") {
+        return parse_csv(&content).with_context(|| format!("Failed to parse {}", path.display()));
+    }
+    let is_vtt = match ext.as_deref() {
+        Some("vtt") => true,
+        Some("srt") => false,
+        _ => content.trim_start_matches('\u{feff}').starts_with("WEBVTT"),
+    };
+    let parsed = if is_vtt {
+        parse_vtt(&content)
+    } else {
+        parse_srt(&content)
+    };
+    parsed.with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Parse SubRip (.srt) text. Cue numbers are optional; multi-line cue text is
+/// joined with spaces since our layout does its own wrapping.
+pub fn parse_srt(content: &str) -> Result<Vec<CaptionSegment>> {
+    parse_cues(content, false)
+}
+
+/// Parse WebVTT text. The header, NOTE/STYLE/REGION blocks, cue identifiers and
+/// cue settings are skipped.
+pub fn parse_vtt(content: &str) -> Result<Vec<CaptionSegment>> {
+    let body = content.trim_start_matches('\u{feff}');
+    if !body.starts_with("WEBVTT") {
+        return Err(anyhow!("Missing WEBVTT header"));
+    }
+    parse_cues(body, true)
+}
+
+// Both formats are blank-line separated blocks with a `start --> end` timing line
+fn parse_cues(content: &str, vtt: bool) -> Result<Vec<CaptionSegment>> {
+    let content = content.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let mut segments = Vec::new();
+    let mut line_no = 1;
+    for block in content.split("\n\n") {
+        let block_start = line_no;
+        line_no += block.lines().count() + 1;
+        let leading = block.lines().take_while(|l| l.trim().is_empty()).count();
+        let lines: Vec<&str> = block.lines().skip(leading).collect();
+        let Some(timing_idx) = lines.iter().position(|l| l.contains("-->")) else {
+            if vtt || lines.iter().all(|l| l.trim().is_empty()) {
+                continue; // header, NOTE/STYLE/REGION blocks
+            }
+            return Err(anyhow!("Cue near line {} has no timing line", block_start));
+        };
+        let timing_line = block_start + leading + timing_idx;
+        let (start, end) = lines[timing_idx]
+            .split_once("-->")
+            .ok_or_else(|| anyhow!("Bad timing on line {}", timing_line))?;
+        // VTT cue settings ("align:start line:90%") follow the end time
+        let end = end.split_whitespace().next().unwrap_or("");
+        let start_ms = parse_timestamp(start.trim())
+            .ok_or_else(|| anyhow!("Bad start time '{}' on line {}", start.trim(), timing_line))?;
+        let end_ms = parse_timestamp(end)
+            .ok_or_else(|| anyhow!("Bad end time '{}' on line {}", end, timing_line))?;
+        if end_ms < start_ms {
+            return Err(anyhow!("Cue on line {} ends before it starts", timing_line));
+        }
+
+        let text = lines[timing_idx + 1..]
+            .iter()
+            .map(|l| strip_markup(l))
+            .filter(|l| !l.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if text.is_empty() {
+            continue;
+        }
+        segments.push(CaptionSegment {
+            start_ms,
+            end_ms,
+            text,
+            words: Vec::new(),
+            no_speech_prob: None,
+            position: None,
+        });
+    }
+    Ok(segments)
+}
+
+/// Render segments as SubRip text, numbering cues from 1
+pub fn to_srt(segments: &[CaptionSegment]) -> String {
+    segments
+        .iter()
+        .filter(|s| !s.text.trim().is_empty())
+        .enumerate()
+        .map(|(i, s)| {
+            format!(
+                "{}\n{} --> {}\n{}\n",
+                i + 1,
+                format_timestamp(s.start_ms, ','),
+                format_timestamp(s.end_ms.max(s.start_ms), ','),
+                s.text.trim()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render segments as WebVTT text
+pub fn to_vtt(segments: &[CaptionSegment]) -> String {
+    let cues = segments
+        .iter()
+        .filter(|s| !s.text.trim().is_empty())
+        .map(|s| {
+            format!(
+                "{} --> {}\n{}\n",
+                format_timestamp(s.start_ms, '.'),
+                format_timestamp(s.end_ms.max(s.start_ms), '.'),
+                s.text.trim()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("WEBVTT\n\n{}", cues)
+}
+
+/// A pause at least this long after a finished sentence starts a new paragraph
+const PARAGRAPH_PAUSE_MS: u64 = 1500;
+/// Paragraphs this long break at the next sentence end even without a pause
+const MAX_PARAGRAPH_CHARS: usize = 600;
+
+/// Render segments as a reading transcript: segment text joined into paragraphs that
+/// only break at sentence ends, on a pause or once a paragraph gets long. With
+/// `timestamps`, each paragraph starts with its `[HH:MM:SS]` start time.
+pub fn to_txt(segments: &[CaptionSegment], timestamps: bool) -> String {
+    let mut paragraphs: Vec<(u64, String)> = Vec::new();
+    let mut open = false; // last paragraph can still take more text
+    for (i, seg) in segments.iter().enumerate() {
+        let text = seg.text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        match paragraphs.last_mut() {
+            Some((_, paragraph)) if open => {
+                paragraph.push(' ');
+                paragraph.push_str(text);
+            }
+            _ => paragraphs.push((seg.start_ms, text.to_string())),
+        }
+        let paragraph = &paragraphs[paragraphs.len() - 1].1;
+        let pause = segments
+            .get(i + 1)
+            .map_or(0, |next| next.start_ms.saturating_sub(seg.end_ms));
+        let sentence_end = text
+            .split_whitespace()
+            .last()
+            .is_some_and(crate::whisper::ends_sentence);
+        open = !(sentence_end
+            && (pause >= PARAGRAPH_PAUSE_MS || paragraph.len() >= MAX_PARAGRAPH_CHARS));
+    }
+    paragraphs
+        .into_iter()
+        .map(|(start_ms, text)| {
+            if timestamps {
+                format!("[{}] {}\n", format_clock(start_ms), text)
+            } else {
+                format!("{}\n", text)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+const CSV_HEADER: &str = "index,start_ms,end_ms,start_tc,end_tc,text";
+
+/// Render segments as CSV for spreadsheet editing, one row per cue with a header row.
+/// Text containing commas, quotes or line breaks is quoted.
+pub fn to_csv(segments: &[CaptionSegment]) -> String {
+    let mut out = format!("{}\r\n", CSV_HEADER);
+    for (i, s) in segments
+        .iter()
+        .filter(|s| !s.text.trim().is_empty())
+        .enumerate()
+    {
+        let end_ms = s.end_ms.max(s.start_ms);
+        out.push_str(&format!(
+            "{},{},{},{},{},{}\r\n",
+            i + 1,
+            s.start_ms,
+            end_ms,
+            format_timestamp(s.start_ms, '.'),
+            format_timestamp(end_ms, '.'),
+            csv_field(s.text.trim())
+        ));
+    }
+    out
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Parse a CSV written by [`to_csv`] (and edited in a spreadsheet) back into segments.
+/// Columns are found by header name; `start_ms`/`end_ms` win over the timecode columns,
+/// which are used when the millisecond cells are blank. Word timings are not kept.
+pub fn parse_csv(content: &str) -> Result<Vec<CaptionSegment>> {
+    let mut rows = csv_rows(content.trim_start_matches('\u{feff}'))?.into_iter();
+    let header = rows.next().ok_or_else(|| anyhow!("Empty CSV file"))?;
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(name))
+    };
+    let text_col = column("text").ok_or_else(|| anyhow!("CSV has no \"text\" column"))?;
+    let (start_ms_col, end_ms_col) = (column("start_ms"), column("end_ms"));
+    let (start_tc_col, end_tc_col) = (column("start_tc"), column("end_tc"));
+
+    let mut segments = Vec::new();
+    for (i, row) in rows.enumerate() {
+        let row_no = i + 2; // 1-based, after the header
+        if row.iter().all(|cell| cell.trim().is_empty()) {
+            continue;
+        }
+        let time = |ms_col: Option<usize>, tc_col: Option<usize>, name: &str| {
+            let cell = |col: Option<usize>| {
+                col.and_then(|c| row.get(c))
+                    .map(|v| v.trim())
+                    .filter(|v| !v.is_empty())
+            };
+            if let Some(ms) = cell(ms_col) {
+                ms.parse::<u64>()
+                    .map_err(|_| anyhow!("Row {}: bad {}_ms \"{}\"", row_no, name, ms))
+            } else if let Some(tc) = cell(tc_col) {
+                parse_timestamp(tc)
+                    .ok_or_else(|| anyhow!("Row {}: bad {}_tc \"{}\"", row_no, name, tc))
+            } else {
+                Err(anyhow!("Row {}: missing {} time", row_no, name))
+            }
+        };
+        let start_ms = time(start_ms_col, start_tc_col, "start")?;
+        let end_ms = time(end_ms_col, end_tc_col, "end")?;
+        let text = row
+            .get(text_col)
+            .map(|t| t.split_whitespace().collect::<Vec<_>>().join(" "))
+            .unwrap_or_default();
+        segments.push(CaptionSegment {
+            start_ms,
+            end_ms: end_ms.max(start_ms),
+            text,
+            words: Vec::new(),
+            no_speech_prob: None,
+            position: None,
+        });
+    }
+    Ok(segments)
+}
+
+// RFC 4180 records: quoted fields may hold commas, doubled quotes and line breaks
+fn csv_rows(content: &str) -> Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(anyhow!("Unterminated quoted field"));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Characters per CEA-608 caption row
+pub const SCC_ROW_CHARS: usize = 32;
+/// Rows shown at once; 608 allows four, but two is what broadcasters accept
+const SCC_MAX_ROWS: usize = 2;
+
+// Channel 1 control codes, before parity: resume caption loading, erase non-displayed
+// memory, end of caption (show what was loaded) and erase displayed memory
+const SCC_RCL: [u8; 2] = [0x14, 0x20];
+const SCC_ENM: [u8; 2] = [0x14, 0x2E];
+const SCC_EOC: [u8; 2] = [0x14, 0x2F];
+const SCC_EDM: [u8; 2] = [0x14, 0x2C];
+
+/// Render segments as Scenarist SCC (CEA-608, channel 1) pop-on captions. Text is
+/// plain and wrapped to 32-character rows, at most two rows per caption; longer
+/// segments become several captions sharing the segment's time. Characters 608
+/// can't show (emoji, most non-Latin scripts) are left out.
+pub fn to_scc(segments: &[CaptionSegment], rate: FrameRate) -> String {
+    // (frame it should appear on, frame it should clear on, rows)
+    let mut captions: Vec<(u64, u64, Vec<String>)> = Vec::new();
+    for s in segments {
+        let rows = scc_rows(&s.text);
+        let chunks: Vec<&[String]> = rows.chunks(SCC_MAX_ROWS).collect();
+        let total: usize = rows.iter().map(|r| r.len()).sum();
+        let duration = s.end_ms.saturating_sub(s.start_ms);
+        let mut done = 0;
+        for chunk in chunks {
+            let start_ms = s.start_ms + duration * done as u64 / total.max(1) as u64;
+            done += chunk.iter().map(|r| r.len()).sum::<usize>();
+            let end_ms = s.start_ms + duration * done as u64 / total.max(1) as u64;
+            captions.push((
+                timecode::ms_to_frames(start_ms, rate),
+                timecode::ms_to_frames(end_ms, rate),
+                chunk.to_vec(),
+            ));
+        }
+    }
+
+    let mut out = String::from("Scenarist_SCC V1.0\n");
+    let mut next_free = 0; // first frame not yet used by an earlier transmission
+    for (i, (show, clear, rows)) in captions.iter().enumerate() {
+        let words = scc_caption_words(rows);
+        // Load early so the final EOC lands on the frame the caption should appear
+        let frame = show.saturating_sub(words.len() as u64 - 1).max(next_free);
+        out.push_str(&scc_line(frame, &words, rate));
+        next_free = frame + words.len() as u64;
+        // A caption that follows straight on replaces this one; otherwise clear it
+        let next_show = captions.get(i + 1).map(|c| c.0);
+        if next_show.is_none_or(|n| n > *clear) {
+            let frame = (*clear).max(next_free);
+            out.push_str(&scc_line(frame, &[SCC_EDM, SCC_EDM], rate));
+            next_free = frame + 2;
+        }
+    }
+    out
+}
+
+// Caption text as 608 rows of at most 32 characters, wrapped between words
+fn scc_rows(text: &str) -> Vec<String> {
+    let mut rows: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        let word: String = word.chars().filter(|&c| scc_char(c).is_some()).collect();
+        if word.is_empty() {
+            continue;
+        }
+        match rows.last_mut() {
+            Some(row) if row.chars().count() + 1 + word.chars().count() <= SCC_ROW_CHARS => {
+                row.push(' ');
+                row.push_str(&word);
+            }
+            _ => {
+                let chars: Vec<char> = word.chars().collect();
+                rows.extend(chars.chunks(SCC_ROW_CHARS).map(|c| c.iter().collect()));
+            }
+        }
+    }
+    rows
+}
+
+// One 608 character: a basic byte, or a two-byte code (special or extended set)
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SccChar {
+    Basic(u8),
+    Code([u8; 2]),
+    // Extended characters overwrite the basic fallback sent before them
+    Extended(u8, [u8; 2]),
+}
+
+fn scc_char(c: char) -> Option<SccChar> {
+    const SPECIAL: &str = "®°½¿™¢£♪à\0èâêîôû";
+    const EXTENDED_12: &str = "ÁÉÓÚÜü‘¡*’—©℠•“”ÀÂÇÈÊËëÎÏïÔÙùÛ«»";
+    const EXTENDED_13: &str = "ÃãÍÌìÒòÕõ{}\\^_|~ÄäÖöß¥¤¦ÅåØø";
+    const FALLBACK_12: &str = "AEOUUu'!.'-cs.\"\"AACEEEeIIiOUuU\"\"";
+    const FALLBACK_13: &str = "AaIIiOoOo()/ -!-AaOosY$ AaOo";
+    let basic = match c {
+        'á' => Some(0x2A),
+        'é' => Some(0x5C),
+        'í' => Some(0x5E),
+        'ó' => Some(0x5F),
+        'ú' => Some(0x60),
+        'ç' => Some(0x7B),
+        '÷' => Some(0x7C),
+        'Ñ' => Some(0x7D),
+        'ñ' => Some(0x7E),
+        '█' => Some(0x7F),
+        '`' => Some(b'\''),
+        // These ASCII codes show accented letters in 608; they come from the extended sets
+        '*' | '\\' | '^' | '_' | '{' | '|' | '}' | '~' => None,
+        ' '..='~' => Some(c as u8),
+        _ => None,
+    };
+    if let Some(b) = basic {
+        return Some(SccChar::Basic(b));
+    }
+    if let Some(i) = SPECIAL.chars().position(|s| s == c && s != '\0') {
+        return Some(SccChar::Code([0x11, 0x30 + i as u8]));
+    }
+    for (first, set, fallback) in [
+        (0x12, EXTENDED_12, FALLBACK_12),
+        (0x13, EXTENDED_13, FALLBACK_13),
+    ] {
+        if let Some(i) = set.chars().position(|s| s == c) {
+            let fallback = fallback.as_bytes()[i];
+            return Some(SccChar::Extended(fallback, [first, 0x20 + i as u8]));
+        }
+    }
+    None
+}
+
+// Byte pairs that load rows into the hidden buffer and flip it on screen
+fn scc_caption_words(rows: &[String]) -> Vec<[u8; 2]> {
+    let mut words = vec![SCC_RCL, SCC_RCL, SCC_ENM, SCC_ENM];
+    let first_row = 16 - rows.len();
+    for (i, row) in rows.iter().enumerate() {
+        let len = row.chars().count();
+        let column = (SCC_ROW_CHARS - len.min(SCC_ROW_CHARS)) / 2;
+        let pac = scc_pac(first_row + i, column / 4 * 4);
+        words.extend([pac, pac]);
+        let offset = column % 4;
+        if offset > 0 {
+            // Tab offset 1-3 finishes the centering
+            let tab = [0x17, 0x20 + offset as u8];
+            words.extend([tab, tab]);
+        }
+        // Basic characters go two to a word; codes start a word of their own
+        let mut pending: Option<u8> = None;
+        for c in row.chars().filter_map(scc_char) {
+            let (basic, code) = match c {
+                SccChar::Basic(b) => (Some(b), None),
+                SccChar::Code(code) => (None, Some(code)),
+                SccChar::Extended(fallback, code) => (Some(fallback), Some(code)),
+            };
+            if let Some(b) = basic {
+                match pending.take() {
+                    Some(first) => words.push([first, b]),
+                    None => pending = Some(b),
+                }
+            }
+            if let Some(code) = code {
+                if let Some(first) = pending.take() {
+                    words.push([first, 0x00]);
+                }
+                words.extend([code, code]);
+            }
+        }
+        if let Some(first) = pending {
+            words.push([first, 0x00]);
+        }
+    }
+    words.extend([SCC_EOC, SCC_EOC]);
+    words
+}
+
+// Preamble address code: row 1-15, indent 0-28 in steps of 4, white text
+fn scc_pac(row: usize, indent: usize) -> [u8; 2] {
+    const ROWS: [(u8, u8); 15] = [
+        (0x11, 0x40),
+        (0x11, 0x60),
+        (0x12, 0x40),
+        (0x12, 0x60),
+        (0x15, 0x40),
+        (0x15, 0x60),
+        (0x16, 0x40),
+        (0x16, 0x60),
+        (0x17, 0x40),
+        (0x17, 0x60),
+        (0x10, 0x40),
+        (0x13, 0x40),
+        (0x13, 0x60),
+        (0x14, 0x40),
+        (0x14, 0x60),
+    ];
+    let (first, second) = ROWS[row.clamp(1, 15) - 1];
+    [first, second + 0x10 + (indent.min(28) / 4 * 2) as u8]
+}
+
+// Odd parity in the top bit, as every 608 byte is sent
+fn scc_parity(b: u8) -> u8 {
+    let b = b & 0x7F;
+    if b.count_ones().is_multiple_of(2) {
+        b | 0x80
+    } else {
+        b
+    }
+}
+
+// SCC is defined for NTSC 29.97 only; players read any other rate's timecodes as 29.97
+// and every caption drifts. Drop-frame unless asked for non-drop.
+fn scc_frame_rate(fps: Option<f64>, drop_frame: Option<bool>) -> Result<FrameRate> {
+    let fps = fps.unwrap_or(29.97);
+    let rate = FrameRate::from_fps(fps)?;
+    if (rate.num, rate.den) != (30_000, 1001) {
+        return Err(anyhow!(
+            "SCC captions need 29.97 fps (drop-frame or non-drop), not {}; export SRT or VTT for other rates",
+            fps
+        ));
+    }
+    Ok(FrameRate {
+        drop_frame: drop_frame.unwrap_or(true),
+        ..rate
+    })
+}
+
+fn scc_line(frame: u64, words: &[[u8; 2]], rate: FrameRate) -> String {
+    let hex: Vec<String> = words
+        .iter()
+        .map(|[a, b]| format!("{:02x}{:02x}", scc_parity(*a), scc_parity(*b)))
+        .collect();
+    format!(
+        "\n{}\t{}\n",
+        timecode::format_frames(frame, rate),
+        hex.join(" ")
+    )
+}
+
+/// Current `schemaVersion` of the public JSON export
+pub const EXPORT_SCHEMA_VERSION: u32 = 1;
+
+/// Render segments in the public JSON export format (see [`CaptionsExport`])
+pub fn to_json(
+    segments: &[CaptionSegment],
+    duration: Option<f64>,
+    language: Option<String>,
+) -> Result<String> {
+    let export = CaptionsExport {
+        schema_version: EXPORT_SCHEMA_VERSION,
+        duration,
+        language,
+        segments: segments
+            .iter()
+            .map(|s| ExportSegment {
+                start_ms: s.start_ms,
+                end_ms: s.end_ms.max(s.start_ms),
+                text: s.text.trim().to_string(),
+                words: s
+                    .words
+                    .iter()
+                    .map(|w| ExportWord {
+                        start_ms: w.start_ms,
+                        end_ms: w.end_ms.max(w.start_ms),
+                        text: w.text.trim().to_string(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    };
+    Ok(serde_json::to_string_pretty(&export)?)
+}
+
+/// Write segments to `output_path` as SRT, WebVTT, a plain-text transcript, JSON, CSV
+/// or SCC
+pub fn export_subtitles(params: ExportSubtitlesParams) -> Result<ExportSubtitlesResult> {
+    let content = match params.format.to_ascii_lowercase().as_str() {
+        "srt" => to_srt(&params.segments),
+        "vtt" => to_vtt(&params.segments),
+        "txt" => to_txt(&params.segments, params.timestamps),
+        "json" => to_json(&params.segments, params.duration, params.language)?,
+        "csv" => to_csv(&params.segments),
+        "scc" => to_scc(
+            &params.segments,
+            scc_frame_rate(params.fps, params.drop_frame)?,
+        ),
+        other => {
+            return Err(anyhow!(
+                "Unsupported subtitle format \"{}\" (expected srt, vtt, txt, json, csv or scc)",
+                other
+            ))
+        }
+    };
+    std::fs::write(&params.output_path, content)
+        .with_context(|| format!("Failed to write {}", params.output_path))?;
+    Ok(ExportSubtitlesResult {
+        path: params.output_path,
+    })
+}
+
+/// `HH:MM:SS<sep>mmm`, with ',' for SRT and '.' for WebVTT
+pub fn format_timestamp(ms: u64, sep: char) -> String {
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        sep,
+        ms % 1000
+    )
+}
+
+// `HH:MM:SS` without milliseconds; hours keep growing past 99
+fn format_clock(ms: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60
+    )
+}
+
+/// Parse `HH:MM:SS,mmm`, `HH:MM:SS.mmm` or `MM:SS.mmm` into milliseconds
+pub fn parse_timestamp(s: &str) -> Option<u64> {
+    let (clock, frac) = s.split_once([',', '.'])?;
+    if frac.is_empty() || frac.len() > 3 || !frac.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    // "5" after the separator is 500 ms, not 5
+    let ms: u64 = format!("{:0<3}", frac).parse().ok()?;
+    let parts = clock
+        .split(':')
+        .map(|p| p.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let (h, m, sec) = match parts[..] {
+        [h, m, s] => (h, m, s),
+        [m, s] => (0, m, s),
+        _ => return None,
+    };
+    if m >= 60 || sec >= 60 {
+        return None;
+    }
+    Some(((h * 60 + m) * 60 + sec) * 1000 + ms)
+}
+
+// Drop HTML-style tags (<i>, <c.yellow>, <00:01.000>) and ASS overrides ({\an8})
+fn strip_markup(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut closer = None;
+    for c in line.chars() {
+        match (closer, c) {
+            (None, '<') => closer = Some('>'),
+            (None, '{') => closer = Some('}'),
+            (None, _) => out.push(c),
+            (Some(end), _) if c == end => closer = None,
+            _ => {}
+        }
+    }
+    let decoded = out
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ");
+    decoded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp_formats() {
+        assert_eq!(parse_timestamp("00:00:01,500"), Some(1500));
+        assert_eq!(parse_timestamp("01:02:03.004"), Some(3_723_004));
+        assert_eq!(parse_timestamp("02:03.5"), Some(123_500));
+        assert_eq!(parse_timestamp("00:61:00,000"), None);
+        assert_eq!(parse_timestamp("00:00:01"), None);
+        assert_eq!(parse_timestamp("aa:00:01,000"), None);
+    }
+
+    #[test]
+    fn test_parse_srt_joins_lines_and_strips_tags() {
+        let srt = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\n<i>Hello</i>\r\nworld\r\n\r\n\
+                   2\r\n00:00:03,000 --> 00:00:04,000\r\n{\\an8}Second &amp; last\r\n";
+        let segments = parse_srt(srt).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].text, "Hello world");
+        assert_eq!((segments[0].start_ms, segments[0].end_ms), (1000, 2500));
+        assert_eq!(segments[1].text, "Second & last");
+        assert!(segments[1].words.is_empty());
+    }
+
+    #[test]
+    fn test_parse_vtt_skips_header_notes_and_settings() {
+        let vtt = "WEBVTT - demo\n\nNOTE written by hand\n\nintro\n\
+                   00:01.000 --> 00:02.000 align:start line:90%\n<c.yellow>Hi</c> there\n\n\
+                   00:00:02.500 --> 00:00:03.000\n\n";
+        let segments = parse_vtt(vtt).unwrap();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].text, "Hi there");
+        assert_eq!((segments[0].start_ms, segments[0].end_ms), (1000, 2000));
+        assert!(parse_vtt("1\n00:00:01,000 --> 00:00:02,000\nx\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_srt_reports_bad_timing_line() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nok\n\n2\n00:00:03,000 --> soon\nbad\n";
+        let err = parse_srt(srt).unwrap_err().to_string();
+        assert!(err.contains("line 6"), "{}", err);
+    }
+
+    #[test]
+    fn test_to_srt_round_trips() {
+        let segments = vec![
+            CaptionSegment {
+                start_ms: 1_000,
+                end_ms: 2_500,
+                text: "Hello world".into(),
+                words: Vec::new(),
+                no_speech_prob: None,
+                position: None,
+            },
+            CaptionSegment {
+                start_ms: 3_723_004,
+                end_ms: 3_724_000,
+                text: " Later ".into(),
+                words: Vec::new(),
+                no_speech_prob: None,
+                position: None,
+            },
+        ];
+        let srt = to_srt(&segments);
+        assert!(srt.starts_with("1\n00:00:01,000 --> 00:00:02,500\nHello world\n\n2\n01:02:03,004"));
+        let parsed = parse_srt(&srt).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[1].text, "Later");
+        assert_eq!(
+            (parsed[1].start_ms, parsed[1].end_ms),
+            (3_723_004, 3_724_000)
+        );
+    }
+
+    #[test]
+    fn test_to_txt_builds_paragraphs_at_sentence_ends() {
+        let seg = |start_ms, end_ms, text: &str| CaptionSegment {
+            start_ms,
+            end_ms,
+            text: text.into(),
+            words: Vec::new(),
+            no_speech_prob: None,
+            position: None,
+        };
+        let segments = vec![
+            seg(0, 1000, "Welcome to the show."),
+            seg(1100, 2000, "Today we talk"),
+            // Long pause, but mid-sentence: stays in the paragraph
+            seg(5000, 6000, "about captions."),
+            seg(9000, 10000, "Next topic"),
+            seg(10100, 11000, "is fonts!"),
+        ];
+        assert_eq!(
+            to_txt(&segments, false),
+            "Welcome to the show. Today we talk about captions.\n\nNext topic is fonts!\n"
+        );
+        let stamped = to_txt(&segments, true);
+        assert!(
+            stamped.starts_with("[00:00:00] Welcome")
+                && stamped.contains("\n\n[00:00:09] Next topic"),
+            "{}",
+            stamped
+        );
+        assert!(to_vtt(&segments).starts_with("WEBVTT\n\n00:00:00.000 --> 00:00:01.000\nWelcome"));
+        // Hours past 99 aren't cut off
+        let late = vec![seg(360_000_000 + 5000, 360_001_000 + 5000, "Still going.")];
+        assert_eq!(to_txt(&late, true), "[100:00:05] Still going.\n");
+    }
+
+    #[test]
+    fn test_json_export_schema() {
+        let segments = vec![CaptionSegment {
+            start_ms: 500,
+            end_ms: 1500,
+            text: " Hi there ".into(),
+            words: vec![
+                crate::types::WordSpan {
+                    start_ms: 500,
+                    end_ms: 900,
+                    text: " Hi".into(),
+                },
+                crate::types::WordSpan {
+                    start_ms: 900,
+                    end_ms: 1500,
+                    text: " there".into(),
+                },
+            ],
+            no_speech_prob: Some(0.1),
+            position: None,
+        }];
+        let json: serde_json::Value =
+            serde_json::from_str(&to_json(&segments, Some(2.0), Some("en".into())).unwrap())
+                .unwrap();
+        // The documented v1 shape, exactly: internal fields must not leak into it
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "schemaVersion": 1,
+                "duration": 2.0,
+                "language": "en",
+                "segments": [{
+                    "startMs": 500,
+                    "endMs": 1500,
+                    "text": "Hi there",
+                    "words": [
+                        { "startMs": 500, "endMs": 900, "text": "Hi" },
+                        { "startMs": 900, "endMs": 1500, "text": "there" },
+                    ],
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_csv_round_trips_quoted_text() {
+        let seg = |start_ms, end_ms, text: &str| CaptionSegment {
+            start_ms,
+            end_ms,
+            text: text.into(),
+            words: Vec::new(),
+            no_speech_prob: None,
+            position: None,
+        };
+        let segments = vec![
+            seg(0, 1200, "Well, \"hello\" there"),
+            seg(61_000, 62_500, "Plain"),
+        ];
+        let csv = to_csv(&segments);
+        assert!(csv.starts_with(
+            "index,start_ms,end_ms,start_tc,end_tc,text\r\n\
+             1,0,1200,00:00:00.000,00:00:01.200,\"Well, \"\"hello\"\" there\"\r\n"
+        ));
+        let parsed = parse_csv(&csv).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].text, "Well, \"hello\" there");
+        assert_eq!((parsed[1].start_ms, parsed[1].end_ms), (61_000, 62_500));
+
+        // A spreadsheet edit: ms cells cleared, timecode fixed, a line break in the text
+        let edited =
+            "text,start_tc,end_tc,start_ms,end_ms\n\"Two\nlines\",00:00:02.000,00:00:03.000,,\n";
+        let parsed = parse_csv(edited).unwrap();
+        assert_eq!(parsed[0].text, "Two lines");
+        assert_eq!((parsed[0].start_ms, parsed[0].end_ms), (2000, 3000));
+        let err = parse_csv("index,start_ms,end_ms,text\n1,soon,2,x\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Row 2"), "{}", err);
+    }
+
+    fn seg(start_ms: u64, end_ms: u64, text: &str) -> CaptionSegment {
+        CaptionSegment {
+            start_ms,
+            end_ms,
+            text: text.into(),
+            words: Vec::new(),
+            no_speech_prob: None,
+            position: None,
+        }
+    }
+
+    #[test]
+    fn test_scc_pop_on_caption() {
+        let rate = FrameRate::from_fps(29.97).unwrap();
+        let scc = to_scc(&[seg(1000, 2000, "Hello")], rate);
+        // Loaded 12 frames early so EOC lands on frame 30, cleared at 2 s
+        assert_eq!(
+            scc,
+            "Scenarist_SCC V1.0\n\n\
+             00:00:00;18\t9420 9420 94ae 94ae 9476 9476 97a1 97a1 c8e5 ecec ef80 942f 942f\n\n\
+             00:00:02;00\t942c 942c\n"
+        );
+    }
+
+    #[test]
+    fn test_scc_wraps_rows_and_maps_characters() {
+        let rate = FrameRate::from_fps(29.97).unwrap();
+        let text = "one two three four five six seven eight nine ten eleven twelve thirteen";
+        let rows = scc_rows(text);
+        assert!(rows.iter().all(|r| r.len() <= SCC_ROW_CHARS), "{:?}", rows);
+        assert_eq!(rows.len(), 3);
+        let scc = to_scc(&[seg(0, 6000, text), seg(6000, 7000, "next")], rate);
+        // Three rows become two captions; the last follows straight on, so no clear between
+        assert_eq!(scc.matches("942f 942f").count(), 3);
+        assert_eq!(scc.matches("942c 942c").count(), 1);
+
+        assert_eq!(scc_char('é'), Some(SccChar::Basic(0x5C)));
+        assert_eq!(scc_char('♪'), Some(SccChar::Code([0x11, 0x37])));
+        assert_eq!(scc_char('Ü'), Some(SccChar::Extended(b'U', [0x12, 0x24])));
+        assert_eq!(scc_char('ø'), Some(SccChar::Extended(b'o', [0x13, 0x3B])));
+        assert_eq!(scc_char('*'), Some(SccChar::Extended(b'.', [0x12, 0x28])));
+        assert_eq!(scc_char('🎉'), None);
+        assert_eq!(scc_rows("party 🎉 time"), vec!["party time"]);
+    }
+
+    #[test]
+    fn test_scc_only_takes_29_97() {
+        let df = scc_frame_rate(None, None).unwrap();
+        assert_eq!((df.num, df.den, df.drop_frame), (30_000, 1001, true));
+        let ndf = scc_frame_rate(Some(29.97), Some(false)).unwrap();
+        assert_eq!((ndf.num, ndf.den, ndf.drop_frame), (30_000, 1001, false));
+        let scc = to_scc(&[seg(1000, 2000, "Hello")], ndf);
+        assert!(scc.contains("\n00:00:02:00\t"), "{}", scc);
+
+        for fps in [25.0, 30.0, 23.976, 59.94] {
+            let err = scc_frame_rate(Some(fps), None).unwrap_err().to_string();
+            assert!(err.contains("29.97"), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use crate::video::ProbeResult;
+use anyhow::{anyhow, Result};
+
+/// A frame rate for SMPTE timecodes, kept as an exact fraction so NTSC rates such as
+/// 30000/1001 don't drift over a long video
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRate {
+    pub num: u64,         // Frames per `den` seconds
+    pub den: u64,         // 1000 for decimal rates, 1001 for NTSC
+    pub drop_frame: bool, // Count with drop-frame timecode (29.97 and 59.94 only)
+}
+
+// NTSC rates that ffprobe reports as rounded decimals
+const NTSC_RATES: [u64; 4] = [24_000, 30_000, 48_000, 60_000];
+
+impl FrameRate {
+    /// From a probed frame rate. NTSC rates (23.976, 29.97, 59.94, ...) snap to their exact
+    /// x/1001 fraction; 29.97 and 59.94 count in drop-frame, as broadcast expects.
+    pub fn from_fps(fps: f64) -> Result<Self> {
+        if !(fps.is_finite() && fps >= 1.0) {
+            return Err(anyhow!("Invalid frame rate {}", fps));
+        }
+        if let Some(&num) = NTSC_RATES
+            .iter()
+            .find(|&&n| (fps - n as f64 / 1001.0).abs() < 0.005)
+        {
+            return Ok(FrameRate {
+                num,
+                den: 1001,
+                drop_frame: num % 30_000 == 0,
+            });
+        }
+        Ok(FrameRate {
+            num: (fps * 1000.0).round() as u64,
+            den: 1000,
+            drop_frame: false,
+        })
+    }
+
+    /// The frame rate of a probed video
+    pub fn from_probe(probe: &ProbeResult) -> Result<Self> {
+        let fps = probe
+            .fps
+            .ok_or_else(|| anyhow!("The video's frame rate is unknown"))?;
+        Self::from_fps(fps)
+    }
+
+    /// Frames counted per timecode second: 30 for 29.97
+    pub fn nominal(&self) -> u64 {
+        ((self.num + self.den / 2) / self.den).max(1)
+    }
+}
+
+/// Nearest frame to a time in ms (a time exactly between two frames goes to the later one)
+pub fn ms_to_frames(ms: u64, rate: FrameRate) -> u64 {
+    ((ms as u128 * rate.num as u128 * 2 + rate.den as u128 * 1000) / (rate.den as u128 * 2000))
+        as u64
+}
+
+/// Start time of a frame, rounded to the nearest ms
+pub fn frames_to_ms(frames: u64, rate: FrameRate) -> u64 {
+    ((frames as u128 * rate.den as u128 * 2000 + rate.num as u128) / (rate.num as u128 * 2)) as u64
+}
+
+/// `HH:MM:SS:FF` for a frame count; drop-frame rates use `HH:MM:SS;FF` and skip the
+/// frame labels 0 and 1 (0-3 at 59.94) every minute except each tenth
+pub fn format_frames(frames: u64, rate: FrameRate) -> String {
+    let fps = rate.nominal();
+    let mut label = frames;
+    if rate.drop_frame {
+        let dropped = fps / 15; // 2 at 29.97, 4 at 59.94
+        let per_minute = fps * 60 - dropped;
+        let per_ten_minutes = per_minute * 10 + dropped;
+        let tens = frames / per_ten_minutes;
+        let rest = frames % per_ten_minutes;
+        label += dropped * 9 * tens;
+        if rest > dropped {
+            label += dropped * ((rest - dropped) / per_minute);
+        }
+    }
+    let seconds = label / fps;
+    format!(
+        "{:02}:{:02}:{:02}{}{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        if rate.drop_frame { ';' } else { ':' },
+        label % fps
+    )
+}
+
+/// Timecode of the frame nearest to a time in ms
+pub fn format_ms(ms: u64, rate: FrameRate) -> String {
+    format_frames(ms_to_frames(ms, rate), rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_rate_snaps_ntsc() {
+        let df = FrameRate::from_fps(29.97).unwrap();
+        assert_eq!((df.num, df.den, df.drop_frame), (30_000, 1001, true));
+        assert_eq!(df.nominal(), 30);
+        let film = FrameRate::from_fps(23.976).unwrap();
+        assert_eq!((film.num, film.drop_frame), (24_000, false));
+        assert_eq!(film.nominal(), 24);
+        let pal = FrameRate::from_fps(25.0).unwrap();
+        assert_eq!((pal.num, pal.den, pal.nominal()), (25_000, 1000, 25));
+        assert!(FrameRate::from_fps(0.0).is_err());
+        assert!(FrameRate::from_fps(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_ms_to_frames_rounds_at_half_frame() {
+        let pal = FrameRate::from_fps(25.0).unwrap();
+        assert_eq!(ms_to_frames(19, pal), 0);
+        assert_eq!(ms_to_frames(20, pal), 1); // exactly half a frame
+        assert_eq!(ms_to_frames(1000, pal), 25);
+        assert_eq!(frames_to_ms(25, pal), 1000);
+
+        let df = FrameRate::from_fps(29.97).unwrap();
+        // One hour of wall clock is 107892 NTSC frames, with no drift
+        assert_eq!(ms_to_frames(3_600_000, df), 107_892);
+        assert_eq!(frames_to_ms(107_892, df), 3_599_996);
+        for frame in [0, 1, 29, 30, 1799, 17_982, 107_892] {
+            assert_eq!(ms_to_frames(frames_to_ms(frame, df), df), frame);
+        }
+    }
+
+    #[test]
+    fn test_format_non_drop() {
+        let rate = FrameRate::from_fps(24.0).unwrap();
+        assert_eq!(format_frames(0, rate), "00:00:00:00");
+        assert_eq!(format_frames(23, rate), "00:00:00:23");
+        assert_eq!(format_frames(24, rate), "00:00:01:00");
+        assert_eq!(format_ms(3_723_500, rate), "01:02:03:12");
+    }
+
+    #[test]
+    fn test_format_drop_frame_skips_labels() {
+        let df = FrameRate::from_fps(29.97).unwrap();
+        assert_eq!(format_frames(1799, df), "00:00:59;29");
+        assert_eq!(format_frames(1800, df), "00:01:00;02");
+        assert_eq!(format_frames(3597, df), "00:01:59;29");
+        assert_eq!(format_frames(3598, df), "00:02:00;02");
+        // Every tenth minute keeps its 0 and 1 frames
+        assert_eq!(format_frames(17_981, df), "00:09:59;29");
+        assert_eq!(format_frames(17_982, df), "00:10:00;00");
+        assert_eq!(format_ms(3_600_000, df), "01:00:00;00");
+
+        let df60 = FrameRate::from_fps(59.94).unwrap();
+        assert_eq!(format_frames(3599, df60), "00:00:59;59");
+        assert_eq!(format_frames(3600, df60), "00:01:00;04");
+    }
+}
@@ -9,6 +9,24 @@ pub struct CaptionSegment {
     // Optional word-level timing (used when split_by_words = true)
     #[serde(default)]
     pub words: Vec<WordSpan>,
+    // Whisper's probability that the segment is not speech (API transcriptions only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_speech_prob: Option<f32>,
+    // Manual placement for this segment's captions, overriding the global `position`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<SegmentPosition>,
+}
+
+/// Caption anchor for one segment, in the same terms as a preview cue's `xPct`/`yPct`:
+/// the point the text is aligned to (bottom center for bottom captions). A missing
+/// coordinate keeps the global placement on that axis.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentPosition {
+    #[serde(default)]
+    pub x_pct: Option<f32>, // Horizontal anchor as percentage from left
+    #[serde(default)]
+    pub y_pct: Option<f32>, // Vertical anchor as percentage from top
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,16 +37,59 @@ pub struct WordSpan {
     pub text: String,
 }
 
+/// OpenAI API key. `Debug` only ever prints a masked form (`sk-***`), so params can be
+/// logged or put in error messages without leaking it; use `expose` to send it.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    pub fn new(key: impl Into<String>) -> Self {
+        Self(key.into())
+    }
+
+    /// The raw key, for the Authorization header only
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn masked(&self) -> String {
+        crate::logging::mask_secret(&self.0)
+    }
+}
+
+impl std::fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ApiKey({})", self.masked())
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct TranscribeSegmentsParams {
-    pub audio: String,              // Path to audio file to transcribe
-    pub model: Option<String>,      // Whisper model to use (default: "whisper-1")
-    pub language: Option<String>,   // Language hint for better accuracy
-    pub split_by_words: bool,       // Whether to split by words or segments
-    pub api_key: Option<String>,    // OpenAI API key
-    pub prompt: Option<String>,     // Context prompt to improve accuracy
+    pub audio: String,            // Path to audio file to transcribe
+    pub model: Option<String>,    // whisper-1, gpt-4o(-mini)-transcribe or a local model name
+    pub language: Option<String>, // Language hint for better accuracy
+    pub split_by_words: bool,     // Whether to split by words or segments
+    pub api_key: Option<ApiKey>,  // OpenAI API key
+    pub prompt: Option<String>,   // Context prompt to improve accuracy
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub model_fallbacks: Vec<String>, // API models to try in order when the model before fails with a retryable error (e.g. 429)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vocabulary: Vec<String>, // Names and jargon to bias toward; merged into the prompt
     pub video_file: Option<String>, // Original video file path (for JSON output location)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_gap_ms: Option<u32>, // Merge consecutive segments separated by less than this gap
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drop_no_speech_above: Option<f32>, // Drop segments Whisper rates likelier than this to be non-speech
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resegment: Option<String>, // "whisper" (default: keep Whisper's segments) or "sentence" (regroup words into sentences)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cps: Option<f32>, // Reading-speed limit in characters per second; faster cues are lengthened into nearby silence
+    #[serde(default)]
+    pub force: bool, // Ignore a cached transcription and transcribe again (result is re-cached)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ffmpeg_path: Option<String>, // ffmpeg to run instead of the detected one, as for generateCaptions
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -38,6 +99,8 @@ pub struct TranscribeSegmentsResult {
     pub full_text: String,             // Complete transcription text
     pub duration: Option<f64>,         // Total audio duration
     pub json_file: String,             // Path to saved JSON captions file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detected_language: Option<String>, // Spoken language as an ISO 639-1 code (e.g. "en"), when known
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -61,6 +124,28 @@ pub struct WhisperCacheIndex {
     pub entries: Vec<WhisperCacheEntry>,
 }
 
+/// Optional `config.json` in the CapSlap config directory
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CoreConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_dir: Option<String>, // Root for the transcription cache and models (CAPSLAP_CACHE_DIR wins)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_jobs: Option<usize>, // Heavy requests run at once (CAPSLAP_MAX_JOBS wins)
+    #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+    pub request_timeouts: std::collections::HashMap<String, u64>, // Method -> timeout in seconds (0 = none) for methods with a default limit; a request's timeoutSec wins
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStatsResult {
+    pub cache_dir: String,            // Resolved transcription cache directory
+    pub models_dir: String,           // Resolved whisper model directory
+    pub source: String,               // "env", "config" or "default"
+    pub transcription_entries: usize, // Cached transcriptions in the index
+    pub cache_bytes: u64,             // Total size of the cache directory (incl. stored audio)
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct WhisperSegment {
@@ -68,6 +153,12 @@ pub struct WhisperSegment {
     pub start: f64,
     pub end: f64,
     pub text: String,
+    #[serde(
+        rename = "no_speech_prob",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub no_speech_prob: Option<f64>, // Same key as the OpenAI verbose_json response
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -95,29 +186,272 @@ pub struct ExtractAudioParams {
     pub input: String,         // Path to input video file
     pub codec: Option<String>, // Audio codec to use (default: "aac")
     pub out: Option<String>,   // Output path (default: input filename with .m4a extension)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<u32>, // Resample to this rate in Hz (e.g. 16000 for Whisper)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channels: Option<u8>, // Downmix to this many channels (1 = mono)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<String>, // Encoder bitrate (e.g. "32k"); default 160k for AAC
+    #[serde(default)]
+    pub normalize: bool, // Loudness-normalize (EBU R128 loudnorm) while extracting
+    #[serde(default)]
+    pub denoise: bool, // Reduce background noise (afftdn) while extracting
+    #[serde(default)]
+    pub dry_run: bool, // Return the ffmpeg command instead of running it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim_start_ms: Option<u64>, // Extract from this point of the input on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim_end_ms: Option<u64>, // Extract up to this point of the input (default: its end)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ffmpeg_path: Option<String>, // ffmpeg to run instead of the detected one, as for generateCaptions
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ExtractAudioResult {
-    pub audio: String, // Path to the extracted audio file
+    pub audio: String, // Path to the extracted audio file (not written on a dry run)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<Vec<String>>, // The ffmpeg argv, on a dry run
+}
+
+/// Optional caption layout/styling knobs shared by the generate, burn and preview requests.
+/// Flattened into those params, so the fields appear at the top level of the JSON.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptionStyleOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_chars_per_line: Option<u32>, // Wrap caption text onto new lines after this many characters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_words_per_cue: Option<u32>, // Karaoke: show at most this many words on screen at once
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub animation: Option<String>, // Karaoke active word animation: "none", "pop", "fade" or "fill" (left-to-right sweep)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub writing_mode: Option<String>, // "horizontal" (default) or "vertical" (stacked CJK columns)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<ShadowSpec>, // Offset drop shadow, drawn in addition to the outline
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background_color: Option<String>, // Box behind the text as hex string (enables the box)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background_opacity: Option<f32>, // Box opacity 0.0-1.0 (default 0.6)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background_radius: Option<f32>, // Box corner radius in pixels (default 0 = square)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_gradient: Option<(String, String)>, // Top and bottom fill colors as hex strings; overrides text_color
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emoji_font: Option<String>, // Outline font for emoji runs (default: first installed of Noto Emoji, Segoe UI Emoji, ...); burned emoji are monochrome and left out when no such font is installed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fade_ms: Option<u32>, // Fade each cue in and out over this many ms (default 0 = hard cut)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_cue_ms: Option<u32>, // Extend shorter cues into the following gap
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_cue_ms: Option<u32>, // Split longer cues at word boundaries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_transform: Option<String>, // Display case: "upper" (default), "lower", "title" or "none" (as transcribed)
+    #[serde(default)]
+    pub strip_punctuation: bool, // Drop sentence punctuation (. , ! ? … quotes) from displayed words; apostrophes in contractions stay
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profanity_filter: Option<String>, // "off" (default), "mask" (f***) or "remove"; timing of other words is kept
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub profanity_words: Vec<String>, // Extra words for the profanity filter, on top of the bundled list
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replacements: Vec<TextReplacement>, // Case-insensitive find/replace on caption text before rendering
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub karaoke_timing: Option<String>, // "words" (default: transcribed word timings) or "estimate" (spread each segment over its words by length)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reveal: Option<String>, // "none" (default) or "typewriter": characters appear one at a time as their words are spoken
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub safe_margin_top_pct: Option<f32>, // Keep captions at least this far (percent of frame height) below the top edge
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub safe_margin_bottom_pct: Option<f32>, // Keep captions at least this far (percent of frame height) above the bottom edge
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub safe_margin_x_pct: Option<f32>, // Keep caption lines this far (percent of frame width) from each side edge
+    #[serde(default)]
+    pub balance_lines: bool, // Break wrapped cues into lines of even length instead of filling the top line first
+}
+
+/// One find/replace rule for caption text, e.g. fixing a misheard brand name.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TextReplacement {
+    pub find: String,    // Text to look for (case-insensitive)
+    pub replace: String, // Replacement text; with `regex`, $1 etc. refer to capture groups
+    #[serde(default)]
+    pub whole_word: bool, // Only match whole words ("AI" won't touch "said")
+    #[serde(default)]
+    pub regex: bool, // Treat `find` as a regular expression
+}
+
+/// Classic offset drop shadow, independent of the outline and glow.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowSpec {
+    #[serde(default)]
+    pub x: f32, // Horizontal offset in pixels (positive = right)
+    #[serde(default)]
+    pub y: f32, // Vertical offset in pixels (positive = down)
+    #[serde(default)]
+    pub blur: f32, // Blur radius in pixels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>, // Shadow color as hex string (default black)
+}
+
+/// Optional encoder knobs shared by the generate and burn requests.
+/// Flattened into those params, so the fields appear at the top level of the JSON.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct EncodeOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crf: Option<u8>, // Constant quality 0-51, lower = better and larger (default 16)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_bitrate: Option<String>, // Target video bitrate (e.g. "6M"); overrides crf
+    #[serde(default)]
+    pub two_pass: bool, // Two-pass libx264 encode for predictable sizes (software only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_size_mb: Option<f64>, // Two-pass size goal in MB (10^6 bytes); sets the bitrate
+    #[serde(default)]
+    pub allow_upscale: bool, // Let an output_size preset exceed the source resolution
+    #[serde(flatten)]
+    pub metadata: MetadataOptions, // Container tags to keep, strip or set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<WatermarkSpec>, // Logo composited into every export, under the captions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress_bar: Option<ProgressBarSpec>, // Bar along an edge that fills as the video plays
+    #[serde(default)]
+    pub intro_hold_ms: u64, // Freeze the first frame (audio silent) this long before playback; captions shift with it
+    #[serde(default)]
+    pub outro_hold_ms: u64, // Freeze the last frame (audio silent) this long after playback
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim_start_ms: Option<u64>, // Only process the source from here on; segments stay on the source timeline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim_end_ms: Option<u64>, // Only process the source up to here (default: its end)
+    #[serde(default)]
+    pub bitexact: bool, // Byte-identical output for identical inputs (single-threaded libx264, no tags or timestamps)
+    #[serde(default)]
+    pub dry_run: bool, // Build the ffmpeg commands and return them (see CaptionedVideoResult.commands) without running them; generateCaptions then skips transcription too
+}
+
+impl EncodeOptions {
+    /// Whether only part of the source is processed
+    pub fn is_trimmed(&self) -> bool {
+        self.trim_start_ms.is_some_and(|ms| ms > 0) || self.trim_end_ms.is_some()
+    }
+}
+
+/// A thin bar that grows from the left edge to full width over the video's duration
+/// (skipped when the duration can't be probed)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressBarSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>, // #rrggbb or #rrggbbaa (default white)
+    #[serde(default = "default_progress_bar_height")]
+    pub height: f32, // Bar thickness as a fraction of the output height (default 0.008)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<String>, // "bottom" (default) or "top"
+}
+
+fn default_progress_bar_height() -> f32 {
+    0.008
+}
+
+/// A logo overlay. Size and margins are fractions of the output frame, so one spec
+/// places the logo consistently across 9:16, 16:9 and square exports.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WatermarkSpec {
+    pub path: String, // PNG (alpha is kept) or any image ffmpeg can decode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<String>, // "top-left", "top-right", "bottom-left", "bottom-right" (default) or "center"
+    #[serde(default = "default_watermark_scale")]
+    pub scale: f32, // Logo width as a fraction of the output width (default 0.15)
+    #[serde(default = "default_watermark_opacity")]
+    pub opacity: f32, // 0.0 (invisible) to 1.0 (default)
+    #[serde(default = "default_watermark_margin")]
+    pub margin_x: f32, // Gap from the left/right edge, fraction of output width (default 0.03)
+    #[serde(default = "default_watermark_margin")]
+    pub margin_y: f32, // Gap from the top/bottom edge, fraction of output height (default 0.03)
+}
+
+fn default_watermark_scale() -> f32 {
+    0.15
+}
+
+fn default_watermark_opacity() -> f32 {
+    1.0
+}
+
+fn default_watermark_margin() -> f32 {
+    0.03
+}
+
+/// Container metadata handling for exported files. Shared by the caption encodes
+/// and the plain export, flattened like [`EncodeOptions`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataOptions {
+    #[serde(default = "default_true")]
+    pub copy_metadata: bool, // Carry source tags (creation date, GPS, camera) over (default true)
+    #[serde(default)]
+    pub strip_metadata: bool, // Privacy mode: drop every source tag; wins over copy_metadata
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_title: Option<String>, // Custom title tag written to the output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_comment: Option<String>, // Custom comment tag written to the output
+}
+
+impl Default for MetadataOptions {
+    fn default() -> Self {
+        Self {
+            copy_metadata: true,
+            strip_metadata: false,
+            metadata_title: None,
+            metadata_comment: None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct GenerateCaptionsParams {
-    pub input_video: String,         // Path to input video file
-    pub export_formats: Vec<String>, // List of aspect ratios to export (e.g., ["9:16", "16:9"])
-    pub karaoke: bool,               // Whether to use karaoke-style highlighting
+    pub input_video: String, // Path to input video file
+    #[serde(default)]
+    pub export_formats: Vec<String>, // List of aspect ratios to export (e.g., ["9:16", "16:9"]); may come from the preset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>, // Platform preset ("tiktok", "reels", "shorts", "youtube") filling export fields left unset
+    pub karaoke: bool, // Whether to use karaoke-style highlighting
     #[serde(default)]
     pub multiline: bool, // Whether to allow multiple lines (karaoke)
-    pub font_name: Option<String>,   // Font name for captions (defaults to "Montserrat Black")
+    pub font_name: Option<String>, // Font name for captions (defaults to "Montserrat Black")
     #[serde(skip_serializing_if = "Option::is_none")]
     pub font_size: Option<u32>, // Base font size (at 1080p reference)
-    pub split_by_words: bool,        // Whether to split transcription by words or segments
-    pub model: Option<String>,       // Whisper model to use (default: "whisper-1")
-    pub language: Option<String>,    // Language hint for better accuracy
-    pub prompt: Option<String>,      // Context prompt to improve accuracy
+    pub split_by_words: bool, // Whether to split transcription by words or segments
+    pub model: Option<String>, // "whisper-1", "gpt-4o-transcribe", "gpt-4o-mini-transcribe" or a local model name
+    pub language: Option<String>, // Language hint for better accuracy
+    pub prompt: Option<String>, // Context prompt to improve accuracy
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub model_fallbacks: Vec<String>, // API models to try in order when the model before fails with a retryable error (e.g. 429)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vocabulary: Vec<String>, // Names and jargon to bias toward; merged into the prompt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_gap_ms: Option<u32>, // Merge consecutive segments separated by less than this gap
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drop_no_speech_above: Option<f32>, // Drop segments Whisper rates likelier than this to be non-speech
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resegment: Option<String>, // "whisper" (default: keep Whisper's segments) or "sentence" (regroup words into sentences)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cps: Option<f32>, // Reading-speed limit in characters per second; faster cues are lengthened into nearby silence
+    #[serde(default)]
+    pub normalize_audio: bool, // Loudness-normalize the extracted audio before transcription
+    #[serde(default)]
+    pub denoise_audio: bool, // Denoise the extracted audio before transcription
+    #[serde(default)]
+    pub inline_audio: bool, // Pipe short clips' audio straight into the API upload instead of writing an audio file (not cached)
+    #[serde(default)]
+    pub save_captions: bool, // Write the <video>.capslap.json sidecar right after transcription
+    #[serde(default)]
+    pub force: bool, // Redo audio extraction and transcription instead of resuming from cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub work_dir: Option<String>, // Directory for intermediates (default CAPSLAP_WORK_DIR, else OS temp)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub text_color: Option<String>, // Text color as hex string (e.g., "#ffffff")
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -128,18 +462,40 @@ pub struct GenerateCaptionsParams {
     pub glow_effect: bool, // Whether to apply glow effect
     #[serde(skip_serializing_if = "Option::is_none")]
     pub position: Option<String>, // Caption position: "bottom" or "center"
-    pub api_key: Option<String>,     // OpenAI API key
+    pub api_key: Option<ApiKey>, // OpenAI API key
     #[serde(skip_serializing_if = "Option::is_none")]
     pub output_size: Option<String>, // Target output size (e.g., "1080p", "original")
     #[serde(skip_serializing_if = "Option::is_none")]
     pub crop_strategy: Option<String>, // "start", "center", "end", "fit" (letterbox)
+    #[serde(flatten)]
+    pub style_options: CaptionStyleOptions, // Extra layout/styling options
+    #[serde(flatten)]
+    pub encode_options: EncodeOptions, // Encoder quality/bitrate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ffmpeg_path: Option<String>, // ffmpeg binary for this request instead of the detected one; ffprobe is taken from the same directory when it's there. Every method accepts it
+}
+
+/// Up-front guess at what a generateCaptions run will cost and how long it will take
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EstimateResult {
+    pub duration_sec: f64, // Length that gets transcribed and exported (after trimming)
+    pub model: String,     // Transcription model the estimate assumes
+    pub engine: String,    // "api" or "local", as routed with the models installed now
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_models: Vec<String>, // API models tried next if `model` fails
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transcription_cost_usd: Option<f64>, // OpenAI charge for `model`; 0 for local, None when its price is unknown
+    pub transcribe_sec: f64, // Rough transcription time
+    pub export_sec: f64,     // Rough encode time for all export formats
+    pub total_sec: f64,      // Rough wall time for the whole run
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct GenerateCaptionsResult {
     pub probe_result: crate::video::ProbeResult, // Original video information
-    pub audio_file: String,                      // Path to extracted audio file
+    pub audio_file: String, // Path to extracted audio file (empty when streamed inline)
     pub transcription: TranscribeSegmentsResult, // Transcription results and segments
     pub captioned_videos: Vec<CaptionedVideoResult>, // List of generated videos with captions
 }
@@ -147,13 +503,14 @@ pub struct GenerateCaptionsResult {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct BurnCaptionsParams {
-    pub input_video: String,           // Path to input video file
+    pub input_video: String, // Path to input video file
+    #[serde(default)]
     pub segments: Vec<CaptionSegment>, // The edited segments to burn
-    pub export_formats: Vec<String>,   // List of aspect ratios to export
-    pub karaoke: bool,                 // Whether to use karaoke-style highlighting
+    pub export_formats: Vec<String>, // List of aspect ratios to export
+    pub karaoke: bool,       // Whether to use karaoke-style highlighting
     #[serde(default)]
     pub multiline: bool, // Whether to allow multiple lines (karaoke)
-    pub font_name: Option<String>,     // Font name
+    pub font_name: Option<String>, // Font name
     #[serde(skip_serializing_if = "Option::is_none")]
     pub font_size: Option<u32>, // Base font size
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -170,6 +527,40 @@ pub struct BurnCaptionsParams {
     pub output_size: Option<String>, // Target output size
     #[serde(skip_serializing_if = "Option::is_none")]
     pub crop_strategy: Option<String>, // Crop strategy
+    #[serde(default)]
+    pub timing_offset_ms: i64, // Shift every cue and word by this many ms (negative = earlier)
+    #[serde(default = "default_time_scale")]
+    pub time_scale: f64, // Multiply every timing by this factor, after the offset (default 1.0)
+    #[serde(default)]
+    pub fix_segments: bool, // Sort and clamp bad segment timings instead of rejecting them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>, // "burn" (default) renders captions into pixels; "mux" adds a subtitle track; "overlay" renders only the captions over transparency
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overlay_codec: Option<String>, // Overlay mode: "prores" (ProRes 4444 .mov, default) or "vp9" (VP9 with alpha .webm)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub work_dir: Option<String>, // Directory for intermediates (default CAPSLAP_WORK_DIR, else OS temp)
+    #[serde(flatten)]
+    pub style_options: CaptionStyleOptions, // Extra layout/styling options
+    #[serde(flatten)]
+    pub encode_options: EncodeOptions, // Encoder quality/bitrate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ffmpeg_path: Option<String>, // ffmpeg to run instead of the detected one, as for generateCaptions
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BurnFromSubtitleFileParams {
+    pub subtitle_path: String, // SRT or WebVTT file whose cues replace `segments`
+    #[serde(flatten)]
+    pub burn: BurnCaptionsParams, // Video, formats and styling, as for burn
+}
+
+fn default_time_scale() -> f64 {
+    1.0
+}
+
+fn default_true() -> bool {
+    true
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -180,6 +571,8 @@ pub struct CaptionedVideoResult {
     pub captioned_video: String, // Path to final video with captions
     pub width: u32,              // Video width
     pub height: u32,             // Video height
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<Vec<String>>, // On a dry run, the ffmpeg argv of each pass that would have run (generateCaptions lists its audio extraction first)
 }
 
 // Model download types
@@ -197,17 +590,157 @@ pub struct DownloadModelResult {
     pub size: u64,     // Downloaded file size in bytes
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    pub model: String,  // Model name: "tiny", "base", ...
+    pub path: String,   // Model file path
+    pub size: u64,      // Bytes on disk, including a partial download and checksum record
+    pub verified: bool, // Complete and checksum-verified (what checkModelExists reports)
+    pub partial: bool,  // An unfinished .part download is present
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ListModelsResult {
+    pub models_dir: String,     // Directory models are stored in
+    pub models: Vec<ModelInfo>, // Models with anything on disk
+    pub total_bytes: u64,       // Sum of sizes
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteModelParams {
+    pub model: String, // Model name to delete
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteModelResult {
+    pub model: String,    // Model name that was deleted
+    pub bytes_freed: u64, // Bytes removed (0 when it wasn't downloaded)
+}
+
+/// Sidecar (`<model>.verified.json`) written once a model's SHA-256 matched the hub's
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelChecksum {
+    pub sha256: String, // Hex SHA-256 of the verified file
+    pub size: u64,      // File size when verified
+    pub modified: u64,  // File mtime (unix secs) when verified; a change forces a rehash
+}
+
 // Frame extraction types
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ExtractFirstFrameParams {
     pub video_path: String,
+    #[serde(default)]
+    pub to_file: bool, // Write the frame to a PNG and return its path instead of inline base64
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub out: Option<String>, // PNG path for toFile (default: a per-video file in the temp dir)
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ExtractFirstFrameResult {
-    pub image_data: String, // Base64 encoded image
+    pub image_data: String, // Base64 encoded image (empty in toFile mode)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>, // Path of the written PNG (toFile mode)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractThumbnailsParams {
+    pub video_path: String,
+    #[serde(default)]
+    pub count: Option<u32>, // Number of evenly spaced thumbnails (or use interval_ms)
+    #[serde(default)]
+    pub interval_ms: Option<u64>, // One thumbnail every interval, starting at 0
+    #[serde(default)]
+    pub height: Option<u32>, // Thumbnail height in pixels (default 90); width keeps the aspect ratio
+    #[serde(default)]
+    pub sprite: bool, // Return one sprite-sheet image instead of an image per thumbnail
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Thumbnail {
+    pub time_ms: u64, // Source timestamp of this frame
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_data: Option<String>, // Base64 JPEG data URI (None in sprite mode)
+    pub x: u32,       // Offset within the sprite sheet (0 when not a sprite)
+    pub y: u32,       // Offset within the sprite sheet (0 when not a sprite)
+    pub width: u32,   // Thumbnail width in pixels
+    pub height: u32,  // Thumbnail height in pixels
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractThumbnailsResult {
+    pub thumbnails: Vec<Thumbnail>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sprite: Option<String>, // Base64 JPEG sprite sheet (sprite mode only)
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ListExportFormatsParams {
+    #[serde(default)]
+    pub source_width: Option<u32>, // Source display width; adds the "source" size when set
+    #[serde(default)]
+    pub source_height: Option<u32>, // Source display height; adds the "source" size when set
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSize {
+    pub output_size: String, // Value to pass as outputSize ("source" means omit it)
+    pub width: u32,          // Resolved canvas width in pixels
+    pub height: u32,         // Resolved canvas height in pixels
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportFormatInfo {
+    pub format: String,               // Aspect ratio string accepted in exportFormats
+    pub supports_fit: bool,           // Whether cropStrategy "fit" (letterbox) is available
+    pub crop_strategies: Vec<String>, // Accepted cropStrategy values, default first
+    pub sizes: Vec<ExportSize>,       // Pixel dimensions at each outputSize
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ListExportFormatsResult {
+    pub formats: Vec<ExportFormatInfo>, // Every supported export format
+}
+
+/// Export settings for a platform, applied by `GenerateCaptionsParams.preset`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlatformPreset {
+    pub id: String,                  // Value for the preset param (e.g. "tiktok")
+    pub name: String,                // Display name (e.g. "TikTok")
+    pub export_formats: Vec<String>, // Aspect ratios to export
+    pub output_size: String,         // Output size preset
+    pub crop_strategy: String,       // "fit" or "fill"
+    pub position: String,            // Caption position, clear of the platform's on-screen UI
+    pub video_bitrate: String,       // Target video bitrate matching the platform's upload guidance
+    pub safe_margin_top_pct: f32,    // Safe area clear of the platform's top UI (percent of height)
+    pub safe_margin_bottom_pct: f32, // Safe area clear of the caption bar and controls (percent of height)
+    pub safe_margin_x_pct: f32, // Safe area clear of side buttons (percent of width, each side)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ListPlatformPresetsResult {
+    pub presets: Vec<PlatformPreset>, // Every preset, in UI order
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeParams {
+    pub video_path: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -226,12 +759,20 @@ pub struct PreviewLayoutParams {
     #[serde(default)]
     pub multiline: bool,
     pub glow_effect: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp_ms: Option<u64>, // Playhead for snapshot values such as PreviewWord.fill_pct
+    #[serde(flatten)]
+    pub style_options: CaptionStyleOptions, // Extra layout/styling options
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct PreviewLayoutResult {
     pub cues: Vec<PreviewCue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_shadow: Option<String>, // CSS text-shadow matching the burned drop shadow
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_gradient: Option<String>, // CSS linear-gradient for the fill (use with background-clip: text)
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -241,12 +782,72 @@ pub struct PreviewCue {
     pub end_ms: u64,
     pub lines: Vec<PreviewLine>,
     pub y_pct: f32, // Vertical position as percentage from top
+    #[serde(default = "default_centered_pct")]
+    pub x_pct: f32, // Horizontal anchor as percentage from left (50 = centered)
+    #[serde(default)]
+    pub rtl: bool, // Right-to-left cue: words are in display order and right-aligned
+    #[serde(default)]
+    pub vertical: bool, // Vertical writing mode: each line is a top-to-bottom column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<PreviewBox>, // Background box sized to this cue's wrapped lines
+    #[serde(default)]
+    pub fade_in_ms: u32, // Opacity ramps 0 -> 1 over this many ms from start_ms
+    #[serde(default)]
+    pub fade_out_ms: u32, // Opacity ramps 1 -> 0 over this many ms before end_ms
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visible_chars: Option<u32>, // Typewriter reveal: characters (not spaces) typed by timestamp_ms, all of them without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bounds: Option<PreviewBounds>, // Rendered extent of the text including outline/glow, for hit-testing and drag handles
+}
+
+fn default_centered_pct() -> f32 {
+    50.0
+}
+
+/// Background box geometry for a preview cue, matching the burned box.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewBox {
+    pub x_pct: f32,      // Left edge as percentage of frame width
+    pub y_pct: f32,      // Top edge as percentage of frame height
+    pub width_pct: f32,  // Box width as percentage of frame width
+    pub height_pct: f32, // Box height as percentage of frame height
+    pub radius_px: f32,  // Corner radius in pixels
+    pub color: String,   // Box color as hex string
+    pub opacity: f32,    // 0.0-1.0
+}
+
+/// On-screen extent of a preview cue's text, measured with the caption font.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewBounds {
+    pub x_pct: f32,      // Left edge as percentage of frame width
+    pub y_pct: f32,      // Top edge as percentage of frame height
+    pub width_pct: f32,  // Width as percentage of frame width
+    pub height_pct: f32, // Height as percentage of frame height
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ListFontsResult {
+    pub fonts: Vec<FontInfo>, // Installed and bundled fonts, sorted by family
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FontInfo {
+    pub family: String,    // Family name to pass as font_name
+    pub full_name: String, // Full face name ("Montserrat Black")
+    pub path: String,      // Font file on disk
+    pub bundled: bool,     // Shipped with the app rather than installed on the system
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct PreviewLine {
     pub words: Vec<PreviewWord>,
+    #[serde(default)]
+    pub est_width_px: f32, // Rendered width at the output resolution, measured with the caption font
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -254,6 +855,25 @@ pub struct PreviewLine {
 pub struct PreviewWord {
     pub text: String,
     pub is_highlighted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation_phase: Option<AnimationPhase>, // Karaoke animation timing for the active word
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fill_pct: Option<f32>, // "fill" animation: 0-100 of the word swept at timestampMs (0 without one)
+    #[serde(default)]
+    pub emoji: bool, // Emoji cluster split out of its token; render it as one unit
+    #[serde(default)]
+    pub attached: bool, // Joined to the previous word without a space (split from the same token)
+}
+
+/// Timing of the active word's karaoke animation, so the editor preview matches the burn.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AnimationPhase {
+    pub kind: String,    // "pop", "fade" or "fill"
+    pub start_ms: u64,   // Animation starts (word start)
+    pub peak_ms: u64,    // Full scale / full highlight color reached
+    pub end_ms: u64,     // Settled back to normal size (word end)
+    pub peak_scale: f32, // Scale at the peak (1.0 when only color changes)
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -263,6 +883,71 @@ pub struct SaveCaptionsParams {
     pub segments: Vec<CaptionSegment>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSubtitlesParams {
+    pub segments: Vec<CaptionSegment>, // Segments to export
+    pub format: String, // "srt", "vtt", "txt" (reading transcript), "json", "csv" or "scc"
+    pub output_path: String, // File to write
+    #[serde(default)]
+    pub timestamps: bool, // txt only: start each paragraph with its [HH:MM:SS] time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>, // json only: media duration in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>, // json only: spoken language code, e.g. "en"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fps: Option<f64>, // scc only: frame rate for the timecodes; SCC only allows 29.97 (the default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drop_frame: Option<bool>, // scc only: drop-frame timecodes (default true); false for non-drop
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSubtitlesParams {
+    pub path: String, // .srt, .vtt or .csv file to read
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSubtitlesResult {
+    pub segments: Vec<CaptionSegment>, // Parsed cues (no word timings)
+}
+
+/// Public JSON caption export (`exportSubtitles` with format "json"). This is a stable,
+/// versioned format for other tools: fields are only ever added, and anything that
+/// would break a reader bumps `schemaVersion`. Times are integer milliseconds.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptionsExport {
+    pub schema_version: u32,          // Format version, currently 1
+    pub duration: Option<f64>,        // Media duration in seconds, null if unknown
+    pub language: Option<String>,     // Spoken language code (e.g. "en"), null if unknown
+    pub segments: Vec<ExportSegment>, // Caption cues in order
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSegment {
+    pub start_ms: u64,          // Cue start
+    pub end_ms: u64,            // Cue end
+    pub text: String,           // Cue text
+    pub words: Vec<ExportWord>, // Word timings; empty when not available
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportWord {
+    pub start_ms: u64, // Word start
+    pub end_ms: u64,   // Word end
+    pub text: String,  // Word as displayed
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSubtitlesResult {
+    pub path: String, // File that was written
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct LoadCaptionsParams {
@@ -273,6 +958,60 @@ pub struct LoadCaptionsParams {
 #[serde(rename_all = "camelCase")]
 pub struct LoadCaptionsResult {
     pub segments: Option<Vec<CaptionSegment>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>, // Where the segments came from: "capslap" (our saved JSON), "srt" or "vtt"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>, // The file that was read
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct StylePreset {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_name: Option<String>, // Font family name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<u32>, // Base font size (at 1080p reference)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_color: Option<String>, // Text color as hex string
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight_word_color: Option<String>, // Highlight word color as hex string
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outline_color: Option<String>, // Outline color as hex string
+    #[serde(default)]
+    pub glow_effect: bool, // Whether to apply glow effect
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<String>, // Caption position: "bottom" or "center"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_size: Option<String>, // Target output size (e.g., "1080p", "original")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crop_strategy: Option<String>, // "start", "center", "end", "fit" (letterbox)
+    #[serde(flatten)]
+    pub style_options: CaptionStyleOptions, // Extra layout/styling options
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveStylePresetParams {
+    pub name: String,        // Preset name (letters, digits, spaces, '-' and '_')
+    pub preset: StylePreset, // Styling to persist
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadStylePresetParams {
+    pub name: String, // Preset name to load
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadStylePresetResult {
+    pub preset: Option<StylePreset>, // None when no preset with that name exists
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ListStylePresetsResult {
+    pub names: Vec<String>, // Saved preset names, sorted
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -303,6 +1042,20 @@ pub struct PreviewFrameParams {
     pub output_size: Option<String>, // Target output size
     #[serde(skip_serializing_if = "Option::is_none")]
     pub crop_strategy: Option<String>, // Crop strategy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>, // Exact preview width in pixels (with height; overrides export_format/output_size)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>, // Exact preview height in pixels (with width)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub work_dir: Option<String>, // Directory for intermediates (default CAPSLAP_WORK_DIR, else OS temp)
+    #[serde(default)]
+    pub timing_offset_ms: i64, // Shift every cue and word by this many ms (negative = earlier)
+    #[serde(default = "default_time_scale")]
+    pub time_scale: f64, // Multiply every timing by this factor, after the offset (default 1.0)
+    #[serde(default)]
+    pub transparent: bool, // Draw only the captions on a transparent canvas (PNG with alpha, no video frame)
+    #[serde(flatten)]
+    pub style_options: CaptionStyleOptions, // Extra layout/styling options
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -310,3 +1063,70 @@ pub struct PreviewFrameParams {
 pub struct PreviewFrameResult {
     pub image_data: String, // Base64 encoded image data
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewClipParams {
+    #[serde(flatten)]
+    pub frame: PreviewFrameParams, // Same styling and sizing as previewFrame; timestamp_ms is the clip's center
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>, // Clip length (default 2000, at most 10000)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fps: Option<u32>, // Frame rate of the animation (default 12)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clip_format: Option<String>, // "webp" (default) or "gif"
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewClipResult {
+    pub image_data: String, // Base64 animated WebP/GIF data URI
+    pub start_ms: u64,      // Source time of the first frame
+    pub duration_ms: u64,   // Length of the clip
+    pub width: u32,         // Clip width in pixels
+    pub height: u32,        // Clip height in pixels
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConcatVideosParams {
+    pub inputs: Vec<String>, // Videos to join, in playback order
+    pub output: String,      // Path for the joined video
+    #[serde(default)]
+    pub reencode: bool, // Always re-encode, even when the inputs could be stream-copied
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConcatVideosResult {
+    pub video: String,   // Path to the joined video
+    pub reencoded: bool, // False when the inputs were joined by stream copy
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckFfmpegCapabilitiesParams {
+    #[serde(default)]
+    pub encoders: Vec<String>, // Extra encoders to look for (e.g. "hevc_videotoolbox"), on top of the ones the pipeline uses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ffmpeg_path: Option<String>, // The ffmpeg to check instead of the detected one
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FfmpegCapabilities {
+    pub ffmpeg_path: String,          // The ffmpeg binary that was checked
+    pub version: Option<String>,      // e.g. "7.1"
+    pub subtitles: bool,              // Can burn captions (libass and the ass filter)
+    pub libraries: Vec<String>, // External libraries from -buildconf (e.g. "libass", "libx264")
+    pub encoders: Vec<FfmpegFeature>, // Each encoder checked, in request order
+    pub filters: Vec<FfmpegFeature>, // Each filter the pipeline uses
+    pub missing: Vec<String>,   // Required features that are absent; empty means fully usable
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FfmpegFeature {
+    pub name: String,    // Encoder or filter name as ffmpeg lists it
+    pub available: bool, // Whether this build has it
+}
@@ -9,6 +9,9 @@ use tokio::process::Command as TokioCommand;
 pub fn get_ffmpeg_path_sync() -> String {
     // Try to use cached path or default to "ffmpeg"
     // In sync context, we can't use the full async detection
+    if let Some(path) = crate::whisper::ffmpeg_override() {
+        return path;
+    }
     if let Ok(ffmpeg_path) = std::env::var("FFMPEG_PATH") {
         eprintln!("DEBUG: FFMPEG_PATH set to {}", ffmpeg_path);
         let path = std::path::Path::new(&ffmpeg_path);
@@ -75,7 +78,7 @@ pub fn get_ffmpeg_path_sync() -> String {
 
 /// Get the fonts directory path for subtitle rendering
 /// Returns None if fonts directory cannot be found (libass will use system fonts)
-fn _get_fonts_dir() -> Option<std::path::PathBuf> {
+pub fn get_fonts_dir() -> Option<std::path::PathBuf> {
     // Priority 1: Development environment
     let dev_fonts = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/fonts");
     if dev_fonts.exists() && dev_fonts.is_dir() {
@@ -176,19 +179,23 @@ pub fn canvas_no_downscale(src_w: u32, src_h: u32, ar: TargetAR) -> (u32, u32) {
     (out_w, out_h)
 }
 
+/// Offset that centers `inner` in `outer`, floored to an even pixel so 4:2:0 chroma
+/// stays aligned (at most one pixel off center). 0 when `inner` doesn't fit.
+pub fn center_offset_even(outer: u32, inner: u32) -> u32 {
+    (outer.saturating_sub(inner) / 2) & !1
+}
+
 /// Build a vf that keeps full source, centers it, and pads to target canvas.
 /// NOTE: No scaling! (video stays native pixels)
 fn vf_fit_pad_no_scale(src_w: u32, src_h: u32, ar: TargetAR, pad_color: &str) -> String {
     let (out_w, out_h) = canvas_no_downscale(src_w, src_h, ar);
     // center the source inside the canvas
-    let x = (out_w as i32 - src_w as i32) / 2;
-    let y = (out_h as i32 - src_h as i32) / 2;
     format!(
         "pad={}:{}:{}:{}:{}",
         out_w,
         out_h,
-        x.max(0),
-        y.max(0),
+        center_offset_even(out_w, src_w),
+        center_offset_even(out_h, src_h),
         pad_color
     )
 }
@@ -207,6 +214,182 @@ fn maybe_scale_to_standard(ar: TargetAR, want_standard: bool) -> Option<(u32, u3
     }
 }
 
+/// Output canvas for an aspect ratio. `output_size` presets ("720p", "1080p",
+/// "4k"/"2160p") pin the short side; anything else keeps the source pixels unscaled.
+pub fn export_dimensions(
+    ar: TargetAR,
+    output_size: Option<&str>,
+    src_w: u32,
+    src_h: u32,
+) -> (u32, u32) {
+    let short_side = match output_size {
+        Some("720p") => 720,
+        Some("1080p") => 1080,
+        // 4K usually refers to 3840x2160 (UHD)
+        Some("4k") | Some("2160p") => 2160,
+        _ => return canvas_no_downscale(src_w, src_h, ar),
+    };
+    let (base_w, base_h) = ar_wh(ar);
+    let ratio = base_w as f64 / base_h as f64;
+    // Landscape pins the height, portrait and square pin the width
+    if base_w > base_h {
+        let w = (short_side as f64 * ratio).round() as u32;
+        (round_even(w), short_side)
+    } else {
+        let h = (short_side as f64 / ratio).round() as u32;
+        (short_side, round_even(h))
+    }
+}
+
+/// Shrink `target` so its short side is no larger than the source's, keeping the
+/// aspect ratio. Returns None when the target doesn't upscale.
+pub fn clamp_to_source(target: (u32, u32), src: (u32, u32)) -> Option<(u32, u32)> {
+    let target_short = target.0.min(target.1);
+    let src_short = src.0.min(src.1);
+    if src_short == 0 || target_short <= src_short {
+        return None;
+    }
+    let scale = src_short as f64 / target_short as f64;
+    // Round down to even so the clamp never lands above the source
+    let shrink = |x: u32| (((x as f64 * scale).floor() as u32) & !1).max(2);
+    Some((shrink(target.0), shrink(target.1)))
+}
+
+/// Aspect ratios accepted by `parse_target_ar`, in UI order
+pub const EXPORT_FORMATS: [&str; 4] = ["9:16", "16:9", "1:1", "4:5"];
+/// `output_size` presets understood by `export_dimensions` ("2160p" is an alias of "4k")
+pub const OUTPUT_SIZES: [&str; 3] = ["720p", "1080p", "4k"];
+/// Crop strategies every format supports; "fit" letterboxes, "fill" center-crops
+pub const CROP_STRATEGIES: [&str; 2] = ["fit", "fill"];
+
+/// Every export format with the canvas it resolves to at each output size, so the
+/// UI doesn't keep its own copy of the sizing rules.
+pub fn list_export_formats(
+    params: &crate::types::ListExportFormatsParams,
+) -> anyhow::Result<crate::types::ListExportFormatsResult> {
+    use crate::types::{ExportFormatInfo, ExportSize, ListExportFormatsResult};
+    let source = match (params.source_width, params.source_height) {
+        (Some(w), Some(h)) if w > 0 && h > 0 => Some((w, h)),
+        (None, None) => None,
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Pass both sourceWidth and sourceHeight (non-zero), or neither"
+            ))
+        }
+    };
+
+    let mut formats = Vec::new();
+    for format in EXPORT_FORMATS {
+        let ar = parse_target_ar(format)?;
+        let mut sizes: Vec<ExportSize> = OUTPUT_SIZES
+            .iter()
+            .map(|size| {
+                let (width, height) = export_dimensions(ar, Some(size), 0, 0);
+                ExportSize {
+                    output_size: size.to_string(),
+                    width,
+                    height,
+                }
+            })
+            .collect();
+        if let Some((src_w, src_h)) = source {
+            let (width, height) = export_dimensions(ar, None, src_w, src_h);
+            sizes.push(ExportSize {
+                output_size: "source".into(),
+                width,
+                height,
+            });
+        }
+        formats.push(ExportFormatInfo {
+            format: format.into(),
+            supports_fit: true, // fit pads, so it works for any source/target pair
+            crop_strategies: CROP_STRATEGIES.iter().map(|s| s.to_string()).collect(),
+            sizes,
+        });
+    }
+    Ok(ListExportFormatsResult { formats })
+}
+
+/// Export presets for the platforms captions are usually made for. Vertical platforms put
+/// captions in the middle, above the like/comment buttons and description overlay.
+pub fn platform_presets() -> Vec<crate::types::PlatformPreset> {
+    // Safe areas are (top, bottom, sides) in percent, clear of each app's overlays
+    let preset = |id: &str,
+                  name: &str,
+                  format: &str,
+                  crop: &str,
+                  position: &str,
+                  bitrate: &str,
+                  (top, bottom, x): (f32, f32, f32)| {
+        crate::types::PlatformPreset {
+            id: id.into(),
+            name: name.into(),
+            export_formats: vec![format.into()],
+            output_size: "1080p".into(),
+            crop_strategy: crop.into(),
+            position: position.into(),
+            video_bitrate: bitrate.into(),
+            safe_margin_top_pct: top,
+            safe_margin_bottom_pct: bottom,
+            safe_margin_x_pct: x,
+        }
+    };
+    vec![
+        preset(
+            "tiktok",
+            "TikTok",
+            "9:16",
+            "fill",
+            "center",
+            "6M",
+            (10.0, 20.0, 12.0),
+        ),
+        preset(
+            "reels",
+            "Instagram Reels",
+            "9:16",
+            "fill",
+            "center",
+            "5M",
+            (12.0, 22.0, 10.0),
+        ),
+        preset(
+            "shorts",
+            "YouTube Shorts",
+            "9:16",
+            "fill",
+            "center",
+            "8M",
+            (10.0, 20.0, 12.0),
+        ),
+        preset(
+            "youtube",
+            "YouTube",
+            "16:9",
+            "fit",
+            "bottom",
+            "8M",
+            (5.0, 10.0, 5.0),
+        ),
+    ]
+}
+
+/// Look up a platform preset by id (case-insensitive)
+pub fn platform_preset(name: &str) -> anyhow::Result<crate::types::PlatformPreset> {
+    let presets = platform_presets();
+    let ids: Vec<String> = presets.iter().map(|p| p.id.clone()).collect();
+    presets
+        .into_iter()
+        .find(|p| p.id.eq_ignore_ascii_case(name.trim()))
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unknown preset \"{}\" (expected one of {})",
+                name,
+                ids.join(", ")
+            )
+        })
+}
+
 /// Convert format string to TargetAR enum
 pub fn parse_target_ar(format: &str) -> anyhow::Result<TargetAR> {
     match format {
@@ -261,33 +444,228 @@ pub fn build_fitpad_filter_with_options(
     encoder: HardwareEncoder,
     crop_strategy: &str,
     is_hdr: bool,
+) -> String {
+    build_fitpad_filter_with_fonts(
+        target_w,
+        target_h,
+        subtitle_path,
+        encoder,
+        crop_strategy,
+        is_hdr,
+        get_fonts_dir().as_deref(),
+    )
+}
+
+// Centering offsets for crop/pad, floored to even pixels for 4:2:0 chroma
+const EVEN_CENTER_OFFSETS: &str = "floor(abs(iw-ow)/4)*2:floor(abs(ih-oh)/4)*2";
+
+/// Filter builder with an explicit libass fonts directory (e.g. a resolved font's folder)
+pub fn build_fitpad_filter_with_fonts(
+    target_w: u32,
+    target_h: u32,
+    subtitle_path: Option<&str>,
+    encoder: HardwareEncoder,
+    crop_strategy: &str,
+    is_hdr: bool,
+    fonts_dir: Option<&std::path::Path>,
+) -> String {
+    build_fitpad_filter_with_effects(
+        target_w,
+        target_h,
+        subtitle_path,
+        encoder,
+        crop_strategy,
+        is_hdr,
+        fonts_dir,
+        &FrameEffects::default(),
+    )
+}
+
+/// libass filter burning `subtitle_path`, with fonts also looked up in `fonts_dir`
+pub fn ass_filter(subtitle_path: &str, fonts_dir: Option<&std::path::Path>) -> String {
+    let escaped_path = escape_subtitle_path(subtitle_path);
+
+    // Check for custom fonts directory
+    if let Some(fonts_dir) = fonts_dir {
+        let fonts_path_str = fonts_dir.to_string_lossy().to_string();
+        let escaped_fonts_path = escape_subtitle_path(&fonts_path_str);
+        // Append :fontsdir=... to the ass filter
+        // Note: escape_subtitle_path wraps in single quotes, so we strip them for the param value if needed
+        // but for fontsdir inside the filter string, we need to be careful.
+        // standard syntax: ass='path.ass':fontsdir='fonts_path'
+
+        // Re-escape logic specifically for the filter param structure
+        // We strip the outer quotes from our helper for cleaner composition here
+        let clean_path = escaped_path.trim_matches('\'');
+        let clean_fonts = escaped_fonts_path.trim_matches('\'');
+
+        format!("ass='{}':fontsdir='{}'", clean_path, clean_fonts)
+    } else {
+        format!("ass={}", escaped_path)
+    }
+}
+
+/// Lavfi source for a fully transparent `width`x`height` canvas at frame rate `rate`
+/// (e.g. `30` or `30000/1001`), `duration_sec` long, to draw captions on without any
+/// video (use with `-f lavfi -i`)
+pub fn transparent_canvas(width: u32, height: u32, rate: &str, duration_sec: f64) -> String {
+    format!(
+        "color=c=black@0.0:s={}x{}:r={}:d={:.3},format=rgba",
+        width, height, rate, duration_sec
+    )
+}
+
+/// Video codecs that keep an alpha channel, for caption-only overlay exports.
+///
+/// Alpha only survives in some codec/container pairs:
+/// - ProRes 4444 in QuickTime (`.mov`): imported with transparency by Premiere Pro,
+///   DaVinci Resolve, Final Cut Pro and After Effects. Large files, edit-friendly.
+/// - VP9 in WebM (`.webm`): small files, transparent in browsers, Resolve and
+///   After Effects; Premiere needs a WebM plugin.
+///
+/// H.264 and HEVC in MP4 have no alpha, so overlays can't use the normal export path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaCodec {
+    ProRes4444,
+    Vp9,
+}
+
+impl AlphaCodec {
+    /// From the `overlayCodec` option: "prores" (default) or "vp9"
+    pub fn parse(name: Option<&str>) -> anyhow::Result<Self> {
+        match name.unwrap_or("prores") {
+            "prores" => Ok(AlphaCodec::ProRes4444),
+            "vp9" => Ok(AlphaCodec::Vp9),
+            other => Err(anyhow::anyhow!(
+                "Unsupported overlay codec '{}': use prores or vp9",
+                other
+            )),
+        }
+    }
+
+    /// Container extension that can carry this codec's alpha
+    pub fn extension(self) -> &'static str {
+        match self {
+            AlphaCodec::ProRes4444 => "mov",
+            AlphaCodec::Vp9 => "webm",
+        }
+    }
+
+    /// Encoder arguments, with a pixel format that has an alpha plane
+    pub fn encoder_args(self) -> &'static [&'static str] {
+        match self {
+            AlphaCodec::ProRes4444 => &[
+                "-c:v",
+                "prores_ks",
+                "-profile:v",
+                "4444",
+                "-pix_fmt",
+                "yuva444p10le",
+                "-alpha_bits",
+                "16",
+                "-vendor",
+                "apl0",
+            ],
+            // libvpx drops alpha when alt-ref frames are on
+            AlphaCodec::Vp9 => &[
+                "-c:v",
+                "libvpx-vp9",
+                "-pix_fmt",
+                "yuva420p",
+                "-crf",
+                "30",
+                "-b:v",
+                "0",
+                "-row-mt",
+                "1",
+                "-auto-alt-ref",
+                "0",
+            ],
+        }
+    }
+}
+
+/// Captions alone on a [`transparent_canvas`]: the canvas timestamps are moved to
+/// start at `start_sec` on the caption timeline, and libass writes its coverage into
+/// the alpha channel so the result composites over other footage
+pub fn caption_overlay_filter(
+    subtitle_path: &str,
+    fonts_dir: Option<&std::path::Path>,
+    start_sec: f64,
+) -> String {
+    format!(
+        "setpts=PTS+{:.3}/TB,{}:alpha=1",
+        start_sec,
+        ass_filter(subtitle_path, fonts_dir)
+    )
+}
+
+/// Extra processing around the caption burn: frozen-frame holds and graphics
+/// composited onto the scaled frame, below the captions
+#[derive(Default)]
+pub struct FrameEffects<'a> {
+    /// Freeze the first frame this long before playback starts (tpad)
+    pub intro_hold_ms: u64,
+    /// Freeze the last frame this long after playback ends (tpad)
+    pub outro_hold_ms: u64,
+    pub watermark: Option<&'a crate::types::WatermarkSpec>,
+    /// Progress bar, with its ffmpeg color (`0xRRGGBB@alpha`) and the video duration
+    /// in seconds it fills over
+    pub progress_bar: Option<(&'a crate::types::ProgressBarSpec, String, f64)>,
+}
+
+/// As [`build_fitpad_filter_with_fonts`], applying `effects`: holds are padded on first,
+/// then overlays are composited before the captions are drawn. With overlays the result
+/// is a small graph (`movie` / `color` sources plus `overlay`s) rather than a plain
+/// chain, still usable with `-vf`.
+#[allow(clippy::too_many_arguments)]
+pub fn build_fitpad_filter_with_effects(
+    target_w: u32,
+    target_h: u32,
+    subtitle_path: Option<&str>,
+    encoder: HardwareEncoder,
+    crop_strategy: &str,
+    is_hdr: bool,
+    fonts_dir: Option<&std::path::Path>,
+    effects: &FrameEffects,
 ) -> String {
     let mut filters = Vec::new();
 
+    // 0. Frozen-frame holds, so everything after (logo, bar, captions) covers them too
+    if effects.intro_hold_ms > 0 || effects.outro_hold_ms > 0 {
+        filters.push(format!(
+            "tpad=start_duration={:.3}:start_mode=clone:stop_duration={:.3}:stop_mode=clone",
+            effects.intro_hold_ms as f64 / 1000.0,
+            effects.outro_hold_ms as f64 / 1000.0
+        ));
+    }
+
     // 1. Scaling Strategy
     if crop_strategy == "fill" {
         // "Fill" / "Center Crop" strategy:
         // Scale input so it COVERS the target area (keeping aspect ratio), then crop the center.
         // Formula: scale=w=TARGET_W:h=TARGET_H:force_original_aspect_ratio=increase,crop=TARGET_W:TARGET_H
+        // force_divisible_by keeps the scaled frame even (e.g. 1366x768 -> 4:5 would be 2401 wide)
+        // and the offsets are floored to even pixels so chroma rows stay aligned.
         filters.push(format!(
-            "scale=w={}:h={}:force_original_aspect_ratio=increase",
+            "scale=w={}:h={}:force_original_aspect_ratio=increase:force_divisible_by=2",
             target_w, target_h
         ));
         filters.push(format!(
-            "crop={}:{}:(iw-ow)/2:(ih-oh)/2",
-            target_w, target_h
+            "crop={}:{}:{}",
+            target_w, target_h, EVEN_CENTER_OFFSETS
         ));
     } else {
         // "Fit" / "Letterbox" strategy (default):
         // Scale input so it FITS within the target area (keeping aspect ratio), then pad with black bars.
         // Formula: scale=w=TARGET_W:h=TARGET_H:force_original_aspect_ratio=decrease,pad=TARGET_W:TARGET_H:(ow-iw)/2:(oh-ih)/2:black
         filters.push(format!(
-            "scale={}:{}:flags=lanczos:force_original_aspect_ratio=decrease",
+            "scale={}:{}:flags=lanczos:force_original_aspect_ratio=decrease:force_divisible_by=2",
             target_w, target_h
         ));
         filters.push(format!(
-            "pad={}:{}:(ow-iw)/2:(oh-ih)/2:black",
-            target_w, target_h
+            "pad={}:{}:{}:black",
+            target_w, target_h, EVEN_CENTER_OFFSETS
         ));
     }
 
@@ -306,30 +684,13 @@ pub fn build_fitpad_filter_with_options(
     // For SDR content, we do NOTHING (preserving original colors)
     // The previous code was unconditionally applying tonemap which washed out SDR colors
 
+    // Everything above works on the source frame; the overlays go on top of it, below
+    // the captions
+    let base_len = filters.len();
+
     // 3. Subtitles
     if let Some(path) = subtitle_path {
-        let escaped_path = escape_subtitle_path(path);
-
-        // Check for custom fonts directory
-        let fonts_dir_opt = _get_fonts_dir();
-
-        if let Some(fonts_dir) = fonts_dir_opt {
-            let fonts_path_str = fonts_dir.to_string_lossy().to_string();
-            let escaped_fonts_path = escape_subtitle_path(&fonts_path_str);
-            // Append :fontsdir=... to the ass filter
-            // Note: escape_subtitle_path wraps in single quotes, so we strip them for the param value if needed
-            // but for fontsdir inside the filter string, we need to be careful.
-            // standard syntax: ass='path.ass':fontsdir='fonts_path'
-
-            // Re-escape logic specifically for the filter param structure
-            // We strip the outer quotes from our helper for cleaner composition here
-            let clean_path = escaped_path.trim_matches('\'');
-            let clean_fonts = escaped_fonts_path.trim_matches('\'');
-
-            filters.push(format!("ass='{}':fontsdir='{}'", clean_path, clean_fonts));
-        } else {
-            filters.push(format!("ass={}", escaped_path));
-        }
+        filters.push(ass_filter(path, fonts_dir));
     }
 
     // 3. Encoder-specific format optimization
@@ -348,7 +709,143 @@ pub fn build_fitpad_filter_with_options(
         }
     }
 
-    filters.join(",")
+    // (source chain, overlay filter) per overlay, stacked in order
+    let mut layers = Vec::new();
+    if let Some(spec) = effects.watermark {
+        layers.push(watermark_filters(spec, target_w, target_h));
+    }
+    if let Some((spec, color, duration)) = &effects.progress_bar {
+        layers.push(progress_bar_filters(
+            spec, color, *duration, target_w, target_h,
+        ));
+    }
+    if layers.is_empty() {
+        return filters.join(",");
+    }
+
+    let post = filters.split_off(base_len);
+    let mut graph = Vec::new();
+    for (i, (source, _)) in layers.iter().enumerate() {
+        graph.push(format!("{}[ov{}]", source, i));
+    }
+    let mut chain = format!("[in]{}[base0]", filters.join(","));
+    for (i, (_, overlay)) in layers.iter().enumerate() {
+        chain.push_str(&format!(";[base{}][ov{}]{}", i, i, overlay));
+        if i + 1 < layers.len() {
+            chain.push_str(&format!("[base{}]", i + 1));
+        }
+    }
+    graph.push(format!("{},{}[out]", chain, post.join(",")));
+    graph.join(";")
+}
+
+/// A full-width bar slid in from the left by `overlay`, whose x is re-evaluated every
+/// frame from the playback time `t` (`drawbox` can't see time, so it can't animate)
+fn progress_bar_filters(
+    spec: &crate::types::ProgressBarSpec,
+    color: &str,
+    duration: f64,
+    target_w: u32,
+    target_h: u32,
+) -> (String, String) {
+    let bar_h = round_even((spec.height * target_h as f32).round().max(2.0) as u32);
+    let source = format!("color=c={}:s={}x{},format=rgba", color, target_w, bar_h);
+    let y = match spec.position.as_deref() {
+        Some("top") => "0",
+        _ => "main_h-overlay_h",
+    };
+    let overlay = format!(
+        "overlay=x='-overlay_w+overlay_w*min(t/{:.3},1)':y={}:shortest=1",
+        duration.max(0.001),
+        y
+    );
+    (source, overlay)
+}
+
+/// The logo source chain (scaled, with opacity folded into its alpha) and the
+/// `overlay` that places it, both in output pixels
+fn watermark_filters(
+    spec: &crate::types::WatermarkSpec,
+    target_w: u32,
+    target_h: u32,
+) -> (String, String) {
+    let logo_w = round_even((spec.scale * target_w as f32).round().max(2.0) as u32);
+    let mx = (spec.margin_x * target_w as f32).round() as u32;
+    let my = (spec.margin_y * target_h as f32).round() as u32;
+    let mut source = format!(
+        "movie={},scale={}:-2,format=rgba",
+        escape_subtitle_path(&spec.path),
+        logo_w
+    );
+    if spec.opacity < 1.0 {
+        source.push_str(&format!(
+            ",colorchannelmixer=aa={:.3}",
+            spec.opacity.max(0.0)
+        ));
+    }
+    let (x, y) = match spec.position.as_deref().unwrap_or("bottom-right") {
+        "top-left" => (mx.to_string(), my.to_string()),
+        "top-right" => (format!("main_w-overlay_w-{}", mx), my.to_string()),
+        "bottom-left" => (mx.to_string(), format!("main_h-overlay_h-{}", my)),
+        "center" => (
+            "(main_w-overlay_w)/2".to_string(),
+            "(main_h-overlay_h)/2".to_string(),
+        ),
+        _ => (
+            format!("main_w-overlay_w-{}", mx),
+            format!("main_h-overlay_h-{}", my),
+        ),
+    };
+    (source, format!("overlay={}:{}", x, y))
+}
+
+/// Positions accepted by [`crate::types::WatermarkSpec::position`]
+pub const WATERMARK_POSITIONS: &[&str] = &[
+    "top-left",
+    "top-right",
+    "bottom-left",
+    "bottom-right",
+    "center",
+];
+
+/// Audio filter matching frozen-frame holds: silence before (delay) and after (pad)
+pub fn hold_audio_filter(intro_hold_ms: u64, outro_hold_ms: u64) -> Option<String> {
+    let mut filters = Vec::new();
+    if intro_hold_ms > 0 {
+        filters.push(format!("adelay={}:all=1", intro_hold_ms));
+    }
+    if outro_hold_ms > 0 {
+        filters.push(format!("apad=pad_dur={:.3}", outro_hold_ms as f64 / 1000.0));
+    }
+    (!filters.is_empty()).then(|| filters.join(","))
+}
+
+/// Input options that cut the source to `[start_ms, end_ms)` before decoding. They go
+/// before `-i`, so output timestamps start at 0 at the trim start.
+pub fn trim_input_args(start_ms: Option<u64>, end_ms: Option<u64>) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(start) = start_ms.filter(|&ms| ms > 0) {
+        args.extend(["-ss".to_string(), format!("{:.3}", start as f64 / 1000.0)]);
+    }
+    if let Some(end) = end_ms {
+        args.extend(["-to".to_string(), format!("{:.3}", end as f64 / 1000.0)]);
+    }
+    args
+}
+
+/// Length in seconds of the trimmed range, capped at the source duration
+pub fn trimmed_duration(
+    duration: Option<f64>,
+    start_ms: Option<u64>,
+    end_ms: Option<u64>,
+) -> Option<f64> {
+    let end = match (duration, end_ms) {
+        (Some(d), Some(end)) => d.min(end as f64 / 1000.0),
+        (Some(d), None) => d,
+        (None, Some(end)) => end as f64 / 1000.0,
+        (None, None) => return None,
+    };
+    Some((end - start_ms.unwrap_or(0) as f64 / 1000.0).max(0.0))
 }
 
 /// Determine the best audio codec and settings based on input analysis
@@ -484,14 +981,157 @@ pub async fn is_libass_available() -> bool {
 
     match result {
         Ok(output) => {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            // Look for 'ass' or 'subtitles' filter
-            stdout.contains(" ass ")
+            parse_ffmpeg_filters(&String::from_utf8_lossy(&output.stdout)).contains("ass")
         }
         Err(_) => false,
     }
 }
 
+/// Version from the first line of `ffmpeg -version`: "ffmpeg version 8.0.2 ..."
+pub fn parse_ffmpeg_version(output: &str) -> Option<String> {
+    output.lines().next().and_then(|line| {
+        // Split by whitespace and find "version" then get next word
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let pos = words.iter().position(|&word| word == "version")?;
+        words.get(pos + 1).map(|v| v.to_string())
+    })
+}
+
+/// External libraries enabled in `ffmpeg -buildconf` ("--enable-libass" gives "libass")
+pub fn parse_ffmpeg_libraries(buildconf: &str) -> Vec<String> {
+    buildconf
+        .split_whitespace()
+        .filter_map(|flag| flag.strip_prefix("--enable-"))
+        .filter(|name| name.starts_with("lib"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Encoders every export needs: software H.264 (also the hardware fallback) and AAC
+const REQUIRED_ENCODERS: [&str; 2] = ["libx264", "aac"];
+/// Hardware encoders picked automatically when present
+const HARDWARE_ENCODERS: [&str; 2] = ["h264_videotoolbox", "h264_nvenc"];
+/// Filters the pipeline builds graphs from, and whether a missing one breaks every burn
+const PIPELINE_FILTERS: [(&str, bool); 10] = [
+    ("ass", true),
+    ("scale", true),
+    ("pad", true),
+    ("crop", true),
+    ("overlay", false),  // watermark, progress bar
+    ("tpad", false),     // intro/outro holds
+    ("movie", false),    // watermark source
+    ("zscale", false),   // HDR tone mapping
+    ("loudnorm", false), // normalizeAudio
+    ("afftdn", false),   // denoiseAudio
+];
+
+/// Encoder names from `ffmpeg -encoders` (lines like ` V....D libx264  libx264 H.264 ...`)
+pub fn parse_ffmpeg_encoders(output: &str) -> std::collections::HashSet<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut tokens = line.split_whitespace();
+            let (flags, name) = (tokens.next()?, tokens.next()?);
+            let is_flags = flags.len() == 6 && flags.chars().all(|c| "VASFXBD.".contains(c));
+            (is_flags && name != "=").then(|| name.to_string())
+        })
+        .collect()
+}
+
+/// Filter names from `ffmpeg -filters` (lines like ` ... ass  V->V  Render ASS subtitles`)
+pub fn parse_ffmpeg_filters(output: &str) -> std::collections::HashSet<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let tokens: Vec<&str> = line.split_whitespace().take(3).collect();
+            match tokens[..] {
+                [_, name, io] if io.contains("->") => Some(name.to_string()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Report what the ffmpeg in use can do: subtitle burning, the encoders the pipeline
+/// picks from (plus any extra ones asked for) and the filters it builds graphs from
+pub async fn check_ffmpeg_capabilities(
+    params: &crate::types::CheckFfmpegCapabilitiesParams,
+) -> anyhow::Result<crate::types::FfmpegCapabilities> {
+    use crate::types::{FfmpegCapabilities, FfmpegFeature};
+    let ffmpeg_path = find_ffmpeg_binary()
+        .await
+        .map_err(|e| anyhow::anyhow!("FFmpeg not found: {}", e))?;
+    let run = |arg: &'static str| {
+        let ffmpeg_path = ffmpeg_path.clone();
+        async move {
+            // Some builds log -buildconf to stderr, so read both
+            TokioCommand::new(&ffmpeg_path)
+                .args(["-hide_banner", arg])
+                .output()
+                .await
+                .map(|o| {
+                    let mut text = String::from_utf8_lossy(&o.stdout).into_owned();
+                    text.push_str(&String::from_utf8_lossy(&o.stderr));
+                    text
+                })
+                .map_err(|e| anyhow::anyhow!("Failed to run {} {}: {}", ffmpeg_path, arg, e))
+        }
+    };
+    let version = parse_ffmpeg_version(&run("-version").await?);
+    let encoders = parse_ffmpeg_encoders(&run("-encoders").await?);
+    let filters = parse_ffmpeg_filters(&run("-filters").await?);
+    let libraries = parse_ffmpeg_libraries(&run("-buildconf").await?);
+
+    let mut missing = Vec::new();
+    let mut encoder_names: Vec<&str> = REQUIRED_ENCODERS.to_vec();
+    for name in HARDWARE_ENCODERS
+        .into_iter()
+        .chain(params.encoders.iter().map(String::as_str))
+    {
+        if !encoder_names.contains(&name) {
+            encoder_names.push(name);
+        }
+    }
+    let encoder_features = encoder_names
+        .iter()
+        .map(|&name| FfmpegFeature {
+            name: name.to_string(),
+            available: encoders.contains(name),
+        })
+        .collect::<Vec<_>>();
+    for feature in &encoder_features {
+        let required = REQUIRED_ENCODERS.contains(&feature.name.as_str())
+            || params.encoders.contains(&feature.name);
+        if required && !feature.available {
+            missing.push(format!("encoder {}", feature.name));
+        }
+    }
+    let filter_features = PIPELINE_FILTERS
+        .iter()
+        .map(|&(name, required)| {
+            let available = filters.contains(name);
+            if required && !available {
+                missing.push(format!("filter {}", name));
+            }
+            FfmpegFeature {
+                name: name.to_string(),
+                available,
+            }
+        })
+        .collect();
+
+    Ok(FfmpegCapabilities {
+        version,
+        // The ass filter only exists in builds configured with libass
+        subtitles: filters.contains("ass"),
+        libraries,
+        encoders: encoder_features,
+        filters: filter_features,
+        missing,
+        ffmpeg_path,
+    })
+}
+
 /// Get FFmpeg version to check if it's 8.0+ for Whisper support
 pub async fn get_ffmpeg_version() -> Option<String> {
     let result = Command::new(get_ffmpeg_path_sync())
@@ -499,21 +1139,8 @@ pub async fn get_ffmpeg_version() -> Option<String> {
         .output();
 
     match result {
-        Ok(output) => {
-            // FFmpeg -version outputs to stdout (not stderr like other commands)
-            let stdout = String::from_utf8_lossy(&output.stdout);
-
-            // Extract version from first line: "ffmpeg version 8.0.2 ..."
-            stdout.lines().next().and_then(|line| {
-                // Split by whitespace and find "version" then get next word
-                let words: Vec<&str> = line.split_whitespace().collect();
-                if let Some(pos) = words.iter().position(|&word| word == "version") {
-                    words.get(pos + 1).map(|v| v.to_string())
-                } else {
-                    None
-                }
-            })
-        }
+        // FFmpeg -version outputs to stdout (not stderr like other commands)
+        Ok(output) => parse_ffmpeg_version(&String::from_utf8_lossy(&output.stdout)),
         Err(_) => None,
     }
 }
@@ -719,6 +1346,8 @@ pub struct ExportParams {
     pub format: Option<String>, // Aspect ratio format ("16:9", "9:16", "1:1", "4:5")
     pub use_standard_sizes: Option<bool>, // Whether to scale to standard social media sizes after padding
     pub out: String,                      // Path for output video
+    #[serde(flatten)]
+    pub metadata: crate::types::MetadataOptions, // Container tags to keep, strip or set
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -735,6 +1364,18 @@ pub struct ProbeResult {
     pub color_space: Option<String>,     // Color space (e.g. "bt2020nc")
     pub color_transfer: Option<String>, // Color transfer characteristics (e.g. "smpte2084" for PQ, "arib-std-b67" for HLG)
     pub color_primaries: Option<String>, // Color primaries (e.g. "bt2020")
+    #[serde(default)]
+    pub video_codec: Option<String>, // Video codec name (e.g., "h264", "hevc")
+    #[serde(default)]
+    pub bitrate: Option<i64>, // Overall container bitrate in bits/sec
+    #[serde(default)]
+    pub rotation: i32, // Clockwise display rotation in degrees: 0, 90, 180 or 270
+    #[serde(default)]
+    pub pix_fmt: Option<String>, // Video pixel format (e.g. "yuv420p", "yuv420p10le")
+    #[serde(default)]
+    pub sample_rate: Option<u32>, // Audio sample rate in Hz (e.g. 48000)
+    #[serde(default)]
+    pub channels: Option<u32>, // Audio channel count (e.g. 2 for stereo)
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -767,6 +1408,113 @@ fn detect_content_type(probe_result: Option<&ProbeResult>) -> &'static str {
     "film" // Default to film tuning for live-action
 }
 
+/// ffmpeg flags for the container tags: copy them from input 0, leave them out (ffmpeg
+/// would otherwise copy the global ones anyway), or drop everything including chapters
+/// and the encoder tag (privacy mode), then apply any custom title/comment.
+pub fn metadata_args(opts: &crate::types::MetadataOptions) -> Vec<String> {
+    let mut args: Vec<String> = Vec::new();
+    if opts.strip_metadata {
+        // Global, per-stream and chapter tags all go, including GPS and device info
+        args.extend(["-map_metadata", "-1", "-map_chapters", "-1"].map(String::from));
+        args.extend(["-fflags", "+bitexact"].map(String::from));
+    } else if opts.copy_metadata {
+        args.extend(["-map_metadata", "0"].map(String::from));
+    } else {
+        args.extend(["-map_metadata", "-1"].map(String::from));
+    }
+    for (key, value) in [
+        ("title", &opts.metadata_title),
+        ("comment", &opts.metadata_comment),
+    ] {
+        if let Some(value) = value {
+            args.push("-metadata".into());
+            args.push(format!("{}={}", key, value));
+        }
+    }
+    args
+}
+
+/// How much of ffmpeg's stderr goes into an error message
+pub const STDERR_TAIL_LINES: usize = 15;
+
+// Add a chunk of stderr to the tail. Progress stats (rewritten in place with \r) say
+// nothing about a failure, so they are skipped.
+fn push_stderr_lines(tail: &mut std::collections::VecDeque<String>, chunk: &str) {
+    for line in chunk.split(['\r', '\n']).map(str::trim_end) {
+        if line.trim().is_empty() || line.starts_with("frame=") || line.starts_with("size=") {
+            continue;
+        }
+        if tail.len() == STDERR_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(line.to_string());
+    }
+}
+
+/// The last lines of captured ffmpeg stderr
+pub fn stderr_tail(stderr: &[u8]) -> Vec<String> {
+    let mut tail = std::collections::VecDeque::new();
+    push_stderr_lines(&mut tail, &String::from_utf8_lossy(stderr));
+    tail.into()
+}
+
+/// Drain a child's piped stderr in the background, keeping the last lines for an error
+/// message. Reading as it comes also keeps a full pipe from stalling ffmpeg.
+pub fn collect_stderr_tail(
+    child: &mut tokio::process::Child,
+) -> tokio::task::JoinHandle<Vec<String>> {
+    let stderr = child.stderr.take();
+    tokio::spawn(async move {
+        let mut tail = std::collections::VecDeque::new();
+        if let Some(stderr) = stderr {
+            let mut chunks = tokio::io::BufReader::new(stderr).split(b'\n');
+            while let Ok(Some(chunk)) = chunks.next_segment().await {
+                push_stderr_lines(&mut tail, &String::from_utf8_lossy(&chunk));
+            }
+        }
+        tail.into()
+    })
+}
+
+/// An ffmpeg failure message followed by the stderr lines that explain it
+pub fn with_stderr_tail(message: impl std::fmt::Display, tail: &[String]) -> String {
+    if tail.is_empty() {
+        message.to_string()
+    } else {
+        format!("{}. ffmpeg said:\n{}", message, tail.join("\n"))
+    }
+}
+
+/// Log the stderr tail of a failed ffmpeg run and build the error that carries it
+pub fn ffmpeg_failure(
+    id: &str,
+    message: impl std::fmt::Display,
+    tail: &[String],
+    emit: &mut impl FnMut(RpcEvent),
+) -> anyhow::Error {
+    if !tail.is_empty() {
+        emit(RpcEvent::Log {
+            id: id.into(),
+            message: format!(
+                "ffmpeg stderr (last {} lines):\n{}",
+                tail.len(),
+                tail.join("\n")
+            ),
+        });
+    }
+    anyhow::anyhow!(with_stderr_tail(message, tail))
+}
+
+/// MP4/MOV only write well-known keys unless asked; copying needs
+/// use_metadata_tags so tags like com.apple.quicktime.location survive.
+pub fn movflags(opts: &crate::types::MetadataOptions) -> &'static str {
+    if opts.copy_metadata && !opts.strip_metadata {
+        "+faststart+use_metadata_tags"
+    } else {
+        "+faststart"
+    }
+}
+
 pub async fn export_video(
     id: &str,
     p: ExportParams,
@@ -808,7 +1556,12 @@ pub async fn export_video(
 
     // Check for explicit width/height first (legacy/exact mode)
     if let (Some(width), Some(height)) = (p.width, p.height) {
-        // exact dimensions specified - use old behavior for backward compatibility
+        // exact dimensions specified - use old behavior for backward compatibility.
+        // h264/yuv420p rejects odd sizes, so round up to even.
+        let (width, height) = (
+            round_even(width.max(2) as u32),
+            round_even(height.max(2) as u32),
+        );
         let filter = format!(
             "scale={}:{}:force_original_aspect_ratio=decrease,pad={}:{}:(ow-iw)/2:(oh-ih)/2:black",
             width, height, width, height
@@ -822,11 +1575,8 @@ pub async fn export_video(
     } else if let Some(format) = &p.format {
         // New high-quality aspect ratio conversion
         if let Some(probe_result) = &pr {
-            if let (Some(orig_width), Some(orig_height)) = (probe_result.width, probe_result.height)
-            {
+            if let Some((src_w, src_h)) = probe_result.display_dimensions() {
                 let target_ar = parse_target_ar(format)?;
-                let src_w = orig_width as u32;
-                let src_h = orig_height as u32;
 
                 // Build pad filter (no scaling)
                 let pad_filter = vf_fit_pad_no_scale(src_w, src_h, target_ar, "black");
@@ -958,14 +1708,13 @@ pub async fn export_video(
         cmd.arg(arg); // Additional audio encoding args
     }
 
-    cmd.arg("-map_metadata")
-        .arg("0") // Copy timing/metadata (colors, primaries, etc.)
+    cmd.args(metadata_args(&p.metadata))
         .arg("-map")
         .arg("0:v:0") // Map first video stream
         .arg("-map")
         .arg("0:a?") // Map audio if present (? makes it optional)
         .arg("-movflags")
-        .arg("+faststart") // Fast start for web playback
+        .arg(movflags(&p.metadata)) // Fast start for web playback
         .arg(&p.out);
 
     let encoder_info = match hardware_encoder {
@@ -977,7 +1726,7 @@ pub async fn export_video(
     cmd.arg("-progress").arg("pipe:1");
 
     cmd.stdout(std::process::Stdio::piped());
-    cmd.stderr(std::process::Stdio::inherit());
+    cmd.stderr(std::process::Stdio::piped());
 
     emit(RpcEvent::Log {
         id: id.into(),
@@ -1004,7 +1753,9 @@ pub async fn export_video(
         progress: 0.0,
     });
 
+    crate::logging::log_command(&cmd);
     let mut child = cmd.spawn()?;
+    let stderr_tail = collect_stderr_tail(&mut child);
 
     // Handle stdout for progress
     if let Some(stdout) = child.stdout.take() {
@@ -1038,14 +1789,18 @@ pub async fn export_video(
     }
 
     let status = child.wait().await?;
+    let tail = stderr_tail.await.unwrap_or_default();
 
     // If hardware encoder failed, try falling back to software encoding
     if !status.success() && !matches!(hardware_encoder, HardwareEncoder::Software) {
         emit(RpcEvent::Log {
             id: id.into(),
-            message: format!(
-                "Hardware encoder {} failed, falling back to software encoding (libx264)",
-                encoder_info
+            message: with_stderr_tail(
+                format!(
+                    "Hardware encoder {} failed, falling back to software encoding (libx264)",
+                    encoder_info
+                ),
+                &tail,
             ),
         });
 
@@ -1088,28 +1843,29 @@ pub async fn export_video(
         }
 
         fallback_cmd
-            .arg("-map_metadata")
-            .arg("0")
+            .args(metadata_args(&p.metadata))
             .arg("-map")
             .arg("0:v:0")
             .arg("-map")
             .arg("0:a?")
             .arg("-movflags")
-            .arg("+faststart")
+            .arg(movflags(&p.metadata))
             .arg("-progress")
             .arg("pipe:1") // Enable progress for fallback too
             .arg(&p.out);
 
         fallback_cmd
             .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::inherit());
+            .stderr(std::process::Stdio::piped());
 
         emit(RpcEvent::Log {
             id: id.into(),
             message: "Retrying with software encoder (libx264)...".into(),
         });
 
+        crate::logging::log_command(&fallback_cmd);
         let mut child_fallback = fallback_cmd.spawn()?;
+        let fallback_tail = collect_stderr_tail(&mut child_fallback);
         if let Some(stdout) = child_fallback.stdout.take() {
             let reader = tokio::io::BufReader::new(stdout);
             let mut lines = reader.lines();
@@ -1138,12 +1894,16 @@ pub async fn export_video(
 
         let fallback_status = child_fallback.wait().await?;
         if !fallback_status.success() {
-            return Err(anyhow::anyhow!(
-                "ffmpeg export failed with both hardware and software encoders"
+            let tail = fallback_tail.await.unwrap_or_default();
+            return Err(ffmpeg_failure(
+                id,
+                "ffmpeg export failed with both hardware and software encoders",
+                &tail,
+                &mut emit,
             ));
         }
     } else if !status.success() {
-        return Err(anyhow::anyhow!("ffmpeg export failed"));
+        return Err(ffmpeg_failure(id, "ffmpeg export failed", &tail, &mut emit));
     }
 
     // 100% completion
@@ -1204,6 +1964,7 @@ pub async fn probe(
 
     cmd.kill_on_drop(true);
 
+    crate::logging::log_command(&cmd);
     let child = cmd.spawn()?;
 
     emit(RpcEvent::Log {
@@ -1252,7 +2013,18 @@ pub async fn probe(
 
     // Parse the JSON output from ffprobe
     let v: serde_json::Value = serde_json::from_slice(&out.stdout)?;
+    let result = parse_probe_json(&v);
+
+    emit(RpcEvent::Progress {
+        id: id.into(),
+        status: "Probe complete".into(),
+        progress: 1.0,
+    });
+    Ok(result)
+}
 
+/// Build a ProbeResult from `ffprobe -print_format json -show_streams -show_format` output
+pub fn parse_probe_json(v: &serde_json::Value) -> ProbeResult {
     // Extract duration from format metadata (container level)
     let mut duration = v
         .get("format")
@@ -1260,6 +2032,13 @@ pub async fn probe(
         .and_then(|d| d.as_str())
         .and_then(|s| s.parse::<f64>().ok());
 
+    // Overall bitrate of the container (all streams)
+    let bitrate = v
+        .get("format")
+        .and_then(|f| f.get("bit_rate"))
+        .and_then(|b| b.as_str())
+        .and_then(|s| s.parse::<i64>().ok());
+
     // Initialize stream-specific information
     let mut width = None;
     let mut height = None;
@@ -1267,10 +2046,15 @@ pub async fn probe(
     let mut audio = false;
     let mut video = false;
     let mut audio_codec = None;
+    let mut video_codec = None;
+    let mut rotation = 0;
     let mut audio_bitrate = None;
     let mut color_space = None;
     let mut color_transfer = None;
     let mut color_primaries = None;
+    let mut pix_fmt = None;
+    let mut sample_rate = None;
+    let mut channels = None;
 
     // Analyze each stream in the file
     if let Some(arr) = v.get("streams").and_then(|s| s.as_array()) {
@@ -1279,6 +2063,15 @@ pub async fn probe(
                 match codec_type {
                     "video" => {
                         video = true;
+                        video_codec = st
+                            .get("codec_name")
+                            .and_then(|x| x.as_str())
+                            .map(|s| s.to_string());
+                        rotation = stream_rotation(st);
+                        pix_fmt = st
+                            .get("pix_fmt")
+                            .and_then(|x| x.as_str())
+                            .map(|s| s.to_string());
                         // Extract video dimensions
                         width = st.get("width").and_then(|x| x.as_i64()).map(|x| x as i32);
                         height = st.get("height").and_then(|x| x.as_i64()).map(|x| x as i32);
@@ -1322,6 +2115,15 @@ pub async fn probe(
                             .get("bit_rate")
                             .and_then(|x| x.as_str())
                             .and_then(|s| s.parse::<i32>().ok());
+                        // ffprobe reports the rate as a string, the channel count as a number
+                        sample_rate = st
+                            .get("sample_rate")
+                            .and_then(|x| x.as_str())
+                            .and_then(|s| s.parse::<u32>().ok());
+                        channels = st
+                            .get("channels")
+                            .and_then(|x| x.as_u64())
+                            .map(|x| x as u32);
                     }
                     _ => {} // Ignore other stream types (subtitles, data, etc.)
                 }
@@ -1329,12 +2131,7 @@ pub async fn probe(
         }
     }
 
-    emit(RpcEvent::Progress {
-        id: id.into(),
-        status: "Probe complete".into(),
-        progress: 1.0,
-    });
-    Ok(ProbeResult {
+    ProbeResult {
         duration,
         width,
         height,
@@ -1346,7 +2143,49 @@ pub async fn probe(
         color_space,
         color_transfer,
         color_primaries,
-    })
+        video_codec,
+        bitrate,
+        rotation,
+        pix_fmt,
+        sample_rate,
+        channels,
+    }
+}
+
+/// Clockwise rotation (0, 90, 180 or 270) a player applies to a video stream,
+/// from the display matrix side data or the legacy `rotate` tag
+fn stream_rotation(st: &serde_json::Value) -> i32 {
+    // The display matrix angle is counter-clockwise (phones report -90 for portrait)
+    let from_matrix = st
+        .get("side_data_list")
+        .and_then(|l| l.as_array())
+        .and_then(|l| {
+            l.iter()
+                .find_map(|sd| sd.get("rotation").and_then(|r| r.as_f64()))
+        })
+        .map(|r| -r);
+    let from_tag = st
+        .get("tags")
+        .and_then(|t| t.get("rotate"))
+        .and_then(|r| r.as_str())
+        .and_then(|s| s.parse::<f64>().ok());
+    from_matrix
+        .or(from_tag)
+        .map(|deg| ((deg / 90.0).round() as i32 * 90).rem_euclid(360))
+        .unwrap_or(0)
+}
+
+impl ProbeResult {
+    /// Width and height as the video is shown, after its rotation is applied.
+    /// ffmpeg autorotates decoded frames by default, so filters see these dimensions.
+    pub fn display_dimensions(&self) -> Option<(u32, u32)> {
+        let (w, h) = (self.width? as u32, self.height? as u32);
+        if self.rotation % 180 == 90 {
+            Some((h, w))
+        } else {
+            Some((w, h))
+        }
+    }
 }
 
 /// Check if video is HDR based on probe result
@@ -1389,38 +2228,539 @@ fn parse_fps(s: &str) -> Option<f64> {
 
 /// Extract the first frame of a video as a base64 encoded PNG
 pub fn extract_first_frame(video_path: &str) -> anyhow::Result<String> {
-    let ffmpeg_path = get_ffmpeg_path_sync();
+    use std::io::Read;
+    use std::process::Stdio;
 
-    // Command to extract the first frame
-    // ffmpeg -i input.mp4 -ss 0 -vframes 1 -f image2 -c:v png -
-    let output = Command::new(&ffmpeg_path)
-        .arg("-i")
-        .arg(video_path)
-        .arg("-ss")
-        .arg("0")
-        .arg("-vframes")
-        .arg("1")
-        .arg("-f")
-        .arg("image2")
-        .arg("-c:v")
-        .arg("png") // Use PNG for high quality and transparency support (if applicable)
-        .arg("-") // Output to stdout
-        .output()
+    let ffmpeg_path = get_ffmpeg_path_sync();
+    let mut child = Command::new(&ffmpeg_path)
+        .args(first_frame_args(video_path, "-"))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
         .map_err(|e| anyhow::anyhow!("Failed to run ffmpeg: {}", e))?;
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow::anyhow!(
-            "FFmpeg failed to extract frame: {}",
-            stderr
-        ));
+    // Drain stderr alongside so a chatty ffmpeg can't block on a full pipe
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stderr_reader = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stderr.read_to_end(&mut buf);
+        buf
+    });
+    // Encode while reading, so a 4K frame isn't held both raw and encoded
+    let encoded = png_data_uri(child.stdout.take().expect("stdout is piped"));
+    let status = child.wait()?;
+    let stderr = stderr_reader.join().unwrap_or_default();
+
+    if !status.success() {
+        return Err(anyhow::anyhow!(with_stderr_tail(
+            "FFmpeg failed to extract frame",
+            &stderr_tail(&stderr)
+        )));
+    }
+    Ok(encoded?)
+}
+
+/// Write the first frame of a video to a PNG file (default: a per-video file under the
+/// temp dir, reused by later calls) and return its path
+pub fn extract_first_frame_to_file(video_path: &str, out: Option<&str>) -> anyhow::Result<String> {
+    let out = match out {
+        Some(path) => std::path::PathBuf::from(path),
+        None => first_frame_path(video_path)?,
+    };
+    let output = Command::new(get_ffmpeg_path_sync())
+        .arg("-y")
+        .args(first_frame_args(video_path, &out.to_string_lossy()))
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run ffmpeg: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(with_stderr_tail(
+            "FFmpeg failed to extract frame",
+            &stderr_tail(&output.stderr)
+        )));
+    }
+    Ok(out.to_string_lossy().to_string())
+}
+
+// ffmpeg -i input.mp4 -ss 0 -vframes 1 -f image2 -c:v png <out>  ("-" is stdout)
+fn first_frame_args(video_path: &str, out: &str) -> Vec<String> {
+    [
+        "-i", video_path, "-ss", "0", "-vframes", "1", "-f", "image2",
+        // PNG for high quality and transparency support (if applicable)
+        "-c:v", "png", out,
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+// Default frame file: one per video path, so repeated requests overwrite instead of piling up
+fn first_frame_path(video_path: &str) -> anyhow::Result<std::path::PathBuf> {
+    let dir = std::env::temp_dir().join("capslap").join("frames");
+    std::fs::create_dir_all(&dir)?;
+    let hash = blake3::hash(video_path.as_bytes()).to_hex();
+    Ok(dir.join(format!("{}.png", &hash[..16])))
+}
+
+// PNG bytes from a reader as a data URI, base64-encoded as they stream in
+fn png_data_uri(mut png: impl std::io::Read) -> std::io::Result<String> {
+    use base64::{engine::general_purpose, write::EncoderStringWriter};
+    let mut encoder = EncoderStringWriter::from_consumer(
+        "data:image/png;base64,".to_string(),
+        &general_purpose::STANDARD,
+    );
+    std::io::copy(&mut png, &mut encoder)?;
+    Ok(encoder.into_inner())
+}
+
+const DEFAULT_THUMBNAIL_HEIGHT: u32 = 90;
+const MAX_THUMBNAILS: usize = 500;
+const SPRITE_COLUMNS: usize = 10;
+// Seeking ffmpegs run at once; each decodes from a keyframe on its own core
+const THUMBNAIL_SEEKS: usize = 4;
+
+/// Timestamps for a filmstrip: `count` frames centered in equal slices of the
+/// video, or one every `interval_ms` from the start.
+pub fn thumbnail_times(
+    duration_ms: u64,
+    count: Option<u32>,
+    interval_ms: Option<u64>,
+) -> anyhow::Result<Vec<u64>> {
+    let times: Vec<u64> = match (count, interval_ms) {
+        (Some(_), Some(_)) => {
+            return Err(anyhow::anyhow!("Pass either count or intervalMs, not both"))
+        }
+        (Some(n), None) if n > 0 => {
+            let n = (n as usize).min(MAX_THUMBNAILS);
+            (0..n)
+                .map(|i| ((2 * i + 1) as u64 * duration_ms) / (2 * n as u64))
+                .collect()
+        }
+        (None, Some(step)) if step > 0 => (0..duration_ms.max(1))
+            .step_by(step as usize)
+            .take(MAX_THUMBNAILS)
+            .collect(),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "extractThumbnails needs a positive count or intervalMs"
+            ))
+        }
+    };
+    Ok(times)
+}
+
+// One JPEG frame at `time_ms`, scaled to `height` (input seeking keeps this fast on long videos)
+async fn extract_jpeg_at(
+    ffmpeg_path: &str,
+    video_path: &str,
+    time_ms: u64,
+    height: u32,
+) -> anyhow::Result<Vec<u8>> {
+    let output = TokioCommand::new(ffmpeg_path)
+        .kill_on_drop(true)
+        .arg("-ss")
+        .arg(format!("{:.3}", time_ms as f64 / 1000.0))
+        .arg("-i")
+        .arg(video_path)
+        .arg("-frames:v")
+        .arg("1")
+        .arg("-vf")
+        .arg(format!("scale=-2:{}", height))
+        .arg("-f")
+        .arg("image2")
+        .arg("-c:v")
+        .arg("mjpeg")
+        .arg("-q:v")
+        .arg("4")
+        .arg("-")
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to run ffmpeg: {}", e))?;
+
+    if !output.status.success() || output.stdout.is_empty() {
+        return Err(anyhow::anyhow!(with_stderr_tail(
+            format!("FFmpeg failed to extract thumbnail at {} ms", time_ms),
+            &stderr_tail(&output.stderr)
+        )));
     }
+    Ok(output.stdout)
+}
 
+/// Lay decoded thumbnails out in rows of `SPRITE_COLUMNS`, returning the sheet and each (x, y)
+pub fn build_sprite_sheet(frames: &[image::RgbImage]) -> (image::RgbImage, Vec<(u32, u32)>) {
+    let cell_w = frames.iter().map(|f| f.width()).max().unwrap_or(0);
+    let cell_h = frames.iter().map(|f| f.height()).max().unwrap_or(0);
+    let cols = frames.len().clamp(1, SPRITE_COLUMNS);
+    let rows = frames.len().div_ceil(cols);
+    let mut sheet = image::RgbImage::new(cell_w * cols as u32, cell_h * rows as u32);
+    let offsets = frames
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| {
+            let (x, y) = ((i % cols) as u32 * cell_w, (i / cols) as u32 * cell_h);
+            image::imageops::replace(&mut sheet, frame, x as i64, y as i64);
+            (x, y)
+        })
+        .collect();
+    (sheet, offsets)
+}
+
+/// Extract a filmstrip of JPEG thumbnails for the editor timeline
+pub async fn extract_thumbnails(
+    id: &str,
+    params: crate::types::ExtractThumbnailsParams,
+    mut emit: impl FnMut(RpcEvent),
+) -> anyhow::Result<crate::types::ExtractThumbnailsResult> {
+    use crate::types::{ExtractThumbnailsResult, Thumbnail};
     use base64::{engine::general_purpose, Engine as _};
-    let encoded = general_purpose::STANDARD.encode(&output.stdout);
 
-    // Return with data URI scheme
-    Ok(format!("data:image/png;base64,{}", encoded))
+    let probe_result = probe(id, &params.video_path, &mut emit).await?;
+    if !probe_result.video {
+        return Err(anyhow::anyhow!("{} has no video stream", params.video_path));
+    }
+    let duration_ms = (probe_result.duration.unwrap_or(0.0) * 1000.0).max(0.0) as u64;
+    let times = thumbnail_times(duration_ms, params.count, params.interval_ms)?;
+    let height = params.height.unwrap_or(DEFAULT_THUMBNAIL_HEIGHT).max(2);
+    let ffmpeg_path = find_ffmpeg_binary().await?;
+
+    // A fast seek per thumbnail, a few at a time; results come back in order
+    use futures_util::StreamExt;
+    let mut seeks = futures_util::stream::iter(times.clone())
+        .map(|time_ms| {
+            let (ffmpeg_path, video_path) = (ffmpeg_path.clone(), params.video_path.clone());
+            async move { extract_jpeg_at(&ffmpeg_path, &video_path, time_ms, height).await }
+        })
+        .buffered(THUMBNAIL_SEEKS);
+    let mut frames = Vec::with_capacity(times.len());
+    while let Some(jpeg) = seeks.next().await {
+        frames.push(jpeg?);
+        emit(RpcEvent::Progress {
+            id: id.into(),
+            status: format!("Extracting thumbnails ({}/{})…", frames.len(), times.len()),
+            progress: 0.1 + 0.85 * (frames.len() as f32 / times.len() as f32),
+        });
+    }
+
+    let encode_uri = |jpeg: &[u8]| {
+        format!(
+            "data:image/jpeg;base64,{}",
+            general_purpose::STANDARD.encode(jpeg)
+        )
+    };
+
+    let result = if params.sprite {
+        let decoded = frames
+            .iter()
+            .map(|jpeg| image::load_from_memory(jpeg).map(|img| img.to_rgb8()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!("Failed to decode thumbnail: {}", e))?;
+        let (sheet, offsets) = build_sprite_sheet(&decoded);
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 80)
+            .encode_image(&sheet)
+            .map_err(|e| anyhow::anyhow!("Failed to encode sprite sheet: {}", e))?;
+        ExtractThumbnailsResult {
+            thumbnails: times
+                .iter()
+                .zip(decoded.iter().zip(offsets))
+                .map(|(&time_ms, (img, (x, y)))| Thumbnail {
+                    time_ms,
+                    image_data: None,
+                    x,
+                    y,
+                    width: img.width(),
+                    height: img.height(),
+                })
+                .collect(),
+            sprite: Some(encode_uri(&jpeg)),
+        }
+    } else {
+        let thumbnails = times
+            .iter()
+            .zip(&frames)
+            .map(|(&time_ms, jpeg)| {
+                let (width, height) = image::load_from_memory(jpeg)
+                    .map(|img| (img.width(), img.height()))
+                    .unwrap_or((0, height));
+                Thumbnail {
+                    time_ms,
+                    image_data: Some(encode_uri(jpeg)),
+                    x: 0,
+                    y: 0,
+                    width,
+                    height,
+                }
+            })
+            .collect();
+        ExtractThumbnailsResult {
+            thumbnails,
+            sprite: None,
+        }
+    };
+
+    emit(RpcEvent::Progress {
+        id: id.into(),
+        status: "Thumbnails ready".into(),
+        progress: 1.0,
+    });
+    Ok(result)
+}
+
+/// Why the inputs can't be joined by stream copy, or None when they share codecs,
+/// coded frame size, pixel format, rotation, frame rate and audio layout (sample rate
+/// and channels)
+pub fn concat_mismatch(inputs: &[String], probes: &[ProbeResult]) -> Option<String> {
+    let first = probes.first()?;
+    let fps_differs = |a: Option<f64>, b: Option<f64>| match (a, b) {
+        (Some(a), Some(b)) => (a - b).abs() > 0.01,
+        (a, b) => a.is_some() != b.is_some(),
+    };
+    inputs.iter().zip(probes).skip(1).find_map(|(input, p)| {
+        let reason = if p.video_codec != first.video_codec {
+            "video codec"
+        } else if (p.width, p.height) != (first.width, first.height) {
+            "frame size"
+        } else if p.rotation != first.rotation {
+            // The concat demuxer keeps only the first input's rotation tag
+            "rotation"
+        } else if fps_differs(p.fps, first.fps) {
+            "frame rate"
+        } else if p.pix_fmt != first.pix_fmt {
+            "pixel format"
+        } else if p.audio != first.audio || p.audio_codec != first.audio_codec {
+            "audio"
+        } else if p.sample_rate != first.sample_rate {
+            "audio sample rate"
+        } else if p.channels != first.channels {
+            "audio channels"
+        } else {
+            return None;
+        };
+        Some(format!(
+            "{} differs in {} from {}",
+            input, reason, inputs[0]
+        ))
+    })
+}
+
+/// Concat demuxer list; paths are absolute so they don't resolve against the list's directory
+pub fn concat_list(inputs: &[String]) -> String {
+    inputs
+        .iter()
+        .map(|input| {
+            let path = std::fs::canonicalize(input).unwrap_or_else(|_| input.into());
+            format!("file '{}'\n", path.to_string_lossy().replace('\'', "'\\''"))
+        })
+        .collect()
+}
+
+/// Filter graph that scales, pads and resamples every input to match the first one, then
+/// joins them with the concat filter. Inputs without audio get silence of their length.
+pub fn concat_filter(probes: &[ProbeResult]) -> String {
+    let (w, h) = probes
+        .first()
+        .and_then(ProbeResult::display_dimensions)
+        .map_or((1920, 1080), |(w, h)| (round_even(w), round_even(h)));
+    let fps = probes.first().and_then(|p| p.fps).unwrap_or(30.0);
+    let with_audio = probes.iter().any(|p| p.audio);
+
+    let mut chains = Vec::new();
+    let mut pads = String::new();
+    for (i, p) in probes.iter().enumerate() {
+        chains.push(format!(
+            "[{i}:v:0]scale={w}:{h}:force_original_aspect_ratio=decrease,\
+             pad={w}:{h}:(ow-iw)/2:(oh-ih)/2:black,setsar=1,fps={fps},format=yuv420p[v{i}]"
+        ));
+        pads.push_str(&format!("[v{}]", i));
+        if with_audio {
+            if p.audio {
+                chains.push(format!(
+                    "[{i}:a:0]aresample=48000,aformat=channel_layouts=stereo[a{i}]"
+                ));
+            } else {
+                chains.push(format!(
+                    "anullsrc=r=48000:cl=stereo,atrim=duration={:.3}[a{}]",
+                    p.duration.unwrap_or(0.0),
+                    i
+                ));
+            }
+            pads.push_str(&format!("[a{}]", i));
+        }
+    }
+    chains.push(format!(
+        "{}concat=n={}:v=1:a={}[v]{}",
+        pads,
+        probes.len(),
+        u8::from(with_audio),
+        if with_audio { "[a]" } else { "" }
+    ));
+    chains.join(";")
+}
+
+// Run an ffmpeg command that reports on stdout (-progress pipe:1), forwarding progress.
+// A failure becomes `failure` plus ffmpeg's last stderr lines.
+async fn run_with_progress(
+    id: &str,
+    cmd: &mut TokioCommand,
+    duration_us: u64,
+    status: &str,
+    failure: &str,
+    emit: &mut impl FnMut(RpcEvent),
+) -> anyhow::Result<()> {
+    cmd.kill_on_drop(true)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped());
+    crate::logging::log_command(cmd);
+    let mut child = cmd.spawn()?;
+    let stderr_tail = collect_stderr_tail(&mut child);
+    if let Some(stdout) = child.stdout.take() {
+        let mut lines = tokio::io::BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(us) = line
+                .strip_prefix("out_time_us=")
+                .and_then(|us| us.trim().parse::<u64>().ok())
+            {
+                let progress = if duration_us > 0 {
+                    (us as f64 / duration_us as f64).min(0.99) as f32
+                } else {
+                    0.0
+                };
+                emit(RpcEvent::Progress {
+                    id: id.into(),
+                    status: format!("{} ({:.0}%)", status, progress * 100.0),
+                    progress,
+                });
+            }
+        }
+    }
+    if child.wait().await?.success() {
+        return Ok(());
+    }
+    let tail = stderr_tail.await.unwrap_or_default();
+    Err(ffmpeg_failure(id, failure, &tail, emit))
+}
+
+/// Join videos end to end. Matching inputs are stream-copied through the concat demuxer;
+/// otherwise (or if the copy fails) they are normalized to the first input and re-encoded.
+pub async fn concat_videos(
+    id: &str,
+    p: crate::types::ConcatVideosParams,
+    mut emit: impl FnMut(RpcEvent),
+) -> anyhow::Result<crate::types::ConcatVideosResult> {
+    if p.inputs.len() < 2 {
+        return Err(anyhow::anyhow!("concatVideos needs at least two inputs"));
+    }
+    let output = std::path::Path::new(&p.output);
+    for input in &p.inputs {
+        if !std::path::Path::new(input).is_file() {
+            return Err(anyhow::anyhow!("Input video not found: {}", input));
+        }
+        if std::fs::canonicalize(input).ok() == std::fs::canonicalize(output).ok() {
+            return Err(anyhow::anyhow!(
+                "The output would overwrite input {}",
+                input
+            ));
+        }
+    }
+
+    let mut probes = Vec::new();
+    for input in &p.inputs {
+        let probe_result = probe(id, input, &mut emit).await?;
+        if !probe_result.video {
+            return Err(anyhow::anyhow!("{} has no video stream", input));
+        }
+        probes.push(probe_result);
+    }
+    let duration_us = probes
+        .iter()
+        .map(|p| (p.duration.unwrap_or(0.0) * 1_000_000.0) as u64)
+        .sum();
+    let ffmpeg_path = find_ffmpeg_binary()
+        .await
+        .map_err(|e| anyhow::anyhow!("FFmpeg not found: {}", e))?;
+
+    let mismatch = if p.reencode {
+        Some("re-encode requested".to_string())
+    } else {
+        concat_mismatch(&p.inputs, &probes)
+    };
+    match mismatch {
+        None => {
+            let mut list = tempfile::Builder::new()
+                .prefix("capslap_concat_")
+                .suffix(".txt")
+                .tempfile()?;
+            std::io::Write::write_all(&mut list, concat_list(&p.inputs).as_bytes())?;
+            let mut cmd = TokioCommand::new(&ffmpeg_path);
+            cmd.args(["-y", "-f", "concat", "-safe", "0", "-i"])
+                .arg(list.path())
+                .args(["-map", "0:v:0", "-map", "0:a?", "-c", "copy"])
+                .args(["-movflags", "+faststart", "-progress", "pipe:1"])
+                .arg(&p.output);
+            let copied = run_with_progress(
+                id,
+                &mut cmd,
+                duration_us,
+                "Joining",
+                "Stream copy concat failed",
+                &mut emit,
+            )
+            .await;
+            if copied.is_ok() {
+                emit(RpcEvent::Progress {
+                    id: id.into(),
+                    status: "Done".into(),
+                    progress: 1.0,
+                });
+                return Ok(crate::types::ConcatVideosResult {
+                    video: p.output,
+                    reencoded: false,
+                });
+            }
+            emit(RpcEvent::Log {
+                id: id.into(),
+                message: "Stream copy concat failed, re-encoding instead".into(),
+            });
+        }
+        Some(reason) => emit(RpcEvent::Log {
+            id: id.into(),
+            message: format!("Re-encoding to join the videos: {}", reason),
+        }),
+    }
+
+    let filter = concat_filter(&probes);
+    let with_audio = probes.iter().any(|p| p.audio);
+    let mut cmd = TokioCommand::new(&ffmpeg_path);
+    cmd.arg("-y");
+    for input in &p.inputs {
+        cmd.arg("-i").arg(input);
+    }
+    cmd.arg("-filter_complex")
+        .arg(&filter)
+        .args(["-map", "[v]"]);
+    if with_audio {
+        cmd.args(["-map", "[a]", "-c:a", "aac", "-b:a", "160k"]);
+    }
+    cmd.args(["-c:v", "libx264", "-crf", "18", "-preset", "medium"])
+        .args(["-movflags", "+faststart", "-progress", "pipe:1"])
+        .arg(&p.output);
+    run_with_progress(
+        id,
+        &mut cmd,
+        duration_us,
+        "Joining (re-encode)",
+        "ffmpeg concat failed",
+        &mut emit,
+    )
+    .await?;
+    emit(RpcEvent::Progress {
+        id: id.into(),
+        status: "Done".into(),
+        progress: 1.0,
+    });
+    Ok(crate::types::ConcatVideosResult {
+        video: p.output,
+        reencoded: true,
+    })
 }
 
 #[cfg(test)]
@@ -1648,6 +2988,12 @@ mod tests {
             color_space: None,
             color_transfer: None,
             color_primaries: None,
+            video_codec: None,
+            bitrate: None,
+            rotation: 0,
+            pix_fmt: None,
+            sample_rate: None,
+            channels: None,
         };
         let (codec, _) = determine_audio_codec(Some(&probe));
         assert_eq!(codec, "aac");
@@ -1667,6 +3013,12 @@ mod tests {
             color_space: None,
             color_transfer: None,
             color_primaries: None,
+            video_codec: None,
+            bitrate: None,
+            rotation: 0,
+            pix_fmt: None,
+            sample_rate: None,
+            channels: None,
         };
         let (codec, args) = determine_audio_codec(Some(&probe));
         assert_eq!(codec, "copy");
@@ -1687,6 +3039,12 @@ mod tests {
             color_space: None,
             color_transfer: None,
             color_primaries: None,
+            video_codec: None,
+            bitrate: None,
+            rotation: 0,
+            pix_fmt: None,
+            sample_rate: None,
+            channels: None,
         };
         let (codec, _) = determine_audio_codec(Some(&probe));
         assert_eq!(codec, "aac"); // PCM should be re-encoded
@@ -1706,6 +3064,12 @@ mod tests {
             color_space: None,
             color_transfer: None,
             color_primaries: None,
+            video_codec: None,
+            bitrate: None,
+            rotation: 0,
+            pix_fmt: None,
+            sample_rate: None,
+            channels: None,
         };
         let (codec, _) = determine_audio_codec(Some(&probe));
         assert_eq!(codec, "aac"); // Lossless should be re-encoded for size
@@ -1725,6 +3089,12 @@ mod tests {
             color_space: None,
             color_transfer: None,
             color_primaries: None,
+            video_codec: None,
+            bitrate: None,
+            rotation: 0,
+            pix_fmt: None,
+            sample_rate: None,
+            channels: None,
         };
         let (codec, args) = determine_audio_codec(Some(&probe));
         assert_eq!(codec, "copy");
@@ -1797,4 +3167,606 @@ mod tests {
         assert!(filter.contains("pad="));
         assert!(filter.contains("force_original_aspect_ratio=decrease"));
     }
+
+    // ============================================
+    // thumbnail tests
+    // ============================================
+
+    #[test]
+    fn test_first_frame_streams_base64_or_targets_file() {
+        // Streamed encoding matches encoding the whole buffer at once, across chunk edges
+        let png: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        use base64::{engine::general_purpose, Engine as _};
+        assert_eq!(
+            png_data_uri(std::io::Cursor::new(&png)).unwrap(),
+            format!(
+                "data:image/png;base64,{}",
+                general_purpose::STANDARD.encode(&png)
+            )
+        );
+
+        let args = first_frame_args("/v/a.mp4", "/tmp/f.png");
+        assert_eq!(args.last().unwrap(), "/tmp/f.png");
+        assert_eq!(first_frame_args("/v/a.mp4", "-").last().unwrap(), "-");
+        let path = first_frame_path("/v/a.mp4").unwrap();
+        assert_eq!(path, first_frame_path("/v/a.mp4").unwrap());
+        assert_ne!(path, first_frame_path("/v/b.mp4").unwrap());
+        assert_eq!(path.extension().unwrap(), "png");
+    }
+
+    #[test]
+    fn test_thumbnail_times_by_count_are_centered() {
+        assert_eq!(
+            thumbnail_times(10_000, Some(4), None).unwrap(),
+            vec![1250, 3750, 6250, 8750]
+        );
+    }
+
+    #[test]
+    fn test_thumbnail_times_by_interval_start_at_zero() {
+        assert_eq!(
+            thumbnail_times(2_500, None, Some(1000)).unwrap(),
+            vec![0, 1000, 2000]
+        );
+        assert!(thumbnail_times(1_000, None, None).is_err());
+        assert!(thumbnail_times(1_000, Some(2), Some(100)).is_err());
+        assert_eq!(
+            thumbnail_times(1_000_000, None, Some(1)).unwrap().len(),
+            MAX_THUMBNAILS
+        );
+    }
+
+    #[test]
+    fn test_sprite_sheet_grid_offsets() {
+        let frames: Vec<image::RgbImage> = (0..12)
+            .map(|i| image::RgbImage::from_pixel(16, 9, image::Rgb([i * 20, 0, 0])))
+            .collect();
+        let (sheet, offsets) = build_sprite_sheet(&frames);
+        assert_eq!((sheet.width(), sheet.height()), (160, 18));
+        assert_eq!(offsets[0], (0, 0));
+        assert_eq!(offsets[9], (144, 0));
+        assert_eq!(offsets[11], (16, 9));
+        assert_eq!(sheet.get_pixel(16, 9)[0], 220);
+    }
+
+    // ============================================
+    // parse_probe_json tests
+    // ============================================
+
+    fn phone_probe_json(side_data_rotation: f64) -> serde_json::Value {
+        serde_json::json!({
+            "streams": [
+                {
+                    "codec_type": "video",
+                    "codec_name": "hevc",
+                    "width": 1920,
+                    "height": 1080,
+                    "avg_frame_rate": "30000/1001",
+                    "pix_fmt": "yuv420p10le",
+                    "side_data_list": [
+                        { "side_data_type": "Display Matrix", "rotation": side_data_rotation }
+                    ]
+                },
+                {
+                    "codec_type": "audio",
+                    "codec_name": "aac",
+                    "bit_rate": "128000",
+                    "sample_rate": "48000",
+                    "channels": 2
+                }
+            ],
+            "format": { "duration": "12.5", "bit_rate": "9500000" }
+        })
+    }
+
+    #[test]
+    fn test_parse_probe_json_fields() {
+        let probe = parse_probe_json(&phone_probe_json(-90.0));
+        assert_eq!(probe.video_codec.as_deref(), Some("hevc"));
+        assert_eq!(probe.audio_codec.as_deref(), Some("aac"));
+        assert_eq!(probe.bitrate, Some(9_500_000));
+        assert_eq!(probe.audio_bitrate, Some(128_000));
+        assert_eq!(probe.duration, Some(12.5));
+        assert!((probe.fps.unwrap() - 29.97).abs() < 0.01);
+        assert_eq!(probe.rotation, 90);
+        assert_eq!(probe.pix_fmt.as_deref(), Some("yuv420p10le"));
+        assert_eq!(probe.sample_rate, Some(48_000));
+        assert_eq!(probe.channels, Some(2));
+    }
+
+    #[test]
+    fn test_parse_probe_json_rotation_sources() {
+        assert_eq!(parse_probe_json(&phone_probe_json(90.0)).rotation, 270);
+        assert_eq!(parse_probe_json(&phone_probe_json(180.0)).rotation, 180);
+        assert_eq!(parse_probe_json(&phone_probe_json(0.0)).rotation, 0);
+
+        let legacy = serde_json::json!({
+            "streams": [{ "codec_type": "video", "tags": { "rotate": "90" } }],
+            "format": {}
+        });
+        assert_eq!(parse_probe_json(&legacy).rotation, 90);
+    }
+
+    #[test]
+    fn test_rotated_phone_clip_reframes_upright() {
+        // A portrait phone clip is stored landscape with a -90 degree display matrix
+        let probe = parse_probe_json(&phone_probe_json(-90.0));
+        assert_eq!(probe.display_dimensions(), Some((1080, 1920)));
+        let (w, h) = probe.display_dimensions().unwrap();
+        assert_eq!(canvas_no_downscale(w, h, TargetAR::AR9x16), (1080, 1920));
+
+        let flipped = parse_probe_json(&phone_probe_json(180.0));
+        assert_eq!(flipped.display_dimensions(), Some((1920, 1080)));
+    }
+
+    #[test]
+    fn test_metadata_args_copy_strip_and_tags() {
+        use crate::types::MetadataOptions;
+        let copy = MetadataOptions::default();
+        assert_eq!(metadata_args(&copy), vec!["-map_metadata", "0"]);
+        assert_eq!(movflags(&copy), "+faststart+use_metadata_tags");
+
+        let private = MetadataOptions {
+            strip_metadata: true,
+            metadata_title: Some("Launch day".into()),
+            ..Default::default()
+        };
+        let args = metadata_args(&private);
+        assert_eq!(&args[..2], ["-map_metadata", "-1"]);
+        assert!(args.ends_with(&["-metadata".to_string(), "title=Launch day".to_string()]));
+        assert_eq!(movflags(&private), "+faststart");
+
+        let parsed: MetadataOptions =
+            serde_json::from_str(r#"{"copyMetadata": false, "metadataComment": "v2"}"#).unwrap();
+        assert_eq!(
+            metadata_args(&parsed),
+            vec!["-map_metadata", "-1", "-metadata", "comment=v2"]
+        );
+        let defaults: MetadataOptions = serde_json::from_str("{}").unwrap();
+        assert!(defaults.copy_metadata && !defaults.strip_metadata);
+    }
+
+    #[test]
+    fn test_clamp_to_source_avoids_upscaling() {
+        // 1080p 9:16 from a 720p landscape source drops to 720 wide
+        let target = export_dimensions(TargetAR::AR9x16, Some("1080p"), 1280, 720);
+        assert_eq!(target, (1080, 1920));
+        assert_eq!(clamp_to_source(target, (1280, 720)), Some((720, 1280)));
+        // 4:5 keeps its ratio and even dimensions
+        assert_eq!(clamp_to_source((1080, 1350), (854, 480)), Some((480, 600)));
+        // Sources at or above the preset are left alone
+        assert_eq!(clamp_to_source((1080, 1920), (1920, 1080)), None);
+        assert_eq!(clamp_to_source((1080, 1920), (3840, 2160)), None);
+    }
+
+    #[test]
+    fn test_list_export_formats_matches_export_dimensions() {
+        let params = crate::types::ListExportFormatsParams {
+            source_width: Some(1920),
+            source_height: Some(1080),
+        };
+        let result = list_export_formats(&params).unwrap();
+        assert_eq!(result.formats.len(), EXPORT_FORMATS.len());
+        let portrait = &result.formats[0];
+        assert_eq!(portrait.format, "9:16");
+        assert!(portrait.supports_fit);
+        let dims: Vec<_> = portrait
+            .sizes
+            .iter()
+            .map(|s| (s.output_size.as_str(), s.width, s.height))
+            .collect();
+        assert_eq!(
+            dims,
+            vec![
+                ("720p", 720, 1280),
+                ("1080p", 1080, 1920),
+                ("4k", 2160, 3840),
+                ("source", 1920, 3414),
+            ]
+        );
+        for format in &result.formats {
+            assert!(parse_target_ar(&format.format).is_ok());
+        }
+
+        let no_source = list_export_formats(&Default::default()).unwrap();
+        assert_eq!(no_source.formats[0].sizes.len(), OUTPUT_SIZES.len());
+        let half = crate::types::ListExportFormatsParams {
+            source_width: Some(1920),
+            source_height: None,
+        };
+        assert!(list_export_formats(&half).is_err());
+    }
+
+    #[test]
+    fn test_export_dimensions_square_and_4x5_are_even() {
+        let sizes = [("720p", 720), ("1080p", 1080), ("4k", 2160)];
+        for (size, short) in sizes {
+            assert_eq!(
+                export_dimensions(TargetAR::AR1x1, Some(size), 0, 0),
+                (short, short)
+            );
+            let (w, h) = export_dimensions(TargetAR::AR4x5, Some(size), 0, 0);
+            assert_eq!((w, h), (short, short * 5 / 4));
+            assert!(w.is_multiple_of(2) && h.is_multiple_of(2));
+        }
+        // Source-sized canvases round odd sources up to even
+        for (src_w, src_h) in [(1366, 768), (1279, 719), (720, 1281)] {
+            for ar in [TargetAR::AR1x1, TargetAR::AR4x5] {
+                let (w, h) = export_dimensions(ar, None, src_w, src_h);
+                assert!(w.is_multiple_of(2) && h.is_multiple_of(2), "{}x{}", w, h);
+                assert!(w >= src_w && h >= src_h);
+            }
+        }
+        assert_eq!(
+            export_dimensions(TargetAR::AR4x5, None, 1920, 1080),
+            (1920, 2400)
+        );
+        assert_eq!(
+            export_dimensions(TargetAR::AR1x1, None, 1080, 1920),
+            (1920, 1920)
+        );
+    }
+
+    #[test]
+    fn test_fitpad_filter_keeps_even_offsets() {
+        for strategy in ["fill", "fit"] {
+            let filter = build_fitpad_filter_with_options(
+                1080,
+                1350,
+                None,
+                HardwareEncoder::Software,
+                strategy,
+                false,
+            );
+            assert!(filter.contains("force_divisible_by=2"), "{}", filter);
+            assert!(filter.contains(":floor(abs(iw-ow)/4)*2:floor(abs(ih-oh)/4)*2"));
+        }
+    }
+
+    #[test]
+    fn test_reformat_math_is_even_for_all_sources_and_formats() {
+        let sources = [
+            (1920, 1080),
+            (1080, 1920),
+            (1280, 720),
+            (1366, 768),
+            (1279, 719),
+            (721, 1281),
+            (640, 480),
+            (3840, 2160),
+            (405, 720),
+            (1, 1),
+        ];
+        let sizes = [None, Some("720p"), Some("1080p"), Some("4k"), Some("2160p")];
+        for (src_w, src_h) in sources {
+            for format in EXPORT_FORMATS {
+                let ar = parse_target_ar(format).unwrap();
+                for size in sizes {
+                    let (w, h) = export_dimensions(ar, size, src_w, src_h);
+                    let label =
+                        format!("{}x{} -> {} {:?} = {}x{}", src_w, src_h, format, size, w, h);
+                    assert!(w.is_multiple_of(2) && h.is_multiple_of(2), "{}", label);
+                    assert!(w >= 2 && h >= 2, "{}", label);
+                    if let Some((cw, ch)) = clamp_to_source((w, h), (src_w, src_h)) {
+                        assert!(cw.is_multiple_of(2) && ch.is_multiple_of(2), "{}", label);
+                    }
+                }
+
+                // Native-pixel padding: even canvas and even, centered offsets
+                let pad = vf_fit_pad_no_scale(src_w, src_h, ar, "black");
+                let parts: Vec<u32> = pad
+                    .trim_start_matches("pad=")
+                    .split(':')
+                    .take(4)
+                    .map(|p| p.parse().unwrap())
+                    .collect();
+                let (out_w, out_h, x, y) = (parts[0], parts[1], parts[2], parts[3]);
+                assert!(
+                    out_w.is_multiple_of(2) && out_h.is_multiple_of(2),
+                    "{}",
+                    pad
+                );
+                assert!(x.is_multiple_of(2) && y.is_multiple_of(2), "{}", pad);
+                assert!(x + src_w <= out_w && y + src_h <= out_h, "{}", pad);
+                assert!((out_w - src_w) / 2 - x <= 1 && (out_h - src_h) / 2 - y <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_center_offset_even() {
+        assert_eq!(center_offset_even(1366, 768), 298);
+        assert_eq!(center_offset_even(2276, 720), 778);
+        assert_eq!(center_offset_even(1080, 1080), 0);
+        assert_eq!(center_offset_even(720, 1080), 0);
+    }
+
+    #[test]
+    fn test_watermark_overlay_under_captions() {
+        let spec = crate::types::WatermarkSpec {
+            path: "/brand/logo.png".into(),
+            position: Some("top-right".into()),
+            scale: 0.2,
+            opacity: 0.5,
+            margin_x: 0.05,
+            margin_y: 0.02,
+        };
+        let filter = build_fitpad_filter_with_effects(
+            1080,
+            1920,
+            Some("/tmp/subs.ass"),
+            HardwareEncoder::Software,
+            "fit",
+            false,
+            None,
+            &FrameEffects {
+                watermark: Some(&spec),
+                ..Default::default()
+            },
+        );
+        assert!(
+            filter.starts_with(
+                "movie='/brand/logo.png',scale=216:-2,format=rgba,colorchannelmixer=aa=0.500[ov0];[in]scale="
+            ),
+            "{}",
+            filter
+        );
+        // Overlay sits between the scaled frame and the captions, positioned in output pixels
+        assert!(
+            filter.contains("[base0];[base0][ov0]overlay=main_w-overlay_w-54:38,ass="),
+            "{}",
+            filter
+        );
+        assert!(filter.ends_with("format=yuv420p[out]"), "{}", filter);
+
+        // Without a watermark the chain is unchanged
+        assert_eq!(
+            build_fitpad_filter_with_effects(
+                1080,
+                1920,
+                None,
+                HardwareEncoder::Software,
+                "fit",
+                false,
+                None,
+                &FrameEffects::default()
+            ),
+            build_fitpad_filter_with_fonts(
+                1080,
+                1920,
+                None,
+                HardwareEncoder::Software,
+                "fit",
+                false,
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn test_trim_input_args_and_duration() {
+        assert!(trim_input_args(None, None).is_empty());
+        assert!(trim_input_args(Some(0), None).is_empty());
+        assert_eq!(
+            trim_input_args(Some(65_250), Some(90_000)),
+            vec!["-ss", "65.250", "-to", "90.000"]
+        );
+        assert_eq!(trim_input_args(None, Some(1500)), vec!["-to", "1.500"]);
+
+        assert_eq!(
+            trimmed_duration(Some(120.0), Some(30_000), Some(90_000)),
+            Some(60.0)
+        );
+        // The end is capped at the source, and a range past it is empty
+        assert_eq!(
+            trimmed_duration(Some(60.0), Some(30_000), Some(90_000)),
+            Some(30.0)
+        );
+        assert_eq!(trimmed_duration(Some(20.0), Some(30_000), None), Some(0.0));
+        assert_eq!(trimmed_duration(None, Some(1000), None), None);
+    }
+
+    #[test]
+    fn test_progress_bar_stacks_after_watermark() {
+        let logo: crate::types::WatermarkSpec =
+            serde_json::from_value(serde_json::json!({ "path": "/logo.png" })).unwrap();
+        let bar: crate::types::ProgressBarSpec =
+            serde_json::from_value(serde_json::json!({ "position": "top" })).unwrap();
+        let filter = build_fitpad_filter_with_effects(
+            1920,
+            1080,
+            Some("/tmp/subs.ass"),
+            HardwareEncoder::Software,
+            "fit",
+            false,
+            None,
+            &FrameEffects {
+                watermark: Some(&logo),
+                progress_bar: Some((&bar, "0xFF0000@1.00".into(), 12.5)),
+                ..Default::default()
+            },
+        );
+        assert!(
+            filter.contains(";color=c=0xFF0000@1.00:s=1920x10,format=rgba[ov1];[in]scale="),
+            "{}",
+            filter
+        );
+        assert!(
+            filter.contains(
+                "[base1];[base1][ov1]overlay=x='-overlay_w+overlay_w*min(t/12.500,1)':y=0:shortest=1,ass="
+            ),
+            "{}",
+            filter
+        );
+    }
+
+    #[test]
+    fn test_holds_pad_before_scaling() {
+        let filter = build_fitpad_filter_with_effects(
+            1920,
+            1080,
+            None,
+            HardwareEncoder::Software,
+            "fit",
+            false,
+            None,
+            &FrameEffects {
+                intro_hold_ms: 1000,
+                outro_hold_ms: 500,
+                ..Default::default()
+            },
+        );
+        assert!(
+            filter.starts_with(
+                "tpad=start_duration=1.000:start_mode=clone:stop_duration=0.500:stop_mode=clone,scale="
+            ),
+            "{}",
+            filter
+        );
+        assert_eq!(
+            hold_audio_filter(1000, 500).as_deref(),
+            Some("adelay=1000:all=1,apad=pad_dur=0.500")
+        );
+        assert_eq!(hold_audio_filter(0, 0), None);
+    }
+
+    fn clip_probe(width: i32, height: i32, audio: bool) -> ProbeResult {
+        ProbeResult {
+            duration: Some(4.0),
+            width: Some(width),
+            height: Some(height),
+            fps: Some(30.0),
+            audio,
+            video: true,
+            audio_codec: audio.then(|| "aac".to_string()),
+            audio_bitrate: None,
+            color_space: None,
+            color_transfer: None,
+            color_primaries: None,
+            video_codec: Some("h264".to_string()),
+            bitrate: None,
+            rotation: 0,
+            pix_fmt: None,
+            sample_rate: None,
+            channels: None,
+        }
+    }
+
+    #[test]
+    fn test_concat_mismatch_names_the_odd_input() {
+        let inputs: Vec<String> = ["a.mp4", "b.mp4", "c.mp4"].map(String::from).into();
+        let same = vec![clip_probe(1080, 1920, true); 3];
+        assert_eq!(concat_mismatch(&inputs, &same), None);
+
+        let mut sizes = same.clone();
+        sizes[2] = clip_probe(1920, 1080, true);
+        assert_eq!(
+            concat_mismatch(&inputs, &sizes).as_deref(),
+            Some("c.mp4 differs in frame size from a.mp4")
+        );
+        // A rotated phone clip displays at the same size but is coded sideways
+        sizes[2].rotation = 90;
+        assert_eq!(
+            concat_mismatch(&inputs, &sizes).as_deref(),
+            Some("c.mp4 differs in frame size from a.mp4")
+        );
+
+        let mut rotated = same.clone();
+        rotated[1].rotation = 180;
+        assert_eq!(
+            concat_mismatch(&inputs, &rotated).as_deref(),
+            Some("b.mp4 differs in rotation from a.mp4")
+        );
+
+        let mut silent = same.clone();
+        silent[1] = clip_probe(1080, 1920, false);
+        assert_eq!(
+            concat_mismatch(&inputs, &silent).as_deref(),
+            Some("b.mp4 differs in audio from a.mp4")
+        );
+
+        let mut hdr = same.clone();
+        hdr[1].pix_fmt = Some("yuv420p10le".to_string());
+        assert_eq!(
+            concat_mismatch(&inputs, &hdr).as_deref(),
+            Some("b.mp4 differs in pixel format from a.mp4")
+        );
+
+        let mut resampled = same.clone();
+        resampled[2].sample_rate = Some(44_100);
+        assert_eq!(
+            concat_mismatch(&inputs, &resampled).as_deref(),
+            Some("c.mp4 differs in audio sample rate from a.mp4")
+        );
+
+        let mut mono = same;
+        mono[1].channels = Some(1);
+        assert_eq!(
+            concat_mismatch(&inputs, &mono).as_deref(),
+            Some("b.mp4 differs in audio channels from a.mp4")
+        );
+    }
+
+    #[test]
+    fn test_concat_list_quotes_paths() {
+        let list = concat_list(&["/clips/it's here.mp4".to_string()]);
+        assert_eq!(list, "file '/clips/it'\\''s here.mp4'\n");
+    }
+
+    #[test]
+    fn test_concat_filter_normalizes_to_first_input() {
+        let filter = concat_filter(&[clip_probe(1080, 1920, true), clip_probe(720, 720, false)]);
+        assert!(filter.contains("[1:v:0]scale=1080:1920:force_original_aspect_ratio=decrease"));
+        assert!(filter.contains("anullsrc=r=48000:cl=stereo,atrim=duration=4.000[a1]"));
+        assert!(filter.ends_with("[v0][a0][v1][a1]concat=n=2:v=1:a=1[v][a]"));
+
+        let no_audio = concat_filter(&[clip_probe(640, 360, false), clip_probe(640, 360, false)]);
+        assert!(!no_audio.contains("anullsrc"));
+        assert!(no_audio.ends_with("[v0][v1]concat=n=2:v=1:a=0[v]"));
+    }
+
+    #[test]
+    fn test_parse_ffmpeg_capability_listings() {
+        let encoders = "Encoders:\n V..... = Video\n A..... = Audio\n ------\n V....D libx264              libx264 H.264 / AVC\n V....D h264_videotoolbox    VideoToolbox H.264 Encoder\n A....D aac                  AAC (Advanced Audio Coding)\n";
+        let names = parse_ffmpeg_encoders(encoders);
+        assert_eq!(names.len(), 3);
+        assert!(names.contains("h264_videotoolbox"));
+        assert!(!names.contains("="));
+
+        let filters = "Filters:\n  T.. = Timeline support\n  | = Source or sink filter\n ... ass               V->V       Render ASS subtitles onto input video using the libass library.\n TSC overlay           VV->V      Overlay a video source on top of the input.\n ... color             |->V       Provide an uniformly colored input.\n";
+        let names = parse_ffmpeg_filters(filters);
+        assert_eq!(names.len(), 3);
+        assert!(names.contains("ass") && names.contains("overlay") && names.contains("color"));
+
+        assert_eq!(
+            parse_ffmpeg_version("ffmpeg version 7.1 Copyright (c) 2000-2024\nbuilt with clang")
+                .as_deref(),
+            Some("7.1")
+        );
+        assert_eq!(
+            parse_ffmpeg_libraries("  configuration:\n    --prefix=/opt\n    --enable-gpl\n    --enable-libass\n    --enable-libx264\n"),
+            vec!["libass", "libx264"]
+        );
+    }
+
+    #[test]
+    fn test_stderr_tail_keeps_the_diagnostic() {
+        let mut stderr = String::from("ffmpeg version 7.1\n");
+        for i in 0..40 {
+            stderr.push_str(&format!("  libavfilter config line {}\n", i));
+        }
+        stderr.push_str("frame=  120 fps= 60 q=28.0 size=  512kB\rframe=  240 fps= 60\r");
+        stderr.push_str("[AVFilterGraph @ 0x1] No such filter: 'ass'\nError opening filters!\n");
+        let tail = stderr_tail(stderr.as_bytes());
+        assert_eq!(tail.len(), STDERR_TAIL_LINES);
+        assert_eq!(tail.last().unwrap(), "Error opening filters!");
+        assert!(tail.iter().all(|l| !l.starts_with("frame=")));
+
+        let message = with_stderr_tail("ffmpeg export failed", &tail[tail.len() - 2..]);
+        assert_eq!(
+            message,
+            "ffmpeg export failed. ffmpeg said:\n[AVFilterGraph @ 0x1] No such filter: 'ass'\nError opening filters!"
+        );
+        assert_eq!(with_stderr_tail("failed", &[]), "failed");
+    }
 }
@@ -1,7 +1,7 @@
 use crate::rpc::RpcEvent;
 use crate::types::{
     CaptionSegment, TranscribeSegmentsParams, TranscribeSegmentsResult, WhisperCacheEntry,
-    WhisperCacheIndex, WhisperResponse, WhisperWord, WordSpan,
+    WhisperCacheIndex, WhisperResponse, WhisperSegment, WhisperWord, WordSpan,
 };
 use crate::video::{is_ffmpeg_whisper_available, is_whisper_cpp_available};
 use blake3;
@@ -17,6 +17,7 @@ pub async fn transcribe_with_whisper_cpp(
     audio_path: &str,
     model: Option<String>,
     language: Option<String>,
+    prompt: Option<String>,
     mut emit: impl FnMut(RpcEvent),
 ) -> anyhow::Result<WhisperResponse> {
     // Use requested model or default to tiny
@@ -76,6 +77,8 @@ pub async fn transcribe_with_whisper_cpp(
         }
     };
     let mut cmd = TokioCommand::new(&whisper_binary);
+    // A cancelled or timed-out request drops this future; take whisper down with it
+    cmd.kill_on_drop(true);
     // DTW disabled - causes timestamp issues for some audio files
 
     cmd.arg("-m")
@@ -96,9 +99,13 @@ pub async fn transcribe_with_whisper_cpp(
     if let Some(lang) = &language {
         cmd.arg("-l").arg(lang);
     }
+    if let Some(prompt) = &prompt {
+        cmd.arg("--prompt").arg(prompt);
+    }
 
     cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
 
+    crate::logging::log_command(&cmd);
     let output = cmd.output().await?;
 
     let stdout = String::from_utf8_lossy(&output.stdout);
@@ -413,13 +420,54 @@ fn get_system_whisper_paths() -> Vec<String> {
     ]
 }
 
+tokio::task_local! {
+    // The running request's `ffmpegPath`, if it passed one
+    static FFMPEG_OVERRIDE: Option<String>;
+}
+
+/// Run `fut` with `ffmpeg_path` (when set) taking precedence over `FFMPEG_PATH` and the
+/// bundled/system search. Tasks spawned inside don't inherit it; wrap them again.
+pub async fn with_ffmpeg_path<F: std::future::Future>(
+    ffmpeg_path: Option<String>,
+    fut: F,
+) -> F::Output {
+    FFMPEG_OVERRIDE.scope(ffmpeg_path, fut).await
+}
+
+/// The per-request ffmpeg override in effect, if any
+pub fn ffmpeg_override() -> Option<String> {
+    FFMPEG_OVERRIDE.try_with(Clone::clone).ok().flatten()
+}
+
+/// Check that a requested ffmpeg path is an executable file
+pub fn validate_ffmpeg_path(path: &str) -> anyhow::Result<()> {
+    let meta = std::fs::metadata(path)
+        .map_err(|e| anyhow::anyhow!("ffmpegPath {} can't be used: {}", path, e))?;
+    if !meta.is_file() {
+        return Err(anyhow::anyhow!("ffmpegPath {} is not a file", path));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if meta.permissions().mode() & 0o111 == 0 {
+            return Err(anyhow::anyhow!("ffmpegPath {} is not executable", path));
+        }
+    }
+    Ok(())
+}
+
 /// Find FFmpeg binary using priority order (bundled > project > system)
 pub async fn find_ffmpeg_binary() -> anyhow::Result<String> {
     // Priority order:
+    // 0. The request's ffmpegPath
     // 1. Bundled binary (next to executable)
     // 2. Project binary (for development)
     // 3. System installation (Homebrew, etc.)
 
+    if let Some(path) = ffmpeg_override() {
+        return Ok(path);
+    }
+
     // Allow override via environment
     if let Ok(path) = std::env::var("FFMPEG_PATH") {
         if std::path::Path::new(&path).exists() {
@@ -458,13 +506,31 @@ pub async fn find_ffmpeg_binary() -> anyhow::Result<String> {
     Err(anyhow::anyhow!("FFmpeg binary not found in any location"))
 }
 
+/// The ffprobe beside an ffmpeg binary (`/opt/ff/ffmpeg` -> `/opt/ff/ffprobe`), if there is one
+fn ffprobe_beside(ffmpeg: &str) -> Option<String> {
+    let ffmpeg = std::path::Path::new(ffmpeg);
+    let name = ffmpeg.file_name()?.to_str()?;
+    if !name.contains("ffmpeg") {
+        return None;
+    }
+    let ffprobe = ffmpeg.with_file_name(name.replacen("ffmpeg", "ffprobe", 1));
+    ffprobe
+        .is_file()
+        .then(|| ffprobe.to_string_lossy().to_string())
+}
+
 /// Find ffprobe binary using priority order (bundled > project > system)
 pub async fn find_ffprobe_binary() -> anyhow::Result<String> {
     // Priority order:
+    // 0. Beside the request's ffmpegPath
     // 1. Bundled binary (next to executable)
     // 2. Project binary (for development)
     // 3. System installation (Homebrew, etc.)
 
+    if let Some(path) = ffmpeg_override().as_deref().and_then(ffprobe_beside) {
+        return Ok(path);
+    }
+
     // Allow override via environment
     if let Ok(path) = std::env::var("FFPROBE_PATH") {
         if std::path::Path::new(&path).exists() {
@@ -641,23 +707,12 @@ pub async fn download_model_rpc(
     params: crate::types::DownloadModelParams,
     mut emit: impl FnMut(crate::rpc::RpcEvent),
 ) -> anyhow::Result<crate::types::DownloadModelResult> {
-    use futures_util::StreamExt;
-    use tokio::io::AsyncWriteExt;
-
-    let model_filename = match params.model.as_str() {
-        "tiny" => "ggml-tiny.bin",
-        "base" => "ggml-base.bin",
-        "small" => "ggml-small.bin",
-        "medium" => "ggml-medium.bin",
-        "large" => "ggml-large-v3.bin",
-        "turbo" => "ggml-large-v3-turbo.bin",
-        _ => {
-            return Err(anyhow::anyhow!(
-                "Unknown model: {}. Supported: tiny, base, small, medium, large, turbo",
-                params.model
-            ))
-        }
-    };
+    let model_filename = model_filename(&params.model).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Unknown model: {}. Supported: tiny, base, small, medium, large, turbo",
+            params.model
+        )
+    })?;
 
     let url = get_model_download_url(model_filename);
     let models_dir = get_models_dir().map_err(|e| {
@@ -681,35 +736,188 @@ pub async fn download_model_rpc(
         ),
     });
 
-    // Download with progress
-    let client = reqwest::Client::new();
-    let response = client.get(&url).send().await?;
+    // Checked against the SHA-256 pinned for this file, not anything the host sends
+    let expected_sha256 = pinned_sha256(model_filename)
+        .ok_or_else(|| anyhow::anyhow!("No pinned checksum for {}", model_filename))?
+        .to_string();
+
+    // An earlier download may already be complete (e.g. from before checksums were recorded)
+    if output_path.exists() {
+        let existing = output_path.clone();
+        let actual = tokio::task::spawn_blocking(move || sha256_file(&existing)).await??;
+        if actual == expected_sha256 {
+            let size = write_model_checksum(&output_path, &actual)?;
+            emit(crate::rpc::RpcEvent::Log {
+                id: id.into(),
+                message: format!("{} is already downloaded and verified", model_filename),
+            });
+            return Ok(crate::types::DownloadModelResult {
+                model: params.model,
+                path: output_path.to_string_lossy().to_string(),
+                size,
+            });
+        }
+    }
+
+    // Download into a .part file that survives failures, so retries (and later runs)
+    // resume with a Range request instead of starting over
+    let part_path = models_dir.join(format!("{}.part", model_filename));
+    let client = reqwest::Client::builder()
+        .connect_timeout(DOWNLOAD_CONNECT_TIMEOUT)
+        .read_timeout(DOWNLOAD_READ_TIMEOUT)
+        .build()?;
+    let mut attempt = 0;
+    let downloaded = loop {
+        attempt += 1;
+        match download_attempt(&client, &url, &part_path, id, &params.model, &mut emit).await? {
+            DownloadAttempt::Complete(size) => break size,
+            DownloadAttempt::Failed(e) if attempt < MAX_DOWNLOAD_ATTEMPTS => {
+                let delay = download_retry_delay(attempt);
+                emit(crate::rpc::RpcEvent::Log {
+                    id: id.into(),
+                    message: format!(
+                        "Download interrupted ({}); resuming in {}s (attempt {}/{})",
+                        e,
+                        delay.as_secs(),
+                        attempt + 1,
+                        MAX_DOWNLOAD_ATTEMPTS
+                    ),
+                });
+                tokio::time::sleep(delay).await;
+            }
+            DownloadAttempt::Failed(e) => {
+                return Err(e.context(format!(
+                    "Failed to download {} after {} attempts",
+                    model_filename, MAX_DOWNLOAD_ATTEMPTS
+                )))
+            }
+        }
+    };
 
-    if !response.status().is_success() {
+    emit(crate::rpc::RpcEvent::Log {
+        id: id.into(),
+        message: format!("Verifying {} checksum...", model_filename),
+    });
+    let downloaded_path = part_path.clone();
+    let actual_sha256 =
+        tokio::task::spawn_blocking(move || sha256_file(&downloaded_path)).await??;
+    if actual_sha256 != expected_sha256 {
+        let _ = tokio::fs::remove_file(&part_path).await;
         return Err(anyhow::anyhow!(
-            "Failed to download model: HTTP {}",
-            response.status()
+            "Downloaded {} is corrupt (SHA-256 {} but expected {}); deleted it, please retry",
+            model_filename,
+            actual_sha256,
+            expected_sha256
         ));
     }
-
-    let total_size = response.content_length().unwrap_or(0);
+    tokio::fs::rename(&part_path, &output_path).await?;
+    write_model_checksum(&output_path, &actual_sha256)?;
 
     emit(crate::rpc::RpcEvent::Log {
         id: id.into(),
         message: format!(
-            "Downloading {} ({:.1} MB)...",
-            model_filename,
-            total_size as f64 / 1024.0 / 1024.0
+            "Successfully downloaded {} model to {}",
+            params.model,
+            output_path.display()
         ),
     });
 
-    let mut file = tokio::fs::File::create(&output_path).await
-        .map_err(|e| anyhow::anyhow!("Cannot create model file at {}: {}. Check app permissions in System Settings > Privacy & Security.", output_path.display(), e))?;
-    let mut downloaded = 0u64;
+    Ok(crate::types::DownloadModelResult {
+        model: params.model,
+        path: output_path.to_string_lossy().to_string(),
+        size: downloaded,
+    })
+}
+
+const MAX_DOWNLOAD_ATTEMPTS: u32 = 5;
+// A stalled connection fails the attempt after this long without data, so it resumes
+const DOWNLOAD_READ_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+const DOWNLOAD_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+enum DownloadAttempt {
+    Complete(u64),         // .part holds the whole file (this many bytes)
+    Failed(anyhow::Error), // transient failure; the .part keeps what arrived
+}
+
+// One GET of the model, appending to whatever the .part file already holds.
+// Errors returned via `Err` are not worth retrying.
+async fn download_attempt(
+    client: &reqwest::Client,
+    url: &str,
+    part_path: &std::path::Path,
+    id: &str,
+    model: &str,
+    emit: &mut impl FnMut(crate::rpc::RpcEvent),
+) -> anyhow::Result<DownloadAttempt> {
+    use futures_util::StreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    let resume_from = tokio::fs::metadata(part_path).await.map_or(0, |m| m.len());
+    let mut request = client.get(url);
+    if resume_from > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", resume_from));
+    }
+    let response = match request.send().await {
+        Ok(r) => r,
+        Err(e) => return Ok(DownloadAttempt::Failed(e.into())),
+    };
+
+    let status = response.status();
+    if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE && resume_from > 0 {
+        // Nothing left to fetch; the checksum decides whether the .part is good
+        return Ok(DownloadAttempt::Complete(resume_from));
+    }
+    if !status.is_success() {
+        let e = anyhow::anyhow!("Failed to download model: HTTP {}", status);
+        return if is_retryable_status(status) {
+            Ok(DownloadAttempt::Failed(e))
+        } else {
+            Err(e)
+        };
+    }
+
+    // 206 continues the .part; a plain 200 means the server ignored the range
+    let resuming = status == reqwest::StatusCode::PARTIAL_CONTENT;
+    let mut downloaded = if resuming { resume_from } else { 0 };
+    let total_size = response
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(content_range_total)
+        .or_else(|| response.content_length().map(|len| len + downloaded))
+        .unwrap_or(0);
+
+    emit(crate::rpc::RpcEvent::Log {
+        id: id.into(),
+        message: if resuming {
+            format!(
+                "Resuming download at {:.1} of {:.1} MB...",
+                mb(downloaded),
+                mb(total_size)
+            )
+        } else {
+            format!("Downloading {} ({:.1} MB)...", model, mb(total_size))
+        },
+    });
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resuming)
+        .truncate(!resuming)
+        .open(part_path)
+        .await
+        .map_err(|e| anyhow::anyhow!("Cannot create model file at {}: {}. Check app permissions in System Settings > Privacy & Security.", part_path.display(), e))?;
     let mut stream = response.bytes_stream();
 
     while let Some(chunk) = stream.next().await {
-        let chunk = chunk?;
+        let chunk = match chunk {
+            Ok(c) => c,
+            Err(e) => {
+                file.flush().await?;
+                return Ok(DownloadAttempt::Failed(e.into()));
+            }
+        };
         file.write_all(&chunk).await?;
         downloaded += chunk.len() as u64;
 
@@ -721,39 +929,59 @@ pub async fn download_model_rpc(
 
         emit(crate::rpc::RpcEvent::Progress {
             id: id.into(),
-            status: format!("Downloading {}...", params.model),
+            status: format!(
+                "Downloading {}... {:.1}/{:.1} MB",
+                model,
+                mb(downloaded),
+                mb(total_size)
+            ),
             progress,
         });
     }
-
     file.flush().await?;
 
-    emit(crate::rpc::RpcEvent::Log {
-        id: id.into(),
-        message: format!(
-            "Successfully downloaded {} model to {}",
-            params.model,
-            output_path.display()
-        ),
-    });
+    if total_size > 0 && downloaded < total_size {
+        return Ok(DownloadAttempt::Failed(anyhow::anyhow!(
+            "connection closed at {} of {} bytes",
+            downloaded,
+            total_size
+        )));
+    }
+    Ok(DownloadAttempt::Complete(downloaded))
+}
 
-    Ok(crate::types::DownloadModelResult {
-        model: params.model,
-        path: output_path.to_string_lossy().to_string(),
-        size: downloaded,
-    })
+fn mb(bytes: u64) -> f64 {
+    bytes as f64 / 1024.0 / 1024.0
+}
+
+// Total length from `Content-Range: bytes 100-199/1000`
+fn content_range_total(header: &str) -> Option<u64> {
+    header
+        .strip_prefix("bytes ")?
+        .split_once('/')?
+        .1
+        .trim()
+        .parse()
+        .ok()
+}
+
+// Timeouts, throttling and server-side errors are worth another try; other 4xx are not
+fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    status.is_server_error()
+        || status == reqwest::StatusCode::REQUEST_TIMEOUT
+        || status == reqwest::StatusCode::TOO_MANY_REQUESTS
 }
 
-/// Check if a model exists
-pub fn check_model_exists(model_name: &str) -> anyhow::Result<bool> {
-    let model_filename = match model_name {
-        "tiny" => "ggml-tiny.bin",
-        "base" => "ggml-base.bin",
-        "small" => "ggml-small.bin",
-        "medium" => "ggml-medium.bin",
-        "large" => "ggml-large-v3.bin",
-        "turbo" => "ggml-large-v3-turbo.bin",
-        _ => return Ok(false),
+// 2s, 4s, 8s, ... capped at 30s
+fn download_retry_delay(attempt: u32) -> std::time::Duration {
+    std::time::Duration::from_secs((1u64 << attempt.min(5)).min(30))
+}
+
+/// Check if a model exists and its checksum was verified (a partial file counts as missing).
+/// A model downloaded before checksums were recorded is hashed once, then recorded or removed.
+pub async fn check_model_exists(model_name: &str) -> anyhow::Result<bool> {
+    let Some(model_filename) = model_filename(model_name) else {
+        return Ok(false);
     };
 
     let models_dir = get_models_dir().map_err(|e| {
@@ -763,11 +991,257 @@ pub fn check_model_exists(model_name: &str) -> anyhow::Result<bool> {
         )
     })?;
     let model_path = models_dir.join(model_filename);
-    Ok(model_path.exists())
+    if is_verified_model(&model_path) {
+        return Ok(true);
+    }
+    let expected = pinned_sha256(model_filename).unwrap_or_default();
+    Ok(tokio::task::spawn_blocking(move || verify_legacy_model(&model_path, expected)).await?)
+}
+
+// A model file with no checksum record (downloaded by an older version): hash it against
+// the pinned checksum and record it when it matches, instead of downloading it again.
+// A file that doesn't match is removed: the download would replace it anyway, and
+// leaving it would have every later check hash it again
+fn verify_legacy_model(model_path: &std::path::Path, expected_sha256: &str) -> bool {
+    if expected_sha256.is_empty()
+        || !model_path.is_file()
+        || model_checksum_path(model_path).exists()
+    {
+        return false;
+    }
+    match sha256_file(model_path) {
+        Ok(actual) if actual == expected_sha256 => {
+            write_model_checksum(model_path, &actual).is_ok()
+        }
+        Ok(_) => {
+            let _ = std::fs::remove_file(model_path);
+            false
+        }
+        Err(_) => false,
+    }
+}
+
+/// Downloaded models with their size on disk
+pub fn list_models() -> anyhow::Result<crate::types::ListModelsResult> {
+    let models_dir = get_models_dir()?;
+    let models = list_models_in(&models_dir);
+    Ok(crate::types::ListModelsResult {
+        models_dir: models_dir.to_string_lossy().to_string(),
+        total_bytes: models.iter().map(|m| m.size).sum(),
+        models,
+    })
+}
+
+/// Delete a model (and any partial download or checksum record), returning bytes freed
+pub fn delete_model(
+    params: crate::types::DeleteModelParams,
+) -> anyhow::Result<crate::types::DeleteModelResult> {
+    let bytes_freed = delete_model_in(&get_models_dir()?, &params.model)?;
+    Ok(crate::types::DeleteModelResult {
+        model: params.model,
+        bytes_freed,
+    })
+}
+
+const MODEL_NAMES: [&str; 6] = ["tiny", "base", "small", "medium", "large", "turbo"];
+
+// Everything a model can leave in the models dir
+fn model_files(models_dir: &std::path::Path, filename: &str) -> [PathBuf; 3] {
+    let model_path = models_dir.join(filename);
+    [
+        models_dir.join(format!("{}.part", filename)),
+        model_checksum_path(&model_path),
+        model_path,
+    ]
+}
+
+fn list_models_in(models_dir: &std::path::Path) -> Vec<crate::types::ModelInfo> {
+    MODEL_NAMES
+        .iter()
+        .filter_map(|&name| {
+            let filename = model_filename(name)?;
+            let [part, _, model_path] = model_files(models_dir, filename);
+            let size: u64 = model_files(models_dir, filename)
+                .iter()
+                .filter_map(|p| std::fs::metadata(p).ok())
+                .map(|m| m.len())
+                .sum();
+            let partial = part.exists();
+            if !partial && !model_path.exists() {
+                return None;
+            }
+            Some(crate::types::ModelInfo {
+                model: name.to_string(),
+                path: model_path.to_string_lossy().to_string(),
+                size,
+                verified: is_verified_model(&model_path),
+                partial,
+            })
+        })
+        .collect()
+}
+
+fn delete_model_in(models_dir: &std::path::Path, model: &str) -> anyhow::Result<u64> {
+    let filename = model_filename(model).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Unknown model: {}. Supported: {}",
+            model,
+            MODEL_NAMES.join(", ")
+        )
+    })?;
+    let mut freed = 0;
+    for path in model_files(models_dir, filename) {
+        let Ok(meta) = std::fs::metadata(&path) else {
+            continue;
+        };
+        std::fs::remove_file(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to delete {}: {}", path.display(), e))?;
+        freed += meta.len();
+    }
+    Ok(freed)
+}
+
+/// ggml file name for a model name
+fn model_filename(model: &str) -> Option<&'static str> {
+    match model {
+        "tiny" => Some("ggml-tiny.bin"),
+        "base" => Some("ggml-base.bin"),
+        "small" => Some("ggml-small.bin"),
+        "medium" => Some("ggml-medium.bin"),
+        "large" => Some("ggml-large-v3.bin"),
+        "turbo" => Some("ggml-large-v3-turbo.bin"),
+        _ => None,
+    }
+}
+
+/// Known SHA-256 of each ggml file in ggerganov/whisper.cpp. Downloads are checked
+/// against these, so a compromised or broken mirror can't hand us a different file.
+const MODEL_SHA256: [(&str, &str); 6] = [
+    (
+        "ggml-tiny.bin",
+        "be07e048e1e599ad46341c8d2a135645097a538221678b7acdd1b1919c6e1b21",
+    ),
+    (
+        "ggml-base.bin",
+        "60ed5bc3dd14eea856493d334349b405782ddcaf0028d4b5df4088345fba2efe",
+    ),
+    (
+        "ggml-small.bin",
+        "1be3a9b2063867b937e64e2ec7483364a79917e157fa98c5d94b5c1fffea987b",
+    ),
+    (
+        "ggml-medium.bin",
+        "6c14d5adee5f86394037b4e4e8b59f1673b6cee10e3cf0b11bbdbee79c156208",
+    ),
+    (
+        "ggml-large-v3.bin",
+        "64d182b440b98d5203c4f9bd541544d84c605196c4f7b845dfa11fb23594d1e2",
+    ),
+    (
+        "ggml-large-v3-turbo.bin",
+        "1fc70f774d38eb169993ac391eea357ef47c88757ef72ee5943879b7e8e2bc69",
+    ),
+];
+
+fn pinned_sha256(filename: &str) -> Option<&'static str> {
+    MODEL_SHA256
+        .iter()
+        .find(|(name, _)| *name == filename)
+        .map(|(_, sha)| *sha)
+}
+
+fn sha256_file(path: &std::path::Path) -> std::io::Result<String> {
+    use sha2::{Digest, Sha256};
+    use std::io::Read;
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+fn model_checksum_path(model_path: &std::path::Path) -> PathBuf {
+    let mut name = model_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".verified.json");
+    model_path.with_file_name(name)
+}
+
+fn modified_secs(meta: &std::fs::Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs())
+}
+
+// Record a verified checksum next to the model; returns the file size
+fn write_model_checksum(model_path: &std::path::Path, sha256: &str) -> anyhow::Result<u64> {
+    let meta = std::fs::metadata(model_path)?;
+    let record = crate::types::ModelChecksum {
+        sha256: sha256.to_string(),
+        size: meta.len(),
+        modified: modified_secs(&meta),
+    };
+    std::fs::write(
+        model_checksum_path(model_path),
+        serde_json::to_string_pretty(&record)?,
+    )?;
+    Ok(meta.len())
+}
+
+/// True when the model has a checksum record and still matches it. Unchanged files
+/// (same size and mtime) skip the rehash; anything else is hashed again.
+pub fn is_verified_model(model_path: &std::path::Path) -> bool {
+    let Ok(meta) = std::fs::metadata(model_path) else {
+        return false;
+    };
+    let record: crate::types::ModelChecksum =
+        match std::fs::read_to_string(model_checksum_path(model_path))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+        {
+            Some(record) => record,
+            None => return false,
+        };
+    if record.size != meta.len() {
+        return false;
+    }
+    if record.modified == modified_secs(&meta) {
+        return true;
+    }
+    match sha256_file(model_path) {
+        Ok(actual) if actual == record.sha256 => {
+            let _ = write_model_checksum(model_path, &actual);
+            true
+        }
+        _ => false,
+    }
 }
 
 /// Get the models directory path
 fn get_models_dir() -> anyhow::Result<std::path::PathBuf> {
+    // Priority 0: user-chosen cache root (CAPSLAP_CACHE_DIR or config cacheDir)
+    if let Some((root, _)) = cache_root_override() {
+        let models_dir = root.join("models");
+        std::fs::create_dir_all(&models_dir).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to create models directory at {}: {}",
+                models_dir.display(),
+                e
+            )
+        })?;
+        return Ok(models_dir);
+    }
+
     // Priority 1: Check if we're in development (project exists)
     let dev_path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("models");
     if dev_path.exists() && dev_path.is_dir() {
@@ -867,6 +1341,7 @@ fn parse_whisper_cpp_output(json_output: &str) -> anyhow::Result<WhisperResponse
                         start: start_sec,
                         end: end_sec,
                         text: text.trim().to_string(),
+                        no_speech_prob: None,
                     });
 
                     // TEMPORARILY DISABLE TOKEN PARSING - use only segment-level timing
@@ -1039,7 +1514,12 @@ fn parse_whisper_cpp_output(json_output: &str) -> anyhow::Result<WhisperResponse
 
     let response = WhisperResponse {
         task: Some("transcribe".to_string()),
-        language: None,
+        // whisper.cpp reports the detected (or requested) language as a code
+        language: json
+            .get("result")
+            .and_then(|r| r.get("language"))
+            .and_then(|l| l.as_str())
+            .map(str::to_string),
         duration: Some(duration),
         text: full_text,
         segments: Some(segments.clone()),
@@ -1100,6 +1580,7 @@ pub async fn transcribe_with_ffmpeg_whisper(
         message: "Running FFmpeg Whisper transcription...".into(),
     });
 
+    crate::logging::log_command(&cmd);
     let output = cmd.output().await?;
 
     if !output.status.success() {
@@ -1171,6 +1652,7 @@ fn parse_ffmpeg_whisper_output(stderr: &str) -> anyhow::Result<WhisperResponse>
                     start,
                     end,
                     text: text.clone(),
+                    no_speech_prob: None,
                 });
             }
         }
@@ -1195,6 +1677,79 @@ fn parse_ffmpeg_whisper_output(stderr: &str) -> anyhow::Result<WhisperResponse>
     })
 }
 
+// Whisper's language names as the OpenAI API reports them, with their ISO 639-1 codes
+const LANGUAGE_CODES: &[(&str, &str)] = &[
+    ("afrikaans", "af"),
+    ("arabic", "ar"),
+    ("armenian", "hy"),
+    ("azerbaijani", "az"),
+    ("belarusian", "be"),
+    ("bosnian", "bs"),
+    ("bulgarian", "bg"),
+    ("catalan", "ca"),
+    ("chinese", "zh"),
+    ("croatian", "hr"),
+    ("czech", "cs"),
+    ("danish", "da"),
+    ("dutch", "nl"),
+    ("english", "en"),
+    ("estonian", "et"),
+    ("finnish", "fi"),
+    ("french", "fr"),
+    ("galician", "gl"),
+    ("german", "de"),
+    ("greek", "el"),
+    ("hebrew", "he"),
+    ("hindi", "hi"),
+    ("hungarian", "hu"),
+    ("icelandic", "is"),
+    ("indonesian", "id"),
+    ("italian", "it"),
+    ("japanese", "ja"),
+    ("kannada", "kn"),
+    ("kazakh", "kk"),
+    ("korean", "ko"),
+    ("latvian", "lv"),
+    ("lithuanian", "lt"),
+    ("macedonian", "mk"),
+    ("malay", "ms"),
+    ("marathi", "mr"),
+    ("maori", "mi"),
+    ("nepali", "ne"),
+    ("norwegian", "no"),
+    ("persian", "fa"),
+    ("polish", "pl"),
+    ("portuguese", "pt"),
+    ("romanian", "ro"),
+    ("russian", "ru"),
+    ("serbian", "sr"),
+    ("slovak", "sk"),
+    ("slovenian", "sl"),
+    ("spanish", "es"),
+    ("swahili", "sw"),
+    ("swedish", "sv"),
+    ("tagalog", "tl"),
+    ("tamil", "ta"),
+    ("thai", "th"),
+    ("turkish", "tr"),
+    ("ukrainian", "uk"),
+    ("urdu", "ur"),
+    ("vietnamese", "vi"),
+    ("welsh", "cy"),
+];
+
+/// Normalize a detected language to the code the `language` param takes: the API
+/// reports names ("english"), whisper.cpp codes ("en"). Unknown values pass through
+/// lowercased.
+pub fn language_code(language: &str) -> String {
+    let language = language.trim().to_lowercase();
+    LANGUAGE_CODES
+        .iter()
+        .find(|(name, _)| *name == language)
+        .map(|(_, code)| code.to_string())
+        .unwrap_or(language)
+}
+
 /// Helper function to create transcription result with JSON file generation
 async fn create_transcription_result(
     id: &str,
@@ -1221,6 +1776,7 @@ async fn create_transcription_result(
     };
 
     // Create JSON export data
+    let detected_language = whisper_response.language.as_deref().map(language_code);
     let json_data = serde_json::json!({
         "segments": segments,
         "fullText": whisper_response.text,
@@ -1228,6 +1784,7 @@ async fn create_transcription_result(
         "splitByWords": params.split_by_words,
         "model": params.model.clone().unwrap_or_else(|| "whisper-1".to_string()),
         "language": params.language.clone(),
+        "detectedLanguage": detected_language,
         "generatedAt": std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
@@ -1242,6 +1799,7 @@ async fn create_transcription_result(
         full_text: whisper_response.text.clone(),
         duration: whisper_response.duration,
         json_file: json_path,
+        detected_language,
     })
 }
 
@@ -1255,20 +1813,32 @@ pub async fn transcribe_segments(
 
 pub async fn transcribe_segments_with_temp(
     id: &str,
-    p: TranscribeSegmentsParams,
+    mut p: TranscribeSegmentsParams,
     temp_dir: Option<&std::path::PathBuf>,
     mut emit: impl FnMut(RpcEvent),
 ) -> anyhow::Result<TranscribeSegmentsResult> {
     use mime_guess::MimeGuess;
-    use reqwest::multipart;
     use tokio::fs;
 
     // QUICK SWITCH: Set to false to force OpenAI API, true for local whisper
     const USE_LOCAL_WHISPER: bool = true;
+    parse_resegment(p.resegment.as_deref())?;
 
     // Check cache first
-    if let Ok(Some(cached_response)) = get_cached_whisper_response(&p.audio, &p).await {
-        let segments = whisper_to_caption_segments(&cached_response, p.split_by_words);
+    let cached = if p.force {
+        None
+    } else {
+        get_cached_whisper_response(&p.audio, &p)
+            .await
+            .ok()
+            .flatten()
+    };
+    if let Some(cached_response) = cached {
+        emit(RpcEvent::Log {
+            id: id.into(),
+            message: "Using cached transcription for this audio (pass force to redo)".into(),
+        });
+        let segments = caption_segments_for(id, &cached_response, &p, &mut emit);
 
         // generate JSON file path for cached response too
         let json_path = if let Some(temp_dir) = temp_dir {
@@ -1286,6 +1856,7 @@ pub async fn transcribe_segments_with_temp(
         };
 
         // save JSON file for cached response as well
+        let detected_language = cached_response.language.as_deref().map(language_code);
         let json_data = serde_json::json!({
             "segments": segments,
             "fullText": cached_response.text,
@@ -1293,6 +1864,7 @@ pub async fn transcribe_segments_with_temp(
             "splitByWords": p.split_by_words,
             "model": p.model.clone().unwrap_or_else(|| "whisper-1".to_string()),
             "language": p.language.clone(),
+            "detectedLanguage": detected_language,
             "generatedAt": std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap()
@@ -1307,11 +1879,12 @@ pub async fn transcribe_segments_with_temp(
             full_text: cached_response.text,
             duration: cached_response.duration,
             json_file: json_path,
+            detected_language,
         });
     }
 
-    // Check if user explicitly selected OpenAI API (whisper-1)
-    let use_openai_directly = p.model.as_ref().map(|m| m == "whisper-1").unwrap_or(false);
+    // Check if user explicitly selected an OpenAI API model (whisper-1, gpt-4o-transcribe, ...)
+    let use_openai_directly = p.model.as_deref().is_some_and(is_api_model);
 
     // Try local whisper.cpp first if available (unless whisper-1 is explicitly selected)
     if !use_openai_directly && USE_LOCAL_WHISPER && is_whisper_cpp_available().await {
@@ -1325,6 +1898,7 @@ pub async fn transcribe_segments_with_temp(
             &p.audio,
             p.model.clone(),
             p.language.clone(),
+            build_prompt(p.prompt.as_deref(), &p.vocabulary),
             &mut emit,
         )
         .await
@@ -1335,7 +1909,7 @@ pub async fn transcribe_segments_with_temp(
                     message: "Local whisper.cpp transcription successful".into(),
                 });
 
-                let segments = whisper_to_caption_segments(&whisper_response, p.split_by_words);
+                let segments = caption_segments_for(id, &whisper_response, &p, &mut emit);
 
                 emit(RpcEvent::Log {
                     id: id.into(),
@@ -1402,7 +1976,7 @@ pub async fn transcribe_segments_with_temp(
                     message: "Local FFmpeg Whisper transcription successful".into(),
                 });
 
-                let segments = whisper_to_caption_segments(&whisper_response, p.split_by_words);
+                let segments = caption_segments_for(id, &whisper_response, &p, &mut emit);
 
                 // Save to cache
                 if let Err(e) = save_cached_whisper_response(&p.audio, &p, &whisper_response).await
@@ -1436,9 +2010,6 @@ pub async fn transcribe_segments_with_temp(
         .api_key
         .as_ref()
         .ok_or_else(|| anyhow::anyhow!("OpenAI API key not provided"))?;
-    // Always use whisper-1 for OpenAI API (local model names like "tiny" are not valid for the API)
-    let model = "whisper-1".to_string();
-
     let bytes = fs::read(&p.audio).await?;
     let filename = std::path::Path::new(&p.audio)
         .file_name()
@@ -1447,52 +2018,30 @@ pub async fn transcribe_segments_with_temp(
         .to_string();
     let mime = MimeGuess::from_path(&p.audio).first_or_octet_stream();
 
-    // build form for verbose_json with appropriate timestamp granularities
-    let mut form = multipart::Form::new()
-        .text("model", model.clone())
-        .part(
-            "file",
-            multipart::Part::bytes(bytes.clone())
-                .file_name(filename.clone())
-                .mime_str(mime.as_ref())
-                .unwrap(),
-        )
-        .text("response_format", "verbose_json".to_string());
-
-    if let Some(lang) = &p.language {
-        form = form.text("language", lang.clone());
-    }
-    if let Some(prompt) = &p.prompt {
-        form = form.text("prompt", prompt.clone());
-    }
-
-    // set timestamp granularities based on split_by_words preference
-    if p.split_by_words {
-        form = form.text("timestamp_granularities[]", "word".to_string());
+    // Text-only models return no timings, so they're spread over the audio's length
+    let duration = if api_model_chain(&p).iter().any(|m| is_text_only_model(m)) {
+        crate::video::probe(id, &p.audio, |_| {})
+            .await
+            .ok()
+            .and_then(|r| r.duration)
     } else {
-        form = form.text("timestamp_granularities[]", "segment".to_string());
-    }
-
-    let client = reqwest::Client::builder()
-        .user_agent("core/1.0.0")
-        .build()?;
-
-    let resp = client
-        .post("https://api.openai.com/v1/audio/transcriptions")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .multipart(form)
-        .send()
-        .await?;
-
-    if !resp.status().is_success() {
-        let status = resp.status();
-        let body = resp.text().await.unwrap_or_default();
-        return Err(anyhow::anyhow!("OpenAI error {}: {}", status, body));
-    }
-
-    let whisper_response: WhisperResponse = resp.json().await?;
+        None
+    };
+    let (whisper_response, model) = openai_transcription_with_fallbacks(
+        id,
+        api_key,
+        UploadAudio::Bytes(bytes),
+        filename,
+        mime.as_ref(),
+        &p,
+        duration,
+        &mut emit,
+    )
+    .await?;
+    // Cached (and reported) under the model that answered, not the one asked for
+    p.model = Some(model);
 
-    let segments = whisper_to_caption_segments(&whisper_response, p.split_by_words);
+    let segments = caption_segments_for(id, &whisper_response, &p, &mut emit);
 
     // Save to cache
     if let Err(e) = save_cached_whisper_response(&p.audio, &p, &whisper_response).await {
@@ -1505,39 +2054,357 @@ pub async fn transcribe_segments_with_temp(
     create_transcription_result(id, &segments, &whisper_response, &p, temp_dir).await
 }
 
-fn is_digits(s: &str) -> bool {
-    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+/// Transcribe with the OpenAI API while ffmpeg extracts the audio, streaming its output
+/// straight into the upload so no audio file is written. `p.audio` only names the upload.
+/// Nothing is cached: the audio never exists whole, so there is no hash to look it up by.
+pub async fn transcribe_audio_stream(
+    id: &str,
+    audio: &crate::types::ExtractAudioParams,
+    duration_sec: Option<f64>,
+    mut p: TranscribeSegmentsParams,
+    temp_dir: Option<&std::path::PathBuf>,
+    mut emit: impl FnMut(RpcEvent),
+) -> anyhow::Result<TranscribeSegmentsResult> {
+    parse_resegment(p.resegment.as_deref())?;
+    let api_key = p
+        .api_key
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("OpenAI API key not provided"))?;
+    let filename = std::path::Path::new(&p.audio)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let mime = mime_guess::MimeGuess::from_path(&p.audio).first_or_octet_stream();
+    let (whisper_response, model) = openai_transcription_with_fallbacks(
+        id,
+        api_key,
+        UploadAudio::Stream(audio),
+        filename,
+        mime.as_ref(),
+        &p,
+        duration_sec,
+        &mut emit,
+    )
+    .await?;
+    p.model = Some(model);
+    let segments = caption_segments_for(id, &whisper_response, &p, &mut emit);
+    create_transcription_result(id, &segments, &whisper_response, &p, temp_dir).await
 }
 
-fn format_with_thousands(digits: String) -> String {
-    // insert commas every 3 from right
-    let mut out = String::new();
-    for (cnt, ch) in digits.chars().rev().enumerate() {
-        if cnt > 0 && cnt % 3 == 0 {
-            out.push(',');
+// The requested API model, then each API model in `model_fallbacks`, without repeats
+fn api_model_chain(p: &TranscribeSegmentsParams) -> Vec<String> {
+    api_models(p.model.as_deref(), &p.model_fallbacks)
+}
+
+fn api_models(model: Option<&str>, fallbacks: &[String]) -> Vec<String> {
+    let mut chain = vec![api_model(model).to_string()];
+    for model in fallbacks {
+        if is_api_model(model) && !chain.contains(model) {
+            chain.push(model.clone());
         }
-        out.push(ch);
     }
-    out.chars().rev().collect()
+    chain
 }
 
-/// Merge currency symbols, thousand-groups, and decimals into single tokens.
-/// Handles patterns like ["$", "225", "000"] → "$225,000" and ["19", ".", "99"] → "19.99"
-/// Returns (text, start_ms, end_ms) tuples ready for CaptionSegment mapping.
-fn merge_numbers_and_currency(
-    words: &[WhisperWord],
-    max_duration_ms: Option<u64>,
-) -> Vec<(String, u64, u64)> {
-    let mut out = Vec::new();
-    let mut i = 0usize;
-
-    while i < words.len() {
-        let cur = words[i].word.trim();
-        // FIX: words[i].start is already in seconds, convert to ms properly
-        let start_ms = (words[i].start * 1000.0) as u64;
-        let mut end_ms = (words[i].end * 1000.0) as u64;
+/// Where a transcription with this model would run, without running it
+#[derive(Debug, Clone, PartialEq)]
+pub enum TranscriptionRoute {
+    Local(String),    // whisper.cpp with this installed model
+    Api(Vec<String>), // OpenAI models, tried in order
+}
 
-        if let Some(max_ms) = max_duration_ms {
+/// Pick the route the way [`transcribe_segments_with_temp`] does: whisper.cpp when the
+/// binary and a model from the requested one's fallback chain are installed, else the API.
+/// (The FFmpeg whisper filter step is left out; it needs a model path, so it rarely runs.)
+pub async fn transcription_route(model: Option<&str>, fallbacks: &[String]) -> TranscriptionRoute {
+    if !model.is_some_and(is_api_model) && is_whisper_cpp_available().await {
+        if let Ok((_, local)) = ensure_whisper_model(model.unwrap_or("tiny")).await {
+            return TranscriptionRoute::Local(local);
+        }
+    }
+    TranscriptionRoute::Api(api_models(model, fallbacks))
+}
+
+// A failure the next model in the chain may not hit: throttling (429), server errors,
+// timeouts and dropped connections
+fn is_retryable_api_error(err: &anyhow::Error) -> bool {
+    err.chain().any(|e| {
+        if let Some(api) = e.downcast_ref::<OpenAiError>() {
+            return is_retryable_status(api.status);
+        }
+        e.downcast_ref::<reqwest::Error>()
+            .is_some_and(|e| e.is_timeout() || e.is_connect())
+    })
+}
+
+// What an API attempt uploads: audio already in memory, or a fresh ffmpeg extraction
+// streamed into the request (a stream can only be sent once, so each model gets its own)
+enum UploadAudio<'a> {
+    Bytes(Vec<u8>),
+    Stream(&'a crate::types::ExtractAudioParams),
+}
+
+// Transcribe with each model of the chain in turn until one succeeds; a model that
+// fails for a non-retryable reason (bad key, bad audio) ends the chain. Returns the
+// response and the model that produced it.
+#[allow(clippy::too_many_arguments)]
+async fn openai_transcription_with_fallbacks(
+    id: &str,
+    api_key: &crate::types::ApiKey,
+    audio: UploadAudio<'_>,
+    filename: String,
+    mime: &str,
+    p: &TranscribeSegmentsParams,
+    duration_sec: Option<f64>,
+    emit: &mut impl FnMut(RpcEvent),
+) -> anyhow::Result<(WhisperResponse, String)> {
+    use reqwest::multipart::Part;
+
+    let chain = api_model_chain(p);
+    for (i, model) in chain.iter().enumerate() {
+        let (file, ffmpeg) = match &audio {
+            UploadAudio::Bytes(bytes) => (Part::bytes(bytes.clone()), None),
+            UploadAudio::Stream(params) => {
+                if i == 0 {
+                    emit(RpcEvent::Log {
+                        id: id.into(),
+                        message: "Streaming audio from the video without an intermediate file"
+                            .into(),
+                    });
+                }
+                let (body, ffmpeg) = crate::audio::stream_audio(params).await?;
+                (Part::stream(body), Some(ffmpeg))
+            }
+        };
+        let file = file.file_name(filename.clone()).mime_str(mime).unwrap();
+        let result = openai_transcription(api_key, model, file, p, duration_sec).await;
+        // A transcript of audio that ffmpeg cut short isn't one; on an API error the
+        // stream is dropped, which kills ffmpeg
+        let result = match (result, ffmpeg) {
+            (Ok(response), Some(ffmpeg)) => ffmpeg.finish(id, emit).await.map(|()| response),
+            (result, _) => result,
+        };
+        match result {
+            Ok(response) => {
+                if chain.len() > 1 {
+                    emit(RpcEvent::Log {
+                        id: id.into(),
+                        message: format!("Transcribed with {}", model),
+                    });
+                }
+                return Ok((response, model.clone()));
+            }
+            Err(e) if i + 1 < chain.len() && is_retryable_api_error(&e) => {
+                emit(RpcEvent::Log {
+                    id: id.into(),
+                    message: format!("{} failed ({}); falling back to {}", model, e, chain[i + 1]),
+                });
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("the model chain always holds the primary model")
+}
+
+/// Non-success reply from the OpenAI API
+#[derive(Debug)]
+struct OpenAiError {
+    status: reqwest::StatusCode,
+    body: String,
+}
+
+impl std::fmt::Display for OpenAiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "OpenAI error {}: {}", self.status, self.body)
+    }
+}
+
+impl std::error::Error for OpenAiError {}
+
+/// Models served by the OpenAI transcription endpoint rather than run locally
+pub fn is_api_model(model: &str) -> bool {
+    model == "whisper-1" || is_text_only_model(model)
+}
+
+/// gpt-4o-transcribe and gpt-4o-mini-transcribe: better text, but no verbose_json, so
+/// no segment or word timings
+pub fn is_text_only_model(model: &str) -> bool {
+    model.starts_with("gpt-4o") && model.contains("transcribe")
+}
+
+// The model sent to the API: the requested one if the API serves it, else whisper-1
+fn api_model(model: Option<&str>) -> &str {
+    model.filter(|m| is_api_model(m)).unwrap_or("whisper-1")
+}
+
+// One request to the OpenAI transcription endpoint. Text-only models get timings
+// estimated over `duration_sec`, which they need.
+async fn openai_transcription(
+    api_key: &crate::types::ApiKey,
+    model: &str,
+    file: reqwest::multipart::Part,
+    p: &TranscribeSegmentsParams,
+    duration_sec: Option<f64>,
+) -> anyhow::Result<WhisperResponse> {
+    use reqwest::multipart;
+
+    let text_only = is_text_only_model(model);
+    if text_only && duration_sec.is_none() {
+        return Err(anyhow::anyhow!(
+            "{} returns no timings and the audio's length is unknown; use whisper-1",
+            model
+        ));
+    }
+
+    // verbose_json with timestamp granularities where the model supports it
+    let mut form = multipart::Form::new()
+        .text("model", model.to_string())
+        .part("file", file)
+        .text(
+            "response_format",
+            if text_only { "json" } else { "verbose_json" },
+        );
+
+    if let Some(lang) = &p.language {
+        form = form.text("language", lang.clone());
+    }
+    if let Some(prompt) = build_prompt(p.prompt.as_deref(), &p.vocabulary) {
+        form = form.text("prompt", prompt);
+    }
+
+    // set timestamp granularities based on split_by_words preference
+    if text_only {
+        // Not accepted by the gpt-4o models
+    } else if p.split_by_words {
+        form = form.text("timestamp_granularities[]", "word".to_string());
+    } else {
+        form = form.text("timestamp_granularities[]", "segment".to_string());
+    }
+
+    let client = reqwest::Client::builder()
+        .user_agent("core/1.0.0")
+        .build()?;
+
+    let resp = client
+        .post("https://api.openai.com/v1/audio/transcriptions")
+        .header("Authorization", format!("Bearer {}", api_key.expose()))
+        .multipart(form)
+        .send()
+        .await?;
+
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let body = resp.text().await.unwrap_or_default();
+        // OpenAI echoes (part of) a rejected key back in the error body
+        return Err(OpenAiError {
+            status,
+            body: crate::logging::redact_secrets(&body),
+        }
+        .into());
+    }
+
+    if text_only {
+        #[derive(serde::Deserialize)]
+        struct TextOnly {
+            text: String,
+        }
+        let body: TextOnly = resp.json().await?;
+        return Ok(estimated_timings(
+            &body.text,
+            duration_sec.unwrap_or_default(),
+            p.language.clone(),
+        ));
+    }
+    Ok(resp.json().await?)
+}
+
+/// Build a verbose-style response from plain text: one segment per sentence and word
+/// timings spread over `duration` in proportion to each word's length
+pub fn estimated_timings(text: &str, duration: f64, language: Option<String>) -> WhisperResponse {
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    // A word's share counts its characters plus the space after it
+    let weight = |w: &str| w.chars().count() as f64 + 1.0;
+    let total: f64 = tokens.iter().map(|w| weight(w)).sum();
+    let mut words = Vec::with_capacity(tokens.len());
+    let mut at = 0.0;
+    for token in &tokens {
+        let span = duration * weight(token) / total;
+        words.push(WhisperWord {
+            word: token.to_string(),
+            start: at,
+            end: at + span,
+        });
+        at += span;
+    }
+
+    let mut segments: Vec<WhisperSegment> = Vec::new();
+    let mut first = 0;
+    for (i, word) in words.iter().enumerate() {
+        let sentence_end = word
+            .word
+            .trim_end_matches(['"', '\'', ')', '”', '’'])
+            .ends_with(['.', '!', '?', '…', '。', '！', '？']);
+        if sentence_end || i + 1 == words.len() {
+            let text = words[first..=i]
+                .iter()
+                .map(|w| w.word.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            segments.push(WhisperSegment {
+                id: segments.len() as u32,
+                start: words[first].start,
+                end: word.end,
+                text,
+                no_speech_prob: None,
+            });
+            first = i + 1;
+        }
+    }
+
+    WhisperResponse {
+        task: Some("transcribe".to_string()),
+        language,
+        duration: Some(duration),
+        text: text.trim().to_string(),
+        segments: Some(segments),
+        words: Some(words),
+    }
+}
+
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+}
+
+fn format_with_thousands(digits: String) -> String {
+    // insert commas every 3 from right
+    let mut out = String::new();
+    for (cnt, ch) in digits.chars().rev().enumerate() {
+        if cnt > 0 && cnt % 3 == 0 {
+            out.push(',');
+        }
+        out.push(ch);
+    }
+    out.chars().rev().collect()
+}
+
+/// Merge currency symbols, thousand-groups, and decimals into single tokens.
+/// Handles patterns like ["$", "225", "000"] → "$225,000" and ["19", ".", "99"] → "19.99"
+/// Returns (text, start_ms, end_ms) tuples ready for CaptionSegment mapping.
+fn merge_numbers_and_currency(
+    words: &[WhisperWord],
+    max_duration_ms: Option<u64>,
+) -> Vec<(String, u64, u64)> {
+    let mut out = Vec::new();
+    let mut i = 0usize;
+
+    while i < words.len() {
+        let cur = words[i].word.trim();
+        // FIX: words[i].start is already in seconds, convert to ms properly
+        let start_ms = (words[i].start * 1000.0) as u64;
+        let mut end_ms = (words[i].end * 1000.0) as u64;
+
+        if let Some(max_ms) = max_duration_ms {
             if start_ms > max_ms {
                 break;
             }
@@ -1639,6 +2506,227 @@ fn merge_numbers_and_currency(
     out
 }
 
+/// Caption segments for a transcription request, with the request's post-processing applied.
+fn caption_segments_for(
+    id: &str,
+    response: &WhisperResponse,
+    params: &TranscribeSegmentsParams,
+    mut emit: impl FnMut(RpcEvent),
+) -> Vec<CaptionSegment> {
+    let segments = drop_no_speech_segments(
+        whisper_to_caption_segments(response, params.split_by_words),
+        params.drop_no_speech_above,
+    );
+    // Requests are validated first, so an unknown mode never gets here
+    let segments = if parse_resegment(params.resegment.as_deref()).unwrap_or_default() {
+        resegment_by_sentence(segments)
+    } else {
+        segments
+    };
+    let segments = merge_adjacent_segments(segments, params.merge_gap_ms);
+    let Some(max_cps) = params.max_cps.filter(|cps| *cps > 0.0) else {
+        return segments;
+    };
+    let (segments, report) = enforce_max_cps(segments, max_cps);
+    if report.adjusted > 0 || report.too_fast > 0 {
+        let mut message = format!(
+            "Reading speed: adjusted {} cue(s) to stay under {} characters/second",
+            report.adjusted, max_cps
+        );
+        if report.too_fast > 0 {
+            message.push_str(&format!(
+                "; {} cue(s) are still too fast (speech leaves no room to slow them down)",
+                report.too_fast
+            ));
+        }
+        emit(RpcEvent::Log {
+            id: id.into(),
+            message,
+        });
+    }
+    segments
+}
+
+/// What [`enforce_max_cps`] changed
+#[derive(Debug, Default, PartialEq)]
+pub struct CpsReport {
+    pub adjusted: usize, // Cues that were lengthened
+    pub too_fast: usize, // Resulting cues still over the limit
+}
+
+fn chars_per_second(text: &str, start_ms: u64, end_ms: u64) -> f32 {
+    let chars = text.trim().chars().count() as f32;
+    chars * 1000.0 / end_ms.saturating_sub(start_ms).max(1) as f32
+}
+
+/// Keep cues under `max_cps` characters per second by lengthening too-fast cues into
+/// the silence around them: first past their end up to the next cue's start, then
+/// before their start back to the previous cue's end. Cues never overlap, and the last
+/// cue is not extended past its end since the media may end with it.
+///
+/// Splitting is not used: the pieces of a split cue share its span of time, so the
+/// faster piece always reads at least as fast as the whole. Speech that is simply too
+/// dense for the limit is left as it is and counted in `too_fast`. Cues that run too
+/// long are split at render time instead, by the `maxCueMs` style option.
+pub fn enforce_max_cps(
+    mut segments: Vec<CaptionSegment>,
+    max_cps: f32,
+) -> (Vec<CaptionSegment>, CpsReport) {
+    let mut report = CpsReport::default();
+    for i in 0..segments.len() {
+        let seg = &segments[i];
+        if chars_per_second(&seg.text, seg.start_ms, seg.end_ms) <= max_cps {
+            continue;
+        }
+        let needed_ms = (seg.text.trim().chars().count() as f32 * 1000.0 / max_cps).ceil() as u64;
+        let next_start = segments.get(i + 1).map_or(seg.end_ms, |next| next.start_ms);
+        let prev_end = if i == 0 { 0 } else { segments[i - 1].end_ms };
+
+        let end_ms = (seg.start_ms + needed_ms).min(next_start).max(seg.end_ms);
+        let start_ms = end_ms
+            .saturating_sub(needed_ms)
+            .max(prev_end)
+            .min(seg.start_ms);
+        if (start_ms, end_ms) != (seg.start_ms, seg.end_ms) {
+            report.adjusted += 1;
+        }
+        let seg = &mut segments[i];
+        seg.start_ms = start_ms;
+        seg.end_ms = end_ms;
+        if chars_per_second(&seg.text, seg.start_ms, seg.end_ms) > max_cps {
+            report.too_fast += 1;
+        }
+    }
+    (segments, report)
+}
+
+/// Whether `resegment` asks to regroup words into sentences ("sentence") rather than keep
+/// Whisper's segments ("whisper", the default)
+pub fn parse_resegment(value: Option<&str>) -> anyhow::Result<bool> {
+    match value {
+        None | Some("whisper") => Ok(false),
+        Some("sentence") => Ok(true),
+        Some(other) => Err(anyhow::anyhow!(
+            "Unknown resegment mode '{}': use whisper or sentence",
+            other
+        )),
+    }
+}
+
+/// Regroup words into one segment per sentence, ending each at a word that closes
+/// with `.`, `!`, `?` or `…` (closing quotes and brackets allowed after it). Each
+/// segment spans its first word's start to its last word's end. Needs word timings
+/// throughout; if any segment has none, Whisper's segments are returned unchanged.
+pub fn resegment_by_sentence(segments: Vec<CaptionSegment>) -> Vec<CaptionSegment> {
+    if segments.iter().any(|s| s.words.is_empty()) {
+        return segments;
+    }
+
+    let mut out = Vec::new();
+    let mut sentence: Vec<WordSpan> = Vec::new();
+    let mut no_speech_prob: Option<f32> = None;
+    let words = segments.into_iter().flat_map(|s| {
+        let prob = s.no_speech_prob;
+        s.words.into_iter().map(move |w| (w, prob))
+    });
+    for (word, prob) in words {
+        no_speech_prob = match (no_speech_prob, prob) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        let ends_sentence = ends_sentence(&word.text);
+        sentence.push(word);
+        if ends_sentence {
+            out.push(sentence_segment(
+                std::mem::take(&mut sentence),
+                no_speech_prob,
+            ));
+            no_speech_prob = None;
+        }
+    }
+    if !sentence.is_empty() {
+        out.push(sentence_segment(sentence, no_speech_prob));
+    }
+    out
+}
+
+pub(crate) fn ends_sentence(word: &str) -> bool {
+    let word = word.trim_end_matches(['"', '\'', '”', '’', ')', ']', '»']);
+    word.ends_with(['.', '!', '?', '…'])
+}
+
+fn sentence_segment(words: Vec<WordSpan>, no_speech_prob: Option<f32>) -> CaptionSegment {
+    let text = words
+        .iter()
+        .map(|w| w.text.trim())
+        .collect::<Vec<_>>()
+        .join(" ");
+    CaptionSegment {
+        start_ms: words[0].start_ms,
+        end_ms: words[words.len() - 1].end_ms,
+        text,
+        words,
+        no_speech_prob,
+        position: None,
+    }
+}
+
+/// Drop segments whose `no_speech_prob` is above `threshold`: Whisper's phantom
+/// "Thanks for watching!" over music or a silent intro. Segments without a probability
+/// (local transcription) are kept, as is everything when no threshold is set.
+pub fn drop_no_speech_segments(
+    segments: Vec<CaptionSegment>,
+    threshold: Option<f32>,
+) -> Vec<CaptionSegment> {
+    let Some(threshold) = threshold else {
+        return segments;
+    };
+    segments
+        .into_iter()
+        .filter(|s| s.no_speech_prob.is_none_or(|p| p <= threshold))
+        .collect()
+}
+
+// `no_speech_prob` of the Whisper segment a word-level caption starts in
+fn no_speech_prob_at(response: &WhisperResponse, start_ms: u64) -> Option<f32> {
+    response
+        .segments
+        .as_ref()?
+        .iter()
+        .find(|s| (s.start * 1000.0) as u64 <= start_ms && start_ms < (s.end * 1000.0) as u64)
+        .and_then(|s| s.no_speech_prob)
+        .map(|p| p as f32)
+}
+
+/// Merge consecutive segments whose gap (next `start_ms` minus previous `end_ms`) is
+/// below `max_gap_ms`, concatenating text and words. Segments carry no speaker labels
+/// yet; once they do, a speaker change must also prevent the merge.
+pub fn merge_adjacent_segments(
+    segments: Vec<CaptionSegment>,
+    max_gap_ms: Option<u32>,
+) -> Vec<CaptionSegment> {
+    let Some(max_gap_ms) = max_gap_ms else {
+        return segments;
+    };
+    let mut out: Vec<CaptionSegment> = Vec::with_capacity(segments.len());
+    for seg in segments {
+        match out.last_mut() {
+            Some(prev) if seg.start_ms.saturating_sub(prev.end_ms) < max_gap_ms as u64 => {
+                prev.end_ms = prev.end_ms.max(seg.end_ms);
+                prev.text = format!("{} {}", prev.text.trim_end(), seg.text.trim_start());
+                prev.words.extend(seg.words);
+                // Speech in either half means the merged segment has speech
+                prev.no_speech_prob = match (prev.no_speech_prob, seg.no_speech_prob) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+            }
+            _ => out.push(seg),
+        }
+    }
+    out
+}
+
 pub fn whisper_to_caption_segments(
     response: &WhisperResponse,
     split_by_words: bool,
@@ -1664,6 +2752,8 @@ pub fn whisper_to_caption_segments(
                             end_ms,
                             text,
                         }],
+                        no_speech_prob: no_speech_prob_at(response, start_ms),
+                        position: None,
                     })
                 })
                 .collect();
@@ -1739,6 +2829,8 @@ pub fn whisper_to_caption_segments(
                             end_ms: word_end_ms,
                             text: word_text,
                         }],
+                        no_speech_prob: seg.no_speech_prob.map(|p| p as f32),
+                        position: None,
                     });
                 }
             }
@@ -1801,6 +2893,8 @@ pub fn whisper_to_caption_segments(
                     end_ms: final_end_ms,
                     text: seg.text.clone(),
                     words: segment_words,
+                    no_speech_prob: seg.no_speech_prob.map(|p| p as f32),
+                    position: None,
                 })
             })
             .collect()
@@ -1817,6 +2911,8 @@ pub fn whisper_to_caption_segments(
                 end_ms: duration as u64,
                 text,
             }],
+            no_speech_prob: None,
+            position: None,
         }]
     }
 }
@@ -1825,28 +2921,66 @@ pub async fn get_cached_whisper_response(
     audio_path: &str,
     params: &TranscribeSegmentsParams,
 ) -> anyhow::Result<Option<WhisperResponse>> {
-    let (audio_hash, params_hash) = compute_segments_cache_key(audio_path, params)?;
+    let audio_bytes = std::fs::read(audio_path)?;
+    get_cached_response_for(&audio_bytes, params).await
+}
+
+// Cached response for this exact audio content and these parameters
+async fn get_cached_response_for(
+    audio_bytes: &[u8],
+    params: &TranscribeSegmentsParams,
+) -> anyhow::Result<Option<WhisperResponse>> {
     let index = load_cache_index().await?;
 
-    for entry in &index.entries {
-        if entry.audio_hash == audio_hash
-            && entry.params_hash == params_hash
-            && std::path::Path::new(&entry.response_path).exists()
-        {
-            let content = fs::read_to_string(&entry.response_path).await?;
-            let response: WhisperResponse = serde_json::from_str(&content)?;
-            return Ok(Some(response));
-        }
+    for (audio_hash, params_hash) in cache_keys_for(audio_bytes, params) {
+        let Some(entry) = index.entries.iter().find(|e| {
+            e.audio_hash == audio_hash
+                && e.params_hash == params_hash
+                && std::path::Path::new(&e.response_path).exists()
+        }) else {
+            continue;
+        };
+        let content = fs::read_to_string(&entry.response_path).await?;
+        let response: WhisperResponse = serde_json::from_str(&content)?;
+        return Ok(Some(response));
     }
     Ok(None)
 }
 
+// Keys a cached transcription may sit under, in lookup order: the model as requested
+// (local results and older entries), then each model of the API chain, which is what
+// API results are stored under (e.g. "whisper-1" for an unset or local model)
+fn cache_keys_for(audio_bytes: &[u8], params: &TranscribeSegmentsParams) -> Vec<(String, String)> {
+    let mut models = vec![params.model.clone()];
+    for model in api_model_chain(params) {
+        let model = Some(model);
+        if !models.contains(&model) {
+            models.push(model);
+        }
+    }
+    models
+        .iter()
+        .map(|model| segments_cache_key_with_model(audio_bytes, params, model.as_deref()))
+        .collect()
+}
+
 pub async fn save_cached_whisper_response(
     audio_path: &str,
     params: &TranscribeSegmentsParams,
     response: &WhisperResponse,
 ) -> anyhow::Result<()> {
     let (audio_hash, params_hash) = compute_segments_cache_key(audio_path, params)?;
+    let json_content = serde_json::to_string_pretty(response)?;
+    // Finish the write on its own task: if the request is cancelled right after
+    // transcribing (e.g. during the burn), the paid-for result still lands in the cache
+    tokio::spawn(store_cached_response(audio_hash, params_hash, json_content)).await?
+}
+
+async fn store_cached_response(
+    audio_hash: String,
+    params_hash: String,
+    json_content: String,
+) -> anyhow::Result<()> {
     let mut index = load_cache_index().await?;
     let cache_dir = get_cache_dir()?;
     let timestamp = std::time::SystemTime::now()
@@ -1856,8 +2990,7 @@ pub async fn save_cached_whisper_response(
     // create cache filename and save JSON response
     let cache_filename = format!("{}_{}.json", &audio_hash[..8], &params_hash[..8]);
     let cached_json_path = cache_dir.join(cache_filename);
-    let json_content = serde_json::to_string_pretty(response)?;
-    fs::write(&cached_json_path, json_content).await?;
+    write_atomic(&cached_json_path, &json_content).await?;
 
     // add new entry
     let new_entry = WhisperCacheEntry {
@@ -1893,34 +3026,117 @@ pub async fn save_cached_whisper_response(
     Ok(())
 }
 
+/// Whisper only reads the last ~224 tokens of a prompt; keep well inside that
+const MAX_PROMPT_CHARS: usize = 800;
+
+/// The prompt actually sent to Whisper. `vocabulary` terms are deduplicated and appended
+/// to the manual `prompt` as a sentence listing them ("Glossary: CapSlap, OpenAI."), which
+/// Whisper treats as text it has already heard and so spells the same way. The manual
+/// prompt is kept verbatim; terms that would push the total past [`MAX_PROMPT_CHARS`]
+/// are dropped from the end of the list.
+pub fn build_prompt(prompt: Option<&str>, vocabulary: &[String]) -> Option<String> {
+    let prompt = prompt.map(str::trim).filter(|p| !p.is_empty());
+    let mut terms: Vec<&str> = Vec::new();
+    for term in vocabulary
+        .iter()
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+    {
+        if !terms.iter().any(|t| t.eq_ignore_ascii_case(term)) {
+            terms.push(term);
+        }
+    }
+    if terms.is_empty() {
+        return prompt.map(str::to_string);
+    }
+
+    let mut out = match prompt {
+        Some(p) if p.ends_with(['.', '!', '?']) => format!("{} ", p),
+        Some(p) => format!("{}. ", p),
+        None => String::new(),
+    };
+    out.push_str("Glossary: ");
+    for (i, term) in terms.into_iter().enumerate() {
+        let sep = if i == 0 { "" } else { ", " };
+        if i > 0 && out.len() + sep.len() + term.len() + 1 > MAX_PROMPT_CHARS {
+            break;
+        }
+        out.push_str(sep);
+        out.push_str(term);
+    }
+    out.push('.');
+    Some(out)
+}
+
 pub fn compute_segments_cache_key(
     audio_path: &str,
     params: &TranscribeSegmentsParams,
 ) -> anyhow::Result<(String, String)> {
     // hash audio file content
     let audio_bytes = std::fs::read(audio_path)?;
-    let audio_hash = blake3::hash(&audio_bytes).to_hex().to_string();
+    Ok(segments_cache_key_for(&audio_bytes, params))
+}
+
+// (audio hash, params hash) cache key for audio content
+fn segments_cache_key_for(
+    audio_bytes: &[u8],
+    params: &TranscribeSegmentsParams,
+) -> (String, String) {
+    segments_cache_key_with_model(audio_bytes, params, params.model.as_deref())
+}
+
+fn segments_cache_key_with_model(
+    audio_bytes: &[u8],
+    params: &TranscribeSegmentsParams,
+    model: Option<&str>,
+) -> (String, String) {
+    let audio_hash = blake3::hash(audio_bytes).to_hex().to_string();
 
     // hash relevant parameters (excluding video_file as it doesn't affect transcription)
     let params_for_hash = serde_json::json!({
-        "model": params.model,
+        "model": model,
         "language": params.language,
         "split_by_words": params.split_by_words,
-        "prompt": params.prompt,
+        "prompt": build_prompt(params.prompt.as_deref(), &params.vocabulary),
         "version": "v2_merged_tokens", // Invalidate cache for new merging logic
     });
     let params_hash = blake3::hash(params_for_hash.to_string().as_bytes())
         .to_hex()
         .to_string();
 
-    Ok((audio_hash, params_hash))
+    (audio_hash, params_hash)
 }
 
 pub async fn save_cache_index(index: &WhisperCacheIndex) -> anyhow::Result<()> {
     let cache_dir = get_cache_dir()?;
     let index_path = cache_dir.join("index.json");
     let content = serde_json::to_string_pretty(index)?;
-    fs::write(index_path, content).await?;
+    write_atomic(&index_path, &content).await?;
+    Ok(())
+}
+
+/// Write via a sibling temp file that is synced to disk and renamed over `path`, so
+/// readers (or a crash) never see a half-written file
+pub(crate) async fn write_atomic(path: &std::path::Path, content: &str) -> anyhow::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(format!(".{}.tmp", uuid::Uuid::new_v4()));
+    let tmp_path = path.with_file_name(tmp_name);
+    let written = async {
+        let mut file = fs::File::create(&tmp_path).await?;
+        file.write_all(content.as_bytes()).await?;
+        file.sync_all().await
+    }
+    .await;
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp_path).await;
+        return Err(e.into());
+    }
+    if let Err(e) = fs::rename(&tmp_path, path).await {
+        let _ = fs::remove_file(&tmp_path).await;
+        return Err(e.into());
+    }
     Ok(())
 }
 
@@ -1941,12 +3157,63 @@ pub async fn load_cache_index() -> anyhow::Result<WhisperCacheIndex> {
 }
 
 pub fn get_cache_dir() -> std::io::Result<PathBuf> {
-    let mut cache_dir = std::env::temp_dir();
-    cache_dir.push("capslap_whisper_cache");
+    let cache_dir = match cache_root_override() {
+        Some((root, _)) => root.join("whisper"),
+        None => std::env::temp_dir().join("capslap_whisper_cache"),
+    };
     std::fs::create_dir_all(&cache_dir)?;
     Ok(cache_dir)
 }
 
+/// Cache root picked by the user, with where it came from ("env" or "config").
+/// None means the built-in locations (temp dir for the cache, app data for models).
+pub fn cache_root_override() -> Option<(PathBuf, &'static str)> {
+    let config = crate::captions::core_config_or_default();
+    resolve_cache_root(std::env::var_os("CAPSLAP_CACHE_DIR"), config.cache_dir)
+}
+
+fn resolve_cache_root(
+    env: Option<std::ffi::OsString>,
+    config: Option<String>,
+) -> Option<(PathBuf, &'static str)> {
+    if let Some(dir) = env.filter(|d| !d.is_empty()) {
+        return Some((PathBuf::from(dir), "env"));
+    }
+    config
+        .filter(|d| !d.trim().is_empty())
+        .map(|d| (PathBuf::from(d), "config"))
+}
+
+/// Where the cache and models live and how much the cache holds
+pub async fn cache_stats() -> anyhow::Result<crate::types::CacheStatsResult> {
+    let cache_dir = get_cache_dir()?;
+    let models_dir = get_models_dir()?;
+    let index = load_cache_index().await?;
+    Ok(crate::types::CacheStatsResult {
+        cache_dir: cache_dir.to_string_lossy().to_string(),
+        models_dir: models_dir.to_string_lossy().to_string(),
+        source: cache_root_override()
+            .map_or("default", |(_, source)| source)
+            .to_string(),
+        transcription_entries: index.entries.len(),
+        cache_bytes: dir_size(&cache_dir),
+    })
+}
+
+fn dir_size(dir: &std::path::Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| match e.metadata() {
+            Ok(m) if m.is_dir() => dir_size(&e.path()),
+            Ok(m) => m.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2163,20 +3430,20 @@ mod tests {
     // check_model_exists tests
     // ============================================
 
-    #[test]
-    fn test_check_model_exists_unknown_model() {
+    #[tokio::test]
+    async fn test_check_model_exists_unknown_model() {
         // Unknown model names should return Ok(false)
-        let result = check_model_exists("nonexistent_model_xyz");
+        let result = check_model_exists("nonexistent_model_xyz").await;
         assert!(result.is_ok());
         assert!(!result.unwrap());
     }
 
-    #[test]
-    fn test_check_model_exists_known_model_names() {
+    #[tokio::test]
+    async fn test_check_model_exists_known_model_names() {
         // These should at least not error, even if model doesn't exist
         let models = ["tiny", "base", "small", "medium", "large", "turbo"];
         for model in models {
-            let result = check_model_exists(model);
+            let result = check_model_exists(model).await;
             assert!(
                 result.is_ok(),
                 "check_model_exists should not error for {}",
@@ -2218,6 +3485,41 @@ mod tests {
         assert!(paths.contains(&"ffprobe".to_string()));
     }
 
+    // ============================================
+    // merge_adjacent_segments tests
+    // ============================================
+
+    #[test]
+    fn test_merge_adjacent_segments_by_gap() {
+        let seg = |start_ms, end_ms, text: &str| CaptionSegment {
+            start_ms,
+            end_ms,
+            text: text.to_string(),
+            words: vec![WordSpan {
+                start_ms,
+                end_ms,
+                text: text.to_string(),
+            }],
+            no_speech_prob: None,
+            position: None,
+        };
+        let segments = vec![
+            seg(0, 1000, "hello"),
+            seg(1040, 2000, "there"),
+            seg(2600, 3000, "friend"),
+        ];
+
+        let merged = merge_adjacent_segments(segments.clone(), Some(100));
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].text, "hello there");
+        assert_eq!((merged[0].start_ms, merged[0].end_ms), (0, 2000));
+        assert_eq!(merged[0].words.len(), 2);
+        assert_eq!(merged[1].text, "friend");
+
+        // No threshold leaves the transcription untouched
+        assert_eq!(merge_adjacent_segments(segments, None).len(), 3);
+    }
+
     // ============================================
     // get_cache_dir tests
     // ============================================
@@ -2232,4 +3534,445 @@ mod tests {
             .to_string_lossy()
             .contains("capslap_whisper_cache"));
     }
+
+    #[tokio::test]
+    async fn test_write_atomic_replaces_without_leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.json");
+        write_atomic(&path, "{\"entries\":[]}").await.unwrap();
+        write_atomic(&path, "{\"entries\":[1]}").await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{\"entries\":[1]}");
+        let names: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec![std::ffi::OsString::from("index.json")]);
+    }
+
+    #[test]
+    fn test_resolve_cache_root_prefers_env_then_config() {
+        assert_eq!(
+            resolve_cache_root(Some("/mnt/big".into()), Some("/cfg".into())),
+            Some((PathBuf::from("/mnt/big"), "env"))
+        );
+        assert_eq!(
+            resolve_cache_root(Some("".into()), Some("/cfg".into())),
+            Some((PathBuf::from("/cfg"), "config"))
+        );
+        assert_eq!(resolve_cache_root(None, Some("  ".into())), None);
+        assert_eq!(resolve_cache_root(None, None), None);
+    }
+
+    #[test]
+    fn test_dir_size_counts_nested_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.json"), b"12345").unwrap();
+        std::fs::create_dir(dir.path().join("audio")).unwrap();
+        std::fs::write(dir.path().join("audio/a.mp3"), b"123").unwrap();
+        assert_eq!(dir_size(dir.path()), 8);
+        assert_eq!(dir_size(&dir.path().join("missing")), 0);
+    }
+
+    #[test]
+    fn test_sha256_file_and_pinned_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ggml-test.bin");
+        std::fs::write(&path, b"abc").unwrap();
+        let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert_eq!(sha256_file(&path).unwrap(), abc);
+
+        // Every downloadable model has a well-formed pinned checksum
+        for name in MODEL_NAMES {
+            let sha = pinned_sha256(model_filename(name).unwrap()).unwrap();
+            assert_eq!(sha.len(), 64, "{}", name);
+            assert!(sha.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')));
+        }
+        assert_eq!(pinned_sha256("ggml-unknown.bin"), None);
+    }
+
+    #[test]
+    fn test_legacy_model_is_hashed_once_and_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ggml-test.bin");
+        std::fs::write(&path, b"abc").unwrap();
+        let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert!(verify_legacy_model(&path, abc));
+        assert!(is_verified_model(&path));
+        // Only files without a record count as legacy
+        assert!(!verify_legacy_model(&path, abc));
+        assert!(!verify_legacy_model(&dir.path().join("missing.bin"), abc));
+    }
+
+    #[test]
+    fn test_mismatched_legacy_model_is_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ggml-test.bin");
+        std::fs::write(&path, b"abc").unwrap();
+        // A different file than the pinned one is dropped, so it isn't hashed on every check
+        assert!(!verify_legacy_model(&path, &"0".repeat(64)));
+        assert!(!path.exists());
+        assert!(!is_verified_model(&path));
+    }
+
+    #[test]
+    fn test_is_verified_model_rejects_missing_and_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ggml-test.bin");
+        std::fs::write(&path, b"model bytes").unwrap();
+        // No checksum record yet: treated as missing
+        assert!(!is_verified_model(&path));
+
+        let sha = sha256_file(&path).unwrap();
+        assert_eq!(write_model_checksum(&path, &sha).unwrap(), 11);
+        assert!(is_verified_model(&path));
+
+        // Truncation changes the size and fails straight away
+        std::fs::write(&path, b"model").unwrap();
+        assert!(!is_verified_model(&path));
+        assert!(!is_verified_model(&dir.path().join("missing.bin")));
+    }
+
+    #[test]
+    fn test_download_resume_helpers() {
+        assert_eq!(content_range_total("bytes 100-199/1000"), Some(1000));
+        assert_eq!(content_range_total("bytes 0-0/*"), None);
+        assert_eq!(content_range_total("items 0-1/2"), None);
+
+        assert!(is_retryable_status(reqwest::StatusCode::BAD_GATEWAY));
+        assert!(is_retryable_status(reqwest::StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_retryable_status(reqwest::StatusCode::NOT_FOUND));
+
+        assert_eq!(download_retry_delay(1).as_secs(), 2);
+        assert_eq!(download_retry_delay(3).as_secs(), 8);
+        assert_eq!(download_retry_delay(10).as_secs(), 30);
+    }
+
+    #[test]
+    fn test_list_and_delete_models() {
+        let dir = tempfile::tempdir().unwrap();
+        let tiny = dir.path().join("ggml-tiny.bin");
+        std::fs::write(&tiny, b"tiny model").unwrap();
+        write_model_checksum(&tiny, &sha256_file(&tiny).unwrap()).unwrap();
+        std::fs::write(dir.path().join("ggml-large-v3.bin.part"), b"half").unwrap();
+        std::fs::write(dir.path().join("unrelated.txt"), b"keep me").unwrap();
+
+        let models = list_models_in(dir.path());
+        let names: Vec<_> = models.iter().map(|m| m.model.as_str()).collect();
+        assert_eq!(names, ["tiny", "large"]);
+        assert!(models[0].verified && !models[0].partial);
+        assert!(models[0].size > 10);
+        assert!(!models[1].verified && models[1].partial);
+        assert_eq!(models[1].size, 4);
+
+        let freed = delete_model_in(dir.path(), "tiny").unwrap();
+        assert_eq!(freed, models[0].size);
+        assert!(!tiny.exists());
+        assert_eq!(delete_model_in(dir.path(), "large").unwrap(), 4);
+        assert_eq!(delete_model_in(dir.path(), "base").unwrap(), 0);
+        assert!(delete_model_in(dir.path(), "huge").is_err());
+        assert!(list_models_in(dir.path()).is_empty());
+        assert!(dir.path().join("unrelated.txt").exists());
+    }
+
+    #[test]
+    fn test_build_prompt_merges_vocabulary() {
+        let vocab: Vec<String> = ["CapSlap", "OpenAI", " capslap ", ""]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            build_prompt(None, &vocab).as_deref(),
+            Some("Glossary: CapSlap, OpenAI.")
+        );
+        assert_eq!(
+            build_prompt(Some("A podcast about video tools"), &vocab).as_deref(),
+            Some("A podcast about video tools. Glossary: CapSlap, OpenAI.")
+        );
+        assert_eq!(build_prompt(Some("Hi!"), &[]).as_deref(), Some("Hi!"));
+        assert_eq!(build_prompt(Some("  "), &[]), None);
+
+        let many: Vec<String> = (0..500).map(|i| format!("Term{}", i)).collect();
+        let prompt = build_prompt(None, &many).unwrap();
+        assert!(prompt.len() <= MAX_PROMPT_CHARS, "{}", prompt.len());
+        assert!(prompt.starts_with("Glossary: Term0, Term1,") && prompt.ends_with('.'));
+    }
+
+    #[test]
+    fn test_drop_no_speech_segments() {
+        let response: WhisperResponse = serde_json::from_value(serde_json::json!({
+            "text": "Thanks for watching! Hello there.",
+            "duration": 6.0,
+            "segments": [
+                { "id": 0, "start": 0.0, "end": 2.5, "text": "Thanks for watching!", "no_speech_prob": 0.91 },
+                { "id": 1, "start": 3.0, "end": 5.0, "text": "Hello there.", "no_speech_prob": 0.02 },
+            ],
+        }))
+        .unwrap();
+        let segments = whisper_to_caption_segments(&response, false);
+        assert_eq!(segments[0].no_speech_prob, Some(0.91));
+
+        // Off by default
+        assert_eq!(drop_no_speech_segments(segments.clone(), None).len(), 2);
+        let kept = drop_no_speech_segments(segments, Some(0.6));
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].text, "Hello there.");
+
+        // Word captions inherit the probability of the segment they start in
+        let words = whisper_to_caption_segments(&response, true);
+        assert_eq!(words[0].no_speech_prob, Some(0.91));
+        assert_eq!(words.last().unwrap().no_speech_prob, Some(0.02));
+
+        // Survives the transcription cache round trip under the API's key
+        let cached = serde_json::to_value(&response).unwrap();
+        assert_eq!(cached["segments"][1]["no_speech_prob"], 0.02);
+    }
+
+    #[test]
+    fn test_detected_language_is_reported_as_code() {
+        assert_eq!(language_code("English"), "en");
+        assert_eq!(language_code("finnish"), "fi");
+        assert_eq!(language_code("en"), "en");
+        assert_eq!(language_code("Klingon"), "klingon");
+
+        let response = parse_whisper_cpp_output(
+            r#"{
+                "result": { "language": "fi" },
+                "transcription": [
+                    { "offsets": { "from": 0, "to": 1200 }, "text": " Hei maailma" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(response.language.as_deref(), Some("fi"));
+    }
+
+    #[test]
+    fn test_parse_resegment() {
+        assert!(!parse_resegment(None).unwrap());
+        assert!(!parse_resegment(Some("whisper")).unwrap());
+        assert!(parse_resegment(Some("sentence")).unwrap());
+        assert_eq!(
+            parse_resegment(Some("sentences")).unwrap_err().to_string(),
+            "Unknown resegment mode 'sentences': use whisper or sentence"
+        );
+    }
+
+    #[test]
+    fn test_resegment_by_sentence() {
+        let word = |text: &str, start_ms, end_ms| WordSpan {
+            start_ms,
+            end_ms,
+            text: text.into(),
+        };
+        let seg = |words: Vec<WordSpan>| CaptionSegment {
+            start_ms: words[0].start_ms,
+            end_ms: words[words.len() - 1].end_ms,
+            text: words
+                .iter()
+                .map(|w| w.text.as_str())
+                .collect::<Vec<_>>()
+                .join(" "),
+            words,
+            no_speech_prob: None,
+            position: None,
+        };
+        // Whisper broke mid-sentence twice
+        let segments = vec![
+            seg(vec![
+                word("Hello", 0, 400),
+                word("there.", 400, 800),
+                word("How", 900, 1100),
+            ]),
+            seg(vec![
+                word("are", 1100, 1300),
+                word("you?\"", 1300, 1700),
+                word("Fine", 2000, 2400),
+            ]),
+        ];
+        let out = resegment_by_sentence(segments.clone());
+        let cues: Vec<(&str, u64, u64)> = out
+            .iter()
+            .map(|s| (s.text.as_str(), s.start_ms, s.end_ms))
+            .collect();
+        assert_eq!(
+            cues,
+            vec![
+                ("Hello there.", 0, 800),
+                ("How are you?\"", 900, 1700),
+                ("Fine", 2000, 2400),
+            ]
+        );
+
+        // Without word timings Whisper's segments are kept
+        let mut missing = segments;
+        missing[1].words.clear();
+        let out = resegment_by_sentence(missing);
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].text, "Hello there. How");
+    }
+
+    #[test]
+    fn test_enforce_max_cps_extends_into_silence() {
+        let cue = |start_ms, end_ms, text: &str| CaptionSegment {
+            start_ms,
+            end_ms,
+            text: text.into(),
+            words: Vec::new(),
+            no_speech_prob: None,
+            position: None,
+        };
+        let segments = vec![
+            cue(0, 1000, "Plenty of time"),
+            // 20 characters need 1177ms at 17 cps: runs on to the next cue, then starts earlier
+            cue(1500, 2000, "Supercalifragilistic"),
+            cue(2300, 2500, "Bye now friends"), // boxed in: 2300..3000 is still 21.4 cps
+            cue(3000, 3200, "Too fast again"),  // last cue, never extended
+        ];
+        let (out, report) = enforce_max_cps(segments, 17.0);
+        let spans: Vec<(u64, u64)> = out.iter().map(|s| (s.start_ms, s.end_ms)).collect();
+        assert_eq!(
+            spans,
+            vec![(0, 1000), (1123, 2300), (2300, 3000), (3000, 3200)]
+        );
+        assert_eq!(
+            report,
+            CpsReport {
+                adjusted: 2,
+                too_fast: 2
+            }
+        );
+        for pair in out.windows(2) {
+            assert!(pair[0].end_ms <= pair[1].start_ms, "{:?}", pair);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_ffmpeg_path_override() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let ffmpeg = dir.path().join("ffmpeg-libass");
+        std::fs::write(&ffmpeg, "#!/bin/sh\n").unwrap();
+        let path = ffmpeg.to_string_lossy().to_string();
+
+        let err = validate_ffmpeg_path(&path).unwrap_err().to_string();
+        assert!(err.contains("is not executable"), "{}", err);
+        std::fs::set_permissions(&ffmpeg, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(validate_ffmpeg_path(&path).is_ok());
+        assert!(validate_ffmpeg_path(&dir.path().to_string_lossy()).is_err());
+        assert!(validate_ffmpeg_path("/no/such/ffmpeg").is_err());
+
+        let found = with_ffmpeg_path(Some(path.clone()), find_ffmpeg_binary())
+            .await
+            .unwrap();
+        assert_eq!(found, path);
+        assert_eq!(ffmpeg_override(), None);
+
+        // ffprobe is only taken from beside the override when it exists there
+        assert_eq!(ffprobe_beside(&path), None);
+        let ffprobe = dir.path().join("ffprobe-libass");
+        std::fs::write(&ffprobe, "#!/bin/sh\n").unwrap();
+        let found = with_ffmpeg_path(Some(path), find_ffprobe_binary())
+            .await
+            .unwrap();
+        assert_eq!(found, ffprobe.to_string_lossy());
+    }
+
+    #[test]
+    fn test_text_only_models_get_estimated_timings() {
+        assert!(is_api_model("whisper-1"));
+        assert!(is_api_model("gpt-4o-mini-transcribe"));
+        assert!(is_text_only_model("gpt-4o-transcribe"));
+        assert!(!is_text_only_model("whisper-1"));
+        assert!(!is_api_model("base.en"));
+        assert_eq!(api_model(Some("small")), "whisper-1");
+        assert_eq!(api_model(Some("gpt-4o-transcribe")), "gpt-4o-transcribe");
+
+        let response = estimated_timings("Hi there. How are you?", 10.0, None);
+        let segments = response.segments.as_ref().unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].text, "Hi there.");
+        assert_eq!(segments[1].text, "How are you?");
+        let words = response.words.as_ref().unwrap();
+        assert_eq!(words.len(), 5);
+        assert_eq!(words[0].start, 0.0);
+        assert!((words[4].end - 10.0).abs() < 1e-9);
+        // Longer words get more time
+        assert!(words[1].end - words[1].start > words[0].end - words[0].start);
+        assert_eq!(segments[1].start, words[2].start);
+
+        // Maps into caption segments like a verbose_json response
+        let captions = whisper_to_caption_segments(&response, false);
+        assert_eq!(captions.len(), 2);
+        assert_eq!(captions[1].end_ms, 10_000);
+        assert_eq!(whisper_to_caption_segments(&response, true).len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_model_fallback_chain_and_retryable_errors() {
+        let mut p: TranscribeSegmentsParams = serde_json::from_value(serde_json::json!({
+            "audio": "/a.mp3",
+            "splitByWords": false,
+            "model": "gpt-4o-transcribe",
+            "modelFallbacks": ["gpt-4o-transcribe", "base", "whisper-1"],
+        }))
+        .unwrap();
+        // Repeats and local models are skipped
+        assert_eq!(api_model_chain(&p), ["gpt-4o-transcribe", "whisper-1"]);
+        // An API model never goes local, whatever is installed
+        assert_eq!(
+            transcription_route(p.model.as_deref(), &p.model_fallbacks).await,
+            TranscriptionRoute::Api(vec!["gpt-4o-transcribe".into(), "whisper-1".into()])
+        );
+
+        let status = |code: u16| -> anyhow::Error {
+            OpenAiError {
+                status: reqwest::StatusCode::from_u16(code).unwrap(),
+                body: String::new(),
+            }
+            .into()
+        };
+        assert!(is_retryable_api_error(&status(429)));
+        assert!(is_retryable_api_error(&status(503)));
+        assert!(is_retryable_api_error(&status(429).context("transcribing")));
+        assert!(!is_retryable_api_error(&status(401)));
+        assert!(!is_retryable_api_error(&anyhow::anyhow!("bad audio")));
+        assert!(status(401).to_string().contains("401 Unauthorized"));
+
+        // The model that answered is part of the cache key
+        let primary = segments_cache_key_for(b"audio", &p);
+        p.model = Some("whisper-1".to_string());
+        assert_ne!(segments_cache_key_for(b"audio", &p).1, primary.1);
+    }
+
+    #[test]
+    fn test_api_results_are_found_under_the_requested_model() {
+        // An API result is stored under the model that answered...
+        let stored_under = |requested: serde_json::Value, answered: &str| {
+            let mut p: TranscribeSegmentsParams = serde_json::from_value(serde_json::json!({
+                "audio": "/a.mp3",
+                "splitByWords": true,
+                "model": requested,
+            }))
+            .unwrap();
+            let lookup = cache_keys_for(b"audio", &p);
+            p.model = Some(answered.to_string());
+            (lookup, segments_cache_key_for(b"audio", &p))
+        };
+        // ...and a later request for the same audio finds it, model unset or local
+        let (lookup, stored) = stored_under(serde_json::Value::Null, "whisper-1");
+        assert!(lookup.contains(&stored));
+        let (lookup, stored) = stored_under("base".into(), "whisper-1");
+        assert!(lookup.contains(&stored));
+        // The key as requested is still tried first (local results, older entries)
+        let p: TranscribeSegmentsParams = serde_json::from_value(serde_json::json!({
+            "audio": "/a.mp3",
+            "splitByWords": true,
+        }))
+        .unwrap();
+        assert_eq!(
+            cache_keys_for(b"audio", &p)[0],
+            segments_cache_key_for(b"audio", &p)
+        );
+    }
 }